@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+#[path = "kernel/src/screen_mode.rs"]
+mod screen_mode;
+
 fn main() {
     // set by cargo, build scripts should use this directory for output files
     let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
@@ -7,15 +10,23 @@ fn main() {
     // https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies
     let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_kernel").unwrap());
 
+    // the smallest screen mode to pick, the kernel warns when it didn't get it
+    let (min_width, min_height) = screen_mode::MIN_SCREEN_SIZE;
+    let mut boot_config = bootloader::BootConfig::default();
+    boot_config.frame_buffer.minimum_framebuffer_width = Some(min_width);
+    boot_config.frame_buffer.minimum_framebuffer_height = Some(min_height);
+
     // create an UEFI disk image (optional)
     let uefi_path = out_dir.join("uefi.img");
     bootloader::UefiBoot::new(&kernel)
+        .set_boot_config(&boot_config)
         .create_disk_image(&uefi_path)
         .unwrap();
 
     // create a BIOS disk image
     let bios_path = out_dir.join("bios.img");
     bootloader::BiosBoot::new(&kernel)
+        .set_boot_config(&boot_config)
         .create_disk_image(&bios_path)
         .unwrap();
 
@@ -10,10 +10,13 @@ use x86_64::{
     },
 };
 
-use crate::serial_println;
+use crate::{log, serial::LogLevel};
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+/// Window surfaces keep their pixels on the heap, a 640x480 window is 1.2 MiB at 32 bits per
+/// pixel. Files loaded whole, like the images of the image viewer, take up to 4 MiB more. The
+/// desktop frees caches before it fills up, see `desktop::memory_pressure`.
+pub const HEAP_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
 
 #[global_allocator]
 pub static mut ALLOCATOR: CountingAllocator = CountingAllocator::empty();
@@ -41,7 +44,8 @@ pub fn init_heap(
         init_allocator(&raw mut ALLOCATOR, HEAP_START, HEAP_SIZE);
     }
 
-    serial_println!(
+    log!(
+        LogLevel::Info,
         "Heap initialized successfully at {:#x} with size {} bytes",
         HEAP_START,
         HEAP_SIZE
@@ -0,0 +1,144 @@
+use alloc::vec::Vec;
+
+use crate::framebuffer::Color;
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+
+/// Encode pixels (row by row, top row first) as an uncompressed 24-bit BMP file
+pub fn encode(width: usize, height: usize, pixels: &[Color]) -> Vec<u8> {
+    // Every row is padded to a multiple of 4 bytes
+    let row_size = (width * 3 + 3) & !3;
+    let data_offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+    let file_size = data_offset + row_size * height;
+
+    let mut data = Vec::with_capacity(file_size);
+
+    // BITMAPFILEHEADER
+    data.extend_from_slice(b"BM");
+    data.extend_from_slice(&(file_size as u32).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes()); // Reserved
+    data.extend_from_slice(&(data_offset as u32).to_le_bytes());
+
+    // BITMAPINFOHEADER
+    data.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32).to_le_bytes()); // Positive height means bottom-up rows
+    data.extend_from_slice(&1u16.to_le_bytes()); // Color planes
+    data.extend_from_slice(&24u16.to_le_bytes()); // Bits per pixel
+    data.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB, no compression
+    data.extend_from_slice(&((row_size * height) as u32).to_le_bytes());
+    data.extend_from_slice(&2835i32.to_le_bytes()); // 72 DPI horizontally
+    data.extend_from_slice(&2835i32.to_le_bytes()); // and vertically
+    data.extend_from_slice(&0u32.to_le_bytes()); // Colors in the palette
+    data.extend_from_slice(&0u32.to_le_bytes()); // Important colors
+
+    if width == 0 {
+        return data;
+    }
+
+    for row in pixels.chunks_exact(width).take(height).rev() {
+        for color in row {
+            data.extend_from_slice(&color.to_bgr());
+        }
+        data.resize(data.len() + row_size - width * 3, 0);
+    }
+
+    data
+}
+
+/// A decoded image, pixels are stored row by row, top row first
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, &'static str> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or("Truncated BMP header")
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, &'static str> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or("Truncated BMP header")
+}
+
+/// Width and height from the header, without decoding the pixels
+pub fn dimensions(data: &[u8]) -> Result<(usize, usize), &'static str> {
+    if !data.starts_with(b"BM") {
+        return Err("Not a BMP file");
+    }
+
+    let width = read_u32(data, 18)? as i32;
+    let raw_height = read_u32(data, 22)? as i32;
+    if width <= 0 || raw_height == 0 || raw_height == i32::MIN {
+        return Err("Invalid BMP dimensions");
+    }
+
+    Ok((width as usize, raw_height.unsigned_abs() as usize))
+}
+
+/// Decode an uncompressed 24 or 32-bit BMP file
+pub fn decode(data: &[u8]) -> Result<Bitmap, &'static str> {
+    if !data.starts_with(b"BM") {
+        return Err("Not a BMP file");
+    }
+
+    let data_offset = read_u32(data, 10)? as usize;
+    let header_size = read_u32(data, 14)? as usize;
+    if header_size < INFO_HEADER_SIZE {
+        return Err("Unsupported BMP header version");
+    }
+
+    let width = read_u32(data, 18)? as i32;
+    let raw_height = read_u32(data, 22)? as i32;
+    let bits_per_pixel = read_u16(data, 28)?;
+    let compression = read_u32(data, 30)?;
+
+    if width <= 0 || raw_height == 0 || raw_height == i32::MIN {
+        return Err("Invalid BMP dimensions");
+    }
+
+    // Bitfields are only accepted for 32-bit images, where they are the usual BGRA layout
+    match (bits_per_pixel, compression) {
+        (24, 0) | (32, 0) | (32, 3) => {}
+        _ => return Err("Only uncompressed 24 and 32-bit BMPs are supported"),
+    }
+
+    let width = width as usize;
+    // A negative height means the rows are stored top-down
+    let top_down = raw_height < 0;
+    let height = raw_height.unsigned_abs() as usize;
+
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let row_size = (width * bytes_per_pixel + 3) & !3;
+
+    let needed = row_size
+        .checked_mul(height)
+        .and_then(|size| size.checked_add(data_offset))
+        .ok_or("Invalid BMP dimensions")?;
+    if needed > data.len() {
+        return Err("Truncated BMP pixel data");
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let source_row = if top_down { y } else { height - 1 - y };
+        let row_start = data_offset + source_row * row_size;
+
+        for pixel in
+            data[row_start..row_start + width * bytes_per_pixel].chunks_exact(bytes_per_pixel)
+        {
+            pixels.push(Color::new(pixel[2], pixel[1], pixel[0]));
+        }
+    }
+
+    Ok(Bitmap {
+        width,
+        height,
+        pixels,
+    })
+}
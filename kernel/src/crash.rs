@@ -0,0 +1,362 @@
+//! Post-mortems for panics and double faults, which would otherwise only leave a halted machine.
+//!
+//! A crash writes a report to serial and into `CRASH.LOG` in the root directory, so it
+//! survives a reboot. The file is a ring of fixed-size records that the boot sets up, each
+//! crash overwrites the oldest one. It writes the record's sectors in place, with
+//! interrupts off and the drive polled, so saving needs neither the heap nor the
+//! filesystem code. Every record starts with a header line with its sequence number:
+//!
+//! ```text
+//! #CRASH 00000007 N
+//! === 2025-08-15 22:42:10 PANIC ===
+//! Uptime: 81234 ms
+//! panicked at kernel/src/desktop/paint.rs:120:9:
+//! index out of bounds
+//! ```
+//!
+//! The last byte of the header is `N` for a report no boot has shown yet, `R` once one did
+//! and `-` while the record is empty. The next boot logs the newest report if it is new and
+//! shows it on the desktop, then marks it as reported so it only comes up once.
+
+use alloc::{string::String, vec};
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+};
+use spin::{Mutex, MutexGuard};
+use x86_64::{
+    instructions::interrupts,
+    registers::control::{Cr2, Cr3},
+    structures::idt::InterruptStackFrame,
+};
+
+use crate::{
+    fs::{
+        fat32::{DiskOperations, open_flags},
+        manager::{
+            file_sectors, find_file_in_root, read_file, root_directory_cluster, try_with_disk,
+            write_file_with_flags,
+        },
+    },
+    interrupts::uptime_ms,
+    log, log_buffer,
+    serial::LogLevel,
+    serial_println,
+    time::{RtcTime, get_utc_time},
+};
+
+/// Save reports to disk, turn off if writing from a broken kernel is too risky
+pub const SAVE_CRASH_RECORD: bool = true;
+/// Kernel log lines at the end of a double fault report
+pub const CRASH_LOG_LINES: usize = 10;
+
+pub const CRASH_FILENAME: &str = "CRASH.LOG";
+/// Reports the crash log keeps, a new one replaces the oldest
+pub const CRASH_LOG_RECORDS: usize = 4;
+
+/// Starts the header line of every report
+const ENTRY_MARKER: &str = "===";
+/// Starts every record, followed by its sequence number, its state and a newline
+const RECORD_MARKER: &[u8] = b"#CRASH ";
+const SEQUENCE_OFFSET: usize = RECORD_MARKER.len();
+const SEQUENCE_DIGITS: usize = 8;
+const STATE_OFFSET: usize = SEQUENCE_OFFSET + SEQUENCE_DIGITS + 1;
+/// Bytes in front of the report
+const HEADER_SIZE: usize = STATE_OFFSET + 2;
+
+/// The byte at [`STATE_OFFSET`]
+mod record_state {
+    pub const EMPTY: u8 = b'-';
+    pub const NEW: u8 = b'N';
+    pub const REPORTED: u8 = b'R';
+}
+
+const SECTOR_SIZE: usize = 512;
+/// Whole sectors, so a crash can write the record without reading it first
+const RECORD_SIZE: usize = 4096;
+const RECORD_SECTORS: usize = RECORD_SIZE / SECTOR_SIZE;
+const LOG_SIZE: usize = CRASH_LOG_RECORDS * RECORD_SIZE;
+
+/// The record is built without the heap, it may be what broke. The unused rest stays zero.
+struct Report {
+    bytes: [u8; RECORD_SIZE],
+    len: usize,
+}
+
+impl Report {
+    /// Start over with an empty report, the header is filled in when it is saved
+    fn clear(&mut self) {
+        self.bytes.fill(0);
+        self.len = HEADER_SIZE;
+    }
+
+    fn text(&self) -> &str {
+        core::str::from_utf8(&self.bytes[HEADER_SIZE..self.len]).unwrap_or("(invalid report)")
+    }
+}
+
+impl Write for Report {
+    /// Text that doesn't fit anymore is dropped
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut count = s.len().min(RECORD_SIZE - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.bytes[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+fn header(sequence: u32, state: u8) -> [u8; HEADER_SIZE] {
+    let mut header = [b' '; HEADER_SIZE];
+    header[..SEQUENCE_OFFSET].copy_from_slice(RECORD_MARKER);
+
+    let mut rest = sequence;
+    for digit in header[SEQUENCE_OFFSET..][..SEQUENCE_DIGITS]
+        .iter_mut()
+        .rev()
+    {
+        *digit = b'0' + (rest % 10) as u8;
+        rest /= 10;
+    }
+
+    header[STATE_OFFSET] = state;
+    header[HEADER_SIZE - 1] = b'\n';
+    header
+}
+
+/// The sequence number, the state and the report of a record read back from the disk
+fn parse_record(bytes: &[u8]) -> Option<(u32, u8, &str)> {
+    if bytes.len() != RECORD_SIZE || !bytes.starts_with(RECORD_MARKER) {
+        return None;
+    }
+
+    let sequence = core::str::from_utf8(&bytes[SEQUENCE_OFFSET..][..SEQUENCE_DIGITS]).ok()?;
+    let text = &bytes[HEADER_SIZE..];
+    let len = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+    let text = core::str::from_utf8(&text[..len]).ok()?;
+    Some((sequence.parse().ok()?, bytes[STATE_OFFSET], text.trim_end()))
+}
+
+/// An empty record, for a new crash log
+fn empty_record() -> [u8; RECORD_SIZE] {
+    let mut record = [0; RECORD_SIZE];
+    record[..HEADER_SIZE].copy_from_slice(&header(0, record_state::EMPTY));
+    record
+}
+
+/// Held while a report is written, so a crash while reporting doesn't report again
+static REPORT: Mutex<Report> = Mutex::new(Report {
+    bytes: [0; RECORD_SIZE],
+    len: 0,
+});
+
+/// Where the crash log is on the disk and where the next report goes, found at boot since
+/// looking it up would allocate
+struct CrashLog {
+    sectors: [u64; CRASH_LOG_RECORDS * RECORD_SECTORS],
+    /// Record that gets the next report, the oldest one
+    next: usize,
+    sequence: u32,
+}
+
+static CRASH_LOG: Mutex<Option<CrashLog>> = Mutex::new(None);
+
+/// The last report of the previous boot, until the desktop shows it
+static PREVIOUS_CRASH: Mutex<Option<String>> = Mutex::new(None);
+
+/// Formats as `YYYY-MM-DD HH:MM:SS`
+struct Timestamp(RtcTime);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let t = self.0;
+        write!(
+            f,
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+            t.year, t.month, t.day, t.hours, t.minutes, t.seconds
+        )
+    }
+}
+
+/// Start a new report, `None` if one is already being written
+fn start_report(kind: &str) -> Option<MutexGuard<'static, Report>> {
+    let mut report = REPORT.try_lock()?;
+
+    report.clear();
+    let _ = writeln!(
+        report,
+        "{} {} {} {}",
+        ENTRY_MARKER,
+        Timestamp(get_utc_time()),
+        kind,
+        ENTRY_MARKER
+    );
+    let _ = writeln!(report, "Uptime: {} ms", uptime_ms());
+    Some(report)
+}
+
+/// Write the report to serial, and to the crash log if possible
+fn finish_report(report: &mut Report) {
+    serial_println!("{}", report.text());
+
+    if !SAVE_CRASH_RECORD {
+        return;
+    }
+
+    match interrupts::without_interrupts(|| save_record(report)) {
+        Ok(()) => serial_println!("Crash record saved to {}", CRASH_FILENAME),
+        Err(e) => serial_println!("Crash record not saved: {}", e),
+    }
+}
+
+/// Overwrite the oldest record on the disk through the driver, the filesystem code allocates
+fn save_record(report: &mut Report) -> Result<(), &'static str> {
+    let mut guard = CRASH_LOG.try_lock().ok_or("Crash log is busy")?;
+    let crash_log = guard.as_mut().ok_or("No crash log was set up at boot")?;
+    let sectors = &crash_log.sectors[crash_log.next * RECORD_SECTORS..][..RECORD_SECTORS];
+    let header = header(crash_log.sequence, record_state::NEW);
+    report.bytes[..HEADER_SIZE].copy_from_slice(&header);
+
+    try_with_disk(|disk| {
+        // Halting for the drive's IRQ would let interrupts into a broken kernel
+        disk.use_polling();
+
+        // A deleted record's sectors may belong to another file by now
+        let mut first = [0; SECTOR_SIZE];
+        disk.read_sector(sectors[0], &mut first)?;
+        if !first.starts_with(RECORD_MARKER) {
+            return Err("Crash log was changed since boot");
+        }
+
+        for (&sector, data) in sectors.iter().zip(report.bytes.chunks_exact(SECTOR_SIZE)) {
+            disk.write_sector(sector, data)?;
+        }
+        disk.flush().map(|_| ())
+    })?;
+
+    crash_log.next = (crash_log.next + 1) % CRASH_LOG_RECORDS;
+    crash_log.sequence += 1;
+    Ok(())
+}
+
+/// Dump the CPU state and the last log lines, for the double fault handler
+pub fn report_double_fault(stack_frame: &InterruptStackFrame, error_code: u64) {
+    let Some(mut report) = start_report("DOUBLE FAULT") else {
+        return;
+    };
+
+    let (page_table, cr3_flags) = Cr3::read();
+    let _ = writeln!(report, "Error code: {:#x}", error_code);
+    let _ = writeln!(report, "CR2: {:?}", Cr2::read());
+    let _ = writeln!(report, "CR3: {:?} {:?}", page_table, cr3_flags);
+    let _ = writeln!(report, "{:#?}", stack_frame);
+    let _ = writeln!(report, "Last log lines:");
+    if !log_buffer::try_write_tail(CRASH_LOG_LINES, &mut *report) {
+        let _ = writeln!(report, "(log buffer is locked)");
+    }
+
+    finish_report(&mut report);
+}
+
+/// Record the message and location of a panic, for the panic handler
+pub fn report_panic(info: &PanicInfo) {
+    let Some(mut report) = start_report("PANIC") else {
+        return;
+    };
+
+    let _ = writeln!(report, "{}", info);
+    finish_report(&mut report);
+}
+
+/// Set up the crash log for this boot, and look for a report of the last boot that wasn't
+/// shown yet. It is logged and kept for [`take_previous_crash`], and marked as reported in
+/// the log.
+pub fn check_previous_crash() {
+    if let Err(e) = prepare_log() {
+        log!(LogLevel::Warn, "Crash record: {}", e);
+    }
+}
+
+fn prepare_log() -> Result<(), &'static str> {
+    let root = root_directory_cluster()?;
+    let mut file = find_file_in_root(CRASH_FILENAME)?;
+    if file
+        .as_ref()
+        .is_some_and(|entry| entry.size as usize != LOG_SIZE)
+    {
+        log!(
+            LogLevel::Warn,
+            "Crash record: {} has the wrong size, starting over",
+            CRASH_FILENAME
+        );
+        file = None;
+    }
+
+    let mut bytes = match &file {
+        Some(entry) => read_file(entry.first_cluster, entry.size)?,
+        None => vec![0; LOG_SIZE],
+    };
+
+    // Records without a header get one, a crash only writes over records it recognizes
+    let mut changed = file.is_none();
+    let mut newest: Option<(usize, u32)> = None;
+    for (index, record) in bytes.chunks_exact_mut(RECORD_SIZE).enumerate() {
+        match parse_record(record) {
+            Some((_, record_state::EMPTY, _)) => {}
+            Some((sequence, _, _)) => {
+                if newest.is_none_or(|(_, best)| sequence > best) {
+                    newest = Some((index, sequence));
+                }
+            }
+            None => {
+                record.copy_from_slice(&empty_record());
+                changed = true;
+            }
+        }
+    }
+
+    let (next, sequence) = match newest {
+        Some((index, sequence)) => ((index + 1) % CRASH_LOG_RECORDS, sequence + 1),
+        None => (0, 1),
+    };
+
+    if let Some((index, _)) = newest {
+        let record = &mut bytes[index * RECORD_SIZE..][..RECORD_SIZE];
+        if let Some((_, record_state::NEW, report)) = parse_record(record) {
+            log!(LogLevel::Warn, "The previous boot crashed:");
+            for line in report.lines() {
+                log!(LogLevel::Warn, "  {}", line);
+            }
+            *PREVIOUS_CRASH.lock() = Some(String::from(report));
+
+            record[STATE_OFFSET] = record_state::REPORTED;
+            changed = true;
+        }
+    }
+
+    // Writing moves the file to new clusters, so the sectors are looked up after it
+    let file = match file {
+        Some(file) if !changed => file,
+        _ => {
+            let flags = open_flags::CREATE | open_flags::TRUNCATE;
+            write_file_with_flags(root, CRASH_FILENAME, &bytes, flags)?
+        }
+    };
+
+    let sectors = file_sectors(file.first_cluster, file.size)?;
+    *CRASH_LOG.lock() = Some(CrashLog {
+        sectors: sectors
+            .try_into()
+            .map_err(|_| "Crash log has the wrong size")?,
+        next,
+        sequence,
+    });
+    Ok(())
+}
+
+/// The report found by [`check_previous_crash`], only returned once
+pub fn take_previous_crash() -> Option<String> {
+    PREVIOUS_CRASH.lock().take()
+}
@@ -0,0 +1,141 @@
+//! Short animations when windows open and close.
+//!
+//! They can be turned off with `ANIMATE.CFG` in the root directory, loaded when the desktop
+//! starts:
+//!
+//! ```text
+//! # Windows appear and vanish at once with no
+//! enabled = no
+//! ```
+//!
+//! A missing file or an invalid value keeps them on.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    desktop::config::{entries, read_root_config},
+    framebuffer::{Color, FrameBufferWriter},
+    interrupts::{ms_to_ticks, ticks},
+    surface::Rect,
+};
+
+pub const CONFIG_FILENAME: &str = "ANIMATE.CFG";
+
+const DURATION_MS: u64 = 150;
+/// Height of the title bar in the animated outline
+const TITLE_BAR_HEIGHT: usize = 20;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Load the setting from the config file
+pub fn init() {
+    if let Some(enabled) = read_root_config(CONFIG_FILENAME).and_then(|text| parse(&text)) {
+        ENABLED.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Whether a config file turns the animations on or off, `None` if it doesn't say
+fn parse(text: &str) -> Option<bool> {
+    entries(text)
+        .filter_map(|entry| match entry {
+            ("enabled", "yes") => Some(true),
+            ("enabled", "no") => Some(false),
+            _ => None,
+        })
+        .last()
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AnimationKind {
+    /// Grows from the middle of the window and fades in
+    Open,
+    /// Shrinks a little and fades out
+    Close,
+}
+
+/// A window outline drawn over everything for a few frames. Every frame it's drawn over
+/// what was restored below it, so the area of the last frame has to be redrawn first.
+pub struct Animation {
+    kind: AnimationKind,
+    /// Full bounds of the window, with title bar and border
+    bounds: Rect,
+    color: Color,
+    start: u64,
+    duration: u64,
+}
+
+impl Animation {
+    /// Animate a window that was just opened, `None` with animations off
+    pub fn open(bounds: Rect, color: Color) -> Option<Self> {
+        Self::new(AnimationKind::Open, bounds, color)
+    }
+
+    /// Animate a window that was just closed, `None` with animations off
+    pub fn close(bounds: Rect, color: Color) -> Option<Self> {
+        Self::new(AnimationKind::Close, bounds, color)
+    }
+
+    fn new(kind: AnimationKind, bounds: Rect, color: Color) -> Option<Self> {
+        enabled().then(|| Self {
+            kind,
+            bounds,
+            color,
+            start: ticks(),
+            duration: ms_to_ticks(DURATION_MS).max(1),
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        ticks() >= self.start + self.duration
+    }
+
+    /// Outline and opacity `progress` of the way through, out of 255
+    fn frame(&self, progress: u64) -> (Rect, u8) {
+        let (scale, alpha) = match self.kind {
+            AnimationKind::Open => (128 + progress / 2, progress),
+            AnimationKind::Close => (255 - progress / 4, 255 - progress),
+        };
+
+        let width = (self.bounds.width as u64 * scale / 255) as usize;
+        let height = (self.bounds.height as u64 * scale / 255) as usize;
+        let rect = Rect::new(
+            self.bounds.x + (self.bounds.width - width) / 2,
+            self.bounds.y + (self.bounds.height - height) / 2,
+            width,
+            height,
+        );
+        (rect, alpha as u8)
+    }
+
+    /// Draw the frame for now, returns the area it covers
+    pub fn render(&self, framebuffer: &mut FrameBufferWriter) -> Option<Rect> {
+        let elapsed = ticks().saturating_sub(self.start);
+        if elapsed >= self.duration {
+            return None;
+        }
+
+        let (rect, alpha) = self.frame(elapsed * 255 / self.duration);
+        if rect.width == 0 || rect.height == 0 {
+            return None;
+        }
+
+        let right = rect.x + rect.width - 1;
+        let bottom = rect.y + rect.height - 1;
+        let title_bottom = rect.y + TITLE_BAR_HEIGHT.min(rect.height) - 1;
+        framebuffer.blend_rect((rect.x, rect.y), (right, title_bottom), Color::BLACK, alpha);
+        if title_bottom < bottom {
+            framebuffer.blend_rect(
+                (rect.x, title_bottom + 1),
+                (right, bottom),
+                self.color,
+                alpha,
+            );
+        }
+
+        Some(rect)
+    }
+}
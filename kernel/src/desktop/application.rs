@@ -0,0 +1,111 @@
+//! What the window manager needs from the app in a window. Every hook but the drawing ones
+//! does nothing by default, so an app only implements the events it uses.
+
+use alloc::string::String;
+use pc_keyboard::{KeyCode, Modifiers};
+
+use crate::{
+    desktop::{associations::AppId, clipboard::Clip},
+    framebuffer::Color,
+    fs::fat32::FileEntry,
+    surface::Surface,
+};
+
+/// Something an app asks the window manager to do, see [`Application::take_action`]
+pub enum Action {
+    /// Open a file in a directory with an app
+    OpenFile(FileEntry, u32, AppId),
+    /// Close the app's window
+    Close,
+    /// Close the app's window and paste the newest clipboard item into the one below
+    Paste,
+}
+
+/// An app shown in a window. Coordinates are relative to the window content.
+pub trait Application {
+    /// Title of the window. It is read again after every render, the title bar follows when
+    /// it changes.
+    fn title(&self) -> String;
+
+    /// Color the window content is cleared to
+    fn background_color(&self) -> Color;
+
+    /// Whether the window is sized to what the app shows, it only gets its saved position
+    /// back and not its saved size
+    fn content_sized(&self) -> bool {
+        false
+    }
+
+    /// Add the shapes to the surface of the new window
+    fn init(&mut self, surface: &mut Surface);
+
+    /// Update the surface after the app state changed
+    fn render(&mut self, surface: &mut Surface);
+
+    /// Whether [`Application::render`] has to run again next frame, without an event
+    fn needs_redraw(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the app used the click
+    fn handle_click(
+        &mut self,
+        _x: usize,
+        _y: usize,
+        _modifiers: &Modifiers,
+        _surface: &mut Surface,
+    ) -> bool {
+        false
+    }
+
+    fn handle_right_click(&mut self, _x: usize, _y: usize, _surface: &mut Surface) {}
+
+    /// Returns true to get the mouse moves until the button is released
+    fn handle_mouse_down(&mut self, _x: usize, _y: usize, _surface: &mut Surface) -> bool {
+        false
+    }
+
+    /// The mouse moved while the app has it, see [`Application::handle_mouse_down`]
+    fn handle_mouse_drag(&mut self, _x: usize, _y: usize, _surface: &mut Surface) {}
+
+    fn handle_mouse_up(&mut self, _surface: &mut Surface) {}
+
+    /// A typed character
+    fn handle_char(&mut self, _ch: char, _surface: &mut Surface) {}
+
+    /// A key without a character, and Tab
+    fn handle_key(&mut self, _key: KeyCode, _modifiers: &Modifiers, _surface: &mut Surface) {}
+
+    /// Asked before the window is closed, it stays open when this returns false
+    fn can_close(&mut self) -> bool {
+        true
+    }
+
+    /// Ctrl+C, what to put on the clipboard
+    fn copy(&self) -> Option<Clip> {
+        None
+    }
+
+    /// Ctrl+V, insert a copied item
+    fn paste(&mut self, _clip: &Clip, _surface: &mut Surface) {}
+
+    /// Ctrl+S, save the open document
+    fn handle_save(&mut self) {}
+
+    /// Whether there are unsaved changes and the auto-save interval ran out
+    fn is_autosave_due(&self) -> bool {
+        false
+    }
+
+    fn autosave(&mut self, _surface: &mut Surface) {}
+
+    /// Memory is low, free what can be loaded again. Returns the bytes freed.
+    fn trim_memory(&mut self, _surface: &mut Surface) -> usize {
+        0
+    }
+
+    /// Collect what the last event asked for
+    fn take_action(&mut self) -> Option<Action> {
+        None
+    }
+}
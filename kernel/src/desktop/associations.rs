@@ -0,0 +1,150 @@
+//! File type associations, which applications open which file extensions.
+//!
+//! The registry is loaded from `ASSOC.CFG` in the root directory. Every line maps an
+//! extension to a comma separated list of applications, the first one is the default:
+//!
+//! ```text
+//! # Lines starting with # are ignored
+//! txt = notepad
+//! bmp = image viewer, notepad
+//! ```
+//!
+//! Extensions missing from the file keep their built-in defaults.
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    desktop::{
+        calculator,
+        config::{entries, read_root_config},
+        image_viewer, notepad,
+        window_manager::WindowManager,
+    },
+    fs::{
+        fat32::{FileEntry, open_flags},
+        manager::{root_directory_cluster, write_file_with_flags},
+    },
+};
+
+pub const CONFIG_FILENAME: &str = "ASSOC.CFG";
+
+/// An application that can open files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppId {
+    Notepad,
+    ImageViewer,
+    Calculator,
+}
+
+impl AppId {
+    pub const ALL: [AppId; 3] = [AppId::Notepad, AppId::ImageViewer, AppId::Calculator];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AppId::Notepad => "notepad",
+            AppId::ImageViewer => "image viewer",
+            AppId::Calculator => "calculator",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        Self::ALL
+            .into_iter()
+            .find(|app| app.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Open a file in a directory with the app
+    pub fn open(self, window_manager: &mut WindowManager, file: FileEntry, directory: u32) {
+        match self {
+            AppId::Notepad => notepad::launch_with_file(window_manager, file, directory),
+            AppId::ImageViewer => image_viewer::launch(window_manager, file),
+            AppId::Calculator => calculator::launch(window_manager), // Who tf opens his files in calculator?!
+        }
+    }
+}
+
+pub struct Associations {
+    /// Lowercase extension without the dot, to the apps that open it (default first)
+    map: BTreeMap<String, Vec<AppId>>,
+}
+
+impl Associations {
+    pub fn defaults() -> Self {
+        let mut map = BTreeMap::new();
+        map.insert("txt".to_string(), vec![AppId::Notepad]);
+        map.insert("cfg".to_string(), vec![AppId::Notepad]);
+        map.insert("log".to_string(), vec![AppId::Notepad]);
+        map.insert("bmp".to_string(), vec![AppId::ImageViewer]);
+
+        Self { map }
+    }
+
+    /// Load the registry from the config file, the defaults are used if it doesn't exist
+    pub fn load() -> Self {
+        let mut associations = Self::defaults();
+
+        if let Some(text) = read_root_config(CONFIG_FILENAME) {
+            associations.parse(&text);
+        }
+
+        associations
+    }
+
+    /// Apply the lines of a config file, invalid lines and unknown apps are skipped
+    pub fn parse(&mut self, text: &str) {
+        for (extension, apps) in entries(text) {
+            let apps: Vec<AppId> = apps.split(',').filter_map(AppId::from_name).collect();
+            if !apps.is_empty() {
+                self.set(extension, apps);
+            }
+        }
+    }
+
+    pub fn set(&mut self, extension: &str, apps: Vec<AppId>) {
+        let extension = extension
+            .trim()
+            .trim_start_matches('.')
+            .to_ascii_lowercase();
+        self.map.insert(extension, apps);
+    }
+
+    /// Apps that can open a file, the default first. Unknown extensions get every app.
+    pub fn apps_for(&self, filename: &str) -> Vec<AppId> {
+        filename
+            .rsplit_once('.')
+            .and_then(|(_, extension)| self.map.get(&extension.to_ascii_lowercase()))
+            .cloned()
+            .unwrap_or_else(|| AppId::ALL.to_vec())
+    }
+
+    /// The app that opens a file by default, if its extension is registered
+    pub fn default_app(&self, filename: &str) -> Option<AppId> {
+        let extension = filename.rsplit_once('.')?.1.to_ascii_lowercase();
+        self.map.get(&extension)?.first().copied()
+    }
+
+    /// Write the registry back to the config file
+    pub fn save(&self) -> Result<(), &'static str> {
+        let mut text = String::from("# extension = default app, other apps\n");
+        for (extension, apps) in &self.map {
+            let names: Vec<&str> = apps.iter().map(|app| app.name()).collect();
+            text.push_str(&format!("{} = {}\n", extension, names.join(", ")));
+        }
+
+        // Replace the old config, it's fine if there is none yet
+        write_file_with_flags(
+            root_directory_cluster()?,
+            CONFIG_FILENAME,
+            text.as_bytes(),
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )?;
+        Ok(())
+    }
+}
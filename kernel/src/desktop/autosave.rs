@@ -0,0 +1,79 @@
+//! How often the editors save unsaved changes on their own.
+//!
+//! The interval is loaded from `AUTOSAVE.CFG` in the root directory when the desktop starts:
+//!
+//! ```text
+//! # Seconds between an edit and the save, 0 turns auto-save off
+//! interval = 30
+//! ```
+//!
+//! A missing file or an invalid value keeps the default of 30 seconds.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    desktop::config::{entries, read_root_config},
+    interrupts::{ms_to_ticks, ticks},
+};
+
+pub const CONFIG_FILENAME: &str = "AUTOSAVE.CFG";
+
+const DEFAULT_INTERVAL_SECS: u64 = 30;
+/// Longer intervals are clamped, a day is plenty
+const MAX_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+static INTERVAL_SECS: AtomicU64 = AtomicU64::new(DEFAULT_INTERVAL_SECS);
+
+/// Load the interval from the config file
+pub fn init() {
+    if let Some(secs) = read_root_config(CONFIG_FILENAME).and_then(|text| parse(&text)) {
+        INTERVAL_SECS.store(secs, Ordering::Relaxed);
+    }
+}
+
+/// The interval of a config file in seconds, clamped to [`MAX_INTERVAL_SECS`]
+fn parse(text: &str) -> Option<u64> {
+    entries(text)
+        .filter(|&(key, _)| key == "interval")
+        .filter_map(|(_, value)| value.parse::<u64>().ok())
+        .map(|secs| secs.min(MAX_INTERVAL_SECS))
+        .last()
+}
+
+/// Ticks between an edit and the save, `None` when auto-save is off
+fn interval_ticks() -> Option<u64> {
+    match INTERVAL_SECS.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(ms_to_ticks(secs * 1000)),
+    }
+}
+
+/// Counts down from the first unsaved change of an editor to its auto-save
+#[derive(Default)]
+pub struct AutoSave {
+    /// Tick the changes are saved at, `None` without unsaved changes
+    due: Option<u64>,
+}
+
+impl AutoSave {
+    /// Call on every edit, the countdown starts with the first one since the last save
+    pub fn mark_changed(&mut self) {
+        if self.due.is_none() {
+            self.due = interval_ticks().map(|interval| ticks() + interval);
+        }
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.due = None;
+    }
+
+    /// Try again after another interval, for when saving failed
+    pub fn retry_later(&mut self) {
+        self.due = None;
+        self.mark_changed();
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.due.is_some_and(|due| ticks() >= due)
+    }
+}
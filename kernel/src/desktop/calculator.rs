@@ -1,15 +1,31 @@
 use alloc::{
+    boxed::Box,
     format,
     string::{String, ToString},
     vec::Vec,
 };
 use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::Modifiers;
 
 use crate::{
+    desktop::{
+        application::Application,
+        window_manager::{Window, WindowManager},
+    },
     framebuffer::Color,
-    surface::{Shape, Surface},
+    interrupts::{ms_to_ticks, ticks},
+    surface::{Rect, Shape, Surface},
 };
 
+/// Button labels, row by row
+const BUTTON_LABELS: [char; 16] = [
+    '7', '8', '9', ':', '4', '5', '6', 'x', '1', '2', '3', '-', '0', '.', '=', '+',
+];
+const BUTTON_COLOR: Color = Color::WHITE;
+/// A button pressed with the keyboard lights up this long
+const HIGHLIGHT_MS: u64 = 150;
+const HIGHLIGHT_COLOR: Color = Color::new(200, 200, 200);
+
 pub enum Operation {
     Add,
     Subtract,
@@ -29,7 +45,10 @@ pub struct Calculator {
     display_text: String,
     current_input: String,
     display_idx: usize,
-    button_regions: Vec<(usize, usize, usize, usize)>, // (x, y, width, height)
+    button_regions: Vec<Rect>,
+    button_shapes: Vec<(usize, usize)>, // (background idx, label idx)
+    /// The button lit up by a key, and the tick it goes back to normal
+    highlight: Option<(usize, u64)>,
 }
 
 impl Calculator {
@@ -41,116 +60,96 @@ impl Calculator {
             current_input: String::new(),
             display_idx: 0,
             button_regions: Vec::new(),
+            button_shapes: Vec::new(),
+            highlight: None,
         }
     }
 
-    pub fn init(&mut self, surface: &mut Surface) {
-        let button_height: usize = 50;
-        let button_width: usize = 40;
-        let button_spacing: usize = 5;
-        let start_x = 15;
-        let start_y = 85;
-
-        surface.add_shape(Shape::Rectangle {
-            x: 15,
-            y: 15,
-            width: 175,
-            height: 60,
-            color: Color::WHITE,
-            filled: true,
-            hide: false,
-        });
-
-        self.display_idx = surface.add_shape(Shape::Text {
-            x: 20,
-            y: 30,
-            content: self.display_text.clone(),
-            color: Color::BLACK,
-            background_color: Color::WHITE,
-            font_size: RasterHeight::Size32,
-            font_weight: FontWeight::Light,
-            hide: false,
-        });
-
-        let buttons = [
-            ["7", "8", "9", ":"],
-            ["4", "5", "6", "x"],
-            ["1", "2", "3", "-"],
-            ["0", ".", "=", "+"],
-        ];
-
-        for (row, button_row) in buttons.iter().enumerate() {
-            for (col, &button) in button_row.iter().enumerate() {
-                let x = start_x + col * (button_width + button_spacing);
-                let y = start_y + row * (button_height + button_spacing);
-                self.button_regions
-                    .push((x, y, button_width, button_height));
-
-                surface.add_shape(Shape::Rectangle {
-                    x,
-                    y,
-                    width: button_width,
-                    height: button_height,
-                    color: Color::WHITE,
-                    filled: true,
-                    hide: false,
-                });
-                surface.add_shape(Shape::Text {
-                    x: x + 13,
-                    y: y + 15,
-                    content: button.to_string(),
-                    color: Color::BLACK,
-                    background_color: Color::WHITE,
-                    font_size: RasterHeight::Size24,
-                    font_weight: FontWeight::Light,
-                    hide: false,
-                });
+    pub fn handle_mouse_click(&mut self, x: usize, y: usize) {
+        for (idx, region) in self.button_regions.iter().enumerate() {
+            if region.contains_point(x, y) {
+                self.press(BUTTON_LABELS[idx]);
+                return;
             }
         }
     }
 
-    pub fn handle_mouse_click(&mut self, x: usize, y: usize) {
-        for (idx, &(button_x, button_y, width, height)) in self.button_regions.iter().enumerate() {
-            if x >= button_x && x < button_x + width && y >= button_y && y < button_y + height {
-                self.handle_button_click(idx);
+    /// Typed keys press the matching button, Backspace removes a digit and Escape clears
+    pub fn handle_char_input(&mut self, ch: char, surface: &mut Surface) {
+        let label = match ch {
+            '\u{08}' => {
+                self.backspace();
+                return;
+            }
+            '\u{1b}' => {
+                self.clear();
                 return;
             }
+            '*' | 'X' => 'x',
+            '/' => ':',
+            ',' => '.',
+            '\r' | '\n' => '=',
+            ch => ch,
+        };
+
+        if let Some(idx) = BUTTON_LABELS.iter().position(|&button| button == label) {
+            self.highlight_button(idx, surface);
+            self.press(label);
+        }
+    }
+
+    fn set_button_color(&self, idx: usize, color: Color, surface: &mut Surface) {
+        if let Some(&(background_idx, label_idx)) = self.button_shapes.get(idx) {
+            surface.update_rectangle_color(background_idx, color);
+            surface.update_text_background(label_idx, color);
+        }
+    }
+
+    fn highlight_button(&mut self, idx: usize, surface: &mut Surface) {
+        if let Some((previous, _)) = self.highlight.take() {
+            self.set_button_color(previous, BUTTON_COLOR, surface);
         }
+
+        self.set_button_color(idx, HIGHLIGHT_COLOR, surface);
+        self.highlight = Some((idx, ticks() + ms_to_ticks(HIGHLIGHT_MS)));
     }
 
-    fn handle_button_click(&mut self, idx: usize) {
-        let label = match idx {
-            0 => "7",
-            1 => "8",
-            2 => "9",
-            3 => ":",
-            4 => "4",
-            5 => "5",
-            6 => "6",
-            7 => "x",
-            8 => "1",
-            9 => "2",
-            10 => "3",
-            11 => "-",
-            12 => "0",
-            13 => ".",
-            14 => "=",
-            15 => "+",
-            _ => return, // Invalid index
+    /// Whether a button is still lit up, so [`Calculator::render`] has to run again
+    pub fn is_highlighting(&self) -> bool {
+        self.highlight.is_some()
+    }
+
+    fn backspace(&mut self) {
+        if self.current_input.pop().is_none() {
+            return;
+        }
+
+        self.display_text = if self.current_input.is_empty() {
+            "0".to_string()
+        } else {
+            self.current_input.clone()
         };
+    }
+
+    fn clear(&mut self) {
+        self.state = CalculatorState::InputFirst;
+        self.current_input.clear();
+        self.display_text = "0".to_string();
+    }
 
+    fn press(&mut self, label: char) {
         match label {
-            "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
-                self.current_input.push_str(label);
+            '0'..='9' => {
+                self.current_input.push(label);
                 self.display_text = self.current_input.clone();
             }
-            "." => {
+            '.' => {
                 if !self.current_input.contains('.') {
                     self.current_input.push('.');
                     self.display_text = self.current_input.clone();
                 }
             }
-            "=" => {
+            '=' => {
                 if let CalculatorState::InputSecond(first, op) = &self.state {
                     if let Ok(second) = self.current_input.parse::<f64>() {
                         let result = match op {
@@ -169,28 +168,28 @@ impl Calculator {
                     }
                 }
             }
-            "+" => {
+            '+' => {
                 if let Ok(first) = self.current_input.parse::<f64>() {
                     self.state = CalculatorState::InputSecond(first, Operation::Add);
                     self.current_input.clear();
                     self.display_text += " + ";
                 }
             }
-            "-" => {
+            '-' => {
                 if let Ok(first) = self.current_input.parse::<f64>() {
                     self.state = CalculatorState::InputSecond(first, Operation::Subtract);
                     self.current_input.clear();
                     self.display_text += " - ";
                 }
             }
-            "x" => {
+            'x' => {
                 if let Ok(first) = self.current_input.parse::<f64>() {
                     self.state = CalculatorState::InputSecond(first, Operation::Multiply);
                     self.current_input.clear();
                     self.display_text += " x ";
                 }
             }
-            ":" => {
+            ':' => {
                 if let Ok(first) = self.current_input.parse::<f64>() {
                     self.state = CalculatorState::InputSecond(first, Operation::Divide);
                     self.current_input.clear();
@@ -200,8 +199,94 @@ impl Calculator {
             _ => {}
         }
     }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    window_manager.add_window(Window::new(
+        100,
+        100,
+        205,
+        315,
+        1,
+        Box::new(Calculator::new()),
+    ));
+}
+
+impl Application for Calculator {
+    fn title(&self) -> String {
+        "Calculator".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        Color::GRAY
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        let button_height: usize = 50;
+        let button_width: usize = 40;
+        let button_spacing: usize = 5;
+        let start_x = 15;
+        let start_y = 85;
+
+        surface.add_shape(Shape::Rectangle {
+            x: 15,
+            y: 15,
+            width: 175,
+            height: 60,
+            color: Color::WHITE,
+            filled: true,
+            hide: false,
+        });
+
+        self.display_idx = surface.add_shape(Shape::Text {
+            x: 20,
+            y: 30,
+            content: self.display_text.clone(),
+            color: Color::BLACK,
+            background_color: Color::WHITE,
+            font_size: RasterHeight::Size32,
+            font_weight: FontWeight::Light,
+            hide: false,
+        });
+
+        for (idx, button) in BUTTON_LABELS.iter().enumerate() {
+            let (row, col) = (idx / 4, idx % 4);
+            let x = start_x + col * (button_width + button_spacing);
+            let y = start_y + row * (button_height + button_spacing);
+            self.button_regions
+                .push(Rect::new(x, y, button_width, button_height));
+
+            let background_idx = surface.add_shape(Shape::Rectangle {
+                x,
+                y,
+                width: button_width,
+                height: button_height,
+                color: BUTTON_COLOR,
+                filled: true,
+                hide: false,
+            });
+            let label_idx = surface.add_shape(Shape::Text {
+                x: x + 13,
+                y: y + 15,
+                content: button.to_string(),
+                color: Color::BLACK,
+                background_color: BUTTON_COLOR,
+                font_size: RasterHeight::Size24,
+                font_weight: FontWeight::Light,
+                hide: false,
+            });
+            self.button_shapes.push((background_idx, label_idx));
+        }
+    }
+
+    fn render(&mut self, surface: &mut Surface) {
+        if let Some((idx, until)) = self.highlight {
+            if ticks() >= until {
+                self.set_button_color(idx, BUTTON_COLOR, surface);
+                self.highlight = None;
+            }
+        }
 
-    pub fn render(&mut self, surface: &mut Surface) {
         if self.display_text == self.previous_display_text {
             return; // No change in display text, nothing to update
         }
@@ -210,4 +295,23 @@ impl Calculator {
 
         self.previous_display_text = self.display_text.clone();
     }
+
+    fn needs_redraw(&self) -> bool {
+        self.is_highlighting()
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        _surface: &mut Surface,
+    ) -> bool {
+        self.handle_mouse_click(x, y);
+        true
+    }
+
+    fn handle_char(&mut self, ch: char, surface: &mut Surface) {
+        self.handle_char_input(ch, surface);
+    }
 }
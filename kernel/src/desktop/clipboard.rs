@@ -0,0 +1,207 @@
+//! Copied text and files, with the last few items kept to paste again.
+//!
+//! Ctrl+C copies from the topmost window and Ctrl+V pastes the newest item into it,
+//! Ctrl+Shift+V opens the history to pick an older one. Nothing is written to disk.
+
+use alloc::{format, string::String, vec::Vec};
+use spin::Mutex;
+
+use crate::time::get_utc_time;
+
+/// Older items fall off the end of the history
+pub const MAX_ITEMS: usize = 10;
+/// Memory for all the items together, longer text is cut and older items make room
+pub const MAX_BYTES: usize = 16 * 1024;
+
+static CLIPBOARD: Mutex<History> = Mutex::new(History::new());
+
+/// Something that was copied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Clip {
+    Text(String),
+    /// A file by its path from the root, like `/DOCS/NOTE.TXT`
+    File(String),
+}
+
+impl Clip {
+    /// The text to paste, the path for a file
+    pub fn text(&self) -> &str {
+        match self {
+            Clip::Text(text) | Clip::File(text) => text,
+        }
+    }
+
+    /// The first line, cut to `max_chars`, to show the item in a list
+    pub fn preview(&self, max_chars: usize) -> String {
+        let line = self.text().lines().next().unwrap_or_default();
+        let mut preview: String = line.chars().take(max_chars).collect();
+        if preview.len() < self.text().len() {
+            preview.push_str("...");
+        }
+
+        match self {
+            Clip::Text(_) => preview,
+            Clip::File(_) => format!("File {}", preview),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClipItem {
+    pub clip: Clip,
+    /// When it was copied, as `HH:MM`
+    pub copied: String,
+}
+
+/// The copied items, the newest first
+pub struct History {
+    items: Vec<ClipItem>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub const fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    pub fn items(&self) -> &[ClipItem] {
+        &self.items
+    }
+
+    pub fn latest(&self) -> Option<&Clip> {
+        self.items.first().map(|item| &item.clip)
+    }
+
+    fn size(&self) -> usize {
+        self.items.iter().map(|item| item.clip.text().len()).sum()
+    }
+
+    /// Put an item at the top. Copying the same thing again moves it up instead of adding
+    /// it twice.
+    pub fn push(&mut self, mut clip: Clip, copied: String) {
+        if let Clip::Text(text) = &mut clip {
+            if text.len() > MAX_BYTES {
+                let mut end = MAX_BYTES;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                text.truncate(end);
+            }
+        }
+
+        self.items.retain(|item| item.clip != clip);
+        self.items.insert(0, ClipItem { clip, copied });
+
+        self.items.truncate(MAX_ITEMS);
+        while self.items.len() > 1 && self.size() > MAX_BYTES {
+            self.items.pop();
+        }
+    }
+
+    /// Drop all but the newest item, returns the bytes freed
+    pub fn trim(&mut self) -> usize {
+        let freed = self
+            .items
+            .drain(self.items.len().min(1)..)
+            .map(|item| item.clip.text().len())
+            .sum();
+        self.items.shrink_to_fit();
+        freed
+    }
+
+    /// Move an older item to the top, so it's the one pasted
+    pub fn promote(&mut self, index: usize) -> Option<Clip> {
+        if index >= self.items.len() {
+            return None;
+        }
+
+        let item = self.items.remove(index);
+        let clip = item.clip.clone();
+        self.items.insert(0, item);
+        Some(clip)
+    }
+}
+
+pub fn copy(clip: Clip) {
+    let time = get_utc_time();
+    let copied = format!("{:02}:{:02}", time.hours, time.minutes);
+    CLIPBOARD.lock().push(clip, copied);
+}
+
+/// The item Ctrl+V pastes
+pub fn latest() -> Option<Clip> {
+    CLIPBOARD.lock().latest().cloned()
+}
+
+/// The copied items, the newest first
+pub fn history() -> Vec<ClipItem> {
+    CLIPBOARD.lock().items().to_vec()
+}
+
+pub fn promote(index: usize) -> Option<Clip> {
+    CLIPBOARD.lock().promote(index)
+}
+
+/// Forget the older items when memory is low, returns the bytes freed
+pub fn trim() -> usize {
+    CLIPBOARD.lock().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn text(text: &str) -> Clip {
+        Clip::Text(text.to_string())
+    }
+
+    #[test_case]
+    fn test_history_keeps_newest_first_without_repeats() {
+        let mut history = History::new();
+        history.push(text("one"), String::new());
+        history.push(Clip::File("/TWO.TXT".to_string()), String::new());
+        history.push(text("three"), String::new());
+        history.push(text("one"), String::new());
+
+        let clips: Vec<&Clip> = history.items().iter().map(|item| &item.clip).collect();
+        assert_eq!(
+            clips,
+            [
+                &text("one"),
+                &text("three"),
+                &Clip::File("/TWO.TXT".to_string())
+            ]
+        );
+
+        assert_eq!(history.promote(2), Some(Clip::File("/TWO.TXT".to_string())));
+        assert_eq!(history.latest(), Some(&Clip::File("/TWO.TXT".to_string())));
+        assert_eq!(history.promote(3), None);
+
+        assert_eq!(history.trim(), "one".len() + "three".len());
+        assert_eq!(history.items().len(), 1);
+        assert_eq!(history.trim(), 0);
+    }
+
+    #[test_case]
+    fn test_history_is_bounded() {
+        let mut history = History::new();
+        for i in 0..MAX_ITEMS + 5 {
+            history.push(text(&format!("item {}", i)), String::new());
+        }
+        assert_eq!(history.items().len(), MAX_ITEMS);
+
+        // A large item pushes the older ones out, and is cut itself
+        let large = "x".repeat(MAX_BYTES - 10);
+        history.push(text(&large), String::new());
+        assert!(history.size() <= MAX_BYTES);
+        history.push(text(&"y".repeat(MAX_BYTES * 2)), String::new());
+        assert_eq!(history.items().len(), 1);
+        assert_eq!(history.size(), MAX_BYTES);
+    }
+}
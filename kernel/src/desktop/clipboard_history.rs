@@ -0,0 +1,137 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::Modifiers;
+
+use crate::{
+    desktop::{
+        application::{Action, Application},
+        clipboard::{self, ClipItem},
+        widgets::button::Button,
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::Color,
+    surface::{Shape, Surface},
+};
+
+pub const WINDOW_WIDTH: usize = 360;
+const BACKGROUND_COLOR: Color = Color::new(240, 240, 240);
+
+const MARGIN: usize = 10;
+const ROW_HEIGHT: usize = 26;
+const ROW_GAP: usize = 4;
+/// Characters of an item shown, after its time
+const PREVIEW_CHARS: usize = 30;
+
+/// The copied items, a click pastes one into the window below and closes the list
+pub struct ClipboardHistory {
+    items: Vec<ClipItem>,
+    rows: Vec<Button>,
+    /// An item was picked
+    pasting: bool,
+}
+
+impl Default for ClipboardHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClipboardHistory {
+    pub fn new() -> Self {
+        let items = clipboard::history();
+        let rows = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let label = format!("{}  {}", item.copied, item.clip.preview(PREVIEW_CHARS));
+                Button::new(
+                    MARGIN,
+                    MARGIN + i * (ROW_HEIGHT + ROW_GAP),
+                    WINDOW_WIDTH - 2 * MARGIN,
+                    ROW_HEIGHT,
+                    &label,
+                )
+            })
+            .collect();
+
+        Self {
+            items,
+            rows,
+            pasting: false,
+        }
+    }
+
+    /// Window height that fits every item, or the note that there are none
+    pub fn height(&self) -> usize {
+        let rows = self.items.len().max(1);
+        2 * MARGIN + rows * ROW_HEIGHT + (rows - 1) * ROW_GAP
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    let history = ClipboardHistory::new();
+    window_manager.add_window(Window::new(
+        260,
+        160,
+        WINDOW_WIDTH,
+        history.height(),
+        11,
+        Box::new(history),
+    ));
+}
+
+impl Application for ClipboardHistory {
+    fn title(&self) -> String {
+        "Clipboard".into()
+    }
+
+    fn background_color(&self) -> Color {
+        BACKGROUND_COLOR
+    }
+
+    fn content_sized(&self) -> bool {
+        true
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        if self.items.is_empty() {
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: MARGIN + 5,
+                content: "Nothing copied yet".into(),
+                color: Color::DARKGRAY,
+                background_color: BACKGROUND_COLOR,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+        }
+
+        for row in &mut self.rows {
+            row.add_to_surface(surface);
+        }
+    }
+
+    fn render(&mut self, _surface: &mut Surface) {
+        // The list is only read when the window opens
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        _surface: &mut Surface,
+    ) -> bool {
+        let Some(index) = self.rows.iter().position(|row| row.contains(x, y)) else {
+            return false;
+        };
+
+        self.pasting = clipboard::promote(index).is_some();
+        true
+    }
+
+    fn take_action(&mut self) -> Option<Action> {
+        core::mem::take(&mut self.pasting).then_some(Action::Paste)
+    }
+}
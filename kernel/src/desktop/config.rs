@@ -0,0 +1,51 @@
+//! Settings files in the root directory, like `ASSOC.CFG`. Every line is `key = value`,
+//! lines starting with `#` and lines without `=` are skipped.
+
+use alloc::string::String;
+
+use crate::{
+    fs::manager::{find_file_in_root, read_text_file},
+    log,
+    serial::LogLevel,
+};
+
+/// The text of a config file, `None` if there is none or it can't be read
+pub fn read_root_config(filename: &str) -> Option<String> {
+    let Ok(Some(file)) = find_file_in_root(filename) else {
+        return None;
+    };
+
+    match read_text_file(file.first_cluster, file.size) {
+        Ok(text) => Some(text),
+        Err(e) => {
+            log!(LogLevel::Warn, "Error reading config {}: {}", filename, e);
+            None
+        }
+    }
+}
+
+/// The keys and values of a config file, trimmed and in file order. Keys may repeat, when
+/// they are applied in order the last valid value wins.
+pub fn entries(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn test_entries() {
+        let text = "# key = comment\n\n  edge = top  \nno equals sign\nheight=40\nedge = bottom\n";
+        let entries: Vec<(&str, &str)> = entries(text).collect();
+        assert_eq!(
+            entries,
+            [("edge", "top"), ("height", "40"), ("edge", "bottom")]
+        );
+    }
+}
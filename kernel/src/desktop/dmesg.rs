@@ -0,0 +1,228 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::{KeyCode, Modifiers};
+
+use crate::{
+    desktop::{
+        application::Application,
+        widgets::button::Button,
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::{Color, measure_text},
+    log_buffer,
+    surface::{Shape, Surface},
+};
+
+pub const WINDOW_WIDTH: usize = 600;
+pub const WINDOW_HEIGHT: usize = 380;
+
+const MARGIN: usize = 10;
+const TOOLBAR_HEIGHT: usize = 40;
+const LINE_HEIGHT: usize = 18;
+const VISIBLE_LINES: usize = (WINDOW_HEIGHT - TOOLBAR_HEIGHT - MARGIN) / LINE_HEIGHT;
+/// Newest lines kept when memory is low, Refresh loads the others again
+const MIN_SCROLLBACK_LINES: usize = 2 * VISIBLE_LINES;
+
+pub const BACKGROUND_COLOR: Color = Color::new(20, 20, 20);
+const TEXT_COLOR: Color = Color::new(200, 200, 200);
+
+/// Shows the kernel log buffer, newest lines at the bottom
+pub struct Dmesg {
+    lines: Vec<String>,
+    /// Index of the first visible line
+    scroll: usize,
+
+    refresh_button: Button,
+    clear_button: Button,
+    up_button: Button,
+    down_button: Button,
+
+    line_idxs: Vec<usize>,
+    position_idx: usize,
+}
+
+impl Dmesg {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            scroll: 0,
+            refresh_button: Button::new(MARGIN, 8, 70, 24, "Refresh"),
+            clear_button: Button::new(MARGIN + 75, 8, 60, 24, "Clear"),
+            up_button: Button::new(MARGIN + 150, 8, 24, 24, "^"),
+            down_button: Button::new(MARGIN + 178, 8, 24, 24, "v"),
+            line_idxs: Vec::new(),
+            position_idx: 0,
+        }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.lines.len().saturating_sub(VISIBLE_LINES)
+    }
+
+    /// Reload the log buffer and jump to the newest lines
+    fn refresh(&mut self, surface: &mut Surface) {
+        self.lines = log_buffer::lines();
+        self.scroll = self.max_scroll();
+        self.update_lines(surface);
+    }
+
+    fn scroll_by(&mut self, delta: isize, surface: &mut Surface) {
+        let scroll = (self.scroll as isize + delta).clamp(0, self.max_scroll() as isize) as usize;
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            self.update_lines(surface);
+        }
+    }
+
+    fn update_lines(&mut self, surface: &mut Surface) {
+        let (char_width, _) = measure_text("M", FontWeight::Regular, RasterHeight::Size16);
+        let max_chars = (WINDOW_WIDTH - 2 * MARGIN) / char_width.max(1);
+
+        for (i, idx) in self.line_idxs.iter().enumerate() {
+            // Long lines are cut, the full text is still on the serial port
+            let content = self
+                .lines
+                .get(self.scroll + i)
+                .map(|line| line.chars().take(max_chars).collect())
+                .unwrap_or_default();
+
+            // The old text has to be cleared when the new one is shorter
+            if let Some(bounds) = surface.get_shape_bounds(*idx) {
+                surface.mark_region_dirty(bounds);
+            }
+            surface.update_text_content(*idx, content, None);
+        }
+
+        let last = (self.scroll + VISIBLE_LINES).min(self.lines.len());
+        surface.update_text_content(
+            self.position_idx,
+            format!(
+                "Lines {}-{} of {}",
+                (self.scroll + 1).min(last),
+                last,
+                self.lines.len()
+            ),
+            None,
+        );
+    }
+
+    pub fn handle_mouse_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.refresh_button.contains(x, y) {
+            self.refresh(surface);
+        } else if self.clear_button.contains(x, y) {
+            log_buffer::clear();
+            self.refresh(surface);
+        } else if self.up_button.contains(x, y) {
+            self.scroll_by(-(VISIBLE_LINES as isize), surface);
+        } else if self.down_button.contains(x, y) {
+            self.scroll_by(VISIBLE_LINES as isize, surface);
+        }
+    }
+
+    pub fn handle_key_input(&mut self, key: KeyCode, surface: &mut Surface) {
+        match key {
+            KeyCode::ArrowUp => self.scroll_by(-1, surface),
+            KeyCode::ArrowDown => self.scroll_by(1, surface),
+            KeyCode::PageUp => self.scroll_by(-(VISIBLE_LINES as isize), surface),
+            KeyCode::PageDown => self.scroll_by(VISIBLE_LINES as isize, surface),
+            KeyCode::Home => self.scroll_by(-(self.scroll as isize), surface),
+            KeyCode::End => self.refresh(surface),
+            _ => {}
+        }
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    window_manager.add_window(Window::new(
+        140,
+        90,
+        WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        9,
+        Box::new(Dmesg::new()),
+    ));
+}
+
+impl Application for Dmesg {
+    fn title(&self) -> String {
+        "Kernel Log".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        BACKGROUND_COLOR
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        self.refresh_button.add_to_surface(surface);
+        self.clear_button.add_to_surface(surface);
+        self.up_button.add_to_surface(surface);
+        self.down_button.add_to_surface(surface);
+
+        self.position_idx = surface.add_shape(Shape::Text {
+            x: MARGIN + 215,
+            y: 12,
+            content: String::new(),
+            color: TEXT_COLOR,
+            background_color: BACKGROUND_COLOR,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        for i in 0..VISIBLE_LINES {
+            self.line_idxs.push(surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: TOOLBAR_HEIGHT + i * LINE_HEIGHT,
+                content: String::new(),
+                color: TEXT_COLOR,
+                background_color: BACKGROUND_COLOR,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            }));
+        }
+
+        self.refresh(surface);
+    }
+
+    fn render(&mut self, _surface: &mut Surface) {
+        // Lines are only reloaded on request, so they don't move while reading
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        self.handle_mouse_click(x, y, surface);
+        true
+    }
+
+    fn handle_key(&mut self, key: KeyCode, _modifiers: &Modifiers, surface: &mut Surface) {
+        self.handle_key_input(key, surface);
+    }
+
+    fn trim_memory(&mut self, surface: &mut Surface) -> usize {
+        let dropped = self.lines.len().saturating_sub(MIN_SCROLLBACK_LINES);
+        if dropped == 0 {
+            return 0;
+        }
+
+        let freed = self
+            .lines
+            .drain(..dropped)
+            .map(|line| line.capacity())
+            .sum();
+        self.lines.shrink_to_fit();
+        self.scroll = self.scroll.saturating_sub(dropped);
+        self.update_lines(surface);
+        freed
+    }
+}
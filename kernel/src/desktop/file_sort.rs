@@ -0,0 +1,221 @@
+//! The order of the file manager's list, picked by clicking a column header.
+//!
+//! The last choice is kept in `FMSORT.CFG` in the root directory:
+//!
+//! ```text
+//! # column = name, size or modified, order = ascending or descending
+//! column = size
+//! order = descending
+//! ```
+//!
+//! Without a valid column the files stay in directory order. Folders always come first.
+
+use alloc::string::String;
+use core::cmp::Ordering;
+use spin::Mutex;
+
+use crate::{
+    desktop::config::{entries, read_root_config},
+    fs::{
+        fat32::{FileEntry, open_flags},
+        manager::{is_mounted, root_directory_cluster, write_file_with_flags},
+    },
+    log,
+    serial::LogLevel,
+};
+
+pub const CONFIG_FILENAME: &str = "FMSORT.CFG";
+
+static SORT: Mutex<Option<FileSort>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortColumn {
+    Name,
+    Size,
+    /// Last write date and time
+    Modified,
+}
+
+impl SortColumn {
+    fn key(self) -> &'static str {
+        match self {
+            SortColumn::Name => "name",
+            SortColumn::Size => "size",
+            SortColumn::Modified => "modified",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileSort {
+    pub column: SortColumn,
+    pub descending: bool,
+}
+
+impl FileSort {
+    /// The sort after a click on a header, the sorted column flips its order
+    pub fn clicked(sort: Option<FileSort>, column: SortColumn) -> FileSort {
+        match sort {
+            Some(sort) if sort.column == column => FileSort {
+                column,
+                descending: !sort.descending,
+            },
+            _ => FileSort {
+                column,
+                descending: false,
+            },
+        }
+    }
+
+    fn compare(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        let ordering = match self.column {
+            SortColumn::Name => a.name.to_uppercase().cmp(&b.name.to_uppercase()),
+            SortColumn::Size => a.size.cmp(&b.size),
+            SortColumn::Modified => {
+                (a.last_write_date, a.last_write_time).cmp(&(b.last_write_date, b.last_write_time))
+            }
+        };
+
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Put folders before files, each sorted by `sort` or left in directory order
+pub fn sort_files(files: &mut [FileEntry], sort: Option<FileSort>) {
+    files.sort_by(|a, b| {
+        let folders_first = b.is_directory.cmp(&a.is_directory);
+        match sort {
+            Some(sort) => folders_first.then_with(|| sort.compare(a, b)),
+            None => folders_first,
+        }
+    });
+}
+
+/// Read the sort from disk, directory order if there is no file or no filesystem
+pub fn load() {
+    *SORT.lock() = read_root_config(CONFIG_FILENAME).and_then(|text| parse(&text));
+}
+
+/// The sort of a config file, `None` without a valid column
+fn parse(text: &str) -> Option<FileSort> {
+    let mut column = None;
+    let mut descending = false;
+
+    for entry in entries(text) {
+        match entry {
+            ("column", "name") => column = Some(SortColumn::Name),
+            ("column", "size") => column = Some(SortColumn::Size),
+            ("column", "modified") => column = Some(SortColumn::Modified),
+            ("order", "ascending") => descending = false,
+            ("order", "descending") => descending = true,
+            _ => {}
+        }
+    }
+
+    column.map(|column| FileSort { column, descending })
+}
+
+/// The sort the file manager opens with
+pub fn saved() -> Option<FileSort> {
+    *SORT.lock()
+}
+
+/// Keep the sort for the next file manager and write it to disk
+pub fn remember(sort: FileSort) {
+    *SORT.lock() = Some(sort);
+    // Only kept until the next boot without a disk
+    if !is_mounted() {
+        return;
+    }
+
+    let mut text = String::from("# column = name, size or modified\n");
+    text.push_str("column = ");
+    text.push_str(sort.column.key());
+    text.push_str(if sort.descending {
+        "\norder = descending\n"
+    } else {
+        "\norder = ascending\n"
+    });
+
+    let result = root_directory_cluster().and_then(|root| {
+        write_file_with_flags(
+            root,
+            CONFIG_FILENAME,
+            text.as_bytes(),
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )
+    });
+    if let Err(e) = result {
+        log!(LogLevel::Warn, "File sort: Error writing config: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::ToString, vec::Vec};
+
+    fn entry(name: &str, size: u32, is_directory: bool) -> FileEntry {
+        FileEntry {
+            name: name.to_string(),
+            is_directory,
+            size,
+            first_cluster: 0,
+            attributes: 0,
+            creation_date: 0,
+            creation_time: 0,
+            last_access_date: 0,
+            last_write_date: 0,
+            last_write_time: 0,
+        }
+    }
+
+    fn names(files: &[FileEntry]) -> Vec<&str> {
+        files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    #[test_case]
+    fn test_sort_keeps_folders_first() {
+        let mut files = [
+            entry("B.TXT", 300, false),
+            entry("DOCS", 0, true),
+            entry("a.txt", 100, false),
+            entry("C.TXT", 200, false),
+        ];
+
+        sort_files(&mut files, None);
+        assert_eq!(names(&files), ["DOCS", "B.TXT", "a.txt", "C.TXT"]);
+
+        let size = FileSort::clicked(None, SortColumn::Size);
+        sort_files(&mut files, Some(size));
+        assert_eq!(names(&files), ["DOCS", "a.txt", "C.TXT", "B.TXT"]);
+
+        // A second click on the same header reverses the order
+        let size = FileSort::clicked(Some(size), SortColumn::Size);
+        assert!(size.descending);
+        sort_files(&mut files, Some(size));
+        assert_eq!(names(&files), ["DOCS", "B.TXT", "C.TXT", "a.txt"]);
+
+        let name = FileSort::clicked(Some(size), SortColumn::Name);
+        assert!(!name.descending);
+        sort_files(&mut files, Some(name));
+        assert_eq!(names(&files), ["DOCS", "a.txt", "B.TXT", "C.TXT"]);
+    }
+
+    #[test_case]
+    fn test_parse_config() {
+        assert_eq!(
+            parse("# sort\ncolumn = modified\norder = descending\n"),
+            Some(FileSort {
+                column: SortColumn::Modified,
+                descending: true,
+            })
+        );
+        assert_eq!(parse("order = descending\n"), None);
+        assert_eq!(parse("column = colour\n"), None);
+    }
+}
@@ -1,26 +1,82 @@
 use alloc::{
+    boxed::Box,
+    collections::BTreeSet,
     format,
     string::{String, ToString},
+    vec,
     vec::Vec,
 };
-use pc_keyboard::KeyCode;
+use pc_keyboard::{KeyCode, Modifiers};
 
 use crate::{
-    framebuffer::Color,
+    desktop::{
+        application::{Action, Application},
+        associations::{AppId, Associations},
+        clipboard::Clip,
+        file_sort::{self, FileSort, SortColumn, sort_files},
+        input::DOUBLE_CLICK_TICKS,
+        recent::{self, RecentFile},
+        toast::{self, ToastKind},
+        widgets::{
+            button::Button,
+            focus::FocusRing,
+            progress_bar::ProgressBar,
+            scrollbar::{SCROLLBAR_WIDTH, ScrollBar},
+            text_input::TextInput,
+        },
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::{Color, measure_text},
     fs::{
-        fat32::FileEntry,
-        manager::{create_file_in_root, delete_file_from_root, list_root_files},
+        fat32::{
+            ConsistencyReport, FileEntry, TRASH_DIRECTORY, attributes, format_date, format_time,
+            validate_filename,
+        },
+        manager::{
+            FILESYSTEM_BUSY, check_filesystem, cluster_chain, create_directory_in_directory,
+            create_file_in_directory, delete_file_from_directory, empty_trash, file_clusters,
+            move_file, move_to_trash, restore_from_trash, root_directory_cluster,
+            root_directory_size, try_list_directory_files, try_list_trash_files, volume_label,
+        },
     },
-    serial_println,
-    surface::{Shape, Surface},
+    icons::Icon,
+    interrupts, log,
+    serial::LogLevel,
+    surface::{Rect, Shape, Surface},
 };
 use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
 
 const FILE_LIST_HEIGHT: usize = 280;
 const FILE_ENTRY_HEIGHT: usize = 20;
+/// Column headers at the top of the browse list, clicking one sorts by it
+const HEADER_HEIGHT: usize = 20;
+/// Top of the first row of the browse list, below the headers
+const ROWS_Y: usize = 45 + HEADER_HEIGHT;
+const BROWSE_ROWS: usize = (FILE_LIST_HEIGHT - HEADER_HEIGHT) / FILE_ENTRY_HEIGHT;
+const HEADER_COLOR: Color = Color::new(225, 225, 225);
 const BUTTON_HEIGHT: usize = 25;
 const MARGIN: usize = 10;
 const TEXT_INPUT_HEIGHT: usize = 25;
+/// How far the mouse has to move before a press on a file turns into a drag
+const DRAG_THRESHOLD: usize = 4;
+
+const DROP_TARGET_COLOR: Color = Color::new(0, 100, 220);
+
+/// Names listed in a confirmation, the rest are counted
+const MAX_LISTED_FILES: usize = 6;
+const LISTED_FILE_HEIGHT: usize = 18;
+
+const NAV_Y: usize = 8;
+const NAV_BUTTON_SIZE: usize = 24;
+const RECENT_BUTTON_WIDTH: usize = 64;
+const RENAME_BUTTON_WIDTH: usize = 64;
+/// Directories remembered for Back and Forward
+const MAX_HISTORY: usize = 16;
+
+// Tab order of the new file dialog
+const FOCUS_INPUT: usize = 0;
+const FOCUS_CREATE: usize = 1;
+const FOCUS_BACK: usize = 2;
 
 #[derive(Clone, Debug)]
 pub enum FileManagerMode {
@@ -28,30 +84,320 @@ pub enum FileManagerMode {
     NewFile,
     DeleteFile,
     ViewFile(FileEntry),
+    /// Properties of a file, or of the whole volume when no file is selected
+    Properties(Option<FileEntry>),
+    Trash,
+    Recent,
+    /// Asks before moving the selected files into the folder
+    ConfirmMove(FileEntry),
+    /// A bulk operation is running, see [`BulkOperation`]
+    Working,
+}
+
+/// Runs of the cluster chain listed in the properties, longer chains are summarized
+const MAX_LISTED_RUNS: usize = 8;
+/// Runs per line of the properties
+const RUNS_PER_LINE: usize = 4;
+
+/// Left edge of a column of the browse list, names start after the icon
+fn column_x(column: SortColumn, width: usize) -> usize {
+    match column {
+        SortColumn::Name => MARGIN + 25,
+        SortColumn::Modified => width - 190 - SCROLLBAR_WIDTH,
+        SortColumn::Size => width - 80 - SCROLLBAR_WIDTH,
+    }
+}
+
+/// Describe how a file is laid out on disk
+fn cluster_chain_lines(first_cluster: u32) -> Vec<String> {
+    let chain = match cluster_chain(first_cluster, MAX_LISTED_RUNS) {
+        Ok(chain) => chain,
+        Err(e) => return vec![format!("Cluster chain: unknown ({})", e)],
+    };
+
+    let mut lines = vec![format!(
+        "Cluster chain: {} clusters, {} {}{}",
+        chain.length,
+        chain.fragments,
+        if chain.fragments == 1 {
+            "fragment"
+        } else {
+            "fragments"
+        },
+        if chain.incomplete { ", loops" } else { "" }
+    )];
+
+    let runs: Vec<String> = chain
+        .runs
+        .iter()
+        .map(|&(start, length)| match length {
+            1 => start.to_string(),
+            _ => format!("{}-{}", start, start + length - 1),
+        })
+        .collect();
+    for chunk in runs.chunks(RUNS_PER_LINE) {
+        lines.push(format!("  {}", chunk.join(", ")));
+    }
+    let hidden = chain.fragments as usize - chain.runs.len();
+    if hidden > 0 {
+        let last = lines.len() - 1;
+        lines[last].push_str(&format!(", ... {} more", hidden));
+    }
+    lines
+}
+
+/// Names of the set attribute bits, like "Read-only, Archive"
+fn attribute_names(bits: u8) -> String {
+    let names: Vec<&str> = [
+        (attributes::READ_ONLY, "Read-only"),
+        (attributes::HIDDEN, "Hidden"),
+        (attributes::SYSTEM, "System"),
+        (attributes::DIRECTORY, "Directory"),
+        (attributes::ARCHIVE, "Archive"),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| bits & bit != 0)
+    .map(|(_, name)| name)
+    .collect();
+
+    if names.is_empty() {
+        "None".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Add a button with a centered label, returns the background shape index
+fn add_button(
+    surface: &mut Surface,
+    x: usize,
+    y: usize,
+    width: usize,
+    label: &str,
+    color: Color,
+) -> usize {
+    let idx = surface.add_shape(Shape::Rectangle {
+        x,
+        y,
+        width,
+        height: BUTTON_HEIGHT,
+        color,
+        filled: true,
+        hide: false,
+    });
+
+    surface.add_shape(Shape::Rectangle {
+        x,
+        y,
+        width,
+        height: BUTTON_HEIGHT,
+        color: Color::BLACK,
+        filled: false,
+        hide: false,
+    });
+
+    let (text_width, _) = measure_text(label, FontWeight::Regular, RasterHeight::Size16);
+    surface.add_shape(Shape::Text {
+        x: x + width.saturating_sub(text_width) / 2,
+        y: y + 5,
+        content: label.to_string(),
+        color: Color::BLACK,
+        background_color: color,
+        font_size: RasterHeight::Size16,
+        font_weight: FontWeight::Regular,
+        hide: false,
+    });
+
+    idx
+}
+
+/// One line summary of a filesystem check
+fn describe_check(report: &ConsistencyReport) -> String {
+    if report.is_clean() {
+        return format!(
+            "Disk is clean ({} files, {} folders)",
+            report.files_checked, report.directories_checked
+        );
+    }
+
+    let mut summary = format!(
+        "Lost: {}, cross-linked: {}, broken: {}, bad size: {}",
+        report.lost_clusters.len(),
+        report.cross_linked_clusters.len(),
+        report.broken_chains.len(),
+        report.size_mismatches.len()
+    );
+    if report.freed_clusters > 0 {
+        summary.push_str(&format!(", freed {}", report.freed_clusters));
+    }
+    if report.repair_skipped {
+        summary.push_str(", not repaired as part of the disk couldn't be walked");
+    }
+    summary
+}
+
+fn format_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{} B", size)
+    } else if size < 1024 * 1024 {
+        format!("{} KB", size / 1024)
+    } else {
+        format!("{} MB", size / (1024 * 1024))
+    }
+}
+
+/// A folder on the path from the root to the current directory
+#[derive(Clone, PartialEq, Eq)]
+struct PathComponent {
+    name: String,
+    cluster: u32,
+}
+
+enum BulkAction {
+    Trash,
+    Delete,
+    /// Into the given folder of the current directory
+    Move(FileEntry),
+}
+
+/// Deletes or moves files one per frame, so the progress shows and Cancel can stop it
+struct BulkOperation {
+    action: BulkAction,
+    names: Vec<String>,
+    /// Files handled so far, successful or not
+    done: usize,
+    failed: Vec<(String, &'static str)>,
+    cancelled: bool,
+}
+
+impl BulkOperation {
+    fn title(&self) -> String {
+        match &self.action {
+            BulkAction::Trash => "Moving files to the trash".to_string(),
+            BulkAction::Delete => "Deleting files".to_string(),
+            BulkAction::Move(folder) => format!("Moving files to '{}'", folder.name),
+        }
+    }
+
+    fn progress_text(&self) -> String {
+        format!("{} of {} files", self.done, self.names.len())
+    }
+
+    /// One line for the status bar once it stopped
+    fn summary(&self) -> String {
+        let succeeded = self.done - self.failed.len();
+        let verb = match &self.action {
+            BulkAction::Trash => "moved to the trash".to_string(),
+            BulkAction::Delete => "deleted".to_string(),
+            BulkAction::Move(folder) => format!("moved to '{}'", folder.name),
+        };
+
+        match (self.failed.first(), self.names.as_slice()) {
+            (Some((name, e)), [_]) => format!("Error with '{}': {}", name, e),
+            (Some((name, e)), _) => format!(
+                "{} files {}, {} failed ('{}': {})",
+                succeeded,
+                verb,
+                self.failed.len(),
+                name,
+                e
+            ),
+            (None, _) if self.cancelled => format!(
+                "Cancelled, {} of {} files {}",
+                succeeded,
+                self.names.len(),
+                verb
+            ),
+            (None, [name]) => format!("File '{}' {}", name, verb),
+            (None, _) => format!("{} files {}", succeeded, verb),
+        }
+    }
+}
+
+/// A file entry being dragged over the file list
+struct FileDrag {
+    file_index: usize,
+    /// Where the button was pressed, the drag starts once the mouse moves away from it
+    start: (usize, usize),
+    ghost_idx: Option<usize>,
+    target_outline_idx: Option<usize>,
+    /// Folder row the file would be dropped on
+    target_index: Option<usize>,
 }
 
 pub struct FileManager {
     mode: FileManagerMode,
     files: Vec<FileEntry>,
+    /// The row clicked last, single file actions like View use it
     selected_file_index: Option<usize>,
+    /// Every selected row, including `selected_file_index`
+    selected_files: BTreeSet<usize>,
     scroll_offset: usize,
-    input_text: String,
+    scrollbar: ScrollBar,
+    /// `None` keeps the directory order, folders are always first
+    sort: Option<FileSort>,
+    input: TextInput,
+    focus: FocusRing,
     status_message: String,
-    open_file_options: Option<Vec<(usize, String)>>, // Y offset, name
-    selected_open_file_app: Option<String>,
+    open_file_options: Option<Vec<(usize, AppId)>>, // Y offset, app
+    selected_open_file_app: Option<AppId>,
+    associations: Associations,
+    trash_files: Vec<FileEntry>,
+    selected_trash_index: Option<usize>,
+    recent_files: Vec<RecentFile>,
+    selected_recent_index: Option<usize>,
+    drag: Option<FileDrag>,
+    operation: Option<BulkOperation>,
+    progress_bar: ProgressBar,
+    progress_text_idx: Option<usize>,
+    /// The file list couldn't be read because the filesystem was busy, retried every frame
+    refresh_pending: bool,
+    /// Summary of the last disk check, shown in the volume properties
+    disk_check_result: Option<String>,
+
+    root_cluster: u32,
+    /// Folders from the root to the current directory, empty for the root itself
+    path: Vec<PathComponent>,
+    back_history: Vec<Vec<PathComponent>>,
+    forward_history: Vec<Vec<PathComponent>>,
+    /// Last clicked row and the tick it was clicked at, for double clicks
+    last_row_click: Option<(usize, u64)>,
+    /// Row whose name is being edited in `input`
+    renaming: Option<usize>,
+    /// The new file dialog creates a folder instead
+    new_folder: bool,
+    /// File to open, picked up by the window manager after the click
+    pending_open: Option<(FileEntry, u32, AppId)>,
+    /// Named after the volume, which is only read once
+    title: String,
+
+    back_button: Button,
+    forward_button: Button,
+    up_button: Button,
+    breadcrumbs: Vec<Button>,
+    recent_button: Button,
+    rename_button: Button,
 
     // UI element indices
     status_text_idx: Option<usize>,
-    input_text_idx: Option<usize>,
 
     // Button indices
     new_file_btn_idx: Option<usize>,
+    new_folder_btn_idx: Option<usize>,
     delete_file_btn_idx: Option<usize>,
     view_file_btn_idx: Option<usize>,
     back_btn_idx: Option<usize>,
     create_btn_idx: Option<usize>,
     confirm_delete_btn_idx: Option<usize>,
+    confirm_move_btn_idx: Option<usize>,
     confirm_open_file_btn_idx: Option<usize>,
+    properties_btn_idx: Option<usize>,
+    trash_btn_idx: Option<usize>,
+    delete_permanently_btn_idx: Option<usize>,
+    restore_btn_idx: Option<usize>,
+    empty_trash_btn_idx: Option<usize>,
+    check_disk_btn_idx: Option<usize>,
+    repair_disk_btn_idx: Option<usize>,
 }
 
 impl FileManager {
@@ -60,60 +406,226 @@ impl FileManager {
             mode: FileManagerMode::Browse,
             files: Vec::new(),
             selected_file_index: None,
+            selected_files: BTreeSet::new(),
             scroll_offset: 0,
-            input_text: String::new(),
+            sort: file_sort::saved(),
+            scrollbar: ScrollBar::new(0, 41, FILE_LIST_HEIGHT - 2),
+            input: TextInput::new(MARGIN, 100, 0, TEXT_INPUT_HEIGHT),
+            focus: FocusRing::new(),
             status_message: "Ready".to_string(),
             open_file_options: None,
             selected_open_file_app: None,
+            associations: Associations::load(),
+            trash_files: Vec::new(),
+            selected_trash_index: None,
+            recent_files: Vec::new(),
+            selected_recent_index: None,
+            drag: None,
+            operation: None,
+            progress_bar: ProgressBar::new(0, 0, 0),
+            progress_text_idx: None,
+            disk_check_result: None,
+            refresh_pending: false,
+
+            root_cluster: root_directory_cluster().unwrap_or(0),
+            path: Vec::new(),
+            back_history: Vec::new(),
+            forward_history: Vec::new(),
+            last_row_click: None,
+            renaming: None,
+            new_folder: false,
+            pending_open: None,
+            title: match volume_label() {
+                Ok(label) if !label.is_empty() => format!("File Manager - {}", label),
+                _ => "File Manager".to_string(),
+            },
+
+            back_button: Button::new(MARGIN, NAV_Y, NAV_BUTTON_SIZE, NAV_BUTTON_SIZE, "<"),
+            forward_button: Button::new(
+                MARGIN + NAV_BUTTON_SIZE + 4,
+                NAV_Y,
+                NAV_BUTTON_SIZE,
+                NAV_BUTTON_SIZE,
+                ">",
+            ),
+            up_button: Button::new(
+                MARGIN + 2 * (NAV_BUTTON_SIZE + 4),
+                NAV_Y,
+                NAV_BUTTON_SIZE,
+                NAV_BUTTON_SIZE,
+                "^",
+            ),
+            breadcrumbs: Vec::new(),
+            // Moved to the right edge once the window size is known
+            recent_button: Button::new(0, NAV_Y, RECENT_BUTTON_WIDTH, NAV_BUTTON_SIZE, "Recent"),
+            rename_button: Button::new(0, NAV_Y, RENAME_BUTTON_WIDTH, NAV_BUTTON_SIZE, "Rename"),
 
             status_text_idx: None,
-            input_text_idx: None,
 
             new_file_btn_idx: None,
+            new_folder_btn_idx: None,
             delete_file_btn_idx: None,
             view_file_btn_idx: None,
             back_btn_idx: None,
             create_btn_idx: None,
             confirm_delete_btn_idx: None,
+            confirm_move_btn_idx: None,
             confirm_open_file_btn_idx: None,
+            properties_btn_idx: None,
+            trash_btn_idx: None,
+            delete_permanently_btn_idx: None,
+            restore_btn_idx: None,
+            empty_trash_btn_idx: None,
+            check_disk_btn_idx: None,
+            repair_disk_btn_idx: None,
         };
 
         fm.refresh_file_list();
         fm
     }
 
-    fn load_recomended_open_list(
-        &self,
-        file_name: &String,
-    ) -> (Option<&'static str>, Vec<&'static str>) {
-        let recomended = if file_name.to_lowercase().ends_with(".txt") {
-            Some("notepad")
-        } else {
-            None
-        };
+    /// The default app for a file, and the other apps: associated ones first, then the rest
+    fn load_recomended_open_list(&self, file_name: &str) -> (Option<AppId>, Vec<AppId>) {
+        let recomended = self.associations.default_app(file_name);
 
-        let other: Vec<&'static str> = if let Some(rec) = recomended {
-            match rec {
-                "notepad" => ["calculator"].to_vec(),
-                _ => ["notepad", "calculator"].to_vec(),
-            }
-        } else {
-            ["notepad", "calculator"].to_vec()
-        };
+        let mut other = self.associations.apps_for(file_name);
+        other.extend(AppId::ALL);
+
+        let mut seen = Vec::new();
+        other.retain(|app| {
+            let keep = Some(*app) != recomended && !seen.contains(app);
+            seen.push(*app);
+            keep
+        });
 
         (recomended, other)
     }
 
+    pub fn current_cluster(&self) -> u32 {
+        self.path
+            .last()
+            .map_or(self.root_cluster, |component| component.cluster)
+    }
+
+    /// Path of a file in the current directory, from the root
+    fn file_path(&self, name: &str) -> String {
+        let mut path = String::new();
+        for component in &self.path {
+            path.push('/');
+            path.push_str(&component.name);
+        }
+        path.push('/');
+        path.push_str(name);
+        path
+    }
+
     fn refresh_file_list(&mut self) {
-        match list_root_files() {
+        self.refresh_pending = false;
+        // The rows may move, an edit in progress is dropped
+        self.renaming = None;
+
+        match try_list_directory_files(self.current_cluster()) {
             Ok(files) => {
-                self.files = files.into_iter().filter(|f| !f.is_directory).collect();
+                // The trash stays hidden, it has its own view
+                self.files = files
+                    .into_iter()
+                    .filter(|f| {
+                        f.name != "."
+                            && f.name != ".."
+                            && !f.name.eq_ignore_ascii_case(TRASH_DIRECTORY)
+                    })
+                    .collect();
+                sort_files(&mut self.files, self.sort);
                 self.status_message = format!("Found {} files", self.files.len());
-                serial_println!("File Manager: Found {} files", self.files.len());
+                log!(
+                    LogLevel::Debug,
+                    "File Manager: Found {} files",
+                    self.files.len()
+                );
+            }
+            Err(FILESYSTEM_BUSY) => {
+                self.files.clear();
+                self.status_message = "Loading...".to_string();
+                self.refresh_pending = true;
+            }
+            Err(e) => {
+                self.status_message = format!("Error: {}", e);
+                log!(LogLevel::Warn, "File Manager: Error listing files: {}", e);
+            }
+        }
+    }
+
+    pub fn is_refresh_pending(&self) -> bool {
+        self.refresh_pending
+    }
+
+    /// Show another directory without touching the history
+    fn enter_path(&mut self, path: Vec<PathComponent>, surface: &mut Surface) {
+        self.path = path;
+        self.select_only(None);
+        self.scroll_offset = 0;
+        self.last_row_click = None;
+        self.refresh_file_list();
+        self.setup_ui(surface);
+    }
+
+    fn navigate_to(&mut self, path: Vec<PathComponent>, surface: &mut Surface) {
+        if path == self.path {
+            return;
+        }
+
+        let previous = core::mem::take(&mut self.path);
+        push_bounded(&mut self.back_history, previous);
+        self.forward_history.clear();
+
+        self.enter_path(path, surface);
+    }
+
+    fn open_directory(&mut self, directory: &FileEntry, surface: &mut Surface) {
+        let mut path = self.path.clone();
+        path.push(PathComponent {
+            name: directory.name.clone(),
+            cluster: directory.first_cluster,
+        });
+
+        self.navigate_to(path, surface);
+    }
+
+    fn go_back(&mut self, surface: &mut Surface) {
+        if let Some(path) = self.back_history.pop() {
+            let current = self.path.clone();
+            push_bounded(&mut self.forward_history, current);
+            self.enter_path(path, surface);
+        }
+    }
+
+    fn go_forward(&mut self, surface: &mut Surface) {
+        if let Some(path) = self.forward_history.pop() {
+            let current = self.path.clone();
+            push_bounded(&mut self.back_history, current);
+            self.enter_path(path, surface);
+        }
+    }
+
+    fn go_up(&mut self, surface: &mut Surface) {
+        if let Some((_, parent)) = self.path.split_last() {
+            self.navigate_to(parent.to_vec(), surface);
+        }
+    }
+
+    fn refresh_trash_list(&mut self) {
+        self.selected_trash_index = None;
+
+        match try_list_trash_files() {
+            Ok(files) => self.trash_files = files,
+            Err(FILESYSTEM_BUSY) => {
+                self.trash_files.clear();
+                self.status_message = "Loading...".to_string();
+                self.refresh_pending = true;
             }
             Err(e) => {
+                self.trash_files.clear();
                 self.status_message = format!("Error: {}", e);
-                serial_println!("File Manager: Error listing files: {}", e);
             }
         }
     }
@@ -126,6 +638,11 @@ impl FileManager {
             FileManagerMode::NewFile => self.setup_new_file_ui(surface),
             FileManagerMode::DeleteFile => self.setup_delete_file_ui(surface),
             FileManagerMode::ViewFile(_) => self.setup_view_file_ui(surface),
+            FileManagerMode::Properties(_) => self.setup_properties_ui(surface),
+            FileManagerMode::Trash => self.setup_trash_ui(surface),
+            FileManagerMode::Recent => self.setup_recent_ui(surface),
+            FileManagerMode::ConfirmMove(_) => self.setup_confirm_move_ui(surface),
+            FileManagerMode::Working => self.setup_working_ui(surface),
         }
     }
 
@@ -133,22 +650,45 @@ impl FileManager {
         surface.clear_all_shapes();
 
         self.status_text_idx = None;
-        self.input_text_idx = None;
+        self.input.detach();
+        self.input.set_focused(false);
+        self.focus.clear();
+        self.drag = None;
+        self.back_button.detach();
+        self.forward_button.detach();
+        self.up_button.detach();
+        self.recent_button.detach();
+        self.rename_button.detach();
+        self.scrollbar.detach();
+        self.breadcrumbs.clear();
         self.open_file_options = None;
+        self.progress_bar.detach();
+        self.progress_text_idx = None;
 
         self.new_file_btn_idx = None;
+        self.new_folder_btn_idx = None;
         self.delete_file_btn_idx = None;
         self.view_file_btn_idx = None;
         self.back_btn_idx = None;
         self.create_btn_idx = None;
         self.confirm_delete_btn_idx = None;
+        self.confirm_move_btn_idx = None;
         self.confirm_open_file_btn_idx = None;
+        self.properties_btn_idx = None;
+        self.trash_btn_idx = None;
+        self.delete_permanently_btn_idx = None;
+        self.restore_btn_idx = None;
+        self.empty_trash_btn_idx = None;
+        self.check_disk_btn_idx = None;
+        self.repair_disk_btn_idx = None;
     }
 
     fn setup_browse_ui(&mut self, surface: &mut Surface) {
         let width = surface.width;
         let height = surface.height;
 
+        self.setup_navigation_ui(surface);
+
         // File list background
         surface.add_shape(Shape::Rectangle {
             x: MARGIN,
@@ -171,9 +711,17 @@ impl FileManager {
             hide: false,
         });
 
+        // Scrollbar inside the right border
+        self.setup_column_headers(surface);
+
+        let max_visible_files = BROWSE_ROWS;
+        self.scrollbar.rect.x = width - MARGIN - 1 - SCROLLBAR_WIDTH;
+        self.scrollbar
+            .set_range(self.files.len(), max_visible_files, self.scroll_offset);
+        self.scroll_offset = self.scrollbar.offset();
+        self.scrollbar.add_to_surface(surface);
+
         // Display files
-        let max_visible_files = FILE_LIST_HEIGHT / FILE_ENTRY_HEIGHT;
-        // let end_idx = (self.scroll_offset + max_visible_files).min(self.files.len());
 
         for (i, file) in self
             .files
@@ -182,8 +730,8 @@ impl FileManager {
             .skip(self.scroll_offset)
             .take(max_visible_files)
         {
-            let y_pos = 45 + (i - self.scroll_offset) * FILE_ENTRY_HEIGHT;
-            let bg_color = if Some(i) == self.selected_file_index {
+            let y_pos = ROWS_Y + (i - self.scroll_offset) * FILE_ENTRY_HEIGHT;
+            let bg_color = if self.selected_files.contains(&i) {
                 Color::new(150, 200, 255)
             } else {
                 Color::WHITE
@@ -193,22 +741,32 @@ impl FileManager {
             surface.add_shape(Shape::Rectangle {
                 x: MARGIN + 2,
                 y: y_pos,
-                width: width - 2 * MARGIN - 4,
+                width: width - 2 * MARGIN - 4 - SCROLLBAR_WIDTH,
                 height: FILE_ENTRY_HEIGHT - 2,
                 color: bg_color,
                 filled: true,
                 hide: false,
             });
 
-            // File name
-            let display_name = if file.name.len() > 35 {
+            // File name, folders end in a slash
+            let mut display_name = if file.name.len() > 35 {
                 format!("{}...", &file.name[..32])
             } else {
                 file.name.clone()
             };
+            if file.is_directory {
+                display_name.push('/');
+            }
+
+            surface.add_shape(Icon::for_file(&file.name, file.is_directory).shape(
+                MARGIN + 5,
+                y_pos + 1,
+                bg_color,
+                false,
+            ));
 
             surface.add_shape(Shape::Text {
-                x: MARGIN + 5,
+                x: MARGIN + 25,
                 y: y_pos + 3,
                 content: display_name,
                 color: Color::BLACK,
@@ -218,17 +776,37 @@ impl FileManager {
                 hide: false,
             });
 
+            // The name being edited is covered by the input, its text starts where the name does
+            if Some(i) == self.renaming {
+                self.input.x = MARGIN + 20;
+                self.input.y = y_pos - 2;
+                self.input.width = 150;
+                self.input.height = FILE_ENTRY_HEIGHT + 2;
+            }
+
             // File size
-            let size_text = if file.size < 1024 {
-                format!("{} B", file.size)
-            } else if file.size < 1024 * 1024 {
-                format!("{} KB", file.size / 1024)
+            let size_text = if file.is_directory {
+                "<DIR>".to_string()
             } else {
-                format!("{} MB", file.size / (1024 * 1024))
+                format_size(file.size as u64)
             };
 
+            // Files without a date have it all zero
+            if file.last_write_date != 0 {
+                surface.add_shape(Shape::Text {
+                    x: column_x(SortColumn::Modified, width),
+                    y: y_pos + 3,
+                    content: format_date(file.last_write_date),
+                    color: Color::BLACK,
+                    background_color: bg_color,
+                    font_size: RasterHeight::Size16,
+                    font_weight: FontWeight::Regular,
+                    hide: false,
+                });
+            }
+
             surface.add_shape(Shape::Text {
-                x: width - 80,
+                x: column_x(SortColumn::Size, width),
                 y: y_pos + 3,
                 content: size_text,
                 color: Color::BLACK,
@@ -338,6 +916,64 @@ impl FileManager {
             hide: false,
         });
 
+        // Properties button
+        self.properties_btn_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: MARGIN + 270,
+            y: button_y,
+            width: 100,
+            height: BUTTON_HEIGHT,
+            color: Color::new(220, 220, 220),
+            filled: true,
+            hide: false,
+        }));
+
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN + 270,
+            y: button_y,
+            width: 100,
+            height: BUTTON_HEIGHT,
+            color: Color::BLACK,
+            filled: false,
+            hide: false,
+        });
+
+        surface.add_shape(Shape::Text {
+            x: MARGIN + 280,
+            y: button_y + 5,
+            content: "Properties".to_string(),
+            color: Color::BLACK,
+            background_color: Color::new(220, 220, 220),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        self.trash_btn_idx = Some(add_button(
+            surface,
+            MARGIN + 380,
+            button_y,
+            80,
+            "Trash",
+            Color::new(220, 220, 220),
+        ));
+
+        self.new_folder_btn_idx = Some(add_button(
+            surface,
+            MARGIN + 470,
+            button_y,
+            100,
+            "New Folder",
+            Color::new(220, 220, 220),
+        ));
+
+        // Added last so it's drawn over the rows around it
+        if self.renaming.is_some_and(|i| {
+            (self.scroll_offset..self.scroll_offset + max_visible_files).contains(&i)
+        }) {
+            self.input.add_to_surface(surface);
+            self.input.set_focused(true);
+        }
+
         // Status bar
         self.status_text_idx = Some(surface.add_shape(Shape::Text {
             x: MARGIN,
@@ -351,15 +987,146 @@ impl FileManager {
         }));
     }
 
+    /// Name, Modified and Size above the rows, the sorted one with an arrow
+    fn setup_column_headers(&self, surface: &mut Surface) {
+        let width = surface.width;
+
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN + 1,
+            y: 41,
+            width: width - 2 * MARGIN - 2 - SCROLLBAR_WIDTH,
+            height: HEADER_HEIGHT,
+            color: HEADER_COLOR,
+            filled: true,
+            hide: false,
+        });
+
+        for (column, label) in [
+            (SortColumn::Name, "Name"),
+            (SortColumn::Modified, "Modified"),
+            (SortColumn::Size, "Size"),
+        ] {
+            let content = match self.sort {
+                Some(sort) if sort.column == column && sort.descending => format!("{} v", label),
+                Some(sort) if sort.column == column => format!("{} ^", label),
+                _ => label.to_string(),
+            };
+
+            surface.add_shape(Shape::Text {
+                x: column_x(column, width),
+                y: 43,
+                content,
+                color: Color::BLACK,
+                background_color: HEADER_COLOR,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Bold,
+                hide: false,
+            });
+        }
+    }
+
+    /// The column header under a point in browse mode
+    fn column_header_at(&self, x: usize, y: usize, width: usize) -> Option<SortColumn> {
+        if !(41..41 + HEADER_HEIGHT).contains(&y)
+            || x < MARGIN
+            || x >= self.scrollbar.rect.x.min(width - MARGIN)
+        {
+            return None;
+        }
+
+        // Every header reaches a little to the left of its text
+        let column = if x + 5 >= column_x(SortColumn::Size, width) {
+            SortColumn::Size
+        } else if x + 5 >= column_x(SortColumn::Modified, width) {
+            SortColumn::Modified
+        } else {
+            SortColumn::Name
+        };
+        Some(column)
+    }
+
+    /// Sort by a column, or flip the order if the list is already sorted by it
+    fn sort_by(&mut self, column: SortColumn, surface: &mut Surface) {
+        let sort = FileSort::clicked(self.sort, column);
+        self.sort = Some(sort);
+        file_sort::remember(sort);
+
+        // The rows move, so the selection would point at other files
+        self.select_only(None);
+        self.renaming = None;
+        sort_files(&mut self.files, self.sort);
+        self.setup_ui(surface);
+    }
+
+    /// Back, Forward and Up buttons followed by a breadcrumb for every folder in the path
+    fn setup_navigation_ui(&mut self, surface: &mut Surface) {
+        let enabled = Color::new(220, 220, 220);
+        let disabled = Color::new(180, 180, 180);
+
+        for (button, available) in [
+            (&mut self.back_button, !self.back_history.is_empty()),
+            (&mut self.forward_button, !self.forward_history.is_empty()),
+            (&mut self.up_button, !self.path.is_empty()),
+        ] {
+            button.add_to_surface(surface);
+            button.set_color(if available { enabled } else { disabled }, surface);
+        }
+
+        self.recent_button.rect.x = surface.width - MARGIN - RECENT_BUTTON_WIDTH;
+        self.recent_button.add_to_surface(surface);
+
+        self.rename_button.rect.x = self.recent_button.rect.x - 4 - RENAME_BUTTON_WIDTH;
+        self.rename_button.add_to_surface(surface);
+
+        let mut x = self.up_button.rect.x + NAV_BUTTON_SIZE + 12;
+        let names = core::iter::once("Root").chain(self.path.iter().map(|c| c.name.as_str()));
+
+        for (i, name) in names.enumerate() {
+            let (text_width, _) = measure_text(name, FontWeight::Regular, RasterHeight::Size16);
+            let button_width = text_width + 12;
+
+            // Folders that don't fit anymore are left out, Up still reaches them
+            if x + button_width + 4 > self.rename_button.rect.x {
+                break;
+            }
+
+            let color = if i == self.path.len() {
+                Color::new(150, 200, 255)
+            } else {
+                enabled
+            };
+            let mut button =
+                Button::new(x, NAV_Y, button_width, NAV_BUTTON_SIZE, name).with_color(color);
+            button.add_to_surface(surface);
+            self.breadcrumbs.push(button);
+
+            x += button_width + 4;
+        }
+    }
+
     fn setup_new_file_ui(&mut self, surface: &mut Surface) {
         let width = surface.width;
         let height = surface.height;
 
+        let (title, label, hint) = if self.new_folder {
+            (
+                "Create New Folder",
+                "Folder name:",
+                "Enter a folder name, then click Create",
+            )
+        } else {
+            (
+                "Create New File",
+                "Filename:",
+                "Enter filename and content, then click Create",
+            )
+        };
+
         // Title
         surface.add_shape(Shape::Text {
             x: MARGIN,
             y: 50,
-            content: "Create New File".to_string(),
+            content: title.to_string(),
             color: Color::BLACK,
             background_color: Color::new(240, 240, 240),
             font_size: RasterHeight::Size16,
@@ -371,7 +1138,7 @@ impl FileManager {
         surface.add_shape(Shape::Text {
             x: MARGIN,
             y: 80,
-            content: "Filename:".to_string(),
+            content: label.to_string(),
             color: Color::BLACK,
             background_color: Color::new(240, 240, 240),
             font_size: RasterHeight::Size16,
@@ -379,38 +1146,9 @@ impl FileManager {
             hide: false,
         });
 
-        // Filename input background
-        surface.add_shape(Shape::Rectangle {
-            x: MARGIN,
-            y: 100,
-            width: width - 2 * MARGIN,
-            height: TEXT_INPUT_HEIGHT,
-            color: Color::WHITE,
-            filled: true,
-            hide: false,
-        });
-
-        surface.add_shape(Shape::Rectangle {
-            x: MARGIN,
-            y: 100,
-            width: width - 2 * MARGIN,
-            height: TEXT_INPUT_HEIGHT,
-            color: Color::BLACK,
-            filled: false,
-            hide: false,
-        });
-
-        // Filename input text
-        self.input_text_idx = Some(surface.add_shape(Shape::Text {
-            x: MARGIN + 5,
-            y: 105,
-            content: format!("{}_", self.input_text),
-            color: Color::BLACK,
-            background_color: Color::WHITE,
-            font_size: RasterHeight::Size16,
-            font_weight: FontWeight::Regular,
-            hide: false,
-        }));
+        // Filename input
+        self.input.width = width - 2 * MARGIN;
+        self.input.add_to_surface(surface);
 
         // Buttons
         let button_y = height - 60;
@@ -483,123 +1221,510 @@ impl FileManager {
         self.status_text_idx = Some(surface.add_shape(Shape::Text {
             x: MARGIN,
             y: height - 25,
-            content: "Enter filename and content, then click Create".to_string(),
+            content: hint.to_string(),
             color: Color::BLACK,
             background_color: Color::new(240, 240, 240),
             font_size: RasterHeight::Size16,
             font_weight: FontWeight::Regular,
             hide: false,
         }));
+
+        let ids = [
+            self.focus.register(self.input.bounds()),
+            self.focus
+                .register(Rect::new(MARGIN, button_y, 80, BUTTON_HEIGHT)),
+            self.focus
+                .register(Rect::new(MARGIN + 90, button_y, 80, BUTTON_HEIGHT)),
+        ];
+        debug_assert_eq!(ids, [FOCUS_INPUT, FOCUS_CREATE, FOCUS_BACK]);
+        self.focus.add_to_surface(surface);
+        self.set_focus(FOCUS_INPUT, surface);
+    }
+
+    /// Move the keyboard focus in the new file dialog, only the focused input shows its caret
+    fn set_focus(&mut self, id: usize, surface: &mut Surface) {
+        self.focus.focus(id, surface);
+        self.sync_input_focus(surface);
+    }
+
+    fn sync_input_focus(&mut self, surface: &mut Surface) {
+        let focused = self.focus.is_focused(FOCUS_INPUT);
+        if self.input.is_focused() != focused {
+            self.input.set_focused(focused);
+            self.input.blink(surface);
+        }
     }
 
     fn setup_delete_file_ui(&mut self, surface: &mut Surface) {
         let height = surface.height;
 
-        if let Some(idx) = self.selected_file_index {
-            if let Some(file) = self.files.get(idx) {
-                // Title
-                surface.add_shape(Shape::Text {
-                    x: MARGIN,
-                    y: 50,
-                    content: "Delete File".to_string(),
-                    color: Color::BLACK,
-                    background_color: Color::new(240, 240, 240),
-                    font_size: RasterHeight::Size16,
-                    font_weight: FontWeight::Bold,
-                    hide: false,
-                });
+        let names = self.selected_names();
+        if let Some(first) = names.first() {
+            let (title, question) = if names.len() == 1 {
+                (
+                    "Delete File".to_string(),
+                    format!("Are you sure you want to delete '{}'?", first),
+                )
+            } else {
+                (
+                    format!("Delete {} Files", names.len()),
+                    format!("Are you sure you want to delete {} files?", names.len()),
+                )
+            };
 
-                // Confirmation message
-                surface.add_shape(Shape::Text {
-                    x: MARGIN,
-                    y: 100,
-                    content: format!("Are you sure you want to delete '{}'?", file.name),
-                    color: Color::BLACK,
-                    background_color: Color::new(240, 240, 240),
-                    font_size: RasterHeight::Size16,
-                    font_weight: FontWeight::Regular,
-                    hide: false,
-                });
+            // Title
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: 50,
+                content: title,
+                color: Color::BLACK,
+                background_color: Color::new(240, 240, 240),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Bold,
+                hide: false,
+            });
 
-                surface.add_shape(Shape::Text {
-                    x: MARGIN,
-                    y: 130,
-                    content: "This action cannot be undone!".to_string(),
-                    color: Color::new(200, 0, 0),
-                    background_color: Color::new(240, 240, 240),
-                    font_size: RasterHeight::Size16,
-                    font_weight: FontWeight::Bold,
-                    hide: false,
-                });
+            // Confirmation message
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: 100,
+                content: question,
+                color: Color::BLACK,
+                background_color: Color::new(240, 240, 240),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
 
-                // Buttons
-                let button_y = height - 60;
+            let notes_y = if names.len() > 1 {
+                self.add_name_list(&names, 125, surface) + 10
+            } else {
+                130
+            };
 
-                // Confirm Delete button
-                self.confirm_delete_btn_idx = Some(surface.add_shape(Shape::Rectangle {
-                    x: MARGIN,
-                    y: button_y,
-                    width: 100,
-                    height: BUTTON_HEIGHT,
-                    color: Color::new(255, 100, 100),
-                    filled: true,
-                    hide: false,
-                }));
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: notes_y,
+                content: "Files in the trash can be restored later.".to_string(),
+                color: Color::BLACK,
+                background_color: Color::new(240, 240, 240),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
 
-                surface.add_shape(Shape::Rectangle {
-                    x: MARGIN,
-                    y: button_y,
-                    width: 100,
-                    height: BUTTON_HEIGHT,
-                    color: Color::BLACK,
-                    filled: false,
-                    hide: false,
-                });
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: notes_y + 20,
+                content: "Deleting permanently cannot be undone!".to_string(),
+                color: Color::new(200, 0, 0),
+                background_color: Color::new(240, 240, 240),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Bold,
+                hide: false,
+            });
 
-                surface.add_shape(Shape::Text {
-                    x: MARGIN + 15,
-                    y: button_y + 5,
-                    content: "Yes, Delete".to_string(),
-                    color: Color::BLACK,
-                    background_color: Color::new(255, 100, 100),
-                    font_size: RasterHeight::Size16,
-                    font_weight: FontWeight::Regular,
-                    hide: false,
-                });
+            // Buttons
+            let button_y = height - 60;
 
-                // Back button
-                self.back_btn_idx = Some(surface.add_shape(Shape::Rectangle {
-                    x: MARGIN + 110,
-                    y: button_y,
-                    width: 80,
-                    height: BUTTON_HEIGHT,
-                    color: Color::new(220, 220, 220),
-                    filled: true,
-                    hide: false,
-                }));
+            self.confirm_delete_btn_idx = Some(add_button(
+                surface,
+                MARGIN,
+                button_y,
+                120,
+                "Move to Trash",
+                Color::new(255, 180, 180),
+            ));
+
+            self.delete_permanently_btn_idx = Some(add_button(
+                surface,
+                MARGIN + 130,
+                button_y,
+                160,
+                "Delete Permanently",
+                Color::new(255, 100, 100),
+            ));
+
+            self.back_btn_idx = Some(add_button(
+                surface,
+                MARGIN + 300,
+                button_y,
+                80,
+                "Cancel",
+                Color::new(220, 220, 220),
+            ));
+        }
+    }
 
-                surface.add_shape(Shape::Rectangle {
-                    x: MARGIN + 110,
-                    y: button_y,
-                    width: 80,
-                    height: BUTTON_HEIGHT,
-                    color: Color::BLACK,
-                    filled: false,
-                    hide: false,
-                });
+    /// List file names below each other, returns the y below the list
+    fn add_name_list(&self, names: &[String], y: usize, surface: &mut Surface) -> usize {
+        let mut lines: Vec<String> = names
+            .iter()
+            .take(MAX_LISTED_FILES)
+            .map(|name| format!("  {}", name))
+            .collect();
+        if names.len() > MAX_LISTED_FILES {
+            lines.push(format!("  and {} more", names.len() - MAX_LISTED_FILES));
+        }
 
-                surface.add_shape(Shape::Text {
-                    x: MARGIN + 135,
-                    y: button_y + 5,
-                    content: "Cancel".to_string(),
-                    color: Color::BLACK,
-                    background_color: Color::new(220, 220, 220),
-                    font_size: RasterHeight::Size16,
-                    font_weight: FontWeight::Regular,
-                    hide: false,
-                });
-            }
+        for (i, line) in lines.iter().enumerate() {
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: y + i * LISTED_FILE_HEIGHT,
+                content: line.clone(),
+                color: Color::BLACK,
+                background_color: Color::new(240, 240, 240),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+        }
+
+        y + lines.len() * LISTED_FILE_HEIGHT
+    }
+
+    fn setup_confirm_move_ui(&mut self, surface: &mut Surface) {
+        let FileManagerMode::ConfirmMove(folder) = &self.mode else {
+            return;
+        };
+        let names = self.selected_names();
+
+        surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 50,
+            content: "Move Files".to_string(),
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        });
+
+        surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 100,
+            content: format!("Move {} files to '{}'?", names.len(), folder.name),
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        self.add_name_list(&names, 125, surface);
+
+        let button_y = surface.height - 60;
+        self.confirm_move_btn_idx = Some(add_button(
+            surface,
+            MARGIN,
+            button_y,
+            80,
+            "Move",
+            Color::new(220, 220, 220),
+        ));
+        self.back_btn_idx = Some(add_button(
+            surface,
+            MARGIN + 90,
+            button_y,
+            80,
+            "Cancel",
+            Color::new(220, 220, 220),
+        ));
+    }
+
+    fn setup_working_ui(&mut self, surface: &mut Surface) {
+        let Some(operation) = &self.operation else {
+            return;
+        };
+        let background = Color::new(240, 240, 240);
+
+        surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 50,
+            content: operation.title(),
+            color: Color::BLACK,
+            background_color: background,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        });
+
+        self.progress_text_idx = Some(surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 100,
+            // Padded, so a shorter text covers the longer one before it
+            content: format!("{:<24}", operation.progress_text()),
+            color: Color::BLACK,
+            background_color: background,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        }));
+
+        self.progress_bar = ProgressBar::new(MARGIN, 130, surface.width - 2 * MARGIN - 50)
+            .with_percentage(background);
+        self.progress_bar
+            .set_progress(operation.done, operation.names.len(), surface);
+        self.progress_bar.add_to_surface(surface);
+
+        self.back_btn_idx = Some(add_button(
+            surface,
+            MARGIN,
+            surface.height - 60,
+            80,
+            "Cancel",
+            Color::new(220, 220, 220),
+        ));
+    }
+
+    fn setup_trash_ui(&mut self, surface: &mut Surface) {
+        let width = surface.width;
+        let height = surface.height;
+
+        // Title
+        surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 15,
+            content: "Trash".to_string(),
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        });
+
+        // File list background
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN,
+            y: 40,
+            width: width - 2 * MARGIN,
+            height: FILE_LIST_HEIGHT,
+            color: Color::WHITE,
+            filled: true,
+            hide: false,
+        });
+
+        // File list border
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN,
+            y: 40,
+            width: width - 2 * MARGIN,
+            height: FILE_LIST_HEIGHT,
+            color: Color::BLACK,
+            filled: false,
+            hide: false,
+        });
+
+        let max_visible_files = FILE_LIST_HEIGHT / FILE_ENTRY_HEIGHT;
+        for (i, file) in self.trash_files.iter().enumerate().take(max_visible_files) {
+            let y_pos = 45 + i * FILE_ENTRY_HEIGHT;
+            let bg_color = if Some(i) == self.selected_trash_index {
+                Color::new(150, 200, 255)
+            } else {
+                Color::WHITE
+            };
+
+            surface.add_shape(Shape::Rectangle {
+                x: MARGIN + 2,
+                y: y_pos,
+                width: width - 2 * MARGIN - 4,
+                height: FILE_ENTRY_HEIGHT - 2,
+                color: bg_color,
+                filled: true,
+                hide: false,
+            });
+
+            surface.add_shape(Icon::for_file(&file.name, file.is_directory).shape(
+                MARGIN + 5,
+                y_pos + 1,
+                bg_color,
+                false,
+            ));
+
+            surface.add_shape(Shape::Text {
+                x: MARGIN + 25,
+                y: y_pos + 3,
+                content: file.name.clone(),
+                color: Color::BLACK,
+                background_color: bg_color,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+
+            surface.add_shape(Shape::Text {
+                x: width - 80,
+                y: y_pos + 3,
+                content: format_size(file.size as u64),
+                color: Color::BLACK,
+                background_color: bg_color,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
         }
+
+        // Buttons
+        let button_y = height - 60;
+
+        self.restore_btn_idx = Some(add_button(
+            surface,
+            MARGIN,
+            button_y,
+            80,
+            "Restore",
+            Color::new(180, 255, 180),
+        ));
+
+        self.empty_trash_btn_idx = Some(add_button(
+            surface,
+            MARGIN + 90,
+            button_y,
+            110,
+            "Empty Trash",
+            Color::new(255, 100, 100),
+        ));
+
+        self.back_btn_idx = Some(add_button(
+            surface,
+            MARGIN + 210,
+            button_y,
+            80,
+            "Back",
+            Color::new(220, 220, 220),
+        ));
+
+        // Status bar
+        self.status_text_idx = Some(surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: height - 25,
+            content: self.status_message.clone(),
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        }));
+    }
+
+    fn setup_recent_ui(&mut self, surface: &mut Surface) {
+        let width = surface.width;
+        let height = surface.height;
+
+        // Title
+        surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 15,
+            content: "Recent files".to_string(),
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        });
+
+        // File list background
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN,
+            y: 40,
+            width: width - 2 * MARGIN,
+            height: FILE_LIST_HEIGHT,
+            color: Color::WHITE,
+            filled: true,
+            hide: false,
+        });
+
+        // File list border
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN,
+            y: 40,
+            width: width - 2 * MARGIN,
+            height: FILE_LIST_HEIGHT,
+            color: Color::BLACK,
+            filled: false,
+            hide: false,
+        });
+
+        for (i, file) in self.recent_files.iter().enumerate() {
+            let y_pos = 45 + i * FILE_ENTRY_HEIGHT;
+            let bg_color = if Some(i) == self.selected_recent_index {
+                Color::new(150, 200, 255)
+            } else {
+                Color::WHITE
+            };
+
+            surface.add_shape(Shape::Rectangle {
+                x: MARGIN + 2,
+                y: y_pos,
+                width: width - 2 * MARGIN - 4,
+                height: FILE_ENTRY_HEIGHT - 2,
+                color: bg_color,
+                filled: true,
+                hide: false,
+            });
+
+            surface.add_shape(Icon::for_file(file.name(), false).shape(
+                MARGIN + 5,
+                y_pos + 1,
+                bg_color,
+                false,
+            ));
+
+            surface.add_shape(Shape::Text {
+                x: MARGIN + 25,
+                y: y_pos + 3,
+                content: file.path.clone(),
+                color: Color::BLACK,
+                background_color: bg_color,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+
+            surface.add_shape(Shape::Text {
+                x: width - 150,
+                y: y_pos + 3,
+                content: file.opened.clone(),
+                color: Color::DARKGRAY,
+                background_color: bg_color,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+        }
+
+        // Buttons
+        let button_y = height - 60;
+
+        self.confirm_open_file_btn_idx = Some(add_button(
+            surface,
+            MARGIN,
+            button_y,
+            80,
+            "Open",
+            Color::new(180, 255, 180),
+        ));
+
+        self.back_btn_idx = Some(add_button(
+            surface,
+            MARGIN + 90,
+            button_y,
+            80,
+            "Back",
+            Color::new(220, 220, 220),
+        ));
+
+        // Status bar
+        self.status_text_idx = Some(surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: height - 25,
+            content: self.status_message.clone(),
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        }));
     }
 
     fn setup_view_file_ui(&mut self, surface: &mut Surface) {
@@ -632,12 +1757,10 @@ impl FileManager {
             let (recommended, all) = self.load_recomended_open_list(&file.name);
 
             if recommended.is_some() && self.selected_open_file_app.is_none() {
-                self.selected_open_file_app = recommended.map(|s| s.to_string());
+                self.selected_open_file_app = recommended;
             }
 
-            if recommended.is_some()
-                && self.selected_open_file_app == recommended.map(|s| s.to_string())
-            {
+            if recommended.is_some() && self.selected_open_file_app == recommended {
                 surface.add_shape(Shape::Rectangle {
                     x: MARGIN,
                     y: 90,
@@ -654,14 +1777,14 @@ impl FileManager {
                 self.open_file_options
                     .as_mut()
                     .unwrap()
-                    .push((90, recommended.unwrap().to_string()));
+                    .push((90, recommended.unwrap()));
             }
 
             surface.add_shape(Shape::Text {
                 x: MARGIN,
                 y: 90,
                 content: recommended
-                    .unwrap_or("No recommended apps found")
+                    .map_or("No recommended apps found", AppId::name)
                     .to_string(),
                 color: Color::BLACK,
                 background_color: Color::new(240, 240, 240),
@@ -682,7 +1805,7 @@ impl FileManager {
             });
 
             for (i, app) in all.iter().enumerate() {
-                if self.selected_open_file_app == Some(app.to_string()) {
+                if self.selected_open_file_app == Some(*app) {
                     surface.add_shape(Shape::Rectangle {
                         x: MARGIN,
                         y: 130 + i * 20,
@@ -697,7 +1820,7 @@ impl FileManager {
                 surface.add_shape(Shape::Text {
                     x: MARGIN,
                     y: 130 + i * 20,
-                    content: app.to_string(),
+                    content: app.name().to_string(),
                     color: Color::BLACK,
                     background_color: Color::new(240, 240, 240),
                     font_size: RasterHeight::Size16,
@@ -708,7 +1831,7 @@ impl FileManager {
                 self.open_file_options
                     .as_mut()
                     .unwrap()
-                    .push((130 + i * 20, app.to_string()));
+                    .push((130 + i * 20, *app));
             }
 
             // Back button
@@ -777,36 +1900,281 @@ impl FileManager {
         }
     }
 
-    pub fn handle_click(
+    fn setup_properties_ui(&mut self, surface: &mut Surface) {
+        let height = surface.height;
+
+        let FileManagerMode::Properties(file) = &self.mode else {
+            return;
+        };
+
+        let (title, lines) = match file {
+            Some(file) => {
+                let mut lines = vec![format!(
+                    "Size: {} ({} bytes)",
+                    format_size(file.size as u64),
+                    file.size
+                )];
+
+                match file_clusters(file.first_cluster) {
+                    Ok((count, cluster_size)) => {
+                        lines.push(format!("Clusters: {} of {} bytes", count, cluster_size));
+                        lines.push(format!(
+                            "Size on disk: {}",
+                            format_size(count as u64 * cluster_size as u64)
+                        ));
+                    }
+                    Err(e) => lines.push(format!("Clusters: unknown ({})", e)),
+                }
+
+                lines.push(format!("First cluster: {}", file.first_cluster));
+                if file.first_cluster >= 2 {
+                    lines.extend(cluster_chain_lines(file.first_cluster));
+                }
+                lines.push(format!("Attributes: {}", attribute_names(file.attributes)));
+                lines.push(format!(
+                    "Created: {} {}",
+                    format_date(file.creation_date),
+                    format_time(file.creation_time)
+                ));
+                lines.push(format!(
+                    "Modified: {} {}",
+                    format_date(file.last_write_date),
+                    format_time(file.last_write_time)
+                ));
+                lines.push(format!("Accessed: {}", format_date(file.last_access_date)));
+
+                (format!("Properties of {}", file.name), lines)
+            }
+            None => {
+                let mut lines = match root_directory_size() {
+                    Ok(size) => {
+                        let mut lines = vec![
+                            format!(
+                                "Total size: {} ({} bytes)",
+                                format_size(size.bytes),
+                                size.bytes
+                            ),
+                            format!("Files: {}", size.files),
+                            format!("Folders: {}", size.directories),
+                        ];
+                        if size.incomplete {
+                            lines.push(
+                                "Warning: the filesystem has loops, totals are partial".to_string(),
+                            );
+                        }
+                        lines
+                    }
+                    Err(e) => vec![format!("Error: {}", e)],
+                };
+
+                if let Some(result) = &self.disk_check_result {
+                    lines.push(result.clone());
+                }
+
+                ("Properties of the volume".to_string(), lines)
+            }
+        };
+
+        // Title
+        surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 50,
+            content: title,
+            color: Color::BLACK,
+            background_color: Color::new(240, 240, 240),
+            font_size: RasterHeight::Size20,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        });
+
+        for (i, line) in lines.into_iter().enumerate() {
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: 85 + i * 20,
+                content: line,
+                color: Color::BLACK,
+                background_color: Color::new(240, 240, 240),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+        }
+
+        // Back button
+        let button_y = height - 60;
+        self.back_btn_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: MARGIN,
+            y: button_y,
+            width: 80,
+            height: BUTTON_HEIGHT,
+            color: Color::new(220, 220, 220),
+            filled: true,
+            hide: false,
+        }));
+
+        surface.add_shape(Shape::Rectangle {
+            x: MARGIN,
+            y: button_y,
+            width: 80,
+            height: BUTTON_HEIGHT,
+            color: Color::BLACK,
+            filled: false,
+            hide: false,
+        });
+
+        surface.add_shape(Shape::Text {
+            x: MARGIN + 25,
+            y: button_y + 5,
+            content: "Back".to_string(),
+            color: Color::BLACK,
+            background_color: Color::new(220, 220, 220),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        if matches!(self.mode, FileManagerMode::Properties(None)) {
+            self.check_disk_btn_idx = Some(add_button(
+                surface,
+                MARGIN + 90,
+                button_y,
+                100,
+                "Check Disk",
+                Color::new(220, 220, 220),
+            ));
+            self.repair_disk_btn_idx = Some(add_button(
+                surface,
+                MARGIN + 200,
+                button_y,
+                80,
+                "Repair",
+                Color::new(255, 220, 160),
+            ));
+        }
+    }
+
+    pub fn handle_mouse_click(
         &mut self,
         x: usize,
         y: usize,
+        modifiers: &Modifiers,
         surface: &mut Surface,
-    ) -> (bool, Option<(FileEntry, String)>) {
+    ) -> (bool, Option<(FileEntry, u32, AppId)>) {
         match &self.mode {
-            FileManagerMode::Browse => (self.handle_browse_click(x, y, surface), None),
+            FileManagerMode::Browse => (self.handle_browse_click(x, y, modifiers, surface), None),
             FileManagerMode::NewFile => (self.handle_new_file_click(x, y, surface), None),
             FileManagerMode::DeleteFile => (self.handle_delete_click(x, y, surface), None),
             FileManagerMode::ViewFile(_) => self.handle_view_click(x, y, surface),
+            FileManagerMode::Properties(_) => (self.handle_properties_click(x, y, surface), None),
+            FileManagerMode::Trash => (self.handle_trash_click(x, y, surface), None),
+            FileManagerMode::Recent => self.handle_recent_click(x, y, surface),
+            FileManagerMode::ConfirmMove(_) => {
+                (self.handle_confirm_move_click(x, y, surface), None)
+            }
+            FileManagerMode::Working => (self.handle_working_click(x, y, surface), None),
         }
     }
 
-    fn handle_browse_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+    fn handle_browse_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        // A click outside the name being edited cancels the rename
+        if self.renaming.is_some() {
+            if self.input.bounds().contains_point(x, y) {
+                return true;
+            }
+            self.cancel_rename(surface);
+        }
+
+        // Check navigation clicks
+        if self.back_button.contains(x, y) {
+            self.go_back(surface);
+            return true;
+        }
+        if self.forward_button.contains(x, y) {
+            self.go_forward(surface);
+            return true;
+        }
+        if self.up_button.contains(x, y) {
+            self.go_up(surface);
+            return true;
+        }
+        if let Some(depth) = self.breadcrumbs.iter().position(|b| b.contains(x, y)) {
+            self.navigate_to(self.path[..depth].to_vec(), surface);
+            return true;
+        }
+        if self.rename_button.contains(x, y) {
+            self.start_rename(surface);
+            return true;
+        }
+        if self.recent_button.contains(x, y) {
+            self.recent_files = recent::list();
+            self.selected_recent_index = None;
+            self.status_message = if self.recent_files.is_empty() {
+                "No files opened yet".to_string()
+            } else {
+                "Select a file to open".to_string()
+            };
+            self.mode = FileManagerMode::Recent;
+            self.setup_ui(surface);
+            return true;
+        }
+
+        if let Some(column) = self.column_header_at(x, y, surface.width) {
+            self.sort_by(column, surface);
+            return true;
+        }
+
         // Check file list clicks
-        if x >= MARGIN && x < surface.width - MARGIN && y >= 45 && y < 45 + FILE_LIST_HEIGHT {
-            let clicked_index = self.scroll_offset + (y - 45) / FILE_ENTRY_HEIGHT;
-            if clicked_index < self.files.len() {
-                self.selected_file_index = Some(clicked_index);
+        if let Some(clicked_index) = self.file_index_at(x, y, surface.width) {
+            // Ctrl toggles a row, Shift selects the rows up to it
+            if modifiers.is_ctrl() || modifiers.is_shifted() {
+                if modifiers.is_shifted() {
+                    let anchor = self.selected_file_index.unwrap_or(clicked_index);
+                    self.selected_files =
+                        (anchor.min(clicked_index)..=anchor.max(clicked_index)).collect();
+                    self.selected_file_index = Some(anchor);
+                } else if self.selected_files.remove(&clicked_index) {
+                    self.selected_file_index = self.selected_files.last().copied();
+                } else {
+                    self.selected_files.insert(clicked_index);
+                    self.selected_file_index = Some(clicked_index);
+                }
+
+                self.last_row_click = None;
+                self.status_message = format!("{} files selected", self.selected_files.len());
                 self.setup_ui(surface);
                 return true;
             }
+
+            let now = interrupts::ticks();
+            let double_click = matches!(
+                self.last_row_click,
+                Some((index, tick)) if index == clicked_index && now - tick <= DOUBLE_CLICK_TICKS
+            );
+            self.last_row_click = Some((clicked_index, now));
+
+            if double_click && self.files[clicked_index].is_directory {
+                let directory = self.files[clicked_index].clone();
+                self.open_directory(&directory, surface);
+                return true;
+            }
+
+            self.select_only(Some(clicked_index));
+            self.setup_ui(surface);
+            return true;
         }
 
         // Check button clicks
         if self.new_file_btn_idx.is_some() {
             if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT) {
                 self.mode = FileManagerMode::NewFile;
-                self.input_text.clear();
+                self.new_folder = false;
+                self.input.clear();
                 self.setup_ui(surface);
                 return true;
             }
@@ -814,7 +2182,7 @@ impl FileManager {
 
         if self.delete_file_btn_idx.is_some() {
             if self.is_button_clicked(x, y, MARGIN + 90, surface.height - 60, 80, BUTTON_HEIGHT) {
-                if self.selected_file_index.is_some() {
+                if !self.selected_files.is_empty() {
                     self.mode = FileManagerMode::DeleteFile;
                     self.setup_ui(surface);
                 } else {
@@ -829,8 +2197,7 @@ impl FileManager {
             if self.is_button_clicked(x, y, MARGIN + 180, surface.height - 60, 80, BUTTON_HEIGHT) {
                 if let Some(idx) = self.selected_file_index {
                     if let Some(file) = self.files.get(idx).cloned() {
-                        self.mode = FileManagerMode::ViewFile(file);
-                        self.setup_ui(surface);
+                        self.view_file(file, surface);
                     }
                 } else {
                     self.status_message = "Please select a file to view".to_string();
@@ -840,10 +2207,46 @@ impl FileManager {
             }
         }
 
+        if self.properties_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN + 270, surface.height - 60, 100, BUTTON_HEIGHT) {
+                let file = self
+                    .selected_file_index
+                    .and_then(|idx| self.files.get(idx).cloned());
+                self.mode = FileManagerMode::Properties(file);
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        if self.trash_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN + 380, surface.height - 60, 80, BUTTON_HEIGHT) {
+                self.status_message = "Select a file to restore".to_string();
+                self.refresh_trash_list();
+                self.mode = FileManagerMode::Trash;
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        if self.new_folder_btn_idx.is_some()
+            && self.is_button_clicked(x, y, MARGIN + 470, surface.height - 60, 100, BUTTON_HEIGHT)
+        {
+            self.mode = FileManagerMode::NewFile;
+            self.new_folder = true;
+            self.input.clear();
+            self.setup_ui(surface);
+            return true;
+        }
+
         false
     }
 
     fn handle_new_file_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if self.input.bounds().contains_point(x, y) {
+            self.set_focus(FOCUS_INPUT, surface);
+            return true;
+        }
+
         if self.create_btn_idx.is_some() {
             if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT) {
                 self.create_file(surface);
@@ -864,14 +2267,116 @@ impl FileManager {
 
     fn handle_delete_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
         if self.confirm_delete_btn_idx.is_some() {
-            if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 100, BUTTON_HEIGHT) {
-                self.delete_selected_file(surface);
+            if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 120, BUTTON_HEIGHT) {
+                self.delete_selected_files(false, surface);
+                return true;
+            }
+        }
+
+        if self.delete_permanently_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN + 130, surface.height - 60, 160, BUTTON_HEIGHT) {
+                self.delete_selected_files(true, surface);
+                return true;
+            }
+        }
+
+        if self.back_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN + 300, surface.height - 60, 80, BUTTON_HEIGHT) {
+                self.mode = FileManagerMode::Browse;
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn handle_confirm_move_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if self.confirm_move_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT) {
+                if let FileManagerMode::ConfirmMove(folder) = &self.mode {
+                    let action = BulkAction::Move(folder.clone());
+                    self.start_operation(action, self.selected_names(), surface);
+                }
+                return true;
+            }
+        }
+
+        if self.back_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN + 90, surface.height - 60, 80, BUTTON_HEIGHT) {
+                self.mode = FileManagerMode::Browse;
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Cancel stops before the next file, files already handled stay that way
+    fn handle_working_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if self.back_btn_idx.is_some()
+            && self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT)
+        {
+            if let Some(operation) = &mut self.operation {
+                operation.cancelled = true;
+            }
+            return true;
+        }
+
+        false
+    }
+
+    fn handle_trash_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        // Check file list clicks
+        if x >= MARGIN && x < surface.width - MARGIN && y >= 45 && y < 45 + FILE_LIST_HEIGHT {
+            let clicked_index = (y - 45) / FILE_ENTRY_HEIGHT;
+            if clicked_index < self.trash_files.len() {
+                self.selected_trash_index = Some(clicked_index);
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        if self.restore_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT) {
+                match self
+                    .selected_trash_index
+                    .and_then(|idx| self.trash_files.get(idx))
+                {
+                    Some(file) => {
+                        let filename = file.name.clone();
+                        self.status_message = match restore_from_trash(&filename) {
+                            Ok(_) => format!("File '{}' restored", filename),
+                            Err(e) => format!("Error restoring file: {}", e),
+                        };
+                        self.refresh_file_list();
+                        self.refresh_trash_list();
+                    }
+                    None => {
+                        self.status_message = "Please select a file to restore".to_string();
+                    }
+                }
+
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        if self.empty_trash_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN + 90, surface.height - 60, 110, BUTTON_HEIGHT) {
+                self.status_message = match empty_trash() {
+                    Ok(_) => "Trash emptied".to_string(),
+                    Err(e) => format!("Error emptying trash: {}", e),
+                };
+                self.refresh_trash_list();
+                self.setup_ui(surface);
                 return true;
             }
         }
 
         if self.back_btn_idx.is_some() {
-            if self.is_button_clicked(x, y, MARGIN + 110, surface.height - 60, 80, BUTTON_HEIGHT) {
+            if self.is_button_clicked(x, y, MARGIN + 210, surface.height - 60, 80, BUTTON_HEIGHT) {
                 self.mode = FileManagerMode::Browse;
                 self.setup_ui(surface);
                 return true;
@@ -881,12 +2386,71 @@ impl FileManager {
         false
     }
 
+    fn handle_recent_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        surface: &mut Surface,
+    ) -> (bool, Option<(FileEntry, u32, AppId)>) {
+        if x >= MARGIN && x < surface.width - MARGIN && y >= 45 && y < 45 + FILE_LIST_HEIGHT {
+            let clicked_index = (y - 45) / FILE_ENTRY_HEIGHT;
+            if clicked_index < self.recent_files.len() {
+                self.selected_recent_index = Some(clicked_index);
+                self.setup_ui(surface);
+                return (true, None);
+            }
+        }
+
+        if self.confirm_open_file_btn_idx.is_some()
+            && self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT)
+        {
+            let Some(path) = self
+                .selected_recent_index
+                .and_then(|idx| self.recent_files.get(idx))
+                .map(|file| file.path.clone())
+            else {
+                self.status_message = "Please select a file to open".to_string();
+                self.setup_ui(surface);
+                return (true, None);
+            };
+
+            let (file, directory) = match recent::find(&path) {
+                Ok(found) => found,
+                Err(e) => {
+                    self.status_message = format!("Can't open {}: {}", path, e);
+                    self.setup_ui(surface);
+                    return (true, None);
+                }
+            };
+            let Some(app) = self.associations.default_app(&file.name) else {
+                self.status_message = format!("No app is set to open {}", file.name);
+                self.setup_ui(surface);
+                return (true, None);
+            };
+
+            recent::record(&path);
+            self.mode = FileManagerMode::Browse;
+            self.setup_ui(surface);
+            return (true, Some((file, directory, app)));
+        }
+
+        if self.back_btn_idx.is_some()
+            && self.is_button_clicked(x, y, MARGIN + 90, surface.height - 60, 80, BUTTON_HEIGHT)
+        {
+            self.mode = FileManagerMode::Browse;
+            self.setup_ui(surface);
+            return (true, None);
+        }
+
+        (false, None)
+    }
+
     fn handle_view_click(
         &mut self,
         x: usize,
         y: usize,
         surface: &mut Surface,
-    ) -> (bool, Option<(FileEntry, String)>) {
+    ) -> (bool, Option<(FileEntry, u32, AppId)>) {
         if self.back_btn_idx.is_some() {
             if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT) {
                 self.mode = FileManagerMode::Browse;
@@ -898,18 +2462,20 @@ impl FileManager {
 
         if self.confirm_open_file_btn_idx.is_some() {
             if self.is_button_clicked(x, y, MARGIN + 90, surface.height - 60, 80, BUTTON_HEIGHT) {
-                if let Some(app) = self.selected_open_file_app.clone() {
+                if let Some(app) = self.selected_open_file_app {
                     let file = self
                         .files
                         .get(self.selected_file_index.unwrap())
                         .cloned()
                         .unwrap();
 
+                    recent::record(&self.file_path(&file.name));
+
                     self.selected_open_file_app = None;
                     self.mode = FileManagerMode::Browse;
                     self.setup_ui(surface);
 
-                    return (true, Some((file, app)));
+                    return (true, Some((file, self.current_cluster(), app)));
                 } else {
                     self.status_message =
                         "Please select an application to open the file".to_string();
@@ -922,7 +2488,7 @@ impl FileManager {
         if let Some(apps) = &self.open_file_options {
             for (app_y, app) in apps {
                 if self.is_button_clicked(x, y, MARGIN, *app_y, 200, 20) {
-                    self.selected_open_file_app = Some(app.to_string());
+                    self.selected_open_file_app = Some(*app);
                     self.setup_ui(surface);
                     return (true, None);
                 }
@@ -932,6 +2498,63 @@ impl FileManager {
         (false, None)
     }
 
+    fn handle_properties_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if self.back_btn_idx.is_some() {
+            if self.is_button_clicked(x, y, MARGIN, surface.height - 60, 80, BUTTON_HEIGHT) {
+                self.mode = FileManagerMode::Browse;
+                self.setup_ui(surface);
+                return true;
+            }
+        }
+
+        let check_clicked = self.check_disk_btn_idx.is_some()
+            && self.is_button_clicked(x, y, MARGIN + 90, surface.height - 60, 100, BUTTON_HEIGHT);
+        let repair_clicked = self.repair_disk_btn_idx.is_some()
+            && self.is_button_clicked(x, y, MARGIN + 200, surface.height - 60, 80, BUTTON_HEIGHT);
+
+        if check_clicked || repair_clicked {
+            self.disk_check_result = Some(match check_filesystem(repair_clicked) {
+                Ok(report) => describe_check(&report),
+                Err(e) => format!("Check failed: {}", e),
+            });
+            self.setup_ui(surface);
+            return true;
+        }
+
+        false
+    }
+
+    fn view_file(&mut self, file: FileEntry, surface: &mut Surface) {
+        if file.is_directory {
+            self.open_directory(&file, surface);
+        } else {
+            self.mode = FileManagerMode::ViewFile(file);
+            self.setup_ui(surface);
+        }
+    }
+
+    /// Index of the file list row under a point in browse mode
+    fn file_index_at(&self, x: usize, y: usize, width: usize) -> Option<usize> {
+        if x < MARGIN
+            || x >= self.scrollbar.rect.x.min(width - MARGIN)
+            || y < ROWS_Y
+            || y >= ROWS_Y + BROWSE_ROWS * FILE_ENTRY_HEIGHT
+        {
+            return None;
+        }
+
+        let index = self.scroll_offset + (y - ROWS_Y) / FILE_ENTRY_HEIGHT;
+        (index < self.files.len()).then_some(index)
+    }
+
+    /// Show the rows from the scrollbar offset, if it moved
+    fn apply_scroll(&mut self, surface: &mut Surface) {
+        if self.scrollbar.offset() != self.scroll_offset {
+            self.scroll_offset = self.scrollbar.offset();
+            self.setup_ui(surface);
+        }
+    }
+
     fn is_button_clicked(
         &self,
         x: usize,
@@ -945,7 +2568,7 @@ impl FileManager {
     }
 
     fn create_file(&mut self, surface: &mut Surface) {
-        if self.input_text.is_empty() {
+        if self.input.is_empty() {
             self.status_message = "Please enter a filename".to_string();
             if let Some(idx) = self.status_text_idx {
                 surface.update_text_content(idx, self.status_message.clone(), None);
@@ -953,9 +2576,14 @@ impl FileManager {
             return;
         }
 
-        match create_file_in_root(&self.input_text, &[]) {
+        if self.new_folder {
+            self.create_folder(surface);
+            return;
+        }
+
+        match create_file_in_directory(self.current_cluster(), self.input.text(), &[]) {
             Ok(_) => {
-                self.status_message = format!("File '{}' created successfully", self.input_text);
+                self.status_message = format!("File '{}' created successfully", self.input.text());
                 self.refresh_file_list();
                 self.mode = FileManagerMode::Browse;
                 self.setup_ui(surface);
@@ -969,88 +2597,353 @@ impl FileManager {
         }
     }
 
-    fn delete_selected_file(&mut self, surface: &mut Surface) {
-        if let Some(idx) = self.selected_file_index {
-            if let Some(file) = self.files.get(idx) {
-                let filename = file.name.clone();
-                match delete_file_from_root(&filename) {
-                    Ok(_) => {
-                        self.status_message = format!("File '{}' deleted successfully", filename);
-                        self.refresh_file_list();
-                        self.selected_file_index = None;
-                        self.mode = FileManagerMode::Browse;
-                        self.setup_ui(surface);
-                    }
-                    Err(e) => {
-                        self.status_message = format!("Error deleting file: {}", e);
-                        self.mode = FileManagerMode::Browse;
-                        self.setup_ui(surface);
+    fn create_folder(&mut self, surface: &mut Surface) {
+        let name = self.input.text();
+        let result = validate_filename(name)
+            .and_then(|_| create_directory_in_directory(self.current_cluster(), name));
+
+        match result {
+            Ok(_) => {
+                self.status_message = format!("Folder '{}' created", name);
+                self.refresh_file_list();
+                self.mode = FileManagerMode::Browse;
+                self.setup_ui(surface);
+            }
+            Err(e) => {
+                self.status_message = format!("Error creating folder: {}", e);
+                if let Some(idx) = self.status_text_idx {
+                    surface.update_text_content(idx, self.status_message.clone(), None);
+                }
+            }
+        }
+    }
+
+    /// Turn the name of the selected file into an input
+    fn start_rename(&mut self, surface: &mut Surface) {
+        let Some(file) = self.selected_file_index.and_then(|idx| self.files.get(idx)) else {
+            self.status_message = "Please select a file to rename".to_string();
+            self.setup_ui(surface);
+            return;
+        };
+
+        self.input.set_text(&file.name);
+        self.renaming = self.selected_file_index;
+        self.status_message = "Enter to rename, Escape to cancel".to_string();
+        self.setup_ui(surface);
+    }
+
+    fn cancel_rename(&mut self, surface: &mut Surface) {
+        self.renaming = None;
+        self.status_message = "Ready".to_string();
+        self.setup_ui(surface);
+    }
+
+    /// Rename the file to the text of the input, errors keep the input open
+    fn commit_rename(&mut self, surface: &mut Surface) {
+        let Some(old_name) = self
+            .renaming
+            .and_then(|idx| self.files.get(idx))
+            .map(|file| file.name.clone())
+        else {
+            return;
+        };
+        let new_name = self.input.text().trim().to_uppercase();
+
+        if new_name == old_name {
+            self.cancel_rename(surface);
+            return;
+        }
+
+        let dir_cluster = self.current_cluster();
+        let result = validate_filename(&new_name)
+            .and_then(|_| move_file(dir_cluster, &old_name, dir_cluster, &new_name));
+
+        match result {
+            Ok(()) => {
+                self.refresh_file_list();
+                self.select_only(self.files.iter().position(|f| f.name == new_name));
+                self.status_message = format!("Renamed '{}' to '{}'", old_name, new_name);
+                self.setup_ui(surface);
+            }
+            Err(e) => {
+                self.status_message = format!("Error renaming file: {}", e);
+                if let Some(idx) = self.status_text_idx {
+                    surface.update_text_content(idx, self.status_message.clone(), None);
+                }
+            }
+        }
+    }
+
+    /// Select a single row, or nothing
+    fn select_only(&mut self, index: Option<usize>) {
+        self.selected_file_index = index;
+        self.selected_files = index.into_iter().collect();
+    }
+
+    /// Names of the selected files, in list order
+    fn selected_names(&self) -> Vec<String> {
+        self.selected_files
+            .iter()
+            .filter_map(|&index| self.files.get(index).map(|file| file.name.clone()))
+            .collect()
+    }
+
+    /// Rows a drag started on a row takes along, the whole selection if the row is in it
+    fn dragged_indices(&self, file_index: usize) -> Vec<usize> {
+        if self.selected_files.contains(&file_index) {
+            self.selected_files.iter().copied().collect()
+        } else {
+            vec![file_index]
+        }
+    }
+
+    fn delete_selected_files(&mut self, permanently: bool, surface: &mut Surface) {
+        let action = if permanently {
+            BulkAction::Delete
+        } else {
+            BulkAction::Trash
+        };
+        self.start_operation(action, self.selected_names(), surface);
+    }
+
+    /// Show the progress of an operation on files in the current directory. A single file
+    /// is handled right away.
+    fn start_operation(&mut self, action: BulkAction, names: Vec<String>, surface: &mut Surface) {
+        if names.is_empty() {
+            self.mode = FileManagerMode::Browse;
+            self.setup_ui(surface);
+            return;
+        }
+
+        let single = names.len() == 1;
+        self.operation = Some(BulkOperation {
+            action,
+            names,
+            done: 0,
+            failed: Vec::new(),
+            cancelled: false,
+        });
+        self.mode = FileManagerMode::Working;
+
+        if single {
+            self.step_operation(surface);
+        } else {
+            self.setup_ui(surface);
+        }
+    }
+
+    /// Handle the next file, or finish once all are done or it was cancelled
+    fn step_operation(&mut self, surface: &mut Surface) {
+        let dir_cluster = self.current_cluster();
+        let Some(operation) = &mut self.operation else {
+            return;
+        };
+
+        if !operation.cancelled {
+            if let Some(name) = operation.names.get(operation.done) {
+                let result = match &operation.action {
+                    BulkAction::Trash => move_to_trash(dir_cluster, name),
+                    BulkAction::Delete => delete_file_from_directory(dir_cluster, name),
+                    BulkAction::Move(folder) => {
+                        move_file(dir_cluster, name, folder.first_cluster, name)
                     }
+                };
+                if let Err(e) = result {
+                    log!(LogLevel::Warn, "File Manager: Error with '{}': {}", name, e);
+                    operation.failed.push((name.clone(), e));
                 }
+                operation.done += 1;
+
+                if let Some(idx) = self.progress_text_idx {
+                    surface.update_text_content(
+                        idx,
+                        format!("{:<24}", operation.progress_text()),
+                        None,
+                    );
+                }
+                self.progress_bar
+                    .set_progress(operation.done, operation.names.len(), surface);
+            }
+        }
+
+        if operation.cancelled || operation.done == operation.names.len() {
+            let summary = operation.summary();
+            let changed = operation.done > operation.failed.len();
+            let kind = if operation.failed.is_empty() {
+                ToastKind::Success
+            } else {
+                ToastKind::Error
+            };
+            self.operation = None;
+            toast::notify(&summary, kind);
+
+            if changed {
+                self.refresh_file_list();
+                self.select_only(None);
             }
+            self.status_message = summary;
+            self.mode = FileManagerMode::Browse;
+            self.setup_ui(surface);
         }
     }
 
     pub fn handle_char_input(&mut self, c: char, surface: &mut Surface) {
         match &self.mode {
+            FileManagerMode::Browse if self.renaming.is_some() => match c {
+                '\n' => self.commit_rename(surface),
+                '\x1b' => self.cancel_rename(surface),
+                '\x08' => {
+                    self.input.backspace();
+                    self.input.update_surface(surface);
+                }
+                '\x7f' => {
+                    self.input.delete();
+                    self.input.update_surface(surface);
+                }
+                c if c.is_ascii() && !c.is_control() => {
+                    self.input.insert_char(c);
+                    self.input.update_surface(surface);
+                }
+                _ => {}
+            },
             FileManagerMode::NewFile => {
+                if c == '\n' {
+                    // Enter activates the focused button, in the input it creates the file
+                    if self.focus.is_focused(FOCUS_BACK) {
+                        self.mode = FileManagerMode::Browse;
+                        self.setup_ui(surface);
+                    } else {
+                        self.create_file(surface);
+                    }
+                    return;
+                }
+
+                // Typing only goes to the input while it has the focus
+                if !self.focus.is_focused(FOCUS_INPUT) {
+                    return;
+                }
+
                 if c == '\x08' {
                     // Backspace
-                    self.input_text.pop();
-                } else if c == '\n' {
-                    // Enter key, create file
-                    self.create_file(surface);
+                    self.input.backspace();
+                } else if c == '\x7f' {
+                    // Delete
+                    self.input.delete();
                 } else if c.is_ascii() && !c.is_control() {
-                    self.input_text.push(c);
+                    self.input.insert_char(c);
                 }
 
-                if let Some(idx) = self.input_text_idx {
-                    surface.update_text_content(idx, format!("{}_", self.input_text), None);
-                }
+                self.input.update_surface(surface);
             }
             _ => {}
         }
     }
 
-    pub fn handle_key_input(&mut self, key: KeyCode, surface: &mut Surface) {
+    /// Whether the name field gets the typed keys
+    fn is_editing_name(&self) -> bool {
+        match self.mode {
+            FileManagerMode::NewFile => self.focus.is_focused(FOCUS_INPUT),
+            FileManagerMode::Browse => self.renaming.is_some(),
+            _ => false,
+        }
+    }
+
+    /// The selected text of the name field, or the selected file
+    pub fn copy(&self) -> Option<Clip> {
+        if self.is_editing_name() {
+            return self
+                .input
+                .selected_text()
+                .map(|text| Clip::Text(text.to_string()));
+        }
+        if !matches!(self.mode, FileManagerMode::Browse) {
+            return None;
+        }
+
+        let file = self.files.get(self.selected_file_index?)?;
+        Some(Clip::File(self.file_path(&file.name)))
+    }
+
+    /// Paste into the name field, a copied file as its name
+    pub fn paste(&mut self, clip: &Clip, surface: &mut Surface) {
+        if !self.is_editing_name() {
+            return;
+        }
+
+        let text = match clip {
+            Clip::Text(text) => text.as_str(),
+            Clip::File(path) => path.rsplit('/').next().unwrap_or(path),
+        };
+        for c in text.chars().filter(|c| c.is_ascii() && !c.is_control()) {
+            self.input.insert_char(c);
+        }
+        self.input.update_surface(surface);
+    }
+
+    pub fn handle_key_input(&mut self, key: KeyCode, modifiers: &Modifiers, surface: &mut Surface) {
         match &self.mode {
-            FileManagerMode::NewFile => match key {
-                KeyCode::Backspace => {
-                    self.input_text.pop();
-                    if let Some(idx) = self.input_text_idx {
-                        surface.update_text_content(idx, format!("{}_", self.input_text), None);
-                    }
+            FileManagerMode::NewFile => {
+                if self.focus.handle_key(key, modifiers, surface) {
+                    self.sync_input_focus(surface);
+                    return;
                 }
-                _ => {}
-            },
+
+                let handled =
+                    self.focus.is_focused(FOCUS_INPUT) && self.input.handle_key(key, modifiers);
+
+                if handled {
+                    self.input.update_surface(surface);
+                }
+            }
+            FileManagerMode::Browse if self.renaming.is_some() => {
+                if self.input.handle_key(key, modifiers) {
+                    self.input.update_surface(surface);
+                }
+            }
             FileManagerMode::Browse => match key {
+                KeyCode::F2 => self.start_rename(surface),
                 KeyCode::ArrowUp => {
-                    if let Some(ref mut idx) = self.selected_file_index {
-                        if *idx > 0 {
-                            *idx -= 1;
+                    if let Some(idx) = self.selected_file_index {
+                        if idx > 0 {
+                            self.select_only(Some(idx - 1));
                             self.setup_ui(surface);
                         }
                     } else if !self.files.is_empty() {
-                        self.selected_file_index = Some(self.files.len() - 1);
+                        self.select_only(Some(self.files.len() - 1));
                         self.setup_ui(surface);
                     }
                 }
                 KeyCode::ArrowDown => {
-                    if let Some(ref mut idx) = self.selected_file_index {
-                        if *idx < self.files.len() - 1 {
-                            *idx += 1;
+                    if let Some(idx) = self.selected_file_index {
+                        if idx < self.files.len() - 1 {
+                            self.select_only(Some(idx + 1));
                             self.setup_ui(surface);
                         }
                     } else if !self.files.is_empty() {
-                        self.selected_file_index = Some(0);
+                        self.select_only(Some(0));
+                        self.setup_ui(surface);
+                    }
+                }
+                KeyCode::Delete => {
+                    if modifiers.is_shifted() {
+                        // Shift+Delete asks to delete permanently
+                        if !self.selected_files.is_empty() {
+                            self.mode = FileManagerMode::DeleteFile;
+                            self.setup_ui(surface);
+                        }
+                    } else if self.selected_files.len() > 1 {
+                        // Several files are only deleted after asking
+                        self.mode = FileManagerMode::DeleteFile;
                         self.setup_ui(surface);
+                    } else {
+                        self.delete_selected_files(false, surface);
                     }
                 }
+                KeyCode::Backspace => self.go_up(surface),
                 KeyCode::Return => {
                     if let Some(idx) = self.selected_file_index {
                         if let Some(file) = self.files.get(idx).cloned() {
-                            self.mode = FileManagerMode::ViewFile(file);
-                            self.setup_ui(surface);
+                            self.view_file(file, surface);
                         }
                     }
                 }
@@ -1060,8 +2953,253 @@ impl FileManager {
         }
     }
 
-    pub fn render(&mut self, _surface: &mut Surface) {
-        // The UI is already set up, just make sure it's current
-        // This could be extended to handle dynamic updates
+    /// Whether [`FileManager::render`] has to run every frame, for the caret blink or a
+    /// bulk operation
+    pub fn is_animating(&self) -> bool {
+        self.input.is_blinking() || self.operation.is_some()
+    }
+}
+
+/// Push onto a history stack, dropping the oldest entry when it's full
+fn push_bounded(history: &mut Vec<Vec<PathComponent>>, path: Vec<PathComponent>) {
+    if history.len() == MAX_HISTORY {
+        history.remove(0);
+    }
+    history.push(path);
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    window_manager.add_window(Window::new(
+        120,
+        80,
+        600,
+        400,
+        4,
+        Box::new(FileManager::new()),
+    ));
+}
+
+impl Application for FileManager {
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn background_color(&self) -> Color {
+        Color::new(240, 240, 240)
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        self.setup_ui(surface);
+    }
+
+    fn render(&mut self, surface: &mut Surface) {
+        self.input.blink(surface);
+
+        if self.operation.is_some() {
+            self.step_operation(surface);
+            return;
+        }
+
+        // The UI is already set up, only a list that couldn't be read yet needs work
+        if !self.refresh_pending {
+            return;
+        }
+
+        if matches!(self.mode, FileManagerMode::Trash) {
+            self.refresh_pending = false;
+            self.refresh_trash_list();
+        } else {
+            self.refresh_file_list();
+        }
+
+        if !self.refresh_pending {
+            self.setup_ui(surface);
+        }
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.is_refresh_pending() || self.is_animating()
+    }
+
+    fn copy(&self) -> Option<Clip> {
+        FileManager::copy(self)
+    }
+
+    fn paste(&mut self, clip: &Clip, surface: &mut Surface) {
+        FileManager::paste(self, clip, surface);
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        let (_, open_app) = self.handle_mouse_click(x, y, modifiers, surface);
+        self.pending_open = open_app;
+        true
+    }
+
+    /// Starts dragging the file under the cursor or the scrollbar thumb. Returns true if
+    /// the file manager wants the following mouse moves.
+    fn handle_mouse_down(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if !matches!(self.mode, FileManagerMode::Browse) || self.renaming.is_some() {
+            return false;
+        }
+
+        if self.scrollbar.handle_mouse_down(x, y, surface) {
+            self.apply_scroll(surface);
+            return true;
+        }
+
+        let Some(file_index) = self.file_index_at(x, y, surface.width) else {
+            return false;
+        };
+
+        self.drag = Some(FileDrag {
+            file_index,
+            start: (x, y),
+            ghost_idx: None,
+            target_outline_idx: None,
+            target_index: None,
+        });
+        true
+    }
+
+    fn handle_mouse_drag(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.scrollbar.is_dragging() {
+            if self.scrollbar.handle_mouse_drag(y, surface) {
+                self.apply_scroll(surface);
+            }
+            return;
+        }
+
+        let target_index = self
+            .file_index_at(x, y, surface.width)
+            .filter(|&index| self.files[index].is_directory);
+        let dragged = self
+            .drag
+            .as_ref()
+            .map_or(Vec::new(), |drag| self.dragged_indices(drag.file_index));
+
+        let Some(drag) = &mut self.drag else {
+            return;
+        };
+        let Some(file) = self.files.get(drag.file_index) else {
+            return;
+        };
+
+        if drag.ghost_idx.is_none() {
+            if x.abs_diff(drag.start.0) < DRAG_THRESHOLD
+                && y.abs_diff(drag.start.1) < DRAG_THRESHOLD
+            {
+                return;
+            }
+
+            // Added last, so both are drawn over the file list
+            drag.target_outline_idx = Some(surface.add_shape(Shape::Rectangle {
+                x: MARGIN + 2,
+                y: ROWS_Y,
+                width: surface.width - 2 * MARGIN - 4 - SCROLLBAR_WIDTH,
+                height: FILE_ENTRY_HEIGHT - 2,
+                color: DROP_TARGET_COLOR,
+                filled: false,
+                hide: true,
+            }));
+            drag.ghost_idx = Some(surface.add_shape(Shape::Text {
+                x,
+                y,
+                content: if dragged.len() > 1 {
+                    format!("{} files", dragged.len())
+                } else {
+                    file.name.clone()
+                },
+                color: Color::WHITE,
+                background_color: Color::new(90, 90, 90),
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            }));
+        }
+
+        if let Some(ghost_idx) = drag.ghost_idx {
+            surface.move_shape(ghost_idx, x + 12, y + 4);
+        }
+
+        // A folder can't be dropped on itself
+        let target_index = target_index.filter(|index| !dragged.contains(index));
+        if target_index != drag.target_index {
+            drag.target_index = target_index;
+
+            if let Some(outline_idx) = drag.target_outline_idx {
+                match target_index {
+                    Some(index) => {
+                        let row_y = ROWS_Y + (index - self.scroll_offset) * FILE_ENTRY_HEIGHT;
+                        surface.move_shape(outline_idx, MARGIN + 2, row_y);
+                        surface.show_shape(outline_idx);
+                    }
+                    None => {
+                        surface.hide_shape(outline_idx);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drops the dragged file on the highlighted folder, anywhere else cancels the drag
+    fn handle_mouse_up(&mut self, surface: &mut Surface) {
+        self.scrollbar.handle_mouse_up();
+
+        let Some(drag) = self.drag.take() else {
+            return;
+        };
+
+        // Never moved far enough, this was a plain click
+        if drag.ghost_idx.is_none() {
+            return;
+        }
+
+        let names: Vec<String> = self
+            .dragged_indices(drag.file_index)
+            .into_iter()
+            .filter_map(|index| self.files.get(index).map(|file| file.name.clone()))
+            .collect();
+
+        // Several files are only moved after asking, they are the selection
+        let folder = drag
+            .target_index
+            .and_then(|index| self.files.get(index))
+            .cloned();
+        match folder {
+            Some(folder) if names.len() > 1 => self.mode = FileManagerMode::ConfirmMove(folder),
+            Some(folder) => {
+                self.start_operation(BulkAction::Move(folder), names, surface);
+                return;
+            }
+            None => {}
+        }
+
+        // Rebuilding the UI also removes the ghost and the drop target outline
+        self.setup_ui(surface);
+    }
+
+    fn handle_char(&mut self, ch: char, surface: &mut Surface) {
+        self.handle_char_input(ch, surface);
+    }
+
+    fn handle_key(&mut self, key: KeyCode, modifiers: &Modifiers, surface: &mut Surface) {
+        self.handle_key_input(key, modifiers, surface);
+    }
+
+    /// A running bulk operation has to be cancelled first
+    fn can_close(&mut self) -> bool {
+        !matches!(self.mode, FileManagerMode::Working)
+    }
+
+    fn take_action(&mut self) -> Option<Action> {
+        self.pending_open
+            .take()
+            .map(|(file, directory, app)| Action::OpenFile(file, directory, app))
     }
 }
@@ -0,0 +1,310 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+
+use crate::{
+    bmp::{self, Bitmap},
+    desktop::{
+        application::Application,
+        widgets::{
+            progress_bar::{PROGRESS_BAR_HEIGHT, ProgressBar},
+            spinner::{SPINNER_SIZE, Spinner},
+        },
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::Color,
+    fs::{
+        fat32::{FileCursor, FileEntry},
+        manager::{read_file, read_file_async, read_file_chunk},
+    },
+    log,
+    serial::LogLevel,
+    surface::{Shape, Surface},
+    task::executor::{self, JoinHandle},
+};
+
+/// Images larger than this are scaled down to fit
+const MAX_IMAGE_WIDTH: usize = 640;
+const MAX_IMAGE_HEIGHT: usize = 440;
+
+const MIN_WINDOW_WIDTH: usize = 200;
+const MIN_WINDOW_HEIGHT: usize = 60;
+
+const MARGIN: usize = 5;
+const STATUS_HEIGHT: usize = 20;
+const STATUS_BACKGROUND: Color = Color::new(240, 240, 240);
+
+/// Width of the bar while the image loads, less if the window is smaller
+const PROGRESS_WIDTH: usize = 200;
+/// Room for the percentage next to the bar
+const PERCENTAGE_WIDTH: usize = 50;
+/// Space between the spinner and the bar below it
+const SPINNER_GAP: usize = 8;
+
+pub struct ImageViewer {
+    file_name: String,
+    /// The image scaled to fit, or the reason it couldn't be loaded
+    image: Result<Bitmap, &'static str>,
+    /// Size of the original image, only meaningful when the header could be read
+    original_size: (usize, usize),
+    /// Size the image is shown at, known from the header before the pixels are loaded
+    display_size: (usize, usize),
+    /// Set while the file is read in the background
+    pending: Option<JoinHandle<Result<Vec<u8>, &'static str>>>,
+    /// Bytes read by the loading task so far
+    bytes_read: Arc<AtomicUsize>,
+    file_size: usize,
+    progress_bar: ProgressBar,
+    spinner: Spinner,
+    status_idx: usize,
+}
+
+impl ImageViewer {
+    pub fn new(file: FileEntry) -> Self {
+        let mut viewer = Self {
+            file_name: file.name.clone(),
+            image: Err("Not loaded"),
+            original_size: (0, 0),
+            display_size: (0, 0),
+            pending: None,
+            bytes_read: Arc::new(AtomicUsize::new(0)),
+            file_size: file.size as usize,
+            progress_bar: ProgressBar::new(0, 0, PROGRESS_WIDTH),
+            spinner: Spinner::new(0, 0, Color::DARKGRAY, STATUS_BACKGROUND),
+            status_idx: 0,
+        };
+
+        // The header is enough to size the window, the rest is read in the background
+        let mut cursor = FileCursor::new(file.first_cluster, file.size);
+        let header = read_file_chunk(&mut cursor).and_then(|chunk| bmp::dimensions(&chunk));
+
+        match header {
+            Ok(size) => {
+                viewer.original_size = size;
+                viewer.display_size = fitted_size(size.0, size.1);
+
+                let bytes_read = viewer.bytes_read.clone();
+                let (first_cluster, file_size) = (file.first_cluster, file.size);
+
+                let spawned = executor::spawn(async move {
+                    read_file_async(first_cluster, file_size, &bytes_read).await
+                });
+
+                match spawned {
+                    Ok(handle) => viewer.pending = Some(handle),
+                    Err(e) => {
+                        log!(
+                            LogLevel::Warn,
+                            "Image viewer: can't read in the background: {}",
+                            e
+                        );
+                        viewer.set_image(read_file(file.first_cluster, file.size));
+                    }
+                }
+            }
+            Err(error) => viewer.set_image(Err(error)),
+        }
+
+        viewer
+    }
+
+    /// Decode the file once it's loaded
+    fn set_image(&mut self, data: Result<Vec<u8>, &'static str>) {
+        let image = data.and_then(|data| bmp::decode(&data));
+
+        match &image {
+            Ok(image) => self.original_size = (image.width, image.height),
+            Err(error) => log!(
+                LogLevel::Warn,
+                "Failed to open image {}: {}",
+                self.file_name,
+                error
+            ),
+        }
+
+        self.image = image.map(scale_to_fit);
+        self.display_size = self
+            .image
+            .as_ref()
+            .map_or((0, 0), |image| (image.width, image.height));
+    }
+
+    /// Size of the viewer content, used to size its window
+    pub fn size(&self) -> (usize, usize) {
+        let (width, height) = self.display_size;
+
+        (
+            (width + 2 * MARGIN).max(MIN_WINDOW_WIDTH),
+            (height + 2 * MARGIN + STATUS_HEIGHT).max(MIN_WINDOW_HEIGHT),
+        )
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn status_text(&self) -> String {
+        if self.is_loading() {
+            return format!("Loading {}...", self.file_name);
+        }
+
+        match &self.image {
+            Ok(image) if (image.width, image.height) == self.original_size => {
+                format!("{} ({}x{})", self.file_name, image.width, image.height)
+            }
+            Ok(_) => format!(
+                "{} ({}x{}, scaled to fit)",
+                self.file_name, self.original_size.0, self.original_size.1
+            ),
+            Err(error) => format!("Can't open {}: {}", self.file_name, error),
+        }
+    }
+
+    fn show_image(&mut self, surface: &mut Surface) {
+        self.progress_bar.hide(surface);
+        self.spinner.hide(surface);
+
+        if let Ok(image) = &mut self.image {
+            // The surface keeps its own copy, we don't need the pixels anymore
+            surface.add_shape(Shape::Image {
+                x: MARGIN,
+                y: MARGIN,
+                width: image.width,
+                height: image.height,
+                pixels: core::mem::take(&mut image.pixels),
+                hide: false,
+            });
+        }
+
+        // The old text has to be cleared when the new one is shorter
+        if let Some(bounds) = surface.get_shape_bounds(self.status_idx) {
+            surface.mark_region_dirty(bounds);
+        }
+        surface.update_text_content(self.status_idx, self.status_text(), None);
+    }
+}
+
+/// Largest size that fits in the viewer while keeping the aspect ratio
+fn fitted_size(width: usize, height: usize) -> (usize, usize) {
+    if width <= MAX_IMAGE_WIDTH && height <= MAX_IMAGE_HEIGHT {
+        return (width, height);
+    }
+
+    // Compare the ratios without floats
+    if width * MAX_IMAGE_HEIGHT > height * MAX_IMAGE_WIDTH {
+        (MAX_IMAGE_WIDTH, (height * MAX_IMAGE_WIDTH / width).max(1))
+    } else {
+        ((width * MAX_IMAGE_HEIGHT / height).max(1), MAX_IMAGE_HEIGHT)
+    }
+}
+
+/// Nearest neighbour downscale so the image fits in the maximum viewer size
+fn scale_to_fit(image: Bitmap) -> Bitmap {
+    if image.width <= MAX_IMAGE_WIDTH && image.height <= MAX_IMAGE_HEIGHT {
+        return image;
+    }
+
+    let (width, height) = fitted_size(image.width, image.height);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let source_row = (y * image.height / height) * image.width;
+        for x in 0..width {
+            pixels.push(image.pixels[source_row + x * image.width / width]);
+        }
+    }
+
+    Bitmap {
+        width,
+        height,
+        pixels,
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager, file: FileEntry) {
+    let viewer = ImageViewer::new(file);
+    let (width, height) = viewer.size();
+    window_manager.add_window(Window::new(80, 60, width, height, 8, Box::new(viewer)));
+}
+
+impl Application for ImageViewer {
+    fn title(&self) -> String {
+        "Image Viewer".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        Color::new(240, 240, 240)
+    }
+
+    fn content_sized(&self) -> bool {
+        true
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        self.status_idx = surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: surface.height - STATUS_HEIGHT,
+            content: self.status_text(),
+            color: Color::BLACK,
+            background_color: STATUS_BACKGROUND,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        if self.is_loading() {
+            // Centered in the space the image will take, with room for the percentage
+            let area_width = surface.width - 2 * MARGIN;
+            let area_height = surface.height - 2 * MARGIN - STATUS_HEIGHT;
+            let width = PROGRESS_WIDTH.min(area_width.saturating_sub(PERCENTAGE_WIDTH));
+
+            self.progress_bar = ProgressBar::new(
+                MARGIN + area_width.saturating_sub(width + PERCENTAGE_WIDTH) / 2,
+                MARGIN + area_height.saturating_sub(PROGRESS_BAR_HEIGHT) / 2,
+                width,
+            )
+            .with_percentage(STATUS_BACKGROUND);
+            self.progress_bar.add_to_surface(surface);
+
+            // Keeps turning when a slow disk holds the bar still
+            let bar = self.progress_bar.rect;
+            self.spinner = Spinner::new(
+                MARGIN + area_width.saturating_sub(SPINNER_SIZE) / 2,
+                bar.y.saturating_sub(SPINNER_SIZE + SPINNER_GAP),
+                Color::DARKGRAY,
+                STATUS_BACKGROUND,
+            );
+            self.spinner.add_to_surface(surface);
+        } else {
+            self.show_image(surface);
+        }
+    }
+
+    fn render(&mut self, surface: &mut Surface) {
+        // The image doesn't change after it was loaded
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let Some(data) = pending.try_take() else {
+            let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+            self.progress_bar
+                .set_progress(bytes_read, self.file_size, surface);
+            self.spinner.update(surface);
+            return;
+        };
+
+        self.pending = None;
+        self.set_image(data);
+        self.show_image(surface);
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.is_loading()
+    }
+}
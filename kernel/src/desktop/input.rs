@@ -1,50 +1,220 @@
 use crate::framebuffer::SCREEN_SIZE;
-use crate::print;
+use crate::serial::LogLevel;
+
+use core::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
 
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
+use pc_keyboard::DecodedKey;
 use ps2_mouse::MouseState;
 
 pub static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
-pub static STATE_QUEUE: OnceCell<ArrayQueue<MouseState>> = OnceCell::uninit();
+pub static STATE_QUEUE: OnceCell<ArrayQueue<MouseEvent>> = OnceCell::uninit();
 pub static CLICK_QUEUE: OnceCell<ArrayQueue<(i16, i16)>> = OnceCell::uninit();
+pub static RIGHT_CLICK_QUEUE: OnceCell<ArrayQueue<(i16, i16)>> = OnceCell::uninit();
+/// Keys that don't come from the PS/2 keyboard, like the on-screen keyboard
+pub static SYNTHETIC_KEY_QUEUE: OnceCell<ArrayQueue<DecodedKey>> = OnceCell::uninit();
+
+/// Two clicks on the same thing within this many timer ticks are a double click
+pub const DOUBLE_CLICK_TICKS: u64 = crate::interrupts::ms_to_ticks(500);
+
+/// A packet from the mouse. PS/2 mice report how far they moved, the vmmouse of a VM
+/// reports where the pointer is.
+#[derive(Debug, Clone, Copy)]
+pub enum MouseEvent {
+    Relative(MouseState),
+    /// Both axes go from 0 to 0xFFFF across the screen
+    Absolute {
+        x: u16,
+        y: u16,
+        left_button_down: bool,
+        right_button_down: bool,
+    },
+}
+
+pub const SCANCODE_QUEUE_SIZE: usize = 256;
+/// The mouse sends a packet per movement, so this fills fastest while the desktop is busy
+pub const STATE_QUEUE_SIZE: usize = 512;
+/// Clicks are what the user actually meant to do, keep plenty of them
+pub const CLICK_QUEUE_SIZE: usize = 64;
+pub const SYNTHETIC_KEY_QUEUE_SIZE: usize = 32;
+
+/// Events thrown away because their queue was full. Bumping a counter is all the drop
+/// path does, since it runs in interrupt handlers where printing could deadlock.
+pub static DROPPED_SCANCODES: AtomicU32 = AtomicU32::new(0);
+pub static DROPPED_MOUSE_STATES: AtomicU32 = AtomicU32::new(0);
+pub static DROPPED_CLICKS: AtomicU32 = AtomicU32::new(0);
+pub static DROPPED_SYNTHETIC_KEYS: AtomicU32 = AtomicU32::new(0);
+
+/// Dropped events per queue, see [`DroppedInput::get`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DroppedInput {
+    pub scancodes: u32,
+    pub mouse_states: u32,
+    /// Left and right clicks together
+    pub clicks: u32,
+    pub synthetic_keys: u32,
+}
+
+impl DroppedInput {
+    pub fn get() -> Self {
+        Self {
+            scancodes: DROPPED_SCANCODES.load(Ordering::Relaxed),
+            mouse_states: DROPPED_MOUSE_STATES.load(Ordering::Relaxed),
+            clicks: DROPPED_CLICKS.load(Ordering::Relaxed),
+            synthetic_keys: DROPPED_SYNTHETIC_KEYS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Sub-pixel steps per pixel, so slow speeds don't lose small movements
+const SUBPIXELS: i32 = 8;
+/// Mouse counts per packet before acceleration starts
+const ACCELERATION_THRESHOLD: i32 = 4;
+
+/// Pointer speed presets, the desktop can switch between them at any time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MouseSpeed {
+    Slow = 0,
+    Normal,
+    Fast,
+    Faster,
+}
+
+impl MouseSpeed {
+    pub const ALL: [MouseSpeed; 4] = [
+        MouseSpeed::Slow,
+        MouseSpeed::Normal,
+        MouseSpeed::Fast,
+        MouseSpeed::Faster,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MouseSpeed::Slow => "Slow",
+            MouseSpeed::Normal => "Normal",
+            MouseSpeed::Fast => "Fast",
+            MouseSpeed::Faster => "Faster",
+        }
+    }
+
+    /// Sub-pixels moved per mouse count
+    fn sensitivity(self) -> i32 {
+        match self {
+            MouseSpeed::Slow => SUBPIXELS / 2,
+            MouseSpeed::Normal => SUBPIXELS,
+            MouseSpeed::Fast => SUBPIXELS * 3 / 2,
+            MouseSpeed::Faster => SUBPIXELS * 2,
+        }
+    }
+
+    /// Turn a raw delta into pixels. Counts above the acceleration threshold move half
+    /// as far again, so fast movements travel farther. The fraction of a pixel that is
+    /// left over is kept in `remainder` for the next packet.
+    pub fn scale(self, delta: i16, remainder: &mut i32) -> i16 {
+        let delta = delta as i32;
+        let excess = (delta.abs() - ACCELERATION_THRESHOLD).max(0) * delta.signum();
+        let subpixels = (delta * 2 + excess) * self.sensitivity() / 2 + *remainder;
+
+        *remainder = subpixels % SUBPIXELS;
+        (subpixels / SUBPIXELS).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+static MOUSE_SPEED: AtomicU8 = AtomicU8::new(MouseSpeed::Normal as u8);
+
+pub fn mouse_speed() -> MouseSpeed {
+    MouseSpeed::ALL[MOUSE_SPEED.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_mouse_speed(speed: MouseSpeed) {
+    MOUSE_SPEED.store(speed as u8, Ordering::Relaxed);
+}
+
+/// Switch to the next faster preset, wrapping around to the slowest one
+pub fn cycle_mouse_speed() -> MouseSpeed {
+    let next = MouseSpeed::ALL[(mouse_speed() as usize + 1) % MouseSpeed::ALL.len()];
+    set_mouse_speed(next);
+    next
+}
+
+/// Tick of the last key or mouse event the desktop handled
+static LAST_INPUT_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// Reset the idle time. Called where the desktop handles an event, not in the interrupt
+/// handlers, so input that is queued but never looked at doesn't count.
+pub fn note_input() {
+    LAST_INPUT_TICK.store(crate::interrupts::ticks(), Ordering::Relaxed);
+}
+
+pub fn last_input_tick() -> u64 {
+    LAST_INPUT_TICK.load(Ordering::Relaxed)
+}
+
+/// Timer ticks since the last key or mouse event
+pub fn idle_ticks() -> u64 {
+    crate::interrupts::ticks().saturating_sub(last_input_tick())
+}
 
 pub fn add_scancode(scancode: u8) {
     if let Some(queue) = SCANCODE_QUEUE.get() {
         if queue.push(scancode).is_err() {
-            print!("Scancode queue is full, dropping scancode: {}", scancode);
+            DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
         }
     } else {
-        print!(
+        crate::log!(
+            LogLevel::Warn,
             "Scancode queue not initialized, cannot add scancode: {}",
             scancode
         );
     }
 }
 
-pub fn add_mouse_state(state: MouseState) {
+pub fn add_mouse_state(state: MouseEvent) {
     if let Some(queue) = STATE_QUEUE.get() {
         if queue.push(state).is_err() {
-            print!("Mouse state queue is full, dropping state: {:?}", state);
+            DROPPED_MOUSE_STATES.fetch_add(1, Ordering::Relaxed);
         }
     } else {
-        print!(
+        crate::log!(
+            LogLevel::Warn,
             "Mouse state queue not initialized, cannot add state: {:?}",
             state
         );
     }
 }
 
+pub fn add_synthetic_key(key: DecodedKey) {
+    if let Some(queue) = SYNTHETIC_KEY_QUEUE.get() {
+        if queue.push(key).is_err() {
+            DROPPED_SYNTHETIC_KEYS.fetch_add(1, Ordering::Relaxed);
+        }
+    } else {
+        crate::log!(
+            LogLevel::Warn,
+            "Synthetic key queue not initialized, cannot add key: {:?}",
+            key
+        );
+    }
+}
+
 pub fn init_queues() {
     SCANCODE_QUEUE
-        .try_init_once(|| ArrayQueue::new(100))
+        .try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE))
         .expect("Scancode queue should only be initialized once");
     STATE_QUEUE
-        .try_init_once(|| ArrayQueue::new(100))
+        .try_init_once(|| ArrayQueue::new(STATE_QUEUE_SIZE))
         .expect("Mouse state queue should only be initialized once");
     CLICK_QUEUE
-        .try_init_once(|| ArrayQueue::new(20))
+        .try_init_once(|| ArrayQueue::new(CLICK_QUEUE_SIZE))
         .expect("Click queue should only be initialized once");
+    RIGHT_CLICK_QUEUE
+        .try_init_once(|| ArrayQueue::new(CLICK_QUEUE_SIZE))
+        .expect("Right click queue should only be initialized once");
+    SYNTHETIC_KEY_QUEUE
+        .try_init_once(|| ArrayQueue::new(SYNTHETIC_KEY_QUEUE_SIZE))
+        .expect("Synthetic key queue should only be initialized once");
 }
 
 pub struct CurrentMouseState {
@@ -61,6 +231,9 @@ pub struct CurrentMouseState {
 
     pub has_moved: bool,
     _screen_size: (u16, u16),
+    /// Movement smaller than a pixel, carried over to the next update
+    subpixel_x: i32,
+    subpixel_y: i32,
 }
 
 impl CurrentMouseState {
@@ -77,42 +250,99 @@ impl CurrentMouseState {
             right_button_down: false,
             has_moved: true, // Ensure the cursor is drawn initially
             _screen_size: screen_size,
+            subpixel_x: 0,
+            subpixel_y: 0,
         }
     }
 
-    pub fn update(&mut self, state: MouseState) {
+    pub fn update(&mut self, event: MouseEvent) {
         self.prev_x = self.x;
         self.prev_y = self.y;
         self.prev_left_button_down = self.left_button_down;
         self.prev_right_button_down = self.right_button_down;
 
-        self.x += state.get_x();
-        self.y -= state.get_y();
+        match event {
+            MouseEvent::Relative(state) => {
+                let speed = mouse_speed();
+                self.x = self
+                    .x
+                    .saturating_add(speed.scale(state.get_x(), &mut self.subpixel_x));
+                self.y = self
+                    .y
+                    .saturating_sub(speed.scale(state.get_y(), &mut self.subpixel_y));
+
+                self.left_button_down = state.left_button_down();
+                self.right_button_down = state.right_button_down();
+            }
+            MouseEvent::Absolute {
+                x,
+                y,
+                left_button_down,
+                right_button_down,
+            } => {
+                // Scale to the screen, the speed setting doesn't apply to a pointer position
+                self.x = (x as i32 * (self._screen_size.0 as i32 - 1) / 0xFFFF) as i16;
+                self.y = (y as i32 * (self._screen_size.1 as i32 - 1) / 0xFFFF) as i16;
+
+                self.left_button_down = left_button_down;
+                self.right_button_down = right_button_down;
+            }
+        }
 
         // Make sure the mouse cursor stays within the screen boundaries
         self.x = self.x.clamp(0, self._screen_size.0 as i16 - 1);
         self.y = self.y.clamp(0, self._screen_size.1 as i16 - 1);
 
-        self.left_button_down = state.left_button_down();
-        self.right_button_down = state.right_button_down();
-
         self.has_moved = self.x != self.prev_x || self.y != self.prev_y; // TODO: fix this
 
         // Detect click: mouse down, no moving, mouse up
         if self.prev_left_button_down && !self.left_button_down && !self.has_moved {
             if let Some(queue) = CLICK_QUEUE.get() {
                 if queue.push((self.x, self.y)).is_err() {
-                    print!(
-                        "Click queue is full, dropping click at: ({}, {})",
-                        self.x, self.y
-                    );
+                    DROPPED_CLICKS.fetch_add(1, Ordering::Relaxed);
                 }
             } else {
-                print!(
+                crate::log!(
+                    LogLevel::Warn,
                     "Click queue not initialized, cannot add click at: ({}, {})",
-                    self.x, self.y
+                    self.x,
+                    self.y
+                );
+            }
+        }
+
+        if self.prev_right_button_down && !self.right_button_down && !self.has_moved {
+            if let Some(queue) = RIGHT_CLICK_QUEUE.get() {
+                if queue.push((self.x, self.y)).is_err() {
+                    DROPPED_CLICKS.fetch_add(1, Ordering::Relaxed);
+                }
+            } else {
+                crate::log!(
+                    LogLevel::Warn,
+                    "Right click queue not initialized, cannot add click at: ({}, {})",
+                    self.x,
+                    self.y
                 );
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn mouse_speed_keeps_subpixels_and_accelerates() {
+        let mut remainder = 0;
+        let moved: i16 = (0..4)
+            .map(|_| MouseSpeed::Slow.scale(1, &mut remainder))
+            .sum();
+        assert_eq!(moved, 2);
+
+        let mut remainder = 0;
+        assert_eq!(MouseSpeed::Normal.scale(3, &mut remainder), 3);
+        assert_eq!(MouseSpeed::Normal.scale(-10, &mut remainder), -13);
+        assert_eq!(remainder, 0);
+    }
+}
@@ -0,0 +1,326 @@
+//! A screen over the desktop that takes all input until the password is typed.
+//!
+//! It locks after some minutes without input, or with Ctrl+Alt+L. The settings are loaded
+//! from `LOCK.CFG` in the root directory when the desktop starts:
+//!
+//! ```text
+//! # Minutes without input before the screen locks, 0 to only lock with Ctrl+Alt+L
+//! timeout = 10
+//! # Hash of the password, the serial command `lockhash <password>` prints it
+//! password = 9e3f0a1b2c3d4e5f
+//! ```
+//!
+//! Without a valid password the screen never locks. The hash only keeps the password from
+//! being read off the disk at a glance, it is not meant to stop anyone.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::DecodedKey;
+use spin::Mutex;
+
+use crate::{
+    desktop::config::{entries, read_root_config},
+    framebuffer::{Color, FrameBufferWriter},
+    interrupts::{ms_to_ticks, uptime_ms},
+    log,
+    serial::LogLevel,
+    surface::{Rect, Shape, Surface},
+};
+
+pub const CONFIG_FILENAME: &str = "LOCK.CFG";
+
+/// Keys are ignored this long after a wrong password
+const WRONG_PASSWORD_DELAY_MS: u64 = 1500;
+/// Time each step of the shake after a wrong password takes
+const SHAKE_STEP_MS: u64 = 40;
+/// How far the password panel moves to the side in each step of the shake
+const SHAKE_OFFSETS: [isize; 8] = [-12, 12, -9, 9, -6, 6, -3, 3];
+const MAX_PASSWORD_LENGTH: usize = 32;
+
+/// Mixed into the hash, so it isn't the plain hash of the password
+const SALT: &[u8] = b"goofy-os lock:";
+
+const BACKGROUND_COLOR: Color = Color::new(20, 30, 45);
+const PANEL_COLOR: Color = Color::new(235, 235, 235);
+const PANEL_WIDTH: usize = 320;
+const PANEL_HEIGHT: usize = 110;
+
+static CONFIG: Mutex<Option<LockConfig>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LockConfig {
+    /// 0 locks only on demand
+    timeout_minutes: u64,
+    password_hash: u64,
+}
+
+/// FNV-1a of the password with [`SALT`] in front
+pub fn hash_password(password: &str) -> u64 {
+    SALT.iter()
+        .chain(password.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Load the settings, the screen doesn't lock without a file or a password in it
+pub fn init() {
+    let Some(text) = read_root_config(CONFIG_FILENAME) else {
+        return;
+    };
+
+    let config = parse(&text);
+    if config.is_none() {
+        log!(LogLevel::Warn, "Lock screen: No valid password in config");
+    }
+    *CONFIG.lock() = config;
+}
+
+/// The settings of a config file, `None` without a password hash
+fn parse(text: &str) -> Option<LockConfig> {
+    let mut timeout_minutes = 0;
+    let mut password_hash = None;
+
+    for (key, value) in entries(text) {
+        match key {
+            "timeout" => {
+                if let Ok(minutes) = value.parse() {
+                    timeout_minutes = minutes;
+                }
+            }
+            "password" => {
+                if let Ok(hash) = u64::from_str_radix(value, 16) {
+                    password_hash = Some(hash);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    password_hash.map(|password_hash| LockConfig {
+        timeout_minutes,
+        password_hash,
+    })
+}
+
+/// Whether there is a password to unlock with
+pub fn is_enabled() -> bool {
+    CONFIG.lock().is_some()
+}
+
+/// Whether the screen should lock after `idle_ticks` without input
+pub fn is_idle_timeout(idle_ticks: u64) -> bool {
+    match *CONFIG.lock() {
+        Some(config) if config.timeout_minutes > 0 => {
+            idle_ticks >= ms_to_ticks(config.timeout_minutes * 60 * 1000)
+        }
+        _ => false,
+    }
+}
+
+/// How far the panel is moved `elapsed_ms` after a wrong password
+fn shake_offset(elapsed_ms: u64) -> isize {
+    SHAKE_OFFSETS
+        .get((elapsed_ms / SHAKE_STEP_MS) as usize)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Covers the whole screen with a password field in the middle
+pub struct LockScreen {
+    background: Surface,
+    panel: Surface,
+    /// Where the panel is when it doesn't shake
+    panel_x: usize,
+    panel_y: usize,
+    /// Where the panel was drawn last
+    drawn_x: usize,
+    input: String,
+    /// Uptime in milliseconds of the last wrong password
+    failed_at: Option<u64>,
+
+    field_idx: usize,
+    message_idx: usize,
+}
+
+impl LockScreen {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut background = Surface::new(width, height, BACKGROUND_COLOR);
+        let panel_x = width.saturating_sub(PANEL_WIDTH) / 2;
+        let panel_y = height.saturating_sub(PANEL_HEIGHT) / 2;
+        background.add_shape(Shape::Text {
+            x: panel_x,
+            y: panel_y.saturating_sub(40),
+            content: "Locked".to_string(),
+            color: Color::WHITE,
+            background_color: BACKGROUND_COLOR,
+            font_size: RasterHeight::Size24,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        let mut panel = Surface::new(PANEL_WIDTH, PANEL_HEIGHT, PANEL_COLOR);
+        panel.add_shape(Shape::Text {
+            x: 15,
+            y: 12,
+            content: "Password".to_string(),
+            color: Color::BLACK,
+            background_color: PANEL_COLOR,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+        panel.add_shape(Shape::Rectangle {
+            x: 15,
+            y: 35,
+            width: PANEL_WIDTH - 30,
+            height: 28,
+            color: Color::WHITE,
+            filled: true,
+            hide: false,
+        });
+        panel.add_shape(Shape::Rectangle {
+            x: 15,
+            y: 35,
+            width: PANEL_WIDTH - 30,
+            height: 28,
+            color: Color::DARKGRAY,
+            filled: false,
+            hide: false,
+        });
+        let field_idx = panel.add_shape(Shape::Text {
+            x: 22,
+            y: 41,
+            content: String::new(),
+            color: Color::BLACK,
+            background_color: Color::WHITE,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+        let message_idx = panel.add_shape(Shape::Text {
+            x: 15,
+            y: 76,
+            content: "Press Enter to unlock".to_string(),
+            color: Color::DARKGRAY,
+            background_color: PANEL_COLOR,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        Self {
+            background,
+            panel,
+            panel_x,
+            panel_y,
+            drawn_x: panel_x,
+            input: String::new(),
+            failed_at: None,
+            field_idx,
+            message_idx,
+        }
+    }
+
+    /// Take a key, returns true once the right password was entered
+    pub fn handle_key(&mut self, key: DecodedKey) -> bool {
+        // Typing is ignored for a moment after a wrong password, to slow down guessing
+        if self
+            .failed_at
+            .is_some_and(|failed_at| uptime_ms() < failed_at + WRONG_PASSWORD_DELAY_MS)
+        {
+            return false;
+        }
+
+        match key {
+            DecodedKey::Unicode('\n') => return self.submit(),
+            DecodedKey::Unicode('\u{8}') => {
+                self.input.pop();
+            }
+            DecodedKey::Unicode('\u{1b}') => self.input.clear(),
+            DecodedKey::Unicode(c) if !c.is_control() && self.input.len() < MAX_PASSWORD_LENGTH => {
+                self.input.push(c);
+            }
+            _ => return false,
+        }
+
+        let stars = "*".repeat(self.input.chars().count());
+        self.panel.update_text_content(self.field_idx, stars, None);
+        false
+    }
+
+    fn submit(&mut self) -> bool {
+        let unlocked = CONFIG
+            .lock()
+            .is_none_or(|config| hash_password(&self.input) == config.password_hash);
+        self.input.clear();
+        if unlocked {
+            return true;
+        }
+
+        log!(LogLevel::Warn, "Lock screen: Wrong password");
+        self.failed_at = Some(uptime_ms());
+        self.panel
+            .update_text_content(self.field_idx, String::new(), None);
+        self.panel
+            .update_text_color(self.message_idx, Color::new(170, 40, 40));
+        self.panel
+            .update_text_content(self.message_idx, "Wrong password".to_string(), None);
+        false
+    }
+
+    /// Draw the lock screen over everything, moving the panel while it shakes
+    pub fn render(&mut self, framebuffer: &mut FrameBufferWriter, damage: &mut Vec<Rect>) {
+        let elapsed_ms = self
+            .failed_at
+            .map_or(u64::MAX, |failed_at| uptime_ms().saturating_sub(failed_at));
+        let x = self.panel_x.saturating_add_signed(shake_offset(elapsed_ms));
+
+        if x != self.drawn_x {
+            self.background.mark_region_exposed(Rect::new(
+                self.drawn_x,
+                self.panel_y,
+                PANEL_WIDTH,
+                PANEL_HEIGHT,
+            ));
+            self.panel
+                .mark_region_exposed(Rect::new(0, 0, PANEL_WIDTH, PANEL_HEIGHT));
+            self.drawn_x = x;
+        }
+
+        self.background.render(framebuffer, 0, 0, damage);
+        self.panel.render(framebuffer, x, self.panel_y, damage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_parse_config() {
+        let hash = hash_password("hunter2");
+        assert_ne!(hash, hash_password("hunter3"));
+
+        let text = alloc::format!("# lock\ntimeout = 5\npassword = {:x}\n", hash);
+        assert_eq!(
+            parse(&text),
+            Some(LockConfig {
+                timeout_minutes: 5,
+                password_hash: hash,
+            })
+        );
+        assert_eq!(parse("timeout = 5\npassword = secret\n"), None);
+    }
+
+    #[test_case]
+    fn test_shake_settles() {
+        assert_eq!(shake_offset(0), SHAKE_OFFSETS[0]);
+        assert_eq!(shake_offset(SHAKE_STEP_MS), SHAKE_OFFSETS[1]);
+        assert_eq!(shake_offset(SHAKE_STEP_MS * SHAKE_OFFSETS.len() as u64), 0);
+        assert_eq!(shake_offset(u64::MAX), 0);
+    }
+}
@@ -1,38 +1,352 @@
 use crate::{
+    crash,
     desktop::{
-        input::{CLICK_QUEUE, CurrentMouseState, SCANCODE_QUEUE, STATE_QUEUE, init_queues},
-        window_manager::{
-            WindowManager, launch_calculator, launch_filemanager, launch_notepad, launch_sysinfo,
+        animation, autosave, calculator, dmesg, file_sort, filemanager,
+        input::{
+            CLICK_QUEUE, CurrentMouseState, RIGHT_CLICK_QUEUE, SCANCODE_QUEUE, STATE_QUEUE,
+            SYNTHETIC_KEY_QUEUE, idle_ticks, init_queues, note_input,
         },
+        lock_screen::{self, LockScreen},
+        memory_pressure, message_box, minesweeper, notepad, onscreen_keyboard, paint, recent,
+        repaint_debug::{self, DamageOutlines},
+        sysinfo, taskbar,
+        toast::{self, ToastKind},
+        window_manager::WindowManager,
+        window_positions, workspaces,
     },
-    framebuffer::{self, Color, FrameBufferWriter, SCREEN_SIZE},
-    serial_println,
+    framebuffer::{self, Color, FrameBufferWriter, SCREEN_SIZE, measure_text},
+    fs::manager::{FILESYSTEM_BUSY, init_filesystem, is_mounted, try_sync},
+    icons::Icon,
+    interrupts::{self, ms_to_ticks},
+    keyboard_leds::{self, LockKeys},
+    log, log_sink,
+    serial::LogLevel,
+    serial_console,
     surface::{Rect, Shape, Surface},
+    task::executor::{self, Executor},
     time::get_utc_time,
 };
-use alloc::{format, string::ToString, vec::Vec};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
-use pc_keyboard::{DecodedKey, HandleControl, Keyboard, ScancodeSet1, layouts};
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode, Keyboard, ScancodeSet1, layouts};
 
-use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::interrupts::{self as cpu_interrupts, without_interrupts};
 
-const TASKBAR_HEIGHT: usize = 50;
 const TASKBAR_COLOR: Color = Color::new(175, 175, 175);
 
+const START_MENU_ENTRY_HEIGHT: usize = 45;
+/// Background of the start menu entry picked with the arrow keys
+const START_MENU_HIGHLIGHT_COLOR: Color = Color::new(150, 200, 255);
+/// Name, icon, launcher and whether the app needs the filesystem
+const START_MENU_APPS: [(&str, Icon, fn(&mut WindowManager), bool); 8] = [
+    ("Calculator", Icon::Calculator, calculator::launch, false),
+    ("Notepad", Icon::TextFile, notepad::launch, true),
+    ("File Manager", Icon::Folder, filemanager::launch, true),
+    ("System Info", Icon::SysInfo, sysinfo::launch, false),
+    ("Keyboard", Icon::Keyboard, onscreen_keyboard::launch, false),
+    ("Paint", Icon::Paint, paint::launch, false),
+    ("Minesweeper", Icon::Minesweeper, minesweeper::launch, false),
+    ("Kernel Log", Icon::Log, dmesg::launch, false),
+];
+/// Start menu labels of apps that can't run without a disk
+const DISABLED_LABEL_COLOR: Color = Color::new(110, 110, 110);
+const NO_DISK_TEXT: &str = "No disk - click to retry";
+/// Lines of a crash report shown at boot, the rest is in the crash log
+const CRASH_REPORT_LINES: usize = 12;
+/// How often the drive's write cache is flushed, a reset loses at most this much
+const SYNC_INTERVAL_MS: u64 = 5000;
+
+struct StartMenuEntry<'a> {
+    /// The separator below the entry, the outline for the placeholder
+    border_idx: usize,
+    /// The label, the background for the placeholder
+    label_idx: usize,
+    bounds: Rect,
+    label: &'a str,
+}
+
+/// The start menu entry picked with the arrow keys, Enter launches it
+struct StartMenuHighlight {
+    /// Drawn below the icon and label of the entry
+    background_idx: usize,
+    /// Index in [`START_MENU_APPS`]
+    app: Option<usize>,
+}
+
+impl StartMenuHighlight {
+    fn set(
+        &mut self,
+        desktop: &mut Surface,
+        entries: &[StartMenuEntry],
+        icons: &[usize],
+        app: Option<usize>,
+    ) {
+        // The first entry is the menu background
+        if let Some(old) = self.app.take() {
+            paint_start_menu_entry(desktop, &entries[old + 1], icons[old], old, TASKBAR_COLOR);
+        }
+
+        match app {
+            Some(app) => {
+                let entry = &entries[app + 1];
+                paint_start_menu_entry(desktop, entry, icons[app], app, START_MENU_HIGHLIGHT_COLOR);
+                desktop.move_shape(self.background_idx, entry.bounds.x, entry.bounds.y);
+                desktop.show_shape(self.background_idx);
+            }
+            None => {
+                desktop.hide_shape(self.background_idx);
+            }
+        }
+        self.app = app;
+    }
+
+    /// Move the highlight by `step` entries, wrapping around, the first key picks the first entry
+    fn step(
+        &mut self,
+        desktop: &mut Surface,
+        entries: &[StartMenuEntry],
+        icons: &[usize],
+        step: isize,
+    ) {
+        let count = START_MENU_APPS.len() as isize;
+        let app = match self.app {
+            Some(app) => (app as isize + step).rem_euclid(count) as usize,
+            None => 0,
+        };
+        self.set(desktop, entries, icons, Some(app));
+    }
+}
+
+/// Give the label and icon of an app's entry another background
+fn paint_start_menu_entry(
+    desktop: &mut Surface,
+    entry: &StartMenuEntry,
+    icon_idx: usize,
+    app: usize,
+    color: Color,
+) {
+    desktop.update_text_background(entry.label_idx, color);
+
+    let (_, icon, _, _) = START_MENU_APPS[app];
+    if let Some(pixels) = desktop.image_pixels_mut(icon_idx) {
+        pixels.copy_from_slice(&icon.bitmap(color).pixels);
+    }
+    if let Some(bounds) = desktop.get_shape_bounds(icon_idx) {
+        desktop.mark_region_dirty(bounds);
+    }
+}
+
+const RECENT_MENU_WIDTH: usize = 200;
+const RECENT_ROW_HEIGHT: usize = 24;
+/// Space above the first row, for the heading
+const RECENT_HEADING_HEIGHT: usize = 34;
+
+/// The recent files, in a panel right of the start menu
+struct RecentMenu {
+    bounds: Rect,
+    /// Border, background and heading, shown together with the rows
+    frame: Vec<usize>,
+    /// Label of every row, and the path it opens while the menu is open
+    rows: Vec<(usize, Option<String>)>,
+}
+
+impl RecentMenu {
+    fn new(desktop: &mut Surface, x: usize, top: usize, height: usize) -> Self {
+        let frame = Vec::from([
+            desktop.add_shape(Shape::Rectangle {
+                x: x - 1,
+                y: top - 2,
+                width: RECENT_MENU_WIDTH + 1,
+                height: height + 2,
+                color: Color::BLACK,
+                filled: false,
+                hide: true,
+            }),
+            desktop.add_shape(Shape::Rectangle {
+                x,
+                y: top - 1,
+                width: RECENT_MENU_WIDTH - 1,
+                height,
+                color: TASKBAR_COLOR,
+                filled: true,
+                hide: true,
+            }),
+            desktop.add_shape(Shape::Text {
+                x: x + 10,
+                y: top + 8,
+                content: "Recent files".to_string(),
+                color: Color::BLACK,
+                background_color: TASKBAR_COLOR,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Bold,
+                hide: true,
+            }),
+        ]);
+
+        let row_count = recent::MAX_ENTRIES
+            .min(height.saturating_sub(RECENT_HEADING_HEIGHT) / RECENT_ROW_HEIGHT);
+        let rows = (0..row_count)
+            .map(|i| {
+                let label_idx = desktop.add_shape(Shape::Text {
+                    x: x + 10,
+                    y: top + RECENT_HEADING_HEIGHT + i * RECENT_ROW_HEIGHT,
+                    content: String::new(),
+                    color: Color::BLACK,
+                    background_color: TASKBAR_COLOR,
+                    font_size: RasterHeight::Size16,
+                    font_weight: FontWeight::Regular,
+                    hide: true,
+                });
+                (label_idx, None)
+            })
+            .collect();
+
+        Self {
+            bounds: Rect::new(x, top, RECENT_MENU_WIDTH, height),
+            frame,
+            rows,
+        }
+    }
+
+    /// Fill the rows from the recent list and show the panel
+    fn show(&mut self, desktop: &mut Surface) {
+        for idx in &self.frame {
+            desktop.show_shape(*idx);
+        }
+
+        let files = recent::list();
+        for (i, (label_idx, path)) in self.rows.iter_mut().enumerate() {
+            let (text, color) = match files.get(i) {
+                Some(file) => (file.name().to_string(), Color::BLACK),
+                None if i == 0 => ("No recent files".to_string(), DISABLED_LABEL_COLOR),
+                None => {
+                    *path = None;
+                    desktop.hide_shape(*label_idx);
+                    continue;
+                }
+            };
+
+            *path = files.get(i).map(|file| file.path.clone());
+            desktop.update_text_content(*label_idx, text, None);
+            desktop.update_text_color(*label_idx, color);
+            desktop.show_shape(*label_idx);
+        }
+    }
+
+    fn hide(&mut self, desktop: &mut Surface) {
+        for idx in &self.frame {
+            desktop.hide_shape(*idx);
+        }
+        for (label_idx, path) in &mut self.rows {
+            desktop.hide_shape(*label_idx);
+            *path = None;
+        }
+    }
+
+    /// The file of the row under a point, if the panel is open
+    fn path_at(&self, x: usize, y: usize) -> Option<&str> {
+        let rows_top = self.bounds.y + RECENT_HEADING_HEIGHT;
+        if !self.bounds.contains_point(x, y) || y < rows_top {
+            return None;
+        }
+
+        let (_, path) = self.rows.get((y - rows_top) / RECENT_ROW_HEIGHT)?;
+        path.as_deref()
+    }
+}
+
+/// The shown workspace, nothing when there is only one
+fn workspace_text(workspace: usize, count: usize) -> String {
+    if count > 1 {
+        format!("Desktop {}/{}", workspace + 1, count)
+    } else {
+        String::new()
+    }
+}
+
+/// The lock keys that are on, always as wide as with all of them on
+fn lock_keys_text(locks: LockKeys) -> String {
+    let name = |on: bool, name: &'static str| if on { name } else { "" };
+    format!(
+        "{:<4} {:<3} {:<4}",
+        name(locks.caps, "CAPS"),
+        name(locks.num, "NUM"),
+        name(locks.scroll, "SCRL")
+    )
+}
+
+/// Show or hide the start menu entries, their icons and the recent files next to them
+fn set_start_menu_visible(
+    desktop: &mut Surface,
+    entries: &[StartMenuEntry],
+    icons: &[usize],
+    recent_menu: &mut RecentMenu,
+    highlight: &mut StartMenuHighlight,
+    visible: bool,
+) {
+    if !visible {
+        highlight.set(desktop, entries, icons, None);
+    }
+
+    for entry in entries {
+        if visible {
+            desktop.show_shape(entry.border_idx);
+            desktop.show_shape(entry.label_idx);
+        } else {
+            desktop.hide_shape(entry.border_idx);
+            desktop.hide_shape(entry.label_idx);
+        }
+    }
+    for icon_idx in icons {
+        if visible {
+            desktop.show_shape(*icon_idx);
+        } else {
+            desktop.hide_shape(*icon_idx);
+        }
+    }
+
+    if visible {
+        recent_menu.show(desktop);
+    } else {
+        recent_menu.hide(desktop);
+    }
+}
+
 pub fn run_desktop() -> ! {
-    serial_println!("Running desktop...");
+    log!(LogLevel::Info, "Running desktop...");
     init_queues();
 
     let mut mouse_state = CurrentMouseState::new();
     let mut window_manager = WindowManager::new();
 
     let click_queue = CLICK_QUEUE.get().expect("Click queue not initialized");
+    let right_click_queue = RIGHT_CLICK_QUEUE
+        .get()
+        .expect("Right click queue not initialized");
     let scancode_queue = SCANCODE_QUEUE
         .try_get()
         .expect("Scancode queue not initialized");
     let mouse_state_queue = STATE_QUEUE
         .try_get()
         .expect("Mouse state queue not initialized");
+    let synthetic_key_queue = SYNTHETIC_KEY_QUEUE
+        .try_get()
+        .expect("Synthetic key queue not initialized");
+
+    taskbar::init();
+    recent::load();
+    window_positions::load();
+    file_sort::load();
+    autosave::init();
+    animation::init();
+    workspaces::init();
+    lock_screen::init();
+    let taskbar = taskbar::layout();
+    let taskbar_bounds = taskbar.bounds();
 
     let screen_size = *SCREEN_SIZE.get().unwrap();
     let mut desktop = Surface::new(
@@ -40,14 +354,8 @@ pub fn run_desktop() -> ! {
         screen_size.1 as usize,
         Color::new(50, 111, 168),
     );
-    desktop.just_fill_bg = true;
 
-    let start_button_region = (
-        0,
-        screen_size.1 as usize - TASKBAR_HEIGHT,
-        160,
-        TASKBAR_HEIGHT,
-    );
+    let start_button_region = Rect::new(0, taskbar_bounds.y, 160, taskbar_bounds.height);
 
     // Taskbar
     // Rerender performance trick:
@@ -55,16 +363,16 @@ pub fn run_desktop() -> ! {
     for i in 0..TASKBAR_CHUNK_AMOUNT {
         desktop.add_shape(Shape::Rectangle {
             x: i * (screen_size.0 as usize / TASKBAR_CHUNK_AMOUNT),
-            y: screen_size.1 as usize - TASKBAR_HEIGHT,
+            y: taskbar_bounds.y,
             width: screen_size.0 as usize / TASKBAR_CHUNK_AMOUNT,
-            height: TASKBAR_HEIGHT,
+            height: taskbar_bounds.height,
             color: TASKBAR_COLOR,
             filled: true,
             hide: false,
         });
         desktop.add_shape(Shape::Rectangle {
             x: i * (screen_size.0 as usize / TASKBAR_CHUNK_AMOUNT),
-            y: screen_size.1 as usize - TASKBAR_HEIGHT - 1,
+            y: taskbar.border_y(),
             width: screen_size.0 as usize / TASKBAR_CHUNK_AMOUNT,
             height: 1,
             color: Color::BLACK,
@@ -75,18 +383,18 @@ pub fn run_desktop() -> ! {
 
     // Start button
     desktop.add_shape(Shape::Rectangle {
-        x: start_button_region.2,
-        y: start_button_region.1,
+        x: start_button_region.width,
+        y: start_button_region.y,
         width: 1,
-        height: start_button_region.3,
+        height: start_button_region.height,
         color: Color::BLACK,
         filled: true,
         hide: false,
     });
 
     desktop.add_shape(Shape::Text {
-        x: start_button_region.0 + 50,
-        y: start_button_region.1 + 15,
+        x: start_button_region.x + 50,
+        y: start_button_region.y + start_button_region.height.saturating_sub(20) / 2,
         content: "Start".to_string(),
         color: Color::BLACK,
         background_color: TASKBAR_COLOR,
@@ -95,154 +403,130 @@ pub fn run_desktop() -> ! {
         hide: false,
     });
 
-    let mut start_menu_entries: Vec<(usize, usize, usize, usize, usize, usize, &str)> = Vec::new(); // (idx, label idx, x, y, width, height, label)
+    // Right of the start button, clicking it shows the next workspace
+    let (workspace_width, workspace_height) = measure_text(
+        &workspace_text(workspaces::MAX_COUNT - 1, workspaces::MAX_COUNT),
+        FontWeight::Regular,
+        RasterHeight::Size16,
+    );
+    let workspace_region = Rect::new(
+        start_button_region.width + 15,
+        taskbar_bounds.y + taskbar_bounds.height.saturating_sub(workspace_height) / 2,
+        workspace_width,
+        workspace_height,
+    );
+    let mut shown_workspace = (window_manager.workspace(), workspaces::count());
+    let workspace_idx = desktop.add_shape(Shape::Text {
+        x: workspace_region.x,
+        y: workspace_region.y,
+        content: workspace_text(shown_workspace.0, shown_workspace.1),
+        color: Color::BLACK,
+        background_color: TASKBAR_COLOR,
+        font_size: RasterHeight::Size16,
+        font_weight: FontWeight::Regular,
+        hide: false,
+    });
+
+    let mut start_menu_entries: Vec<StartMenuEntry> = Vec::new();
     let mut start_menu_open = false;
 
+    let start_menu_height = START_MENU_APPS.len() * START_MENU_ENTRY_HEIGHT + 30;
+    let start_menu_top = taskbar.start_menu_top(start_menu_height);
+
     // Start menu placeholder
-    start_menu_entries.push((
-        desktop.add_shape(Shape::Rectangle {
+    start_menu_entries.push(StartMenuEntry {
+        border_idx: desktop.add_shape(Shape::Rectangle {
             x: 0,
-            y: screen_size.1 as usize - 300 - TASKBAR_HEIGHT - 2,
+            y: start_menu_top - 2,
             width: 201,
-            height: 302,
+            height: start_menu_height + 2,
             color: Color::BLACK,
             filled: false,
             hide: true,
         }),
-        desktop.add_shape(Shape::Rectangle {
+        label_idx: desktop.add_shape(Shape::Rectangle {
             x: 0,
-            y: screen_size.1 as usize - 300 - TASKBAR_HEIGHT - 1,
+            y: start_menu_top - 1,
             width: 200,
-            height: 300,
+            height: start_menu_height,
             color: TASKBAR_COLOR,
             filled: true,
             hide: true,
         }),
-        0,
-        screen_size.1 as usize - 290 - TASKBAR_HEIGHT,
-        200,
-        300,
-        "",
-    ));
-
-    // Calculator start button
-    start_menu_entries.push((
-        desktop.add_shape(Shape::Rectangle {
-            x: 10,
-            y: screen_size.1 as usize - 305,
-            width: 180,
-            height: 1,
-            color: Color::BLACK,
-            filled: true,
-            hide: true,
-        }),
-        desktop.add_shape(Shape::Text {
-            x: 20,
-            y: screen_size.1 as usize - 335,
-            content: "Calculator".to_string(),
-            color: Color::BLACK,
-            background_color: TASKBAR_COLOR,
-            font_size: RasterHeight::Size20,
-            font_weight: FontWeight::Regular,
-            hide: true,
-        }),
-        0,
-        screen_size.1 as usize - 350,
-        200,
-        45,
-        "Calculator",
-    ));
-
-    // Notepad start button
-    start_menu_entries.push((
-        desktop.add_shape(Shape::Rectangle {
-            x: 10,
-            y: screen_size.1 as usize - 260,
-            width: 180,
-            height: 1,
-            color: Color::BLACK,
-            filled: true,
-            hide: true,
-        }),
-        desktop.add_shape(Shape::Text {
-            x: 20,
-            y: screen_size.1 as usize - 290,
-            content: "Notepad".to_string(),
-            color: Color::BLACK,
-            background_color: TASKBAR_COLOR,
-            font_size: RasterHeight::Size20,
-            font_weight: FontWeight::Regular,
-            hide: true,
-        }),
-        0,
-        screen_size.1 as usize - 305,
-        200,
-        45,
-        "Notepad",
-    ));
-
-    // File Manager start button
-    start_menu_entries.push((
-        desktop.add_shape(Shape::Rectangle {
-            x: 10,
-            y: screen_size.1 as usize - 215,
-            width: 180,
-            height: 1,
-            color: Color::BLACK,
-            filled: true,
-            hide: true,
-        }),
-        desktop.add_shape(Shape::Text {
-            x: 20,
-            y: screen_size.1 as usize - 245,
-            content: "File Manager".to_string(),
-            color: Color::BLACK,
-            background_color: TASKBAR_COLOR,
-            font_size: RasterHeight::Size20,
-            font_weight: FontWeight::Regular,
-            hide: true,
-        }),
-        0,
-        screen_size.1 as usize - 260,
-        200,
-        45,
-        "File Manager",
-    ));
-
-    // SysInfo start button
-    start_menu_entries.push((
-        desktop.add_shape(Shape::Rectangle {
-            x: 10,
-            y: screen_size.1 as usize - 170,
-            width: 180,
-            height: 1,
-            color: Color::BLACK,
+        bounds: Rect::new(0, start_menu_top + 10, 200, start_menu_height),
+        label: "",
+    });
+
+    // Above the menu background, below the entries
+    let mut start_menu_highlight = StartMenuHighlight {
+        background_idx: desktop.add_shape(Shape::Rectangle {
+            x: 0,
+            y: start_menu_top,
+            width: 200,
+            height: START_MENU_ENTRY_HEIGHT,
+            color: START_MENU_HIGHLIGHT_COLOR,
             filled: true,
             hide: true,
         }),
-        desktop.add_shape(Shape::Text {
-            x: 20,
-            y: screen_size.1 as usize - 200,
-            content: "System Info".to_string(),
-            color: Color::BLACK,
+        app: None,
+    };
+
+    // Icons are shown and hidden together with the entries
+    let mut start_menu_icons: Vec<usize> = Vec::new();
+
+    let mut storage_available = is_mounted();
+    // Labels of the apps that need the disk, greyed out while there is none
+    let mut disk_app_labels: Vec<usize> = Vec::new();
+
+    // One start button per app
+    for (i, (label, icon, _, needs_disk)) in START_MENU_APPS.iter().enumerate() {
+        let entry_y = start_menu_top + i * START_MENU_ENTRY_HEIGHT;
+
+        start_menu_icons.push(desktop.add_shape(icon.shape(20, entry_y + 15, TASKBAR_COLOR, true)));
+
+        let label_color = if *needs_disk && !storage_available {
+            DISABLED_LABEL_COLOR
+        } else {
+            Color::BLACK
+        };
+        let label_idx = desktop.add_shape(Shape::Text {
+            x: 45,
+            y: entry_y + 13,
+            content: label.to_string(),
+            color: label_color,
             background_color: TASKBAR_COLOR,
             font_size: RasterHeight::Size20,
             font_weight: FontWeight::Regular,
             hide: true,
-        }),
-        0,
-        screen_size.1 as usize - 215,
-        200,
-        45,
-        "System Info",
-    ));
+        });
+        if *needs_disk {
+            disk_app_labels.push(label_idx);
+        }
+
+        start_menu_entries.push(StartMenuEntry {
+            border_idx: desktop.add_shape(Shape::Rectangle {
+                x: 10,
+                y: entry_y + START_MENU_ENTRY_HEIGHT,
+                width: 180,
+                height: 1,
+                color: Color::BLACK,
+                filled: true,
+                hide: true,
+            }),
+            label_idx,
+            bounds: Rect::new(0, entry_y, 200, START_MENU_ENTRY_HEIGHT),
+            label,
+        });
+    }
+
+    let mut recent_menu = RecentMenu::new(&mut desktop, 202, start_menu_top, start_menu_height);
 
     // Time and date background
     desktop.add_shape(Shape::Rectangle {
         x: screen_size.0 as usize - 95,
-        y: screen_size.1 as usize - TASKBAR_HEIGHT + 16,
+        y: taskbar_bounds.y + taskbar_bounds.height / 2 - 9,
         width: 1,
-        height: TASKBAR_HEIGHT - 32,
+        height: 18,
         color: Color::BLACK,
         filled: true,
         hide: false,
@@ -251,7 +535,7 @@ pub fn run_desktop() -> ! {
     // Time
     let time_shape_idx = desktop.add_shape(Shape::Text {
         x: screen_size.0 as usize - 80,
-        y: screen_size.1 as usize - TASKBAR_HEIGHT + 12,
+        y: taskbar_bounds.y + taskbar_bounds.height / 2 - 13,
         content: "22:42".to_string(),
         color: Color::BLACK,
         background_color: TASKBAR_COLOR,
@@ -263,7 +547,7 @@ pub fn run_desktop() -> ! {
     // Date
     let date_shape_idx = desktop.add_shape(Shape::Text {
         x: screen_size.0 as usize - 80,
-        y: screen_size.1 as usize - TASKBAR_HEIGHT + 8 + 16,
+        y: taskbar_bounds.y + taskbar_bounds.height / 2 - 1,
         content: "8/15/2025".to_string(),
         color: Color::BLACK,
         background_color: TASKBAR_COLOR,
@@ -272,37 +556,310 @@ pub fn run_desktop() -> ! {
         hide: false,
     });
 
-    serial_println!("Screen size: {}x{}", screen_size.0, screen_size.1);
+    // Lock keys that are on, left of the clock
+    let (locks_width, _) = measure_text(
+        &lock_keys_text(LockKeys {
+            caps: true,
+            num: true,
+            scroll: true,
+        }),
+        FontWeight::Regular,
+        RasterHeight::Size16,
+    );
+    let locks_shape_idx = desktop.add_shape(Shape::Text {
+        x: (screen_size.0 as usize).saturating_sub(105 + locks_width),
+        y: taskbar_bounds.y + taskbar_bounds.height / 2 - 8,
+        content: lock_keys_text(LockKeys::default()),
+        color: Color::BLACK,
+        background_color: TASKBAR_COLOR,
+        font_size: RasterHeight::Size16,
+        font_weight: FontWeight::Regular,
+        hide: false,
+    });
+    let mut lock_keys = LockKeys::default();
+    // Sent once at the start, so the LEDs match even if the firmware left them on
+    let mut leds_synced = false;
+
+    // Shown left of the lock keys while there is no filesystem, clicking it tries to mount again
+    let (no_disk_width, no_disk_height) =
+        measure_text(NO_DISK_TEXT, FontWeight::Regular, RasterHeight::Size16);
+    let no_disk_region = Rect::new(
+        (screen_size.0 as usize).saturating_sub(115 + locks_width + no_disk_width),
+        taskbar_bounds.y + taskbar_bounds.height.saturating_sub(no_disk_height) / 2,
+        no_disk_width,
+        no_disk_height,
+    );
+    let no_disk_idx = desktop.add_shape(Shape::Text {
+        x: no_disk_region.x,
+        y: no_disk_region.y,
+        content: NO_DISK_TEXT.to_string(),
+        color: Color::new(150, 0, 0),
+        background_color: TASKBAR_COLOR,
+        font_size: RasterHeight::Size16,
+        font_weight: FontWeight::Regular,
+        hide: storage_available,
+    });
+
+    log!(
+        LogLevel::Info,
+        "Screen size: {}x{}",
+        screen_size.0,
+        screen_size.1
+    );
+
+    if let Some(report) = crash::take_previous_crash() {
+        let mut lines = Vec::from([
+            format!("The last boot crashed, see {}:", crash::CRASH_FILENAME),
+            String::new(),
+        ]);
+        lines.extend(report.lines().take(CRASH_REPORT_LINES).map(String::from));
+        message_box::launch(&mut window_manager, "Crash Report", lines);
+    }
 
     let mut keyboard = Keyboard::new(ScancodeSet1::new(), layouts::Azerty, HandleControl::Ignore);
 
-    let time_update_ticks = 60 * 5; // FPS is somewhere between 60 and 50 (hard to test)
-    let mut ticks = 0u64;
+    let time_update_ticks = ms_to_ticks(5000);
+    let mut next_time_update = 0;
+    let sync_ticks = ms_to_ticks(SYNC_INTERVAL_MS);
+    let mut next_sync = sync_ticks;
+    let trim_ticks = ms_to_ticks(memory_pressure::TRIM_INTERVAL_MS);
+    let mut next_trim = 0;
+    // Only the first of a run of failed syncs is shown
+    let mut sync_failed = false;
+
+    // Async work like disk reads runs while the loop waits for input
+    let mut executor = Executor::new();
+    if let Err(e) = executor::spawn(serial_console::run()) {
+        log!(LogLevel::Warn, "Serial commands are unavailable: {}", e);
+    }
+    let mut damage_outlines = DamageOutlines::new();
+    // Shown over everything while locked, it gets all the input
+    let mut lock_screen: Option<LockScreen> = None;
+    let screen_rect = Rect::new(0, 0, screen_size.0 as usize, screen_size.1 as usize);
 
     loop {
         for _ in 0..10000 {
+            // Tasks only do a short step each time, like reading one sector
+            executor.run_ready_tasks();
+
+            let mut busy = false;
+
             // Poll for scancodes
             if let Some(scancode) = scancode_queue.pop() {
+                busy = true;
+                note_input();
                 if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
                     if let Some(key) = keyboard.process_keyevent(key_event) {
-                        match key {
-                            DecodedKey::Unicode(character) => {
-                                window_manager.handle_char_input(character);
+                        // The keyboard decoder doesn't track Scroll Lock, Caps and Num it does
+                        if key == DecodedKey::RawKey(KeyCode::ScrollLock) {
+                            lock_keys.scroll = !lock_keys.scroll;
+                        }
+
+                        // The lock screen takes every key, the rest of the iteration can wait
+                        if let Some(lock) = &mut lock_screen {
+                            if lock.handle_key(key) {
+                                lock_screen = None;
+                                desktop.mark_region_exposed(screen_rect);
+                            }
+                            continue;
+                        }
+
+                        // Ctrl+Alt+L locks right away
+                        let modifiers = keyboard.get_modifiers();
+                        if modifiers.is_ctrl()
+                            && modifiers.is_alt()
+                            && matches!(key, DecodedKey::Unicode('l' | 'L'))
+                            && lock_screen::is_enabled()
+                        {
+                            lock_screen =
+                                Some(LockScreen::new(screen_rect.width, screen_rect.height));
+                            window_manager.cancel_drag();
+                            continue;
+                        }
+
+                        // The Windows key opens the start menu, the arrows pick an entry
+                        let menu_key = match key {
+                            DecodedKey::RawKey(KeyCode::LWin | KeyCode::RWin) => {
+                                start_menu_open = !start_menu_open;
+                                set_start_menu_visible(
+                                    &mut desktop,
+                                    &start_menu_entries,
+                                    &start_menu_icons,
+                                    &mut recent_menu,
+                                    &mut start_menu_highlight,
+                                    start_menu_open,
+                                );
+                                if start_menu_open {
+                                    start_menu_highlight.set(
+                                        &mut desktop,
+                                        &start_menu_entries,
+                                        &start_menu_icons,
+                                        Some(0),
+                                    );
+                                }
+                                true
+                            }
+                            _ if !start_menu_open => false,
+                            DecodedKey::RawKey(KeyCode::ArrowUp) => {
+                                start_menu_highlight.step(
+                                    &mut desktop,
+                                    &start_menu_entries,
+                                    &start_menu_icons,
+                                    -1,
+                                );
+                                true
+                            }
+                            DecodedKey::RawKey(KeyCode::ArrowDown) => {
+                                start_menu_highlight.step(
+                                    &mut desktop,
+                                    &start_menu_entries,
+                                    &start_menu_icons,
+                                    1,
+                                );
+                                true
                             }
-                            DecodedKey::RawKey(key) => {
-                                window_manager.handle_key_input(key);
+                            DecodedKey::Unicode('\n') => {
+                                if let Some(app) = start_menu_highlight.app {
+                                    let (_, _, launch, needs_disk) = START_MENU_APPS[app];
+                                    // Greyed out, the taskbar shows why
+                                    if !needs_disk || storage_available {
+                                        start_menu_open = false;
+                                        set_start_menu_visible(
+                                            &mut desktop,
+                                            &start_menu_entries,
+                                            &start_menu_icons,
+                                            &mut recent_menu,
+                                            &mut start_menu_highlight,
+                                            false,
+                                        );
+                                        launch(&mut window_manager);
+                                    }
+                                }
+                                true
                             }
+                            DecodedKey::Unicode('\u{1b}') => {
+                                start_menu_open = false;
+                                set_start_menu_visible(
+                                    &mut desktop,
+                                    &start_menu_entries,
+                                    &start_menu_icons,
+                                    &mut recent_menu,
+                                    &mut start_menu_highlight,
+                                    false,
+                                );
+                                true
+                            }
+                            _ => false,
+                        };
+
+                        if !menu_key {
+                            window_manager.handle_decoded_key(key, keyboard.get_modifiers());
                         }
                     }
                 }
             }
 
+            let modifiers = keyboard.get_modifiers();
+            let locks = LockKeys {
+                caps: modifiers.capslock,
+                num: modifiers.numlock,
+                ..lock_keys
+            };
+            if locks != lock_keys || !leds_synced {
+                lock_keys = locks;
+                leds_synced = true;
+                keyboard_leds::update(locks);
+                desktop.update_text_content(locks_shape_idx, lock_keys_text(locks), None);
+            }
+
+            let workspace = (window_manager.workspace(), workspaces::count());
+            if workspace != shown_workspace {
+                shown_workspace = workspace;
+                desktop.update_text_content(
+                    workspace_idx,
+                    workspace_text(workspace.0, workspace.1),
+                    None,
+                );
+            }
+
             if let Some(state) = mouse_state_queue.pop() {
+                busy = true;
+                note_input();
                 mouse_state.update(state);
             }
+
+            // Nothing to do, halt until input arrives or the next timer tick, then draw
+            // the frame. The check runs with interrupts off so a wake up can't slip in
+            // between it and the hlt.
+            if !busy {
+                cpu_interrupts::disable();
+                if executor.is_idle()
+                    && scancode_queue.is_empty()
+                    && mouse_state_queue.is_empty()
+                    && synthetic_key_queue.is_empty()
+                {
+                    cpu_interrupts::enable_and_hlt();
+                    break;
+                }
+                cpu_interrupts::enable();
+            }
+        }
+
+        // Keys from the on-screen keyboard take the same path as the real ones
+        while let Some(key) = synthetic_key_queue.pop() {
+            note_input();
+            if lock_screen.is_some() {
+                continue;
+            }
+            window_manager.handle_decoded_key(key, keyboard.get_modifiers());
+        }
+
+        // Windows moved by keyboard shortcuts leave their old place behind
+        for region in window_manager.take_dirty_regions() {
+            desktop.mark_region_exposed(region);
         }
 
-        if ticks % time_update_ticks == 0 {
+        window_manager.run_autosave();
+        log_sink::flush_file_log();
+
+        if lock_screen.is_none() && lock_screen::is_idle_timeout(idle_ticks()) {
+            log!(LogLevel::Info, "Locking the screen after the idle timeout");
+            lock_screen = Some(LockScreen::new(screen_rect.width, screen_rect.height));
+            window_manager.cancel_drag();
+        }
+
+        // Free caches before the heap runs out, again each interval while it stays low
+        if interrupts::ticks() >= next_trim && memory_pressure::is_heap_low() {
+            next_trim = interrupts::ticks() + trim_ticks;
+            memory_pressure::trim(&mut window_manager);
+        }
+
+        if storage_available && interrupts::ticks() >= next_sync {
+            match try_sync() {
+                // Try again next frame
+                Err(FILESYSTEM_BUSY) => {}
+                result => {
+                    next_sync = interrupts::ticks() + sync_ticks;
+                    match result {
+                        Ok(sectors) if sectors > 0 => {
+                            log!(LogLevel::Debug, "Synced {} sectors", sectors)
+                        }
+                        Err(e) => {
+                            log!(LogLevel::Warn, "Sync failed: {}", e);
+                            if !sync_failed {
+                                toast::notify(&format!("Disk error: {}", e), ToastKind::Error);
+                            }
+                        }
+                        _ => {}
+                    }
+                    sync_failed = result.is_err();
+                }
+            }
+        }
+
+        if interrupts::ticks() >= next_time_update {
+            next_time_update = interrupts::ticks() + time_update_ticks;
+
             let raw_time = get_utc_time();
 
             // Update time
@@ -315,9 +872,15 @@ pub fn run_desktop() -> ! {
         }
 
         while let Some((x, y)) = click_queue.pop() {
-            let (mut handled, redraw_region) = window_manager.handle_mouse_click(x, y);
-            if let Some((x, y, width, height)) = redraw_region {
-                desktop.force_dirty_region(x, y, width, height);
+            // The lock screen only takes the keyboard
+            if lock_screen.is_some() {
+                continue;
+            }
+
+            let (mut handled, redraw_region) =
+                window_manager.handle_mouse_click(x, y, keyboard.get_modifiers());
+            if let Some(region) = redraw_region {
+                desktop.mark_region_exposed(region);
             }
 
             if handled {
@@ -327,54 +890,74 @@ pub fn run_desktop() -> ! {
             let x = x as usize;
             let y = y as usize;
 
-            if start_menu_open {
-                for (_, _, item_x, item_y, width, height, label) in &start_menu_entries {
-                    if *item_x <= x && x < *item_x + *width && *item_y <= y && y < *item_y + *height
-                    {
-                        if *label == "Calculator" {
-                            launch_calculator(&mut window_manager);
-
-                            start_menu_open = false;
-                            for (idx, label_idx, _, _, _, _, _) in &start_menu_entries {
-                                desktop.hide_shape(*idx);
-                                desktop.hide_shape(*label_idx);
-                            }
-
-                            handled = true;
-                            break;
+            if !storage_available && no_disk_region.contains_point(x, y) {
+                match init_filesystem() {
+                    Ok(()) => {
+                        log!(LogLevel::Info, "Filesystem mounted on retry");
+                        storage_available = true;
+                        recent::load();
+                        window_positions::load();
+                        file_sort::load();
+                        autosave::init();
+                        animation::init();
+                        workspaces::init();
+                        lock_screen::init();
+                        log_sink::enable_file_log();
+                        desktop.hide_shape(no_disk_idx);
+                        for label_idx in &disk_app_labels {
+                            desktop.update_text_color(*label_idx, Color::BLACK);
                         }
-                        if *label == "Notepad" {
-                            launch_notepad(&mut window_manager);
+                    }
+                    Err(e) => log!(LogLevel::Warn, "Retrying the filesystem failed: {}", e),
+                }
+                continue;
+            }
 
-                            start_menu_open = false;
-                            for (idx, label_idx, _, _, _, _, _) in &start_menu_entries {
-                                desktop.hide_shape(*idx);
-                                desktop.hide_shape(*label_idx);
-                            }
+            if workspace_region.contains_point(x, y) && workspaces::count() > 1 {
+                window_manager
+                    .switch_workspace((window_manager.workspace() + 1) % workspaces::count());
+                continue;
+            }
 
-                            handled = true;
-                            break;
-                        }
-                        if *label == "File Manager" {
-                            launch_filemanager(&mut window_manager);
+            if start_menu_open {
+                if let Some(path) = recent_menu.path_at(x, y).map(String::from) {
+                    window_manager.open_recent(&path);
 
-                            start_menu_open = false;
-                            for (idx, label_idx, _, _, _, _, _) in &start_menu_entries {
-                                desktop.hide_shape(*idx);
-                                desktop.hide_shape(*label_idx);
+                    start_menu_open = false;
+                    set_start_menu_visible(
+                        &mut desktop,
+                        &start_menu_entries,
+                        &start_menu_icons,
+                        &mut recent_menu,
+                        &mut start_menu_highlight,
+                        false,
+                    );
+                    continue;
+                }
+
+                for entry in &start_menu_entries {
+                    if entry.bounds.contains_point(x, y) {
+                        if let Some((_, _, launch, needs_disk)) = START_MENU_APPS
+                            .iter()
+                            .find(|(name, _, _, _)| *name == entry.label)
+                        {
+                            // Greyed out, the taskbar shows why
+                            if *needs_disk && !storage_available {
+                                handled = true;
+                                break;
                             }
 
-                            handled = true;
-                            break;
-                        }
-                        if *label == "System Info" {
-                            launch_sysinfo(&mut window_manager);
+                            launch(&mut window_manager);
 
                             start_menu_open = false;
-                            for (idx, label_idx, _, _, _, _, _) in &start_menu_entries {
-                                desktop.hide_shape(*idx);
-                                desktop.hide_shape(*label_idx);
-                            }
+                            set_start_menu_visible(
+                                &mut desktop,
+                                &start_menu_entries,
+                                &start_menu_icons,
+                                &mut recent_menu,
+                                &mut start_menu_highlight,
+                                false,
+                            );
 
                             handled = true;
                             break;
@@ -388,27 +971,29 @@ pub fn run_desktop() -> ! {
             }
 
             // Check if click is within the start button region
-            if x >= start_button_region.0
-                && x < start_button_region.0 + start_button_region.2
-                && y >= start_button_region.1
-                && y < start_button_region.1 + start_button_region.3
-            {
+            if start_button_region.contains_point(x, y) {
                 start_menu_open = !start_menu_open;
+                set_start_menu_visible(
+                    &mut desktop,
+                    &start_menu_entries,
+                    &start_menu_icons,
+                    &mut recent_menu,
+                    &mut start_menu_highlight,
+                    start_menu_open,
+                );
+            }
+        }
 
-                // Update start menu entries visibility
-                for (idx, label_idx, _, _, _, _, _) in &start_menu_entries {
-                    if start_menu_open {
-                        desktop.show_shape(*idx);
-                        desktop.show_shape(*label_idx);
-                    } else {
-                        desktop.hide_shape(*idx);
-                        desktop.hide_shape(*label_idx);
-                    }
-                }
+        while let Some((x, y)) = right_click_queue.pop() {
+            if lock_screen.is_none() {
+                window_manager.handle_mouse_right_click(x, y);
             }
         }
 
-        if mouse_state.left_button_down && !mouse_state.prev_left_button_down {
+        if lock_screen.is_none()
+            && mouse_state.left_button_down
+            && !mouse_state.prev_left_button_down
+        {
             without_interrupts(|| {
                 if let Some(fb) = framebuffer::FRAMEBUFFER.get() {
                     let fb_lock = fb.lock();
@@ -417,21 +1002,24 @@ pub fn run_desktop() -> ! {
             });
         }
 
-        if !mouse_state.left_button_down && mouse_state.prev_left_button_down {
+        if lock_screen.is_none()
+            && !mouse_state.left_button_down
+            && mouse_state.prev_left_button_down
+        {
             without_interrupts(|| {
                 if let Some(fb) = framebuffer::FRAMEBUFFER.get() {
                     let mut fb_lock = fb.lock();
                     let dirty_regions = window_manager.handle_mouse_release(&mut fb_lock);
 
                     // Mark all dirty regions from window drag completion
-                    for (x, y, width, height) in dirty_regions {
-                        desktop.force_dirty_region(x, y, width, height);
+                    for region in dirty_regions {
+                        desktop.mark_region_exposed(region);
                     }
                 }
             });
         }
 
-        if mouse_state.has_moved && mouse_state.left_button_down {
+        if lock_screen.is_none() && mouse_state.has_moved && mouse_state.left_button_down {
             without_interrupts(|| {
                 if let Some(fb) = framebuffer::FRAMEBUFFER.get() {
                     let mut fb_lock = fb.lock();
@@ -446,34 +1034,41 @@ pub fn run_desktop() -> ! {
             if let Some(fb) = framebuffer::FRAMEBUFFER.get() {
                 let mut fb_lock = fb.lock();
 
-                // Get dirty regions BEFORE rendering (since render() clears them)
-                let dirty_regions: Vec<Rect> = desktop.get_dirty_regions().to_vec();
-
-                // Render desktop
-                let desktop_rendered = desktop.render(&mut fb_lock, 0, 0, false);
+                damage_outlines.erase(&mut fb_lock);
 
-                // Only render windows if they intersect with dirty regions
-                let windows_rendered = window_manager.render(&mut fb_lock, &dirty_regions);
+                // Screen areas redrawn this frame, each layer adds what changed in it
+                let mut damage = Vec::new();
+                if let Some(lock) = &mut lock_screen {
+                    lock.render(&mut fb_lock, &mut damage);
+                } else {
+                    desktop.render(&mut fb_lock, 0, 0, &mut damage);
+                    window_manager.render(&mut fb_lock, &mut damage);
+                }
+                if !damage.is_empty() {
+                    let pixels: usize = damage.iter().map(|r| r.width * r.height).sum();
+                    log!(
+                        LogLevel::Trace,
+                        "Frame damage: {} regions, {} pixels",
+                        damage.len(),
+                        pixels
+                    );
+                }
 
                 // Handle mouse cursor rendering with region optimization
-                let should_redraw_cursor = if mouse_state.has_moved {
+                let should_redraw_cursor = if lock_screen.is_some() {
+                    // Hidden below, nothing to save either
+                    false
+                } else if mouse_state.has_moved {
                     // Mouse moved, always redraw
                     true
-                } else if desktop_rendered || windows_rendered {
-                    // Check if any dirty regions intersect with current or previous cursor position
-                    let current_cursor_bounds = FrameBufferWriter::get_cursor_bounds(
+                } else if !damage.is_empty() {
+                    // Check if any damage intersects with the current or previous cursor position
+                    let current_cursor_rect = FrameBufferWriter::get_cursor_bounds(
                         mouse_state.x as usize,
                         mouse_state.y as usize,
                     );
-                    let current_cursor_rect = Rect::new(
-                        current_cursor_bounds.0,
-                        current_cursor_bounds.1,
-                        current_cursor_bounds.2,
-                        current_cursor_bounds.3,
-                    );
 
-                    // Check if dirty regions intersect with current cursor
-                    let cursor_intersects = dirty_regions
+                    let cursor_intersects = damage
                         .iter()
                         .any(|region| region.intersects(&current_cursor_rect));
 
@@ -483,37 +1078,44 @@ pub fn run_desktop() -> ! {
                     }
 
                     // Also check previous cursor position if it exists
-                    let prev_cursor_intersects =
-                        if let Some((prev_x, prev_y)) = fb_lock.get_previous_cursor_pos() {
-                            let prev_cursor_bounds =
-                                FrameBufferWriter::get_cursor_bounds(prev_x, prev_y);
-                            let prev_cursor_rect = Rect::new(
-                                prev_cursor_bounds.0,
-                                prev_cursor_bounds.1,
-                                prev_cursor_bounds.2,
-                                prev_cursor_bounds.3,
-                            );
-                            dirty_regions
-                                .iter()
-                                .any(|region| region.intersects(&prev_cursor_rect))
-                        } else {
-                            false
-                        };
+                    let prev_cursor_intersects = if let Some((prev_x, prev_y)) =
+                        fb_lock.get_previous_cursor_pos()
+                    {
+                        let prev_cursor_rect = FrameBufferWriter::get_cursor_bounds(prev_x, prev_y);
+                        damage
+                            .iter()
+                            .any(|region| region.intersects(&prev_cursor_rect))
+                    } else {
+                        false
+                    };
 
                     cursor_intersects || prev_cursor_intersects
                 } else {
                     false
                 };
 
-                if should_redraw_cursor {
+                // The lock screen and fullscreen apps hide the cursor, what was under it is
+                // painted over
+                if lock_screen.is_some() || window_manager.is_fullscreen() {
+                    fb_lock.forget_cursor_background();
+                    mouse_state.has_moved = false;
+                } else if should_redraw_cursor {
+                    if repaint_debug::cursor_trail() {
+                        fb_lock.forget_cursor_background();
+                    }
                     fb_lock.draw_mouse_cursor(mouse_state.x as usize, mouse_state.y as usize);
                     mouse_state.has_moved = false;
                 }
+
+                if repaint_debug::outline_damage() {
+                    damage_outlines.draw(&mut fb_lock, &damage);
+                }
+                if repaint_debug::inspect_windows() && lock_screen.is_none() {
+                    damage_outlines.draw_windows(&mut fb_lock, &window_manager.inspect());
+                }
             } else {
-                serial_println!("Framebuffer not initialized");
+                log!(LogLevel::Error, "Framebuffer not initialized");
             }
         });
-
-        ticks += 1;
     }
 }
@@ -0,0 +1,123 @@
+//! Frees memory that can be made again before the heap runs out.
+//!
+//! Once the heap is more than [`LOW_HEAP_PERCENT`] full, the desktop drops the pixels of
+//! windows that aren't shown, the older clipboard items and the older lines of the kernel
+//! log window. What was freed goes to the log.
+
+use crate::{
+    allocator::HEAP_SIZE,
+    desktop::{clipboard, window_manager::WindowManager},
+    log,
+    serial::LogLevel,
+    sysinfo::estimate_heap_usage,
+};
+
+/// Heap use above which memory is trimmed
+pub const LOW_HEAP_PERCENT: usize = 85;
+/// Time between two trims while memory stays low
+pub const TRIM_INTERVAL_MS: u64 = 1000;
+
+pub fn is_heap_low() -> bool {
+    is_low(estimate_heap_usage(), HEAP_SIZE)
+}
+
+fn is_low(used: usize, size: usize) -> bool {
+    used > size / 100 * LOW_HEAP_PERCENT
+}
+
+/// Free what can be made again, returns the bytes freed
+pub fn trim(window_manager: &mut WindowManager) -> usize {
+    let used = estimate_heap_usage();
+    let (pixels, apps) = window_manager.trim_memory();
+    let clipboard = clipboard::trim();
+
+    let freed = pixels + apps + clipboard;
+    if freed > 0 {
+        log!(
+            LogLevel::Warn,
+            "Low memory: freed {} bytes of window pixels, {} of app data and {} of clipboard items, heap use went from {} to {} bytes",
+            pixels,
+            apps,
+            clipboard,
+            used,
+            estimate_heap_usage()
+        );
+    }
+    freed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        desktop::{application::Application, window_manager::Window},
+        framebuffer::{Color, FrameBufferWriter},
+        surface::Surface,
+    };
+    use alloc::{boxed::Box, string::String, vec::Vec};
+    use bootloader_api::info::PixelFormat;
+
+    #[test_case]
+    fn test_is_low() {
+        assert!(!is_low(0, HEAP_SIZE));
+        assert!(!is_low(HEAP_SIZE / 2, HEAP_SIZE));
+        assert!(is_low(HEAP_SIZE / 100 * 90, HEAP_SIZE));
+    }
+
+    /// A window with nothing in it, only its pixels take memory
+    struct Blank;
+
+    impl Application for Blank {
+        fn title(&self) -> String {
+            String::from("Blank")
+        }
+
+        fn background_color(&self) -> Color {
+            Color::WHITE
+        }
+
+        fn init(&mut self, _surface: &mut Surface) {}
+
+        fn render(&mut self, _surface: &mut Surface) {}
+    }
+
+    #[test_case]
+    fn test_low_heap_trims_hidden_windows() {
+        let screen = FrameBufferWriter::offscreen(1, 1, PixelFormat::Rgb, 3);
+        let mut window = Window::new(10, 40, 256, 256, 1, Box::new(Blank));
+        assert!(window.surface.rasterize(&screen));
+        let size = 256 * 256 * 3;
+
+        // Only windows of other workspaces give up their pixels
+        let mut window_manager = WindowManager::new();
+        window_manager.windows.push(window);
+        window_manager.move_to_workspace(1);
+
+        // Fill the heap until a block as large as the pixels doesn't fit. A few KiB stay
+        // free for the small allocations of trimming and logging.
+        let mut filler: Vec<Vec<u8>> = Vec::with_capacity(128);
+        let mut chunk = HEAP_SIZE;
+        while chunk >= 4096 && filler.len() < filler.capacity() {
+            let mut block = Vec::new();
+            if block.try_reserve_exact(chunk).is_ok() {
+                filler.push(block);
+            } else {
+                chunk /= 2;
+            }
+        }
+        assert!(is_heap_low());
+        assert!(Vec::<u8>::new().try_reserve_exact(size).is_err());
+
+        assert!(trim(&mut window_manager) >= size);
+        assert!(Vec::<u8>::new().try_reserve_exact(size).is_ok());
+        assert_eq!(window_manager.trim_memory().0, 0, "nothing left to drop");
+
+        drop(filler);
+        window_manager.switch_workspace(0);
+        let window = &mut window_manager.windows[0];
+        assert!(
+            window.surface.rasterize(&screen),
+            "drawn again from the shapes"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::Modifiers;
+
+use crate::{
+    desktop::{
+        application::{Action, Application},
+        widgets::button::Button,
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::Color,
+    surface::{Shape, Surface},
+};
+
+pub const WINDOW_WIDTH: usize = 480;
+pub const BACKGROUND_COLOR: Color = Color::new(240, 240, 240);
+
+const MARGIN: usize = 15;
+const LINE_HEIGHT: usize = 18;
+/// Longer lines are cut, the window doesn't scroll
+const MAX_LINE_CHARS: usize = 56;
+const BUTTON_WIDTH: usize = 80;
+const BUTTON_HEIGHT: usize = 25;
+
+/// A few lines of text with an OK button that closes the window
+pub struct MessageBox {
+    title: String,
+    lines: Vec<String>,
+    ok_button: Button,
+    /// OK was clicked
+    closing: bool,
+}
+
+impl MessageBox {
+    pub fn new(title: &str, lines: Vec<String>) -> Self {
+        let button_y = Self::height_for(lines.len()) - MARGIN - BUTTON_HEIGHT;
+        Self {
+            title: title.to_string(),
+            lines,
+            ok_button: Button::new(
+                (WINDOW_WIDTH - BUTTON_WIDTH) / 2,
+                button_y,
+                BUTTON_WIDTH,
+                BUTTON_HEIGHT,
+                "OK",
+            ),
+            closing: false,
+        }
+    }
+
+    /// Window height that fits the given number of lines and the button
+    pub fn height_for(line_count: usize) -> usize {
+        MARGIN + line_count * LINE_HEIGHT + MARGIN + BUTTON_HEIGHT + MARGIN
+    }
+
+    pub fn height(&self) -> usize {
+        Self::height_for(self.lines.len())
+    }
+
+    /// Returns true when OK was clicked and the window should close
+    pub fn handle_mouse_click(&self, x: usize, y: usize) -> bool {
+        self.ok_button.contains(x, y)
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager, title: &str, lines: Vec<String>) {
+    let message_box = MessageBox::new(title, lines);
+    window_manager.add_window(Window::new(
+        160,
+        110,
+        WINDOW_WIDTH,
+        message_box.height(),
+        10,
+        Box::new(message_box),
+    ));
+}
+
+impl Application for MessageBox {
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn background_color(&self) -> Color {
+        BACKGROUND_COLOR
+    }
+
+    fn content_sized(&self) -> bool {
+        true
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        for (i, line) in self.lines.iter().enumerate() {
+            let content = match line.char_indices().nth(MAX_LINE_CHARS) {
+                Some((end, _)) => line[..end].to_string(),
+                None => line.clone(),
+            };
+
+            surface.add_shape(Shape::Text {
+                x: MARGIN,
+                y: MARGIN + i * LINE_HEIGHT,
+                content,
+                color: Color::BLACK,
+                background_color: BACKGROUND_COLOR,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            });
+        }
+
+        self.ok_button.add_to_surface(surface);
+    }
+
+    fn render(&mut self, _surface: &mut Surface) {
+        // The text doesn't change
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        _surface: &mut Surface,
+    ) -> bool {
+        self.closing = self.handle_mouse_click(x, y);
+        true
+    }
+
+    fn take_action(&mut self) -> Option<Action> {
+        core::mem::take(&mut self.closing).then_some(Action::Close)
+    }
+}
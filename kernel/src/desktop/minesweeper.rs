@@ -0,0 +1,371 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::Modifiers;
+
+use crate::{
+    desktop::{
+        application::Application,
+        widgets::button::Button,
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::Color,
+    random,
+    surface::{Shape, Surface},
+};
+
+pub const DEFAULT_COLUMNS: usize = 9;
+pub const DEFAULT_ROWS: usize = 9;
+pub const DEFAULT_MINES: usize = 10;
+
+const CELL_SIZE: usize = 24;
+const MARGIN: usize = 10;
+const HEADER_HEIGHT: usize = 40;
+
+const HIDDEN_COLOR: Color = Color::new(190, 190, 190);
+const REVEALED_COLOR: Color = Color::new(235, 235, 235);
+const MINE_COLOR: Color = Color::new(255, 90, 90);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CellState {
+    Hidden,
+    Flagged,
+    Revealed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GameState {
+    /// No mines are placed until the first reveal, so the first click is always safe
+    WaitingForFirstClick,
+    Playing,
+    Won,
+    Lost,
+}
+
+struct Cell {
+    button: Button,
+    state: CellState,
+    mine: bool,
+    adjacent_mines: u8,
+}
+
+pub struct Minesweeper {
+    columns: usize,
+    rows: usize,
+    mines: usize,
+
+    cells: Vec<Cell>,
+    game_state: GameState,
+    flags: usize,
+
+    new_game_button: Button,
+    mines_left_idx: usize,
+    status_idx: usize,
+}
+
+impl Minesweeper {
+    pub fn new(columns: usize, rows: usize, mines: usize) -> Self {
+        let columns = columns.max(2);
+        let rows = rows.max(2);
+
+        let (width, _) = Self::size(columns, rows);
+
+        Self {
+            columns,
+            rows,
+            // Leave at least one safe cell for the first click
+            mines: mines.min(columns * rows - 1),
+            cells: Vec::new(),
+            game_state: GameState::WaitingForFirstClick,
+            flags: 0,
+            new_game_button: Button::new(width - MARGIN - 60, 8, 60, 24, "New"),
+            mines_left_idx: 0,
+            status_idx: 0,
+        }
+    }
+
+    /// Size of the game content for the given board, used to size its window
+    pub fn size(columns: usize, rows: usize) -> (usize, usize) {
+        (
+            (2 * MARGIN + columns * CELL_SIZE).max(260),
+            HEADER_HEIGHT + rows * CELL_SIZE + MARGIN,
+        )
+    }
+
+    fn new_game(&mut self, surface: &mut Surface) {
+        for cell in &mut self.cells {
+            cell.state = CellState::Hidden;
+            cell.mine = false;
+            cell.adjacent_mines = 0;
+            cell.button.set_label("", surface);
+            cell.button.set_color(HIDDEN_COLOR, surface);
+        }
+
+        self.game_state = GameState::WaitingForFirstClick;
+        self.flags = 0;
+        self.update_header(surface);
+    }
+
+    /// Indices of the cells around the given one
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> + use<> {
+        let (columns, rows) = (self.columns as isize, self.rows as isize);
+        let column = (index % self.columns) as isize;
+        let row = (index / self.columns) as isize;
+
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx != 0 || dy != 0)
+            .map(move |(dx, dy)| (column + dx, row + dy))
+            .filter(move |&(x, y)| x >= 0 && y >= 0 && x < columns && y < rows)
+            .map(move |(x, y)| (y * columns + x) as usize)
+    }
+
+    fn place_mines(&mut self, safe_index: usize) {
+        let mut placed = 0;
+        while placed < self.mines {
+            let index = random::range(self.cells.len());
+            if index == safe_index || self.cells[index].mine {
+                continue;
+            }
+
+            self.cells[index].mine = true;
+            placed += 1;
+        }
+
+        for index in 0..self.cells.len() {
+            let count = self
+                .neighbours(index)
+                .filter(|&neighbour| self.cells[neighbour].mine)
+                .count();
+            self.cells[index].adjacent_mines = count as u8;
+        }
+    }
+
+    fn cell_at(&self, x: usize, y: usize) -> Option<usize> {
+        if x < MARGIN || y < HEADER_HEIGHT {
+            return None;
+        }
+
+        let column = (x - MARGIN) / CELL_SIZE;
+        let row = (y - HEADER_HEIGHT) / CELL_SIZE;
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+
+        Some(row * self.columns + column)
+    }
+
+    /// Left click reveals a cell
+    pub fn handle_mouse_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.new_game_button.contains(x, y) {
+            self.new_game(surface);
+            return;
+        }
+
+        if matches!(self.game_state, GameState::Won | GameState::Lost) {
+            return;
+        }
+
+        let Some(index) = self.cell_at(x, y) else {
+            return;
+        };
+        if self.cells[index].state != CellState::Hidden {
+            return;
+        }
+
+        if self.game_state == GameState::WaitingForFirstClick {
+            self.place_mines(index);
+            self.game_state = GameState::Playing;
+        }
+
+        if self.cells[index].mine {
+            self.game_state = GameState::Lost;
+            self.reveal_mines(surface);
+            self.cells[index].button.set_color(MINE_COLOR, surface);
+        } else {
+            self.reveal_from(index, surface);
+
+            let all_safe_revealed = self
+                .cells
+                .iter()
+                .all(|cell| cell.mine || cell.state == CellState::Revealed);
+            if all_safe_revealed {
+                self.game_state = GameState::Won;
+            }
+        }
+
+        self.update_header(surface);
+    }
+
+    /// Right click toggles a flag on a hidden cell
+    pub fn handle_mouse_right_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if matches!(self.game_state, GameState::Won | GameState::Lost) {
+            return;
+        }
+
+        let Some(index) = self.cell_at(x, y) else {
+            return;
+        };
+
+        let cell = &mut self.cells[index];
+        match cell.state {
+            CellState::Hidden => {
+                cell.state = CellState::Flagged;
+                cell.button.set_label("F", surface);
+                self.flags += 1;
+            }
+            CellState::Flagged => {
+                cell.state = CellState::Hidden;
+                cell.button.set_label("", surface);
+                self.flags -= 1;
+            }
+            CellState::Revealed => return,
+        }
+
+        self.update_header(surface);
+    }
+
+    /// Reveal a cell, and flood fill its neighbours if it has no adjacent mines
+    fn reveal_from(&mut self, start: usize, surface: &mut Surface) {
+        let mut stack = vec![start];
+
+        while let Some(index) = stack.pop() {
+            let cell = &mut self.cells[index];
+            if cell.state == CellState::Revealed || cell.mine {
+                continue;
+            }
+
+            // Flags placed on empty cells are removed when the region opens up
+            if cell.state == CellState::Flagged {
+                self.flags -= 1;
+            }
+            cell.state = CellState::Revealed;
+            cell.button.set_color(REVEALED_COLOR, surface);
+
+            if cell.adjacent_mines == 0 {
+                cell.button.set_label("", surface);
+                stack.extend(self.neighbours(index));
+            } else {
+                cell.button
+                    .set_label(&cell.adjacent_mines.to_string(), surface);
+            }
+        }
+    }
+
+    fn reveal_mines(&mut self, surface: &mut Surface) {
+        for cell in &mut self.cells {
+            if cell.mine {
+                cell.button.set_label("*", surface);
+            }
+        }
+    }
+
+    fn update_header(&mut self, surface: &mut Surface) {
+        let mines_left = self.mines as isize - self.flags as isize;
+        surface.update_text_content(self.mines_left_idx, format!("Mines: {}", mines_left), None);
+
+        let status = match self.game_state {
+            GameState::Won => "You win!",
+            GameState::Lost => "Boom!",
+            _ => "",
+        };
+        surface.update_text_content(self.status_idx, status.to_string(), None);
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    let (width, height) = Minesweeper::size(DEFAULT_COLUMNS, DEFAULT_ROWS);
+    window_manager.add_window(Window::new(
+        220,
+        140,
+        width,
+        height,
+        7,
+        Box::new(Minesweeper::new(
+            DEFAULT_COLUMNS,
+            DEFAULT_ROWS,
+            DEFAULT_MINES,
+        )),
+    ));
+}
+
+impl Application for Minesweeper {
+    fn title(&self) -> String {
+        "Minesweeper".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        Color::new(210, 210, 210)
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        self.mines_left_idx = surface.add_shape(Shape::Text {
+            x: MARGIN,
+            y: 12,
+            content: format!("Mines: {}", self.mines),
+            color: Color::BLACK,
+            background_color: Color::new(210, 210, 210),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        self.status_idx = surface.add_shape(Shape::Text {
+            x: MARGIN + 90,
+            y: 12,
+            content: "".to_string(),
+            color: Color::BLACK,
+            background_color: Color::new(210, 210, 210),
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        });
+
+        self.new_game_button.add_to_surface(surface);
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let mut button = Button::new(
+                    MARGIN + column * CELL_SIZE,
+                    HEADER_HEIGHT + row * CELL_SIZE,
+                    CELL_SIZE,
+                    CELL_SIZE,
+                    "",
+                )
+                .with_color(HIDDEN_COLOR);
+                button.add_to_surface(surface);
+
+                self.cells.push(Cell {
+                    button,
+                    state: CellState::Hidden,
+                    mine: false,
+                    adjacent_mines: 0,
+                });
+            }
+        }
+    }
+
+    fn render(&mut self, _surface: &mut Surface) {
+        // Cells are updated directly when they are clicked
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        self.handle_mouse_click(x, y, surface);
+        true
+    }
+
+    fn handle_right_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        self.handle_mouse_right_click(x, y, surface);
+    }
+}
@@ -1,7 +1,31 @@
+pub mod animation;
+pub mod application;
+pub mod associations;
+pub mod autosave;
 pub mod calculator;
+pub mod clipboard;
+pub mod clipboard_history;
+pub mod config;
+pub mod dmesg;
+pub mod file_sort;
 pub mod filemanager;
+pub mod image_viewer;
 pub mod input;
+pub mod lock_screen;
 pub mod main;
+pub mod memory_pressure;
+pub mod message_box;
+pub mod minesweeper;
 pub mod notepad;
+pub mod onscreen_keyboard;
+pub mod osd;
+pub mod paint;
+pub mod recent;
+pub mod repaint_debug;
 pub mod sysinfo;
+pub mod taskbar;
+pub mod toast;
+pub mod widgets;
 pub mod window_manager;
+pub mod window_positions;
+pub mod workspaces;
@@ -1,22 +1,52 @@
 use alloc::{
+    boxed::Box,
+    format,
     string::{String, ToString},
     vec::Vec,
 };
+use core::ops::Range;
 use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
-use pc_keyboard::KeyCode;
+use pc_keyboard::{KeyCode, Modifiers};
 
 use crate::{
+    desktop::{
+        application::Application,
+        autosave::AutoSave,
+        clipboard::Clip,
+        toast::{self, ToastKind},
+        widgets::text_input::{next_word_boundary, previous_word_boundary},
+        window_manager::{Window, WindowManager},
+    },
     framebuffer::Color,
-    fs::{fat32::FileEntry, manager::read_text_file},
-    serial_println,
+    fs::{
+        fat32::{FileCursor, FileEntry, open_flags},
+        manager::{OpenFileRef, read_file_chunk, root_directory_cluster, write_file_with_flags},
+    },
+    log,
+    serial::LogLevel,
     surface::{Shape, Surface},
+    time::get_utc_time,
 };
 
+/// Name for text that wasn't opened from a file
+const UNTITLED_FILENAME: &str = "NOTE.TXT";
+/// Files opened while a Notepad window is open become tabs of it
+const WINDOW_ID: usize = 2;
+
+/// Where the save state is shown, below the text
+const STATUS_X: usize = 440;
+const STATUS_Y: usize = 382;
+const STATUS_COLOR: Color = Color::new(130, 130, 130);
+
 pub struct Notepad {
     text_content: String,
     cursor_position: usize,
     scroll_offset: usize,
     display_lines: Vec<String>,
+    /// Byte offset in the text where each display line starts
+    line_starts: Vec<usize>,
+    /// Column the cursor goes back to when moving up and down over shorter lines
+    desired_column: Option<usize>,
     text_area_idx: usize,
     cursor_idx: usize,
     max_chars_per_line: usize,
@@ -25,82 +55,187 @@ pub struct Notepad {
     prev_cursor_x: usize,
     prev_cursor_y: usize,
     open_file: Option<FileEntry>,
+    /// Keeps the open file from being deleted under the editor
+    open_file_ref: Option<OpenFileRef>,
+    /// Cluster of the directory the open file is in, `None` for the root
+    directory: Option<u32>,
+    /// Rest of the open file, it is read while scrolling towards it
+    file_cursor: Option<FileCursor>,
+    /// Bytes of a character that was split between two chunks
+    partial_char: Vec<u8>,
+    autosave: AutoSave,
+    /// There are unsaved changes, the title starts with a '*'
+    modified: bool,
+    /// Whether the text is saved, shown in the corner
+    status: String,
+    previous_status: String,
+    status_idx: usize,
 }
 
 impl Notepad {
-    pub fn new(mut file_entry: Option<FileEntry>) -> Self {
-        let text_content = if let Some(ref file) = file_entry {
-            match read_text_file(file.first_cluster, file.size) {
-                Ok(content) => content,
-                Err(error) => {
-                    serial_println!("Failed to open file {}", file.name);
-                    serial_println!("Error: {}", error);
-
-                    file_entry = None;
-
-                    String::new()
-                }
-            }
-        } else {
-            String::new()
-        };
+    pub fn new(file_entry: Option<FileEntry>, directory: Option<u32>) -> Self {
+        let file_cursor = file_entry
+            .as_ref()
+            .map(|file| FileCursor::new(file.first_cluster, file.size));
+        let open_file_ref = file_entry
+            .as_ref()
+            .map(|file| OpenFileRef::new(file.first_cluster));
 
-        Self {
-            text_content,
+        let mut notepad = Self {
+            text_content: String::new(),
             cursor_position: 0,
             scroll_offset: 0,
             display_lines: Vec::new(),
+            line_starts: Vec::new(),
+            desired_column: None,
             text_area_idx: 0,
             cursor_idx: 0,
             max_chars_per_line: 84, // Approximate characters that fit in the text area
-            max_visible_lines: 22,  // Number of lines visible in the text area
+            max_visible_lines: 21,  // Number of lines visible above the status
             previous_content: String::new(),
             prev_cursor_x: 0,
             prev_cursor_y: 0,
             open_file: file_entry,
-        }
+            open_file_ref,
+            directory,
+            file_cursor,
+            partial_char: Vec::new(),
+            autosave: AutoSave::default(),
+            modified: false,
+            status: String::new(),
+            previous_status: String::new(),
+            status_idx: 0,
+        };
+
+        notepad.load_more_if_needed();
+        notepad
     }
 
-    pub fn init(&mut self, surface: &mut Surface) {
-        // Text content display
-        self.text_area_idx = surface.add_shape(Shape::Text {
-            x: 5,
-            y: 5,
-            content: self.get_display_text(),
-            color: Color::BLACK,
-            background_color: Color::WHITE,
-            font_size: RasterHeight::Size16,
-            font_weight: FontWeight::Regular,
-            hide: false,
-        });
+    /// Read chunks of the open file until there is a screen of text past the visible one,
+    /// or the cursor is no longer at the end of the loaded text
+    fn load_more_if_needed(&mut self) {
+        while let Some(cursor) = &mut self.file_cursor {
+            let enough_lines =
+                self.display_lines.len() > self.scroll_offset + 2 * self.max_visible_lines;
+            if enough_lines && self.cursor_position < self.text_content.len() {
+                break;
+            }
 
-        // Cursor (simple vertical line)
-        self.cursor_idx = surface.add_shape(Shape::Rectangle {
-            x: 5,
-            y: 5,
-            width: 1,
-            height: 16,
-            color: Color::BLACK,
-            filled: true,
-            hide: false,
-        });
+            match read_file_chunk(cursor) {
+                Ok(chunk) => self.append_chunk(&chunk),
+                Err(error) => {
+                    let name = self
+                        .open_file
+                        .as_ref()
+                        .map_or("", |file| file.name.as_str());
+                    log!(LogLevel::Warn, "Failed to read file {}: {}", name, error);
 
+                    self.file_cursor = None;
+                }
+            }
+
+            if self.file_cursor.is_some_and(|cursor| cursor.is_at_end()) {
+                self.file_cursor = None;
+            }
+
+            self.update_display_lines();
+        }
+    }
+
+    /// Read the rest of the open file
+    fn load_all(&mut self) -> Result<(), &'static str> {
+        loop {
+            let chunk = match &mut self.file_cursor {
+                Some(cursor) if !cursor.is_at_end() => read_file_chunk(cursor)?,
+                _ => break,
+            };
+            self.append_chunk(&chunk);
+        }
+        self.file_cursor = None;
         self.update_display_lines();
+        Ok(())
+    }
+
+    /// Write the text to the open file, or to a new file in the root directory.
+    /// The file is replaced in one step, it is never missing if saving fails.
+    pub fn save(&mut self) -> Result<(), &'static str> {
+        // Everything has to be loaded, or the unread end of the file would be cut off
+        self.load_all()?;
+
+        let directory = match self.directory {
+            Some(directory) => directory,
+            None => root_directory_cluster()?,
+        };
+
+        // A new note must not replace a file that happens to have the same name
+        let (filename, flags) = match &self.open_file {
+            Some(file) => (file.name.clone(), open_flags::TRUNCATE),
+            None => (
+                UNTITLED_FILENAME.to_string(),
+                open_flags::CREATE | open_flags::EXCLUSIVE,
+            ),
+        };
+
+        let file =
+            write_file_with_flags(directory, &filename, self.text_content.as_bytes(), flags)?;
+        log!(LogLevel::Info, "Notepad: saved {}", file.name);
+
+        let time = get_utc_time();
+        self.status = format!("Saved {:02}:{:02}", time.hours, time.minutes);
+        self.autosave.mark_saved();
+        self.modified = false;
+
+        // Saving may have moved the file to other clusters
+        self.open_file_ref = Some(OpenFileRef::new(file.first_cluster));
+        self.open_file = Some(file);
+        self.directory = Some(directory);
+        Ok(())
+    }
+
+    fn mark_changed(&mut self) {
+        self.autosave.mark_changed();
+        self.modified = true;
+        self.status = "Unsaved changes".to_string();
+    }
+
+    fn append_chunk(&mut self, chunk: &[u8]) {
+        let mut bytes = core::mem::take(&mut self.partial_char);
+        bytes.extend_from_slice(chunk);
+
+        match core::str::from_utf8(&bytes) {
+            Ok(text) => self.text_content.push_str(text),
+            // The chunk ends in the middle of a character, keep its start for the next one
+            Err(error) if error.error_len().is_none() => {
+                let valid = error.valid_up_to();
+                self.text_content
+                    .push_str(core::str::from_utf8(&bytes[..valid]).unwrap_or_default());
+                self.partial_char = bytes[valid..].to_vec();
+            }
+            Err(_) => self.text_content.push_str(&String::from_utf8_lossy(&bytes)),
+        }
     }
 
     pub fn handle_char_input(&mut self, ch: char) {
+        self.desired_column = None;
+
         match ch {
             '\u{08}' => {
                 // Backspace
-                if self.cursor_position > 0 {
-                    self.text_content.remove(self.cursor_position - 1);
-                    self.cursor_position -= 1;
+                if let Some(previous) = self.text_content[..self.cursor_position]
+                    .chars()
+                    .next_back()
+                {
+                    self.cursor_position -= previous.len_utf8();
+                    self.text_content.remove(self.cursor_position);
+                    self.mark_changed();
                 }
             }
+            '\u{7f}' => self.delete_forward(),
             '\r' | '\n' => {
                 // Enter - add newline
                 self.text_content.insert(self.cursor_position, '\n');
                 self.cursor_position += 1;
+                self.mark_changed();
             }
             ch if ch.is_control() => {
                 // Ignore other control characters
@@ -108,66 +243,192 @@ impl Notepad {
             _ => {
                 // Regular character
                 self.text_content.insert(self.cursor_position, ch);
-                self.cursor_position += 1;
+                self.cursor_position += ch.len_utf8();
+                self.mark_changed();
             }
         }
 
         self.update_display_lines();
         self.update_scroll_if_needed();
+        self.load_more_if_needed();
     }
 
-    pub fn handle_key_input(&mut self, key: KeyCode) {
+    /// Arrow keys, Home, End and Delete. Held keys arrive again with every repeat of the
+    /// keyboard, so they only move the cursor and keep it in view.
+    pub fn handle_key_input(&mut self, key: KeyCode, modifiers: &Modifiers) {
+        let vertical = matches!(key, KeyCode::ArrowUp | KeyCode::ArrowDown);
+        if !vertical {
+            self.desired_column = None;
+        }
+
         match key {
             KeyCode::ArrowLeft => {
-                if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
+                if let Some(previous) = self.text_content[..self.cursor_position]
+                    .chars()
+                    .next_back()
+                {
+                    self.cursor_position -= previous.len_utf8();
                 }
             }
             KeyCode::ArrowRight => {
-                if self.cursor_position < self.text_content.len() {
-                    self.cursor_position += 1;
+                if let Some(next) = self.text_content[self.cursor_position..].chars().next() {
+                    self.cursor_position += next.len_utf8();
                 }
             }
+            KeyCode::ArrowUp => {
+                let line = self.cursor_line();
+                if line > 0 {
+                    self.move_to_line(line - 1);
+                }
+            }
+            KeyCode::ArrowDown => {
+                let line = self.cursor_line();
+                if line + 1 < self.display_lines.len() {
+                    self.move_to_line(line + 1);
+                }
+            }
+            KeyCode::Home if modifiers.is_ctrl() => self.cursor_position = 0,
+            KeyCode::End if modifiers.is_ctrl() => {
+                if let Err(e) = self.load_all() {
+                    log!(LogLevel::Warn, "Notepad: failed to read file: {}", e);
+                }
+                self.cursor_position = self.text_content.len();
+            }
+            KeyCode::Home => self.cursor_position = self.line_starts[self.cursor_line()],
+            KeyCode::End => {
+                let line = self.cursor_line();
+                self.cursor_position = self.line_end(line);
+            }
+            KeyCode::Backspace if modifiers.is_ctrl() => {
+                let start = previous_word_boundary(&self.text_content, self.cursor_position);
+                self.delete_range(start..self.cursor_position);
+                self.cursor_position = start;
+                self.update_display_lines();
+            }
+            KeyCode::Delete if modifiers.is_ctrl() => {
+                let end = next_word_boundary(&self.text_content, self.cursor_position);
+                self.delete_range(self.cursor_position..end);
+                self.update_display_lines();
+            }
+            KeyCode::Delete => {
+                self.delete_forward();
+                self.update_display_lines();
+            }
             _ => {}
         }
+
+        self.update_scroll_if_needed();
+        self.load_more_if_needed();
+    }
+
+    fn delete_forward(&mut self) {
+        if self.cursor_position < self.text_content.len() {
+            self.text_content.remove(self.cursor_position);
+            self.mark_changed();
+        }
+    }
+
+    /// The line the cursor is on, without its line break
+    fn current_line(&self) -> &str {
+        let before = &self.text_content[..self.cursor_position];
+        let start = before.rfind('\n').map_or(0, |i| i + 1);
+        let end = self.text_content[self.cursor_position..]
+            .find('\n')
+            .map_or(self.text_content.len(), |i| self.cursor_position + i);
+        &self.text_content[start..end]
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        // Line breaks are stored as \n only
+        let text = text.replace('\r', "");
+        if text.is_empty() {
+            return;
+        }
+
+        self.text_content.insert_str(self.cursor_position, &text);
+        self.cursor_position += text.len();
+        self.desired_column = None;
+        self.mark_changed();
+
+        self.update_display_lines();
+        self.update_scroll_if_needed();
+        self.load_more_if_needed();
+    }
+
+    fn delete_range(&mut self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.text_content.replace_range(range, "");
+            self.mark_changed();
+        }
+    }
+
+    /// Display line the cursor is on
+    fn cursor_line(&self) -> usize {
+        // A cursor at the point where a long line wraps shows at the start of the next line
+        self.line_starts
+            .partition_point(|&start| start <= self.cursor_position)
+            .saturating_sub(1)
+    }
+
+    /// Offset where the text of a display line ends, before its newline
+    fn line_end(&self, line: usize) -> usize {
+        let end = self.line_starts[line] + self.display_lines[line].len();
+
+        // The end of a wrapped line is the start of the next, stay before its last character
+        let wrapped = self.line_starts.get(line + 1) == Some(&end);
+        match self.display_lines[line].chars().next_back() {
+            Some(last) if wrapped => end - last.len_utf8(),
+            _ => end,
+        }
+    }
+
+    /// Put the cursor on another display line, in the column it was in before moving up or
+    /// down, or at the end of the line if it is shorter
+    fn move_to_line(&mut self, line: usize) {
+        let start = self.line_starts[self.cursor_line()];
+        let column = *self.desired_column.get_or_insert_with(|| {
+            self.text_content[start..self.cursor_position]
+                .chars()
+                .count()
+        });
+
+        let start = self.line_starts[line];
+        let offset = self.display_lines[line]
+            .char_indices()
+            .nth(column)
+            .map_or(self.display_lines[line].len(), |(offset, _)| offset);
+        self.cursor_position = (start + offset).min(self.line_end(line));
     }
 
     fn update_display_lines(&mut self) {
         self.display_lines.clear();
+        self.line_starts.clear();
 
         // Split text into lines and wrap long lines
-        let lines: Vec<&str> = self.text_content.split('\n').collect();
-
-        for line in lines {
-            if line.len() <= self.max_chars_per_line {
-                self.display_lines.push(line.to_string());
-            } else {
-                // Wrap long lines
-                let mut remaining = line;
-                while remaining.len() > self.max_chars_per_line {
-                    let (chunk, rest) = remaining.split_at(self.max_chars_per_line);
-                    self.display_lines.push(chunk.to_string());
-                    remaining = rest;
-                }
-                if !remaining.is_empty() {
-                    self.display_lines.push(remaining.to_string());
-                }
+        let mut line_start = 0;
+        for line in self.text_content.split('\n') {
+            let mut remaining = line;
+            let mut start = line_start;
+
+            // Wrap long lines, on character boundaries
+            while let Some((split, _)) = remaining.char_indices().nth(self.max_chars_per_line) {
+                let (chunk, rest) = remaining.split_at(split);
+                self.display_lines.push(chunk.to_string());
+                self.line_starts.push(start);
+                start += split;
+                remaining = rest;
+            }
+            if !remaining.is_empty() || start == line_start {
+                self.display_lines.push(remaining.to_string());
+                self.line_starts.push(start);
             }
+
+            line_start += line.len() + 1; // +1 for newline
         }
     }
 
     fn update_scroll_if_needed(&mut self) {
-        // Calculate which line the cursor is on
-        let mut char_count = 0;
-        let mut cursor_line = 0;
-
-        for (line_idx, line) in self.display_lines.iter().enumerate() {
-            if char_count + line.len() + 1 > self.cursor_position {
-                cursor_line = line_idx;
-                break;
-            }
-            char_count += line.len() + 1; // +1 for newline
-        }
+        let cursor_line = self.cursor_line();
 
         // Adjust scroll if cursor is outside visible area
         if cursor_line < self.scroll_offset {
@@ -191,28 +452,15 @@ impl Notepad {
 
     fn get_cursor_visual_position(&self) -> (usize, usize) {
         // Calculate cursor position relative to the visible text area
-        let mut char_count = 0;
-        let mut line_in_visible = 0;
-        let mut col_in_line = 0;
-
-        for (line_idx, line) in self.display_lines.iter().enumerate() {
-            if line_idx < self.scroll_offset {
-                char_count += line.len() + 1;
-                continue;
-            }
-
-            if char_count + line.len() + 1 > self.cursor_position {
-                col_in_line = self.cursor_position - char_count;
-                break;
-            }
-
-            char_count += line.len() + 1;
-            line_in_visible += 1;
-
-            if line_in_visible >= self.max_visible_lines {
-                break;
-            }
-        }
+        let cursor_line = self.cursor_line();
+        let line_in_visible = cursor_line
+            .saturating_sub(self.scroll_offset)
+            .min(self.max_visible_lines - 1);
+        let col_in_line = self.line_starts.get(cursor_line).map_or(0, |&start| {
+            self.text_content[start..self.cursor_position]
+                .chars()
+                .count()
+        });
 
         // Convert to pixel coordinates (approximate)
         let x = 3 + col_in_line * 7; // 8 pixels per character (approximate)
@@ -220,8 +468,80 @@ impl Notepad {
 
         (x, y)
     }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    window_manager.add_window(Window::new(
+        150,
+        150,
+        600,
+        400,
+        WINDOW_ID,
+        Box::new(Notepad::new(None, None)),
+    ));
+}
 
-    pub fn render(&mut self, surface: &mut Surface) {
+/// Open a file in Notepad, as a new tab if a Notepad window is open
+pub fn launch_with_file(window_manager: &mut WindowManager, file: FileEntry, directory: u32) {
+    let notepad = Box::new(Notepad::new(Some(file), Some(directory)));
+    if let Err(notepad) = window_manager.add_tab(WINDOW_ID, notepad) {
+        window_manager.add_window(Window::new(150, 150, 600, 400, WINDOW_ID, notepad));
+    }
+}
+
+impl Application for Notepad {
+    fn title(&self) -> String {
+        let name = self
+            .open_file
+            .as_ref()
+            .map_or("Untitled", |file| file.name.as_str());
+        let marker = if self.modified { "*" } else { "" };
+        format!("{}{} - Notepad", marker, name)
+    }
+
+    fn background_color(&self) -> Color {
+        Color::WHITE
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        // Text content display
+        self.text_area_idx = surface.add_shape(Shape::Text {
+            x: 5,
+            y: 5,
+            content: self.get_display_text(),
+            color: Color::BLACK,
+            background_color: Color::WHITE,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        // Cursor (simple vertical line)
+        self.cursor_idx = surface.add_shape(Shape::Rectangle {
+            x: 5,
+            y: 5,
+            width: 1,
+            height: 16,
+            color: Color::BLACK,
+            filled: true,
+            hide: false,
+        });
+
+        self.status_idx = surface.add_shape(Shape::Text {
+            x: STATUS_X,
+            y: STATUS_Y,
+            content: self.status.clone(),
+            color: STATUS_COLOR,
+            background_color: Color::WHITE,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        self.update_display_lines();
+    }
+
+    fn render(&mut self, surface: &mut Surface) {
         let current_display = self.get_display_text();
 
         // Only update if content changed
@@ -230,6 +550,13 @@ impl Notepad {
             self.previous_content = current_display;
         }
 
+        if self.status != self.previous_status {
+            // Every status is padded to the same width, so a shorter one covers a longer one
+            let padded = format!("{:<16}", self.status);
+            surface.update_text_content(self.status_idx, padded, None);
+            self.previous_status = self.status.clone();
+        }
+
         // Update cursor position
         let (cursor_x, cursor_y) = self.get_cursor_visual_position();
         if cursor_x != self.prev_cursor_x || cursor_y != self.prev_cursor_y {
@@ -239,4 +566,46 @@ impl Notepad {
             self.prev_cursor_y = cursor_y;
         }
     }
+
+    /// Whether there are unsaved changes and the auto-save interval ran out
+    fn is_autosave_due(&self) -> bool {
+        self.autosave.is_due()
+    }
+
+    /// Save the unsaved changes, if that fails it is tried again after another interval
+    fn autosave(&mut self, _surface: &mut Surface) {
+        if let Err(e) = self.save() {
+            log!(LogLevel::Warn, "Notepad: auto-save failed: {}", e);
+            self.status = "Auto-save failed".to_string();
+            self.autosave.retry_later();
+        }
+    }
+
+    fn handle_char(&mut self, ch: char, _surface: &mut Surface) {
+        self.handle_char_input(ch);
+    }
+
+    fn handle_key(&mut self, key: KeyCode, modifiers: &Modifiers, _surface: &mut Surface) {
+        self.handle_key_input(key, modifiers);
+    }
+
+    /// Without a selection, the line the cursor is on is copied
+    fn copy(&self) -> Option<Clip> {
+        let line = self.current_line();
+        (!line.is_empty()).then(|| Clip::Text(line.to_string()))
+    }
+
+    fn paste(&mut self, clip: &Clip, _surface: &mut Surface) {
+        self.insert_text(clip.text());
+    }
+
+    fn handle_save(&mut self) {
+        match self.save() {
+            Ok(()) => toast::notify("File saved", ToastKind::Success),
+            Err(e) => {
+                log!(LogLevel::Warn, "Notepad: save failed: {}", e);
+                toast::notify(&format!("Save failed: {}", e), ToastKind::Error);
+            }
+        }
+    }
 }
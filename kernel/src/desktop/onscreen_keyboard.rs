@@ -0,0 +1,233 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use pc_keyboard::{DecodedKey, KeyCode, Modifiers};
+
+use crate::{
+    desktop::{
+        application::Application,
+        input::add_synthetic_key,
+        widgets::button::Button,
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::Color,
+    surface::Surface,
+};
+
+const KEY_SIZE: usize = 36;
+const KEY_SPACING: usize = 4;
+const MARGIN: usize = 5;
+
+const TOGGLE_ON_COLOR: Color = Color::new(150, 200, 255);
+const TOGGLE_OFF_COLOR: Color = Color::new(220, 220, 220);
+
+const CHARACTER_ROWS: [(&str, &str); 4] = [
+    ("1234567890", "!@#$%^&*()"),
+    ("qwertyuiop", "QWERTYUIOP"),
+    ("asdfghjkl-", "ASDFGHJKL_"),
+    ("zxcvbnm,./", "ZXCVBNM<>?"),
+];
+
+/// The character a key produces with the given modifier state
+fn key_char(row: usize, col: usize, shift: bool, caps_lock: bool) -> char {
+    let (lower, upper) = CHARACTER_ROWS[row];
+    let is_letter = lower.as_bytes()[col].is_ascii_alphabetic();
+
+    if shift ^ (caps_lock && is_letter) {
+        upper.as_bytes()[col] as char
+    } else {
+        lower.as_bytes()[col] as char
+    }
+}
+
+#[derive(Clone, Copy)]
+enum KeyAction {
+    /// Index into `CHARACTER_ROWS`, and the column in that row
+    Character(usize, usize),
+    Shift,
+    CapsLock,
+    Space,
+    Backspace,
+    Enter,
+    Raw(KeyCode),
+}
+
+/// A clickable keyboard that feeds keys into the same input path as the PS/2 keyboard
+pub struct OnScreenKeyboard {
+    keys: Vec<(Button, KeyAction)>,
+    shift: bool,
+    caps_lock: bool,
+}
+
+impl OnScreenKeyboard {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            shift: false,
+            caps_lock: false,
+        }
+    }
+
+    /// Size of the keyboard content, used to size its window
+    pub fn size() -> (usize, usize) {
+        let step = KEY_SIZE + KEY_SPACING;
+        (
+            2 * MARGIN + 10 * step - KEY_SPACING,
+            2 * MARGIN + (CHARACTER_ROWS.len() + 1) * step - KEY_SPACING,
+        )
+    }
+
+    pub fn handle_mouse_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        let Some(action) = self
+            .keys
+            .iter()
+            .find(|(button, _)| button.contains(x, y))
+            .map(|(_, action)| *action)
+        else {
+            return;
+        };
+
+        match action {
+            KeyAction::Character(row, col) => {
+                let c = key_char(row, col, self.shift, self.caps_lock);
+                add_synthetic_key(DecodedKey::Unicode(c));
+
+                // Shift only applies to the next key
+                if self.shift {
+                    self.shift = false;
+                    self.update_labels(surface);
+                }
+            }
+            KeyAction::Shift => {
+                self.shift = !self.shift;
+                self.update_labels(surface);
+            }
+            KeyAction::CapsLock => {
+                self.caps_lock = !self.caps_lock;
+                self.update_labels(surface);
+            }
+            KeyAction::Space => add_synthetic_key(DecodedKey::Unicode(' ')),
+            KeyAction::Backspace => add_synthetic_key(DecodedKey::Unicode('\u{08}')),
+            KeyAction::Enter => add_synthetic_key(DecodedKey::Unicode('\n')),
+            KeyAction::Raw(key) => add_synthetic_key(DecodedKey::RawKey(key)),
+        }
+    }
+
+    fn update_labels(&mut self, surface: &mut Surface) {
+        for (button, action) in &mut self.keys {
+            match *action {
+                KeyAction::Character(row, col) => {
+                    let mut label = [0u8; 4];
+                    let c = key_char(row, col, self.shift, self.caps_lock);
+                    button.set_label(c.encode_utf8(&mut label), surface);
+                }
+                KeyAction::Shift => button.set_color(
+                    if self.shift {
+                        TOGGLE_ON_COLOR
+                    } else {
+                        TOGGLE_OFF_COLOR
+                    },
+                    surface,
+                ),
+                KeyAction::CapsLock => button.set_color(
+                    if self.caps_lock {
+                        TOGGLE_ON_COLOR
+                    } else {
+                        TOGGLE_OFF_COLOR
+                    },
+                    surface,
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    let (width, height) = OnScreenKeyboard::size();
+    window_manager.add_window(Window::new(
+        100,
+        400,
+        width,
+        height,
+        5,
+        Box::new(OnScreenKeyboard::new()),
+    ));
+}
+
+impl Application for OnScreenKeyboard {
+    fn title(&self) -> String {
+        "Keyboard".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        Color::new(175, 175, 175)
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        let step = KEY_SIZE + KEY_SPACING;
+
+        for (row, (chars, _)) in CHARACTER_ROWS.iter().enumerate() {
+            for (col, c) in chars.chars().enumerate() {
+                let mut label = [0u8; 4];
+                self.keys.push((
+                    Button::new(
+                        MARGIN + col * step,
+                        MARGIN + row * step,
+                        KEY_SIZE,
+                        KEY_SIZE,
+                        c.encode_utf8(&mut label),
+                    ),
+                    KeyAction::Character(row, col),
+                ));
+            }
+        }
+
+        // Bottom row with the special keys
+        let y = MARGIN + CHARACTER_ROWS.len() * step;
+        let special_keys = [
+            ("Shift", 2, KeyAction::Shift),
+            ("Caps", 2, KeyAction::CapsLock),
+            ("Space", 3, KeyAction::Space),
+            ("<-", 1, KeyAction::Raw(KeyCode::ArrowLeft)),
+            ("->", 1, KeyAction::Raw(KeyCode::ArrowRight)),
+            ("Bksp", 1, KeyAction::Backspace),
+        ];
+
+        let mut x = MARGIN;
+        for (label, width_in_keys, action) in special_keys {
+            let width = width_in_keys * step - KEY_SPACING;
+            self.keys
+                .push((Button::new(x, y, width, KEY_SIZE, label), action));
+            x += width + KEY_SPACING;
+        }
+
+        // Enter takes up the rest of the row
+        let row_end = MARGIN + 10 * step - KEY_SPACING;
+        self.keys.push((
+            Button::new(x, y, row_end - x, KEY_SIZE, "Enter"),
+            KeyAction::Enter,
+        ));
+
+        for (button, _) in &mut self.keys {
+            button.add_to_surface(surface);
+        }
+    }
+
+    fn render(&mut self, _surface: &mut Surface) {
+        // Everything is updated directly when a key is clicked
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        self.handle_mouse_click(x, y, surface);
+        true
+    }
+}
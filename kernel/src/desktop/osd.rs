@@ -0,0 +1,140 @@
+//! A bar over the desktop showing a level a hotkey just changed, like the mouse speed.
+//!
+//! [`show`] can be called from anywhere, the window manager draws the bar over all windows
+//! with its next frame. A new level replaces the one shown and starts the time again. Like
+//! toasts it fades on its own and doesn't take input.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use spin::Mutex;
+
+use crate::{
+    desktop::{taskbar, toast::fade_alpha, widgets::progress_bar::ProgressBar},
+    framebuffer::{Color, FrameBufferWriter},
+    interrupts::ticks,
+    surface::{Rect, Shape, Surface},
+};
+
+/// How long the bar is shown after the last change, including the fade
+const DURATION_MS: u64 = 1500;
+const FADE_MS: u64 = 400;
+const WIDTH: usize = 260;
+const HEIGHT: usize = 56;
+const PADDING: usize = 12;
+const BACKGROUND_COLOR: Color = Color::new(40, 40, 40);
+
+/// A level waiting for the next frame, with its label
+static PENDING: Mutex<Option<(String, usize)>> = Mutex::new(None);
+
+/// Show a level in percent for a moment
+pub fn show(label: &str, percent: usize) {
+    *PENDING.lock() = Some((label.to_string(), percent));
+}
+
+struct Shown {
+    surface: Surface,
+    bar: ProgressBar,
+    label_idx: usize,
+    rect: Rect,
+    shown_at: u64,
+}
+
+impl Shown {
+    fn new(label: String, percent: usize, rect: Rect) -> Self {
+        let mut surface = Surface::new(WIDTH, HEIGHT, BACKGROUND_COLOR);
+        let label_idx = surface.add_shape(Shape::Text {
+            x: PADDING,
+            y: 8,
+            content: label,
+            color: Color::WHITE,
+            background_color: BACKGROUND_COLOR,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        // Room for the percentage right of the bar
+        let mut bar = ProgressBar::new(PADDING, 34, WIDTH - 2 * PADDING - 50)
+            .with_percentage(BACKGROUND_COLOR);
+        bar.add_to_surface(&mut surface);
+        bar.set_value(percent, &mut surface);
+
+        Self {
+            surface,
+            bar,
+            label_idx,
+            rect,
+            shown_at: ticks(),
+        }
+    }
+
+    fn update(&mut self, label: String, percent: usize) {
+        // The old label may be longer
+        if let Some(bounds) = self.surface.get_shape_bounds(self.label_idx) {
+            self.surface.mark_region_dirty(bounds);
+        }
+        self.surface
+            .update_text_content(self.label_idx, label, None);
+        self.bar.set_value(percent, &mut self.surface);
+        self.shown_at = ticks();
+    }
+}
+
+/// The bar, centered in the lower part of the desktop while there is one
+#[derive(Default)]
+pub struct Osd {
+    shown: Option<Shown>,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draw the bar over everything else. While it fades and once it's gone, its area is added
+    /// to `exposed` to be drawn again from below.
+    pub fn render(
+        &mut self,
+        framebuffer: &mut FrameBufferWriter,
+        damage: &mut Vec<Rect>,
+        exposed: &mut Vec<Rect>,
+    ) {
+        if let Some((label, percent)) = PENDING.lock().take() {
+            match &mut self.shown {
+                Some(shown) => shown.update(label, percent),
+                None => {
+                    let area = taskbar::layout().desktop_area();
+                    let rect = Rect::new(
+                        area.x + area.width.saturating_sub(WIDTH) / 2,
+                        area.y + (area.height * 3 / 4).saturating_sub(HEIGHT / 2),
+                        WIDTH,
+                        HEIGHT,
+                    );
+                    self.shown = Some(Shown::new(label, percent, rect));
+                }
+            }
+        }
+
+        let Some(shown) = &mut self.shown else {
+            return;
+        };
+        let rect = shown.rect;
+        match fade_alpha(ticks().saturating_sub(shown.shown_at), DURATION_MS, FADE_MS) {
+            Some(255) => shown.surface.render(framebuffer, rect.x, rect.y, damage),
+            Some(alpha) => {
+                shown
+                    .surface
+                    .render_blended(framebuffer, rect.x, rect.y, alpha);
+                damage.push(rect);
+                exposed.push(rect);
+            }
+            None => {
+                exposed.push(rect);
+                self.shown = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::Modifiers;
+
+use crate::{
+    bmp,
+    desktop::{
+        application::Application,
+        autosave::AutoSave,
+        widgets::{
+            button::Button,
+            color_picker::{ColorPicker, SWATCH_SIZE},
+        },
+        window_manager::{Window, WindowManager},
+    },
+    framebuffer::Color,
+    fs::{
+        fat32::open_flags,
+        manager::{root_directory_cluster, write_file_with_flags},
+    },
+    surface::{Rect, Shape, Surface},
+};
+
+pub const WINDOW_WIDTH: usize = 420;
+pub const WINDOW_HEIGHT: usize = 424;
+
+const CANVAS_X: usize = 10;
+const CANVAS_Y: usize = 40;
+const CANVAS_WIDTH: usize = 400;
+const CANVAS_HEIGHT: usize = 290;
+
+const TOOLBAR_Y: usize = 8;
+const TOOLBAR_BUTTON_SIZE: usize = SWATCH_SIZE;
+
+/// The color sliders go below the canvas, the status below them
+const SLIDERS_Y: usize = CANVAS_Y + CANVAS_HEIGHT + 8;
+const SLIDERS_WIDTH: usize = 200;
+const STATUS_Y: usize = SLIDERS_Y + 62;
+
+const BACKGROUND_COLOR: Color = Color::new(240, 240, 240);
+
+const SAVE_FILENAME: &str = "PAINT.BMP";
+
+const SELECTED_COLOR: Color = Color::new(150, 200, 255);
+const UNSELECTED_COLOR: Color = Color::new(220, 220, 220);
+
+const PALETTE: [Color; 8] = [
+    Color::BLACK,
+    Color::WHITE,
+    Color::new(128, 128, 128),
+    Color::RED,
+    Color::new(255, 165, 0),
+    Color::new(255, 255, 0),
+    Color::new(0, 160, 0),
+    Color::BLUE,
+];
+
+const BRUSH_SIZES: [usize; 3] = [1, 3, 5];
+
+pub struct Paint {
+    color_picker: ColorPicker,
+    brush_buttons: Vec<Button>,
+    clear_button: Button,
+    save_button: Button,
+
+    brush_size: usize,
+    /// Last canvas position of the current stroke, None when not drawing
+    last_point: Option<(usize, usize)>,
+
+    autosave: AutoSave,
+
+    canvas_idx: usize,
+    status_idx: usize,
+}
+
+impl Paint {
+    pub fn new() -> Self {
+        let step = TOOLBAR_BUTTON_SIZE + 4;
+
+        let brush_x = CANVAS_X + PALETTE.len() * step + 10;
+        let brush_buttons = BRUSH_SIZES
+            .iter()
+            .enumerate()
+            .map(|(i, size)| {
+                Button::new(
+                    brush_x + i * step,
+                    TOOLBAR_Y,
+                    TOOLBAR_BUTTON_SIZE,
+                    TOOLBAR_BUTTON_SIZE,
+                    &size.to_string(),
+                )
+            })
+            .collect();
+
+        let actions_x = brush_x + BRUSH_SIZES.len() * step + 10;
+
+        Self {
+            color_picker: ColorPicker::new(CANVAS_X, TOOLBAR_Y, &PALETTE, PALETTE.len())
+                .with_sliders(CANVAS_X, SLIDERS_Y, SLIDERS_WIDTH, BACKGROUND_COLOR),
+            brush_buttons,
+            clear_button: Button::new(actions_x, TOOLBAR_Y, 50, TOOLBAR_BUTTON_SIZE, "Clear"),
+            save_button: Button::new(actions_x + 55, TOOLBAR_Y, 50, TOOLBAR_BUTTON_SIZE, "Save"),
+            brush_size: BRUSH_SIZES[0],
+            last_point: None,
+            autosave: AutoSave::default(),
+            canvas_idx: 0,
+            status_idx: 0,
+        }
+    }
+
+    fn canvas_rect() -> Rect {
+        Rect::new(CANVAS_X, CANVAS_Y, CANVAS_WIDTH, CANVAS_HEIGHT)
+    }
+
+    /// Convert window coordinates to canvas coordinates, clamped to the canvas
+    fn to_canvas(x: usize, y: usize) -> (usize, usize) {
+        (
+            x.saturating_sub(CANVAS_X).min(CANVAS_WIDTH - 1),
+            y.saturating_sub(CANVAS_Y).min(CANVAS_HEIGHT - 1),
+        )
+    }
+
+    pub fn handle_mouse_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.color_picker.handle_click(x, y, surface) {
+            return;
+        }
+
+        if let Some(index) = self.brush_buttons.iter().position(|b| b.contains(x, y)) {
+            self.brush_size = BRUSH_SIZES[index];
+
+            for (i, button) in self.brush_buttons.iter_mut().enumerate() {
+                let color = if i == index {
+                    SELECTED_COLOR
+                } else {
+                    UNSELECTED_COLOR
+                };
+                button.set_color(color, surface);
+            }
+            return;
+        }
+
+        if self.clear_button.contains(x, y) {
+            if let Some(pixels) = surface.image_pixels_mut(self.canvas_idx) {
+                pixels.fill(Color::WHITE);
+            }
+            surface.mark_region_dirty(Self::canvas_rect());
+            self.autosave.mark_changed();
+            self.set_status("Canvas cleared", surface);
+            return;
+        }
+
+        if self.save_button.contains(x, y) {
+            let status = match self.save(surface) {
+                Ok(()) => format!("Saved as {}", SAVE_FILENAME),
+                Err(e) => format!("Save failed: {}", e),
+            };
+            self.set_status(&status, surface);
+        }
+    }
+
+    fn save(&mut self, surface: &Surface) -> Result<(), &'static str> {
+        let pixels = surface
+            .image_pixels(self.canvas_idx)
+            .ok_or("Canvas not found")?;
+        let data = bmp::encode(CANVAS_WIDTH, CANVAS_HEIGHT, pixels);
+
+        // Replace the previous drawing, it's fine if there is none yet
+        write_file_with_flags(
+            root_directory_cluster()?,
+            SAVE_FILENAME,
+            &data,
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )?;
+        self.autosave.mark_saved();
+        Ok(())
+    }
+
+    fn set_status(&self, status: &str, surface: &mut Surface) {
+        surface.update_text_content(self.status_idx, status.to_string(), None);
+    }
+
+    /// Stamp the brush along the line between two canvas points (Bresenham)
+    fn draw_line(&self, from: (usize, usize), to: (usize, usize), surface: &mut Surface) {
+        let Some(pixels) = surface.image_pixels_mut(self.canvas_idx) else {
+            return;
+        };
+
+        let (mut x, mut y) = (from.0 as isize, from.1 as isize);
+        let (end_x, end_y) = (to.0 as isize, to.1 as isize);
+
+        let dx = (end_x - x).abs();
+        let dy = -(end_y - y).abs();
+        let step_x = if x < end_x { 1 } else { -1 };
+        let step_y = if y < end_y { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            stamp(pixels, x, y, self.brush_size, self.color_picker.color());
+
+            if x == end_x && y == end_y {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+
+        // Only the part of the canvas under the segment has to be redrawn
+        let radius = self.brush_size / 2;
+        let min_x = from.0.min(to.0).saturating_sub(radius);
+        let min_y = from.1.min(to.1).saturating_sub(radius);
+        let max_x = (from.0.max(to.0) + radius).min(CANVAS_WIDTH - 1);
+        let max_y = (from.1.max(to.1) + radius).min(CANVAS_HEIGHT - 1);
+
+        surface.mark_region_dirty(Rect::new(
+            CANVAS_X + min_x,
+            CANVAS_Y + min_y,
+            max_x - min_x + 1,
+            max_y - min_y + 1,
+        ));
+    }
+}
+
+/// Fill a square brush centered on the given canvas point
+fn stamp(pixels: &mut [Color], x: isize, y: isize, size: usize, color: Color) {
+    let radius = (size / 2) as isize;
+
+    for brush_y in (y - radius).max(0)..=(y + radius).min(CANVAS_HEIGHT as isize - 1) {
+        for brush_x in (x - radius).max(0)..=(x + radius).min(CANVAS_WIDTH as isize - 1) {
+            pixels[brush_y as usize * CANVAS_WIDTH + brush_x as usize] = color;
+        }
+    }
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    window_manager.add_window(Window::new(
+        180,
+        120,
+        WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        6,
+        Box::new(Paint::new()),
+    ));
+}
+
+impl Application for Paint {
+    fn title(&self) -> String {
+        "Paint".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        BACKGROUND_COLOR
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
+        self.color_picker.add_to_surface(surface);
+        for button in &mut self.brush_buttons {
+            button.add_to_surface(surface);
+        }
+        self.brush_buttons[0].set_color(SELECTED_COLOR, surface);
+
+        self.clear_button.add_to_surface(surface);
+        self.save_button.add_to_surface(surface);
+
+        self.canvas_idx = surface.add_shape(Shape::Image {
+            x: CANVAS_X,
+            y: CANVAS_Y,
+            width: CANVAS_WIDTH,
+            height: CANVAS_HEIGHT,
+            pixels: vec![Color::WHITE; CANVAS_WIDTH * CANVAS_HEIGHT],
+            hide: false,
+        });
+
+        self.status_idx = surface.add_shape(Shape::Text {
+            x: CANVAS_X,
+            y: STATUS_Y,
+            content: "Draw with the left mouse button".to_string(),
+            color: Color::BLACK,
+            background_color: BACKGROUND_COLOR,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+    }
+
+    fn render(&mut self, _surface: &mut Surface) {
+        // The canvas is updated directly while drawing
+    }
+
+    /// Starts a stroke if the press is on the canvas, or drags a color slider. Returns true
+    /// if either started.
+    fn handle_mouse_down(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if self.color_picker.handle_mouse_down(x, y, surface) {
+            return true;
+        }
+        if !Self::canvas_rect().contains_point(x, y) {
+            return false;
+        }
+
+        let point = Self::to_canvas(x, y);
+        self.draw_line(point, point, surface);
+        self.last_point = Some(point);
+        self.autosave.mark_changed();
+        true
+    }
+
+    fn handle_mouse_drag(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.color_picker.is_dragging() {
+            self.color_picker.handle_mouse_drag(x, y, surface);
+            return;
+        }
+
+        let Some(last_point) = self.last_point else {
+            return;
+        };
+
+        let point = Self::to_canvas(x, y);
+        if point != last_point {
+            self.draw_line(last_point, point, surface);
+            self.last_point = Some(point);
+            self.autosave.mark_changed();
+        }
+    }
+
+    fn handle_mouse_up(&mut self, _surface: &mut Surface) {
+        self.last_point = None;
+        self.color_picker.handle_mouse_up();
+    }
+
+    /// Whether there are unsaved changes and the auto-save interval ran out
+    fn is_autosave_due(&self) -> bool {
+        self.autosave.is_due()
+    }
+
+    /// Save the unsaved changes, if that fails it is tried again after another interval
+    fn autosave(&mut self, surface: &mut Surface) {
+        let status = match self.save(surface) {
+            Ok(()) => format!("Auto-saved as {}", SAVE_FILENAME),
+            Err(e) => {
+                self.autosave.retry_later();
+                format!("Auto-save failed: {}", e)
+            }
+        };
+        self.set_status(&status, surface);
+    }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        self.handle_mouse_click(x, y, surface);
+        true
+    }
+}
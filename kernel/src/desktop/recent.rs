@@ -0,0 +1,141 @@
+//! Files opened from the file manager, the most recent first.
+//!
+//! The list is kept in `RECENT.LST` in the root directory, so it survives a reboot. Every
+//! line is the time the file was opened and its path:
+//!
+//! ```text
+//! 2025-08-15 22:42|/DOCS/NOTE.TXT
+//! ```
+//!
+//! Lines that can't be read are skipped, and a missing list is an empty one.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use spin::Mutex;
+
+use crate::{
+    fs::{
+        fat32::{FileEntry, open_flags},
+        manager::{
+            find_file_in_root, read_text_file, resolve_path, root_directory_cluster,
+            write_file_with_flags,
+        },
+    },
+    log,
+    serial::LogLevel,
+    time::get_utc_time,
+};
+
+pub const LIST_FILENAME: &str = "RECENT.LST";
+/// Older files fall off the end of the list
+pub const MAX_ENTRIES: usize = 8;
+
+static RECENT: Mutex<Vec<RecentFile>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentFile {
+    /// From the root, like `/DOCS/NOTE.TXT`
+    pub path: String,
+    /// When the file was opened, as `YYYY-MM-DD HH:MM`
+    pub opened: String,
+}
+
+impl RecentFile {
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// Read the entries of a list file, invalid lines and repeated paths are skipped
+fn parse(text: &str) -> Vec<RecentFile> {
+    let mut files: Vec<RecentFile> = Vec::new();
+
+    for line in text.lines() {
+        let Some((opened, path)) = line.trim().split_once('|') else {
+            continue;
+        };
+        if !path.starts_with('/') || files.iter().any(|file| file.path == path) {
+            continue;
+        }
+
+        files.push(RecentFile {
+            path: path.to_string(),
+            opened: opened.to_string(),
+        });
+    }
+
+    files.truncate(MAX_ENTRIES);
+    files
+}
+
+/// Read the list from disk, it is empty if there is none yet or no filesystem
+pub fn load() {
+    let files = match find_file_in_root(LIST_FILENAME) {
+        Ok(Some(file)) => match read_text_file(file.first_cluster, file.size) {
+            Ok(text) => parse(&text),
+            Err(e) => {
+                log!(LogLevel::Warn, "Recent files: Error reading list: {}", e);
+                Vec::new()
+            }
+        },
+        _ => Vec::new(),
+    };
+
+    *RECENT.lock() = files;
+}
+
+/// The recent files, the most recent first
+pub fn list() -> Vec<RecentFile> {
+    RECENT.lock().clone()
+}
+
+/// Put a file at the top of the list and write the list to disk
+pub fn record(path: &str) {
+    let time = get_utc_time();
+    let opened = format!(
+        "{}-{:02}-{:02} {:02}:{:02}",
+        time.year, time.month, time.day, time.hours, time.minutes
+    );
+
+    let text = {
+        let mut files = RECENT.lock();
+        files.retain(|file| file.path != path);
+        files.insert(
+            0,
+            RecentFile {
+                path: path.to_string(),
+                opened,
+            },
+        );
+        files.truncate(MAX_ENTRIES);
+
+        files
+            .iter()
+            .map(|file| format!("{}|{}\n", file.opened, file.path))
+            .collect::<String>()
+    };
+
+    let result = root_directory_cluster().and_then(|root| {
+        write_file_with_flags(
+            root,
+            LIST_FILENAME,
+            text.as_bytes(),
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )
+    });
+    if let Err(e) = result {
+        log!(LogLevel::Warn, "Recent files: Error writing list: {}", e);
+    }
+}
+
+/// Look up a file by its path, returns it and the cluster of the directory it is in
+pub fn find(path: &str) -> Result<(FileEntry, u32), &'static str> {
+    match resolve_path(path)? {
+        Some((entry, directory)) if !entry.is_directory => Ok((entry, directory)),
+        Some(_) => Err("Not a file"),
+        None => Err("File not found"),
+    }
+}
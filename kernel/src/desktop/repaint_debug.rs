@@ -0,0 +1,195 @@
+//! Overlays that show what the compositor repaints, for debug builds only:
+//!
+//! - F9 leaves a trail behind the cursor. Its old images stay until something repaints that
+//!   part of the screen, so areas that should have been repainted but weren't stand out.
+//! - F10 outlines every damaged rectangle for one frame.
+//! - F7 outlines each window: its full bounds, its content and the parts of it that changed
+//!   this frame, with a label showing its id, title, position and size.
+
+use alloc::{format, string::String, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::{
+    framebuffer::{Color, FrameBufferWriter, measure_text},
+    log,
+    serial::LogLevel,
+    surface::Rect,
+};
+
+const OUTLINE_COLOR: Color = Color::new(255, 0, 255);
+const FULL_BOUNDS_COLOR: Color = Color::new(0, 200, 255);
+const CONTENT_BOUNDS_COLOR: Color = Color::new(0, 220, 0);
+const DIRTY_COLOR: Color = Color::new(255, 60, 0);
+const LABEL_BACKGROUND_COLOR: Color = Color::new(20, 20, 20);
+
+static CURSOR_TRAIL: AtomicBool = AtomicBool::new(false);
+static OUTLINE_DAMAGE: AtomicBool = AtomicBool::new(false);
+static INSPECT_WINDOWS: AtomicBool = AtomicBool::new(false);
+
+pub fn cursor_trail() -> bool {
+    CURSOR_TRAIL.load(Ordering::Relaxed)
+}
+
+pub fn outline_damage() -> bool {
+    OUTLINE_DAMAGE.load(Ordering::Relaxed)
+}
+
+pub fn inspect_windows() -> bool {
+    INSPECT_WINDOWS.load(Ordering::Relaxed)
+}
+
+fn toggle(flag: &AtomicBool, name: &str) {
+    let enabled = !flag.fetch_xor(true, Ordering::Relaxed);
+    log!(
+        LogLevel::Info,
+        "{} {}",
+        name,
+        if enabled { "enabled" } else { "disabled" }
+    );
+}
+
+/// Toggle an overlay with its hotkey. Returns true if the key was used, never in release
+/// builds.
+pub fn handle_key(key: DecodedKey) -> bool {
+    if !cfg!(debug_assertions) {
+        return false;
+    }
+
+    match key {
+        DecodedKey::RawKey(KeyCode::F9) => toggle(&CURSOR_TRAIL, "Cursor trail"),
+        DecodedKey::RawKey(KeyCode::F10) => toggle(&OUTLINE_DAMAGE, "Damage outlines"),
+        DecodedKey::RawKey(KeyCode::F7) => toggle(&INSPECT_WINDOWS, "Window inspector"),
+        _ => return false,
+    }
+    true
+}
+
+/// What the inspector shows of a window
+pub struct InspectedWindow {
+    pub id: usize,
+    pub title: String,
+    pub full_bounds: Rect,
+    pub content_bounds: Rect,
+    /// What changed in the window last frame, in screen coordinates
+    pub dirty_regions: Vec<Rect>,
+}
+
+/// The outlines drawn over the last frame and the screen pixels under them. Putting the
+/// pixels back doesn't damage anything, so the outlines only ever show real repaints.
+#[derive(Default)]
+pub struct DamageOutlines {
+    saved: Vec<(Rect, Vec<u8>)>,
+}
+
+impl DamageOutlines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove the last outlines, before the next frame is drawn
+    pub fn erase(&mut self, framebuffer: &mut FrameBufferWriter) {
+        // Backwards, where outlines cross the later one saved the earlier one's pixels
+        while let Some((edge, pixels)) = self.saved.pop() {
+            for (row, data) in pixels.chunks(pixels.len() / edge.height).enumerate() {
+                framebuffer.write_raw_pixel_row(edge.x, edge.y + row, data);
+            }
+        }
+    }
+
+    /// Outline each damaged rectangle, on top of everything else
+    pub fn draw(&mut self, framebuffer: &mut FrameBufferWriter, damage: &[Rect]) {
+        for region in damage {
+            self.outline(framebuffer, region, OUTLINE_COLOR);
+        }
+    }
+
+    /// Outline the windows and label them, from the bottom up
+    pub fn draw_windows(
+        &mut self,
+        framebuffer: &mut FrameBufferWriter,
+        windows: &[InspectedWindow],
+    ) {
+        for window in windows {
+            self.outline(framebuffer, &window.full_bounds, FULL_BOUNDS_COLOR);
+            self.outline(framebuffer, &window.content_bounds, CONTENT_BOUNDS_COLOR);
+            for region in &window.dirty_regions {
+                self.outline(framebuffer, region, DIRTY_COLOR);
+            }
+
+            let content = window.content_bounds;
+            let label = format!(
+                "#{} {} ({}, {}) {}x{}",
+                window.id, window.title, content.x, content.y, content.width, content.height
+            );
+            let (width, height) = measure_text(&label, FontWeight::Regular, RasterHeight::Size16);
+            let area = Rect::new(content.x + 2, content.y + 2, width + 4, height + 2);
+            if !self.save(framebuffer, area) {
+                continue;
+            }
+            framebuffer.fill_rect(
+                (area.x, area.y),
+                (area.x + area.width - 1, area.y + area.height - 1),
+                LABEL_BACKGROUND_COLOR,
+            );
+            framebuffer.draw_raw_text(
+                &label,
+                area.x + 2,
+                area.y + 1,
+                Color::WHITE,
+                LABEL_BACKGROUND_COLOR,
+                FontWeight::Regular,
+                RasterHeight::Size16,
+            );
+        }
+    }
+
+    /// Keep the screen pixels under `area` to put back in [`DamageOutlines::erase`]. Returns
+    /// false if none of it is on the screen.
+    fn save(&mut self, framebuffer: &FrameBufferWriter, area: Rect) -> bool {
+        // Rows past the bottom of the screen read as empty
+        let mut pixels = Vec::new();
+        let mut rows = 0;
+        for row in area.y..area.y + area.height {
+            let data = framebuffer.read_raw_pixel_row(area.x, row, area.width);
+            if data.is_empty() {
+                break;
+            }
+            pixels.extend_from_slice(data);
+            rows += 1;
+        }
+        if rows == 0 {
+            return false;
+        }
+        self.saved
+            .push((Rect::new(area.x, area.y, area.width, rows), pixels));
+        true
+    }
+
+    fn outline(&mut self, framebuffer: &mut FrameBufferWriter, region: &Rect, color: Color) {
+        if region.width == 0 || region.height == 0 {
+            return;
+        }
+
+        let right = region.x + region.width - 1;
+        let bottom = region.y + region.height - 1;
+        let edges = [
+            Rect::new(region.x, region.y, region.width, 1),
+            Rect::new(region.x, bottom, region.width, 1),
+            Rect::new(region.x, region.y, 1, region.height),
+            Rect::new(right, region.y, 1, region.height),
+        ];
+
+        for edge in edges {
+            if !self.save(framebuffer, edge) {
+                continue;
+            }
+            framebuffer.fill_rect(
+                (edge.x, edge.y),
+                (edge.x + edge.width - 1, edge.y + edge.height - 1),
+                color,
+            );
+        }
+    }
+}
@@ -1,16 +1,44 @@
-use alloc::{format, string::ToString, vec::Vec};
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::Modifiers;
 
 use crate::{
+    desktop::{
+        application::Application,
+        input::{DroppedInput, MouseSpeed, mouse_speed, set_mouse_speed},
+        widgets::slider::{SLIDER_HEIGHT, Slider},
+        window_manager::{Window, WindowManager},
+    },
     framebuffer::Color,
-    surface::{Shape, Surface},
-    sysinfo::{SystemInfo, estimate_heap_usage, estimate_stack_usage, format_memory_size},
+    surface::{Rect, Shape, Surface},
+    sysinfo::{
+        MemoryKind, SystemInfo, estimate_heap_usage, estimate_stack_usage, format_memory_size,
+    },
 };
 
+const MEMORY_BAR_WIDTH: usize = 370;
+const MEMORY_BAR_HEIGHT: usize = 10;
+
+fn memory_kind_color(kind: MemoryKind) -> Color {
+    match kind {
+        MemoryKind::Usable => Color::new(80, 200, 120),
+        MemoryKind::Bootloader => Color::new(0, 120, 215),
+        MemoryKind::Reserved => Color::GRAY,
+    }
+}
+
 pub struct SysInfo {
     system_info: SystemInfo,
     text_lines: Vec<usize>, // Shape indices for text lines
-    refresh_button_region: (usize, usize, usize, usize), // (x, y, width, height)
+    dropped_input_idx: usize,
+    mouse_speed_idx: usize,
+    mouse_speed_slider: Slider,
+    refresh_button_region: Rect,
     refreshed: bool,
 }
 
@@ -19,12 +47,94 @@ impl SysInfo {
         Self {
             system_info: SystemInfo::gather(),
             text_lines: Vec::new(),
-            refresh_button_region: (0, 0, 0, 0),
+            dropped_input_idx: 0,
+            mouse_speed_idx: 0,
+            mouse_speed_slider: Slider::new(0, 0, 160, MouseSpeed::ALL.len()),
+            refresh_button_region: Rect::default(),
             refreshed: false,
         }
     }
 
-    pub fn init(&mut self, surface: &mut Surface) {
+    /// The memory map as a bar, every kind of memory as wide as its share of all memory
+    fn add_memory_bar(&self, surface: &mut Surface, x: usize, y: usize) {
+        let memory_map = &self.system_info.memory_map;
+        if memory_map.total_bytes == 0 {
+            return;
+        }
+
+        let mut done: u64 = 0;
+        for span in &memory_map.spans {
+            // Edges from the running total, so the rounding never adds up to a gap
+            let start = (done * MEMORY_BAR_WIDTH as u64 / memory_map.total_bytes) as usize;
+            done += span.bytes;
+            let end = (done * MEMORY_BAR_WIDTH as u64 / memory_map.total_bytes) as usize;
+            if end == start {
+                continue;
+            }
+
+            surface.add_shape(Shape::Rectangle {
+                x: x + start,
+                y,
+                width: end - start,
+                height: MEMORY_BAR_HEIGHT,
+                color: memory_kind_color(span.kind),
+                filled: true,
+                hide: false,
+            });
+        }
+    }
+
+    /// Use the speed the slider is set to
+    fn apply_mouse_speed(&mut self, surface: &mut Surface) {
+        let speed = MouseSpeed::ALL[self.mouse_speed_slider.value()];
+        set_mouse_speed(speed);
+        surface.update_text_content(self.mouse_speed_idx, format_mouse_speed(speed), None);
+    }
+
+    pub fn handle_mouse_click(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.mouse_speed_slider.handle_click(x, y, surface) {
+            self.apply_mouse_speed(surface);
+            return;
+        }
+
+        // Check if click is on refresh button
+        if self.refresh_button_region.contains_point(x, y) {
+            self.refresh_data();
+        }
+    }
+
+    fn refresh_data(&mut self) {
+        self.refreshed = true;
+    }
+}
+
+fn format_mouse_speed(speed: MouseSpeed) -> String {
+    format!("Mouse speed: {} (F11)", speed.name())
+}
+
+fn format_dropped_input(dropped: DroppedInput) -> String {
+    format!(
+        "Dropped: {} keys, {} mouse, {} clicks",
+        dropped.scancodes + dropped.synthetic_keys,
+        dropped.mouse_states,
+        dropped.clicks
+    )
+}
+
+pub fn launch(window_manager: &mut WindowManager) {
+    window_manager.add_window(Window::new(200, 100, 400, 470, 3, Box::new(SysInfo::new())));
+}
+
+impl Application for SysInfo {
+    fn title(&self) -> String {
+        "System Information".to_string()
+    }
+
+    fn background_color(&self) -> Color {
+        Color::DARKGRAY
+    }
+
+    fn init(&mut self, surface: &mut Surface) {
         let mut y_offset = 20;
         let line_height = 18;
         let x_start = 15;
@@ -174,7 +284,74 @@ impl SysInfo {
             font_weight: FontWeight::Regular,
             hide: false,
         }));
-        y_offset += line_height + 5;
+        y_offset += line_height;
+
+        let memory_map = &self.system_info.memory_map;
+        self.text_lines.push(surface.add_shape(Shape::Text {
+            x: x_start,
+            y: y_offset,
+            content: format!(
+                "RAM: {} usable / {} (largest free {})",
+                format_memory_size(memory_map.usable_bytes as usize),
+                format_memory_size(memory_map.total_bytes as usize),
+                format_memory_size(memory_map.largest_free_bytes as usize)
+            ),
+            color: Color::WHITE,
+            background_color: Color::DARKGRAY,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        }));
+        y_offset += line_height;
+
+        self.add_memory_bar(surface, x_start, y_offset);
+        y_offset += MEMORY_BAR_HEIGHT + 5;
+
+        // Input events lost to full queues
+        self.text_lines.push(surface.add_shape(Shape::Text {
+            x: x_start,
+            y: y_offset,
+            content: "INPUT".to_string(),
+            color: Color::WHITE,
+            background_color: Color::DARKGRAY,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Bold,
+            hide: false,
+        }));
+        y_offset += line_height;
+
+        self.dropped_input_idx = surface.add_shape(Shape::Text {
+            x: x_start,
+            y: y_offset,
+            content: format_dropped_input(DroppedInput::get()),
+            color: Color::WHITE,
+            background_color: Color::DARKGRAY,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+        self.text_lines.push(self.dropped_input_idx);
+        y_offset += line_height;
+
+        self.mouse_speed_idx = surface.add_shape(Shape::Text {
+            x: x_start,
+            y: y_offset,
+            content: format_mouse_speed(mouse_speed()),
+            color: Color::WHITE,
+            background_color: Color::DARKGRAY,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+        self.text_lines.push(self.mouse_speed_idx);
+        y_offset += line_height;
+
+        self.mouse_speed_slider.rect.x = x_start;
+        self.mouse_speed_slider.rect.y = y_offset;
+        self.mouse_speed_slider
+            .set_value(mouse_speed() as usize, surface);
+        self.mouse_speed_slider.add_to_surface(surface);
+        y_offset += SLIDER_HEIGHT + 5;
 
         // CPU Features
         self.text_lines.push(surface.add_shape(Shape::Text {
@@ -231,20 +408,20 @@ impl SysInfo {
         y_offset += 10;
 
         // Refresh button
-        self.refresh_button_region = (x_start, y_offset, 173, 25);
+        self.refresh_button_region = Rect::new(x_start, y_offset, 173, 25);
         surface.add_shape(Shape::Rectangle {
-            x: self.refresh_button_region.0,
-            y: self.refresh_button_region.1,
-            width: self.refresh_button_region.2,
-            height: self.refresh_button_region.3,
+            x: self.refresh_button_region.x,
+            y: self.refresh_button_region.y,
+            width: self.refresh_button_region.width,
+            height: self.refresh_button_region.height,
             color: Color::new(200, 200, 255),
             filled: true,
             hide: false,
         });
 
         surface.add_shape(Shape::Text {
-            x: self.refresh_button_region.0 + 20,
-            y: self.refresh_button_region.1 + 5,
+            x: self.refresh_button_region.x + 20,
+            y: self.refresh_button_region.y + 5,
             content: "Refresh Memory Data".to_string(),
             color: Color::BLACK,
             background_color: Color::new(200, 200, 255),
@@ -254,22 +431,13 @@ impl SysInfo {
         });
     }
 
-    pub fn handle_mouse_click(&mut self, x: usize, y: usize) {
-        // Check if click is on refresh button
-        if x >= self.refresh_button_region.0
-            && x < self.refresh_button_region.0 + self.refresh_button_region.2
-            && y >= self.refresh_button_region.1
-            && y < self.refresh_button_region.1 + self.refresh_button_region.3
-        {
-            self.refresh_data();
+    fn render(&mut self, surface: &mut Surface) {
+        // The speed can also be changed with the hotkey
+        let speed = mouse_speed();
+        if self.mouse_speed_slider.set_value(speed as usize, surface) {
+            surface.update_text_content(self.mouse_speed_idx, format_mouse_speed(speed), None);
         }
-    }
-
-    fn refresh_data(&mut self) {
-        self.refreshed = true;
-    }
 
-    pub fn render(&mut self, surface: &mut Surface) {
         if self.refreshed {
             let stack_usage = estimate_stack_usage();
             let heap_usage = estimate_heap_usage();
@@ -295,8 +463,42 @@ impl SysInfo {
                 ),
                 None,
             );
+            surface.update_text_content(
+                self.dropped_input_idx,
+                format_dropped_input(DroppedInput::get()),
+                None,
+            );
 
             self.refreshed = false;
         }
     }
+
+    fn handle_click(
+        &mut self,
+        x: usize,
+        y: usize,
+        _modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        self.handle_mouse_click(x, y, surface);
+        true
+    }
+
+    fn handle_mouse_down(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        let grabbed = self.mouse_speed_slider.handle_mouse_down(x, y, surface);
+        if grabbed {
+            self.apply_mouse_speed(surface);
+        }
+        grabbed
+    }
+
+    fn handle_mouse_drag(&mut self, x: usize, y: usize, surface: &mut Surface) {
+        if self.mouse_speed_slider.handle_mouse_drag(x, y, surface) {
+            self.apply_mouse_speed(surface);
+        }
+    }
+
+    fn handle_mouse_up(&mut self, _surface: &mut Surface) {
+        self.mouse_speed_slider.handle_mouse_up();
+    }
 }
@@ -0,0 +1,132 @@
+//! Where the taskbar sits, and the desktop area it leaves for windows.
+//!
+//! The layout is loaded from `TASKBAR.CFG` in the root directory when the desktop starts:
+//!
+//! ```text
+//! # Lines starting with # are ignored
+//! edge = top
+//! height = 40
+//! ```
+//!
+//! Missing or invalid values keep their defaults, the bottom edge and 50 pixels.
+
+use conquer_once::spin::OnceCell;
+
+use crate::{
+    desktop::config::{entries, read_root_config},
+    framebuffer::SCREEN_SIZE,
+    surface::Rect,
+};
+
+pub const CONFIG_FILENAME: &str = "TASKBAR.CFG";
+
+const MIN_HEIGHT: usize = 30;
+const MAX_HEIGHT: usize = 120;
+
+static LAYOUT: OnceCell<TaskbarLayout> = OnceCell::uninit();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskbarEdge {
+    Top,
+    Bottom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskbarLayout {
+    pub edge: TaskbarEdge,
+    pub height: usize,
+}
+
+impl Default for TaskbarLayout {
+    fn default() -> Self {
+        Self {
+            edge: TaskbarEdge::Bottom,
+            height: 50,
+        }
+    }
+}
+
+impl TaskbarLayout {
+    /// Load the layout from the config file, the defaults are used if it doesn't exist
+    pub fn load() -> Self {
+        let mut layout = Self::default();
+
+        if let Some(text) = read_root_config(CONFIG_FILENAME) {
+            layout.parse(&text);
+        }
+
+        layout
+    }
+
+    /// Apply the lines of a config file, unknown keys and invalid values are skipped
+    pub fn parse(&mut self, text: &str) {
+        for entry in entries(text) {
+            match entry {
+                ("edge", "top") => self.edge = TaskbarEdge::Top,
+                ("edge", "bottom") => self.edge = TaskbarEdge::Bottom,
+                ("height", height) => {
+                    if let Ok(height) = height.parse::<usize>() {
+                        self.height = height.clamp(MIN_HEIGHT, MAX_HEIGHT);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn screen_size() -> (usize, usize) {
+        SCREEN_SIZE
+            .get()
+            .map_or((0, 0), |&(w, h)| (w as usize, h as usize))
+    }
+
+    /// The taskbar itself
+    pub fn bounds(&self) -> Rect {
+        let (width, height) = Self::screen_size();
+
+        match self.edge {
+            TaskbarEdge::Top => Rect::new(0, 0, width, self.height),
+            TaskbarEdge::Bottom => {
+                Rect::new(0, height.saturating_sub(self.height), width, self.height)
+            }
+        }
+    }
+
+    /// The part of the screen left for windows
+    pub fn desktop_area(&self) -> Rect {
+        let (width, height) = Self::screen_size();
+        let height = height.saturating_sub(self.height);
+
+        match self.edge {
+            TaskbarEdge::Top => Rect::new(0, self.height, width, height),
+            TaskbarEdge::Bottom => Rect::new(0, 0, width, height),
+        }
+    }
+
+    /// Top of a start menu of the given height, it opens towards the desktop
+    pub fn start_menu_top(&self, menu_height: usize) -> usize {
+        match self.edge {
+            // Below the border line of the taskbar
+            TaskbarEdge::Top => self.height + 3,
+            TaskbarEdge::Bottom => self.bounds().y.saturating_sub(menu_height),
+        }
+    }
+
+    /// Row of the black line between the taskbar and the desktop
+    pub fn border_y(&self) -> usize {
+        match self.edge {
+            TaskbarEdge::Top => self.height,
+            TaskbarEdge::Bottom => self.bounds().y.saturating_sub(1),
+        }
+    }
+}
+
+/// Load the layout, once when the desktop starts
+pub fn init() {
+    LAYOUT.init_once(TaskbarLayout::load);
+}
+
+/// The taskbar layout, the default one before [`init`]
+pub fn layout() -> TaskbarLayout {
+    LAYOUT.get().copied().unwrap_or_default()
+}
@@ -0,0 +1,176 @@
+//! Short messages in a corner of the screen that go away on their own, like "File saved".
+//!
+//! [`notify`] can be called from anywhere, the window manager shows the message over all
+//! windows with its next frame. Toasts don't take input, so the focus stays where it was.
+
+use alloc::{string::String, vec::Vec};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use spin::Mutex;
+
+use crate::{
+    desktop::taskbar,
+    framebuffer::{Color, FrameBufferWriter, measure_text},
+    interrupts::{ms_to_ticks, ticks},
+    surface::{Rect, Shape, Surface},
+};
+
+/// How long a toast is shown, including the fade
+const DURATION_MS: u64 = 3000;
+/// The end of the duration, while it fades out
+const FADE_MS: u64 = 500;
+/// More toasts at once push out the oldest
+const MAX_TOASTS: usize = 4;
+const MAX_WIDTH: usize = 320;
+const HEIGHT: usize = 28;
+const PADDING: usize = 10;
+/// Space between the toasts and to the edges of the desktop
+const GAP: usize = 8;
+
+/// Messages waiting for the next frame
+static PENDING: Mutex<Vec<(String, ToastKind)>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastKind {
+    Info,
+    Success,
+    Error,
+}
+
+impl ToastKind {
+    fn color(self) -> Color {
+        match self {
+            ToastKind::Info => Color::new(60, 60, 60),
+            ToastKind::Success => Color::new(40, 120, 60),
+            ToastKind::Error => Color::new(170, 40, 40),
+        }
+    }
+}
+
+/// Show a message for a few seconds
+pub fn notify(message: &str, kind: ToastKind) {
+    PENDING.lock().push((message.into(), kind));
+}
+
+struct Toast {
+    surface: Surface,
+    /// Where it's on the screen, empty until it was drawn
+    rect: Rect,
+    shown_at: u64,
+}
+
+impl Toast {
+    fn new(message: &str, kind: ToastKind) -> Self {
+        // The font is monospaced, longer messages are cut
+        let (char_width, _) = measure_text("M", FontWeight::Regular, RasterHeight::Size16);
+        let max_chars = (MAX_WIDTH - 2 * PADDING) / char_width.max(1);
+        let text: String = message.chars().take(max_chars).collect();
+        let (text_width, _) = measure_text(&text, FontWeight::Regular, RasterHeight::Size16);
+
+        let color = kind.color();
+        let width = text_width + 2 * PADDING;
+        let mut surface = Surface::new(width, HEIGHT, color);
+        surface.add_shape(Shape::Text {
+            x: PADDING,
+            y: 6,
+            content: text,
+            color: Color::WHITE,
+            background_color: color,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        });
+
+        Self {
+            surface,
+            rect: Rect::new(0, 0, 0, 0),
+            shown_at: ticks(),
+        }
+    }
+
+    fn alpha(&self, now: u64) -> Option<u8> {
+        fade_alpha(now.saturating_sub(self.shown_at), DURATION_MS, FADE_MS)
+    }
+}
+
+/// Opacity out of 255 of an overlay `age` ticks after it was shown, which fades out over the
+/// last `fade_ms` of `duration_ms`. `None` once it's gone.
+pub fn fade_alpha(age: u64, duration_ms: u64, fade_ms: u64) -> Option<u8> {
+    let left = ms_to_ticks(duration_ms)
+        .checked_sub(age)
+        .filter(|&left| left > 0)?;
+    let fade = ms_to_ticks(fade_ms).max(1);
+    Some((left.min(fade) * 255 / fade) as u8)
+}
+
+/// The toasts on the screen, the oldest at the bottom right corner of the desktop and newer
+/// ones above it
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draw the toasts over everything else. Where one was or is fading has to be drawn again
+    /// from below next frame, those areas are added to `exposed`.
+    pub fn render(
+        &mut self,
+        framebuffer: &mut FrameBufferWriter,
+        damage: &mut Vec<Rect>,
+        exposed: &mut Vec<Rect>,
+    ) {
+        for (message, kind) in core::mem::take(&mut *PENDING.lock()) {
+            self.toasts.push(Toast::new(&message, kind));
+        }
+
+        let now = ticks();
+        let mut excess = self.toasts.len().saturating_sub(MAX_TOASTS);
+        self.toasts.retain(|toast| {
+            let keep = toast.alpha(now).is_some() && excess == 0;
+            excess = excess.saturating_sub(1);
+            if !keep && !toast.rect.is_empty() {
+                exposed.push(toast.rect);
+            }
+            keep
+        });
+
+        let area = taskbar::layout().desktop_area();
+        let mut bottom = area.y + area.height;
+        for toast in &mut self.toasts {
+            bottom = bottom.saturating_sub(GAP + HEIGHT);
+            let width = toast.surface.width;
+            let rect = Rect::new(
+                (area.x + area.width).saturating_sub(GAP + width),
+                bottom,
+                width,
+                HEIGHT,
+            );
+
+            // Moves down when an older one is gone
+            if rect != toast.rect {
+                if !toast.rect.is_empty() {
+                    exposed.push(toast.rect);
+                }
+                toast.rect = rect;
+                toast
+                    .surface
+                    .mark_region_exposed(Rect::new(0, 0, width, HEIGHT));
+            }
+
+            match toast.alpha(now) {
+                Some(255) => toast.surface.render(framebuffer, rect.x, rect.y, damage),
+                Some(alpha) => {
+                    toast
+                        .surface
+                        .render_blended(framebuffer, rect.x, rect.y, alpha);
+                    damage.push(rect);
+                    exposed.push(rect);
+                }
+                None => {}
+            }
+        }
+    }
+}
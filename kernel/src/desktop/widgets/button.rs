@@ -0,0 +1,128 @@
+use alloc::string::{String, ToString};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+
+use crate::{
+    framebuffer::{Color, measure_text},
+    surface::{Rect, Shape, Surface},
+};
+
+/// A clickable rectangle with a centered label
+pub struct Button {
+    pub rect: Rect,
+    label: String,
+    color: Color,
+
+    font_size: RasterHeight,
+
+    // Shape indices, only valid while the button is on a surface
+    background_idx: Option<usize>,
+    label_idx: Option<usize>,
+}
+
+impl Button {
+    pub fn new(x: usize, y: usize, width: usize, height: usize, label: &str) -> Self {
+        Self {
+            rect: Rect::new(x, y, width, height),
+            label: label.to_string(),
+            color: Color::new(220, 220, 220),
+            font_size: RasterHeight::Size16,
+            background_idx: None,
+            label_idx: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.rect.contains_point(x, y)
+    }
+
+    fn label_position(&self) -> (usize, usize) {
+        let (text_width, text_height) =
+            measure_text(&self.label, FontWeight::Regular, self.font_size);
+
+        (
+            self.rect.x + self.rect.width.saturating_sub(text_width) / 2,
+            self.rect.y + self.rect.height.saturating_sub(text_height) / 2,
+        )
+    }
+
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        self.background_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: self.rect.x,
+            y: self.rect.y,
+            width: self.rect.width,
+            height: self.rect.height,
+            color: self.color,
+            filled: true,
+            hide: false,
+        }));
+
+        surface.add_shape(Shape::Rectangle {
+            x: self.rect.x,
+            y: self.rect.y,
+            width: self.rect.width,
+            height: self.rect.height,
+            color: Color::BLACK,
+            filled: false,
+            hide: false,
+        });
+
+        let (label_x, label_y) = self.label_position();
+        self.label_idx = Some(surface.add_shape(Shape::Text {
+            x: label_x,
+            y: label_y,
+            content: self.label.clone(),
+            color: Color::BLACK,
+            background_color: self.color,
+            font_size: self.font_size,
+            font_weight: FontWeight::Regular,
+            hide: false,
+        }));
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.background_idx = None;
+        self.label_idx = None;
+    }
+
+    pub fn set_label(&mut self, label: &str, surface: &mut Surface) {
+        if self.label == label {
+            return;
+        }
+
+        self.label = label.to_string();
+
+        if let Some(idx) = self.label_idx {
+            // The background has to be redrawn as well when the label gets shorter
+            let old_bounds = surface.get_shape_bounds(idx);
+
+            let (label_x, label_y) = self.label_position();
+            surface.move_shape(idx, label_x, label_y);
+            surface.update_text_content(idx, self.label.clone(), None);
+
+            if let Some(bounds) = old_bounds {
+                surface.mark_region_dirty(bounds);
+            }
+        }
+    }
+
+    pub fn set_color(&mut self, color: Color, surface: &mut Surface) {
+        self.color = color;
+
+        if let Some(idx) = self.background_idx {
+            surface.update_rectangle_color(idx, color);
+        }
+        if let Some(idx) = self.label_idx {
+            surface.update_text_background(idx, color);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use alloc::{string::ToString, vec::Vec};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+
+use crate::{
+    desktop::widgets::{
+        button::Button,
+        slider::{SLIDER_HEIGHT, Slider},
+    },
+    framebuffer::Color,
+    surface::{Rect, Shape, Surface},
+};
+
+pub const SWATCH_SIZE: usize = 24;
+const SWATCH_GAP: usize = 4;
+/// Width of the letter in front of each slider
+const LABEL_WIDTH: usize = 14;
+const SLIDER_GAP: usize = 4;
+/// The preview is a square as tall as the three sliders
+const PREVIEW_SIZE: usize = 3 * SLIDER_HEIGHT + 2 * SLIDER_GAP;
+
+/// A grid of preset colors, optionally with red, green and blue sliders and a preview of
+/// the mixed color. Read the choice with [`ColorPicker::color`] after an event returned true.
+pub struct ColorPicker {
+    presets: Vec<Color>,
+    swatches: Vec<Button>,
+    /// Red, green and blue, from 0 to 255
+    sliders: Option<[Slider; 3]>,
+    /// Background of the slider labels
+    label_background: Color,
+    preview: Rect,
+    color: Color,
+    /// The slider the mouse was pressed on
+    dragging: Option<usize>,
+
+    // Shape indices, only valid while the picker is on a surface
+    selection_idx: Option<usize>,
+    preview_idx: Option<usize>,
+}
+
+impl ColorPicker {
+    /// Swatches for the presets in rows of `columns`, starting at `x`, `y`. The first
+    /// preset is selected.
+    pub fn new(x: usize, y: usize, presets: &[Color], columns: usize) -> Self {
+        let step = SWATCH_SIZE + SWATCH_GAP;
+        let columns = columns.max(1);
+
+        let swatches = presets
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                Button::new(
+                    x + (i % columns) * step,
+                    y + (i / columns) * step,
+                    SWATCH_SIZE,
+                    SWATCH_SIZE,
+                    "",
+                )
+                .with_color(*color)
+            })
+            .collect();
+
+        Self {
+            presets: presets.to_vec(),
+            swatches,
+            sliders: None,
+            label_background: Color::WHITE,
+            preview: Rect::default(),
+            color: presets.first().copied().unwrap_or(Color::BLACK),
+            dragging: None,
+            selection_idx: None,
+            preview_idx: None,
+        }
+    }
+
+    /// Add sliders to mix any color, stacked at `x`, `y` with the preview to the right.
+    /// `width` includes the labels, the labels are drawn on `background`.
+    pub fn with_sliders(mut self, x: usize, y: usize, width: usize, background: Color) -> Self {
+        let slider_x = x + LABEL_WIDTH;
+        let slider_width = width.saturating_sub(LABEL_WIDTH);
+        let step = SLIDER_HEIGHT + SLIDER_GAP;
+
+        self.sliders =
+            Some([0, 1, 2].map(|i| Slider::new(slider_x, y + i * step, slider_width, 256)));
+        self.label_background = background;
+        self.preview = Rect::new(x + width + SLIDER_GAP * 2, y, PREVIEW_SIZE, PREVIEW_SIZE);
+        self
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Show a color, the sliders and the preview follow
+    pub fn set_color(&mut self, color: Color, surface: &mut Surface) {
+        self.color = color;
+
+        if let Some(sliders) = &mut self.sliders {
+            for (slider, value) in sliders.iter_mut().zip([color.r, color.g, color.b]) {
+                slider.set_value(value as usize, surface);
+            }
+        }
+        if let Some(idx) = self.preview_idx {
+            surface.update_rectangle_color(idx, color);
+        }
+        self.update_selection(surface);
+    }
+
+    /// Outline the swatch of the color, or none if it was mixed with the sliders
+    fn update_selection(&self, surface: &mut Surface) {
+        let Some(idx) = self.selection_idx else {
+            return;
+        };
+
+        match self.presets.iter().position(|&preset| preset == self.color) {
+            Some(index) => {
+                let rect = self.swatches[index].rect;
+                surface.move_shape(idx, rect.x - 2, rect.y - 2);
+                surface.show_shape(idx);
+            }
+            None => {
+                surface.hide_shape(idx);
+            }
+        }
+    }
+
+    /// The color the sliders are set to
+    fn slider_color(sliders: &[Slider; 3]) -> Color {
+        Color::new(
+            sliders[0].value() as u8,
+            sliders[1].value() as u8,
+            sliders[2].value() as u8,
+        )
+    }
+
+    /// Pick a swatch, or jump a slider to the click. Returns true if the color changed.
+    pub fn handle_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if let Some(index) = self.swatches.iter().position(|s| s.contains(x, y)) {
+            let color = self.presets[index];
+            let changed = color != self.color;
+            self.set_color(color, surface);
+            return changed;
+        }
+
+        let Some(sliders) = &mut self.sliders else {
+            return false;
+        };
+        if !sliders
+            .iter_mut()
+            .any(|slider| slider.handle_click(x, y, surface))
+        {
+            return false;
+        }
+
+        let color = Self::slider_color(sliders);
+        self.set_color(color, surface);
+        true
+    }
+
+    /// Start dragging a slider. Returns true if the press was on one, the caller should
+    /// then pass on the mouse moves.
+    pub fn handle_mouse_down(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        let Some(sliders) = &mut self.sliders else {
+            return false;
+        };
+        let Some(index) = sliders
+            .iter_mut()
+            .position(|slider| slider.handle_mouse_down(x, y, surface))
+        else {
+            return false;
+        };
+
+        // The press may have jumped the knob
+        let color = Self::slider_color(sliders);
+        self.dragging = Some(index);
+        self.set_color(color, surface);
+        true
+    }
+
+    /// Move the dragged slider. Returns true if the color changed.
+    pub fn handle_mouse_drag(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        let (Some(index), Some(sliders)) = (self.dragging, &mut self.sliders) else {
+            return false;
+        };
+        if !sliders[index].handle_mouse_drag(x, y, surface) {
+            return false;
+        }
+
+        let color = Self::slider_color(sliders);
+        self.set_color(color, surface);
+        true
+    }
+
+    pub fn handle_mouse_up(&mut self) {
+        if let (Some(index), Some(sliders)) = (self.dragging.take(), &mut self.sliders) {
+            sliders[index].handle_mouse_up();
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.dragging.is_some()
+    }
+
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        // Placed before the swatches so they draw over it, only the border shows
+        let first = self.swatches.first().map_or(Rect::default(), |s| s.rect);
+        self.selection_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: first.x.saturating_sub(2),
+            y: first.y.saturating_sub(2),
+            width: SWATCH_SIZE + 4,
+            height: SWATCH_SIZE + 4,
+            color: Color::BLACK,
+            filled: true,
+            hide: false,
+        }));
+
+        for swatch in &mut self.swatches {
+            swatch.add_to_surface(surface);
+        }
+
+        if let Some(sliders) = &mut self.sliders {
+            for (slider, label) in sliders.iter_mut().zip(["R", "G", "B"]) {
+                surface.add_shape(Shape::Text {
+                    x: slider.rect.x - LABEL_WIDTH,
+                    y: slider.rect.y,
+                    content: label.to_string(),
+                    color: Color::BLACK,
+                    background_color: self.label_background,
+                    font_size: RasterHeight::Size16,
+                    font_weight: FontWeight::Regular,
+                    hide: false,
+                });
+                slider.add_to_surface(surface);
+            }
+
+            self.preview_idx = Some(surface.add_shape(Shape::Rectangle {
+                x: self.preview.x,
+                y: self.preview.y,
+                width: self.preview.width,
+                height: self.preview.height,
+                color: self.color,
+                filled: true,
+                hide: false,
+            }));
+            surface.add_shape(Shape::Rectangle {
+                x: self.preview.x,
+                y: self.preview.y,
+                width: self.preview.width,
+                height: self.preview.height,
+                color: Color::BLACK,
+                filled: false,
+                hide: false,
+            });
+        }
+
+        let color = self.color;
+        self.set_color(color, surface);
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.selection_idx = None;
+        self.preview_idx = None;
+        if let Some(sliders) = &mut self.sliders {
+            for slider in sliders {
+                slider.detach();
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use alloc::vec::Vec;
+use pc_keyboard::{KeyCode, Modifiers};
+
+use crate::{
+    framebuffer::Color,
+    surface::{Rect, Shape, Surface},
+};
+
+const FOCUS_COLOR: Color = Color::new(0, 100, 220);
+/// Space between a widget and its focus outline
+const OUTLINE_GAP: usize = 2;
+
+/// Keyboard focus for the widgets of a window. Widgets are registered in Tab order,
+/// Tab and Shift+Tab move between them and the focused one gets an outline.
+#[derive(Default)]
+pub struct FocusRing {
+    widgets: Vec<Rect>,
+    focused: Option<usize>,
+
+    // Shape index, only valid while the ring is on a surface
+    outline_idx: Option<usize>,
+}
+
+impl FocusRing {
+    pub fn new() -> Self {
+        Self {
+            widgets: Vec::new(),
+            focused: None,
+            outline_idx: None,
+        }
+    }
+
+    /// Forget every widget, for example before the UI is built again
+    pub fn clear(&mut self) {
+        self.widgets.clear();
+        self.focused = None;
+        self.outline_idx = None;
+    }
+
+    /// Add the next widget in Tab order, returns its id
+    pub fn register(&mut self, rect: Rect) -> usize {
+        self.widgets.push(rect);
+        self.widgets.len() - 1
+    }
+
+    pub fn focused(&self) -> Option<usize> {
+        self.focused
+    }
+
+    pub fn is_focused(&self, id: usize) -> bool {
+        self.focused == Some(id)
+    }
+
+    /// Focus a registered widget and move the outline to it
+    pub fn focus(&mut self, id: usize, surface: &mut Surface) {
+        let Some(rect) = self.widgets.get(id) else {
+            return;
+        };
+        self.focused = Some(id);
+
+        if let Some(idx) = self.outline_idx {
+            surface.move_shape(
+                idx,
+                rect.x.saturating_sub(OUTLINE_GAP),
+                rect.y.saturating_sub(OUTLINE_GAP),
+            );
+            surface.update_rectangle_size(
+                idx,
+                rect.width + 2 * OUTLINE_GAP,
+                rect.height + 2 * OUTLINE_GAP,
+            );
+            surface.show_shape(idx);
+        }
+    }
+
+    /// Tab focuses the next widget and Shift+Tab the previous one, both wrap around.
+    /// Returns true if the key was used.
+    pub fn handle_key(
+        &mut self,
+        key: KeyCode,
+        modifiers: &Modifiers,
+        surface: &mut Surface,
+    ) -> bool {
+        if key != KeyCode::Tab || self.widgets.is_empty() {
+            return false;
+        }
+
+        let count = self.widgets.len();
+        let next = match self.focused {
+            None if modifiers.is_shifted() => count - 1,
+            None => 0,
+            Some(id) if modifiers.is_shifted() => (id + count - 1) % count,
+            Some(id) => (id + 1) % count,
+        };
+
+        self.focus(next, surface);
+        true
+    }
+
+    /// Add the outline, after the widgets so it is drawn over them
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        self.outline_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+            color: FOCUS_COLOR,
+            filled: false,
+            hide: true,
+        }));
+
+        if let Some(id) = self.focused {
+            self.focus(id, surface);
+        }
+    }
+}
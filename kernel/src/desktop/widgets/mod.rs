@@ -0,0 +1,8 @@
+pub mod button;
+pub mod color_picker;
+pub mod focus;
+pub mod progress_bar;
+pub mod scrollbar;
+pub mod slider;
+pub mod spinner;
+pub mod text_input;
@@ -0,0 +1,141 @@
+use alloc::{format, string::String};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+
+use crate::{
+    framebuffer::Color,
+    surface::{Rect, Shape, Surface},
+};
+
+pub const PROGRESS_BAR_HEIGHT: usize = 12;
+/// Space between the bar and the percentage next to it
+const TEXT_GAP: usize = 6;
+
+const TRACK_COLOR: Color = Color::new(200, 200, 200);
+const FILL_COLOR: Color = Color::new(0, 120, 215);
+
+/// A horizontal bar that fills up from 0 to 100 percent, optionally with the percentage
+/// written to the right of it
+pub struct ProgressBar {
+    pub rect: Rect,
+    value: usize,
+    /// Background of the percentage text, `None` to leave it out
+    text_background: Option<Color>,
+
+    // Shape indices, only valid while the bar is on a surface
+    track_idx: Option<usize>,
+    fill_idx: Option<usize>,
+    text_idx: Option<usize>,
+}
+
+impl ProgressBar {
+    pub fn new(x: usize, y: usize, width: usize) -> Self {
+        Self {
+            rect: Rect::new(x, y, width, PROGRESS_BAR_HEIGHT),
+            value: 0,
+            text_background: None,
+            track_idx: None,
+            fill_idx: None,
+            text_idx: None,
+        }
+    }
+
+    /// Show the percentage next to the bar, on the given background
+    pub fn with_percentage(mut self, background: Color) -> Self {
+        self.text_background = Some(background);
+        self
+    }
+
+    pub fn value(&self) -> usize {
+        self.value
+    }
+
+    fn fill_width(&self) -> usize {
+        self.rect.width * self.value / 100
+    }
+
+    fn percentage_text(&self) -> String {
+        // Always four characters wide, so the text never has to be cleared behind it
+        format!("{:>3}%", self.value)
+    }
+
+    /// Set the progress in percent, clamped to 100. Only the part of the bar that changed
+    /// is redrawn, and nothing at all if the value is the same. Returns true if it changed.
+    pub fn set_value(&mut self, value: usize, surface: &mut Surface) -> bool {
+        let value = value.min(100);
+        if value == self.value {
+            return false;
+        }
+
+        self.value = value;
+        if let Some(idx) = self.fill_idx {
+            surface.update_rectangle_size(idx, self.fill_width(), self.rect.height);
+        }
+        if let Some(idx) = self.text_idx {
+            surface.update_text_content(idx, self.percentage_text(), None);
+        }
+        true
+    }
+
+    /// Set the progress from a count of finished units, for example bytes read
+    pub fn set_progress(&mut self, done: usize, total: usize, surface: &mut Surface) -> bool {
+        let value = match total {
+            0 => 100,
+            total => (done.min(total) as u64 * 100 / total as u64) as usize,
+        };
+        self.set_value(value, surface)
+    }
+
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        self.track_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: self.rect.x,
+            y: self.rect.y,
+            width: self.rect.width,
+            height: self.rect.height,
+            color: TRACK_COLOR,
+            filled: true,
+            hide: false,
+        }));
+
+        self.fill_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: self.rect.x,
+            y: self.rect.y,
+            width: self.fill_width(),
+            height: self.rect.height,
+            color: FILL_COLOR,
+            filled: true,
+            hide: false,
+        }));
+
+        self.text_idx = self.text_background.map(|background| {
+            surface.add_shape(Shape::Text {
+                x: self.rect.x + self.rect.width + TEXT_GAP,
+                // The text is taller than the bar, so it is centered on it
+                y: (self.rect.y + PROGRESS_BAR_HEIGHT / 2)
+                    .saturating_sub(RasterHeight::Size16.val() / 2),
+                content: self.percentage_text(),
+                color: Color::BLACK,
+                background_color: background,
+                font_size: RasterHeight::Size16,
+                font_weight: FontWeight::Regular,
+                hide: false,
+            })
+        });
+    }
+
+    /// Hide the bar, for example once the work it showed is done
+    pub fn hide(&self, surface: &mut Surface) {
+        for idx in [self.track_idx, self.fill_idx, self.text_idx]
+            .into_iter()
+            .flatten()
+        {
+            surface.hide_shape(idx);
+        }
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.track_idx = None;
+        self.fill_idx = None;
+        self.text_idx = None;
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    framebuffer::Color,
+    surface::{Rect, Shape, Surface},
+};
+
+pub const SCROLLBAR_WIDTH: usize = 12;
+/// The thumb never gets smaller than this, so it can still be grabbed
+const MIN_THUMB_HEIGHT: usize = 16;
+
+const TRACK_COLOR: Color = Color::new(230, 230, 230);
+const THUMB_COLOR: Color = Color::new(160, 160, 160);
+
+/// A vertical scrollbar. The offset is counted in whatever unit the view scrolls by,
+/// for example list rows or pixels.
+pub struct ScrollBar {
+    pub rect: Rect,
+
+    /// Size of the whole content
+    total: usize,
+    /// Size of the part that fits in the view
+    visible: usize,
+    offset: usize,
+
+    /// Distance from the top of the thumb to where it was grabbed, while dragging
+    grab_y: Option<usize>,
+
+    // Shape index, only valid while the scrollbar is on a surface
+    thumb_idx: Option<usize>,
+}
+
+impl ScrollBar {
+    pub fn new(x: usize, y: usize, height: usize) -> Self {
+        Self {
+            rect: Rect::new(x, y, SCROLLBAR_WIDTH, height),
+            total: 0,
+            visible: 0,
+            offset: 0,
+            grab_y: None,
+            thumb_idx: None,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn max_offset(&self) -> usize {
+        self.total.saturating_sub(self.visible)
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.rect.contains_point(x, y)
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.grab_y.is_some()
+    }
+
+    /// Set the content and view size and the offset, which is clamped to the new range.
+    /// Call [`ScrollBar::update_surface`] afterwards if the scrollbar is already on a surface.
+    pub fn set_range(&mut self, total: usize, visible: usize, offset: usize) {
+        self.total = total;
+        self.visible = visible;
+        self.offset = offset.min(self.max_offset());
+    }
+
+    /// Scroll to an offset, returns true if it changed
+    pub fn set_offset(&mut self, offset: usize, surface: &mut Surface) -> bool {
+        let offset = offset.min(self.max_offset());
+        if offset == self.offset {
+            return false;
+        }
+
+        self.offset = offset;
+        self.update_surface(surface);
+        true
+    }
+
+    /// The thumb is as much of the track as the view is of the content
+    fn thumb_rect(&self) -> Rect {
+        let track = self.rect.height;
+        let height = if self.total == 0 {
+            track
+        } else {
+            (track * self.visible / self.total).clamp(MIN_THUMB_HEIGHT.min(track), track)
+        };
+
+        let travel = track - height;
+        let y = match self.max_offset() {
+            0 => 0,
+            max_offset => travel * self.offset / max_offset,
+        };
+
+        Rect::new(self.rect.x, self.rect.y + y, self.rect.width, height)
+    }
+
+    /// Grab the thumb, or page towards the click on the track.
+    /// Returns true if the press was on the scrollbar.
+    pub fn handle_mouse_down(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if !self.contains(x, y) {
+            return false;
+        }
+
+        let thumb = self.thumb_rect();
+        if y < thumb.y {
+            self.set_offset(self.offset.saturating_sub(self.visible), surface);
+        } else if y >= thumb.y + thumb.height {
+            self.set_offset(self.offset + self.visible, surface);
+        } else {
+            self.grab_y = Some(y - thumb.y);
+        }
+
+        true
+    }
+
+    /// Move the grabbed thumb, it stays inside the track. Returns true if the offset changed.
+    pub fn handle_mouse_drag(&mut self, y: usize, surface: &mut Surface) -> bool {
+        let Some(grab_y) = self.grab_y else {
+            return false;
+        };
+
+        let thumb = self.thumb_rect();
+        let travel = self.rect.height - thumb.height;
+        if travel == 0 {
+            return false;
+        }
+
+        let thumb_y = y
+            .saturating_sub(grab_y)
+            .clamp(self.rect.y, self.rect.y + travel)
+            - self.rect.y;
+        // Rounded, so the thumb lands on the closest offset
+        let offset = (thumb_y * self.max_offset() + travel / 2) / travel;
+
+        self.set_offset(offset, surface)
+    }
+
+    pub fn handle_mouse_up(&mut self) {
+        self.grab_y = None;
+    }
+
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        surface.add_shape(Shape::Rectangle {
+            x: self.rect.x,
+            y: self.rect.y,
+            width: self.rect.width,
+            height: self.rect.height,
+            color: TRACK_COLOR,
+            filled: true,
+            hide: false,
+        });
+
+        let thumb = self.thumb_rect();
+        self.thumb_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: thumb.x + 2,
+            y: thumb.y,
+            width: thumb.width - 4,
+            height: thumb.height,
+            color: THUMB_COLOR,
+            filled: true,
+            hide: self.max_offset() == 0,
+        }));
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.thumb_idx = None;
+    }
+
+    pub fn update_surface(&self, surface: &mut Surface) {
+        let Some(idx) = self.thumb_idx else {
+            return;
+        };
+
+        let thumb = self.thumb_rect();
+        surface.move_shape(idx, thumb.x + 2, thumb.y);
+        surface.update_rectangle_size(idx, thumb.width - 4, thumb.height);
+
+        if self.max_offset() == 0 {
+            surface.hide_shape(idx);
+        } else {
+            surface.show_shape(idx);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    framebuffer::Color,
+    surface::{Rect, Shape, Surface},
+};
+
+/// Thickness of a slider, its height or the width of a vertical one
+pub const SLIDER_HEIGHT: usize = 16;
+/// Length of the knob along the track
+const KNOB_WIDTH: usize = 8;
+const TRACK_HEIGHT: usize = 4;
+
+const TRACK_COLOR: Color = Color::new(160, 160, 160);
+const KNOB_COLOR: Color = Color::new(220, 220, 220);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// The value grows to the right
+    Horizontal,
+    /// The value grows downwards
+    Vertical,
+}
+
+/// A slider that snaps to a fixed number of steps, from 0 to `steps - 1`. The knob can be
+/// dragged, or the track clicked to jump there.
+pub struct Slider {
+    pub rect: Rect,
+    orientation: Orientation,
+    steps: usize,
+    value: usize,
+
+    /// Distance from the start of the knob to where it was grabbed, while dragging
+    grab: Option<usize>,
+
+    // Shape index, only valid while the slider is on a surface
+    knob_idx: Option<usize>,
+}
+
+impl Slider {
+    pub fn new(x: usize, y: usize, width: usize, steps: usize) -> Self {
+        Self {
+            rect: Rect::new(x, y, width, SLIDER_HEIGHT),
+            orientation: Orientation::Horizontal,
+            steps: steps.max(2),
+            value: 0,
+            grab: None,
+            knob_idx: None,
+        }
+    }
+
+    pub fn vertical(x: usize, y: usize, height: usize, steps: usize) -> Self {
+        Self {
+            rect: Rect::new(x, y, SLIDER_HEIGHT, height),
+            orientation: Orientation::Vertical,
+            ..Self::new(x, y, 0, steps)
+        }
+    }
+
+    pub fn value(&self) -> usize {
+        self.value
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.grab.is_some()
+    }
+
+    /// Move the knob to a step, returns true if the value changed
+    pub fn set_value(&mut self, value: usize, surface: &mut Surface) -> bool {
+        let value = value.min(self.steps - 1);
+        if value == self.value {
+            return false;
+        }
+
+        self.value = value;
+        // Only the knob's old and new place are redrawn
+        if let Some(idx) = self.knob_idx {
+            let knob = self.knob_rect();
+            surface.move_shape(idx, knob.x, knob.y);
+        }
+        true
+    }
+
+    /// Start and length of the track along the slider
+    fn track(&self) -> (usize, usize) {
+        match self.orientation {
+            Orientation::Horizontal => (self.rect.x, self.rect.width),
+            Orientation::Vertical => (self.rect.y, self.rect.height),
+        }
+    }
+
+    /// How far the knob can move
+    fn travel(&self) -> usize {
+        self.track().1.saturating_sub(KNOB_WIDTH)
+    }
+
+    /// Where the knob starts along the track, for the current value
+    fn knob_offset(&self) -> usize {
+        self.travel() * self.value / (self.steps - 1)
+    }
+
+    fn knob_rect(&self) -> Rect {
+        let offset = self.knob_offset();
+        match self.orientation {
+            Orientation::Horizontal => {
+                Rect::new(self.rect.x + offset, self.rect.y, KNOB_WIDTH, SLIDER_HEIGHT)
+            }
+            Orientation::Vertical => {
+                Rect::new(self.rect.x, self.rect.y + offset, SLIDER_HEIGHT, KNOB_WIDTH)
+            }
+        }
+    }
+
+    /// The coordinate along the track
+    fn position(&self, x: usize, y: usize) -> usize {
+        match self.orientation {
+            Orientation::Horizontal => x,
+            Orientation::Vertical => y,
+        }
+    }
+
+    /// Put the start of the knob at `knob_start`, rounded to the closest step. Returns true
+    /// if the value changed.
+    fn move_knob_to(&mut self, knob_start: usize, surface: &mut Surface) -> bool {
+        let offset = knob_start.saturating_sub(self.track().0).min(self.travel());
+        let travel = self.travel().max(1);
+        let value = (offset * (self.steps - 1) + travel / 2) / travel;
+
+        self.set_value(value, surface)
+    }
+
+    /// Jump to the step closest to a click on the track. A click on the knob does nothing,
+    /// it ends a press that may have grabbed it. Returns true if the value changed.
+    pub fn handle_click(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if !self.rect.contains_point(x, y) || self.knob_rect().contains_point(x, y) {
+            return false;
+        }
+
+        // Center the knob on the click
+        let position = self.position(x, y);
+        self.move_knob_to(position.saturating_sub(KNOB_WIDTH / 2), surface)
+    }
+
+    /// Grab the knob where it was pressed, or jump it to a press on the track and grab its
+    /// center. Returns true if the press was on the slider, the caller should then pass on
+    /// the mouse moves.
+    pub fn handle_mouse_down(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        if !self.rect.contains_point(x, y) {
+            return false;
+        }
+
+        let position = self.position(x, y);
+        if !self.knob_rect().contains_point(x, y) {
+            self.move_knob_to(position.saturating_sub(KNOB_WIDTH / 2), surface);
+        }
+
+        // Measured after the jump, the knob may not be centered on the press when it was
+        // clamped to the end or rounded to a step
+        let knob_start = self.track().0 + self.knob_offset();
+        self.grab = Some(position.saturating_sub(knob_start));
+        true
+    }
+
+    /// Move the grabbed knob so the spot it was grabbed at stays under the mouse, `x` and
+    /// `y` may be outside of the slider. Returns true if the value changed.
+    pub fn handle_mouse_drag(&mut self, x: usize, y: usize, surface: &mut Surface) -> bool {
+        let Some(grab) = self.grab else {
+            return false;
+        };
+
+        let position = self.position(x, y);
+        self.move_knob_to(position.saturating_sub(grab), surface)
+    }
+
+    pub fn handle_mouse_up(&mut self) {
+        self.grab = None;
+    }
+
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        let track_inset = (SLIDER_HEIGHT - TRACK_HEIGHT) / 2;
+        let (x, y, width, height) = match self.orientation {
+            Orientation::Horizontal => (
+                self.rect.x,
+                self.rect.y + track_inset,
+                self.rect.width,
+                TRACK_HEIGHT,
+            ),
+            Orientation::Vertical => (
+                self.rect.x + track_inset,
+                self.rect.y,
+                TRACK_HEIGHT,
+                self.rect.height,
+            ),
+        };
+        surface.add_shape(Shape::Rectangle {
+            x,
+            y,
+            width,
+            height,
+            color: TRACK_COLOR,
+            filled: true,
+            hide: false,
+        });
+
+        let knob = self.knob_rect();
+        self.knob_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: knob.x,
+            y: knob.y,
+            width: knob.width,
+            height: knob.height,
+            color: KNOB_COLOR,
+            filled: true,
+            hide: false,
+        }));
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.knob_idx = None;
+        self.grab = None;
+    }
+}
@@ -0,0 +1,130 @@
+use alloc::vec::Vec;
+
+use crate::{
+    framebuffer::Color,
+    interrupts::{ms_to_ticks, ticks},
+    surface::{Rect, Shape, Surface},
+};
+
+/// Width and height of a spinner
+pub const SPINNER_SIZE: usize = 24;
+const DOTS: usize = 8;
+const DOT_SIZE: usize = 4;
+/// Time the head takes to move on by one dot
+const FRAME_MS: u64 = 100;
+
+/// Dot centers around the middle of the spinner, clockwise from the top
+const DOT_OFFSETS: [(isize, isize); DOTS] = [
+    (0, -9),
+    (6, -6),
+    (9, 0),
+    (6, 6),
+    (0, 9),
+    (-6, 6),
+    (-9, 0),
+    (-6, -6),
+];
+
+/// The frame to show at a tick, so the speed doesn't depend on how often it's drawn
+pub fn frame_at(tick: u64) -> usize {
+    (tick / ms_to_ticks(FRAME_MS).max(1)) as usize % DOTS
+}
+
+/// Where the dots of a spinner with its top left corner at `x`, `y` are
+pub fn dot_rects(x: usize, y: usize) -> [Rect; DOTS] {
+    let center = (
+        (x + SPINNER_SIZE / 2) as isize,
+        (y + SPINNER_SIZE / 2) as isize,
+    );
+    DOT_OFFSETS.map(|(dx, dy)| {
+        Rect::new(
+            (center.0 + dx) as usize - DOT_SIZE / 2,
+            (center.1 + dy) as usize - DOT_SIZE / 2,
+            DOT_SIZE,
+            DOT_SIZE,
+        )
+    })
+}
+
+/// The head of the spinner is `color`, the dots behind it fade into `background`
+pub fn dot_color(dot: usize, frame: usize, color: Color, background: Color) -> Color {
+    let age = ((frame + DOTS - dot) % DOTS) as u16;
+    let mix = |over: u8, under: u8| {
+        ((over as u16 * (DOTS as u16 - age) + under as u16 * age) / DOTS as u16) as u8
+    };
+    Color::new(
+        mix(color.r, background.r),
+        mix(color.g, background.g),
+        mix(color.b, background.b),
+    )
+}
+
+/// A ring of dots that goes around while something is busy. Call [`Spinner::update`] every
+/// frame, it only redraws its dots when the frame changed.
+pub struct Spinner {
+    pub rect: Rect,
+    color: Color,
+    background: Color,
+    /// The frame on the surface
+    frame: usize,
+
+    // Shape indices, only valid while the spinner is on a surface
+    dot_idx: Vec<usize>,
+}
+
+impl Spinner {
+    pub fn new(x: usize, y: usize, color: Color, background: Color) -> Self {
+        Self {
+            rect: Rect::new(x, y, SPINNER_SIZE, SPINNER_SIZE),
+            color,
+            background,
+            frame: frame_at(ticks()),
+            dot_idx: Vec::new(),
+        }
+    }
+
+    /// Move on to the frame for now, returns true if the dots changed
+    pub fn update(&mut self, surface: &mut Surface) -> bool {
+        let frame = frame_at(ticks());
+        if frame == self.frame {
+            return false;
+        }
+
+        self.frame = frame;
+        for (dot, idx) in self.dot_idx.iter().enumerate() {
+            surface
+                .update_rectangle_color(*idx, dot_color(dot, frame, self.color, self.background));
+        }
+        true
+    }
+
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        self.dot_idx = dot_rects(self.rect.x, self.rect.y)
+            .iter()
+            .enumerate()
+            .map(|(dot, rect)| {
+                surface.add_shape(Shape::Rectangle {
+                    x: rect.x,
+                    y: rect.y,
+                    width: rect.width,
+                    height: rect.height,
+                    color: dot_color(dot, self.frame, self.color, self.background),
+                    filled: true,
+                    hide: false,
+                })
+            })
+            .collect();
+    }
+
+    /// Hide the spinner, for example once the work is done
+    pub fn hide(&self, surface: &mut Surface) {
+        for idx in &self.dot_idx {
+            surface.hide_shape(*idx);
+        }
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.dot_idx.clear();
+    }
+}
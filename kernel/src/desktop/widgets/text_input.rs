@@ -0,0 +1,451 @@
+use core::ops::Range;
+
+use alloc::string::{String, ToString};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+use pc_keyboard::{KeyCode, Modifiers};
+
+use crate::{
+    framebuffer::{Color, measure_text},
+    interrupts::{ms_to_ticks, ticks},
+    surface::{Rect, Shape, Surface},
+};
+
+const PADDING: usize = 5;
+const SELECTION_COLOR: Color = Color::new(150, 200, 255);
+
+/// How long the caret stays on and off while blinking
+pub const CARET_BLINK_MS: u64 = 500;
+/// After this long without typing the caret stops blinking and is dimmed
+pub const CARET_IDLE_MS: u64 = 10_000;
+const CARET_IDLE_COLOR: Color = Color::new(160, 160, 160);
+
+/// What a character counts as when deleting by words
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    fn of(c: char) -> Self {
+        match c {
+            ' ' | '\t' => CharClass::Space,
+            c if c.is_alphanumeric() || c == '_' => CharClass::Word,
+            _ => CharClass::Punctuation,
+        }
+    }
+}
+
+/// Walk over the spaces next to the caret and the word or punctuation after them. `chars`
+/// goes away from `position`, with the offset past each character. A line break is only
+/// taken on its own.
+fn word_boundary(position: usize, chars: impl Iterator<Item = (usize, char)>) -> usize {
+    let mut boundary = position;
+    let mut run = None;
+
+    for (offset, c) in chars {
+        if c == '\n' {
+            if boundary == position {
+                return offset;
+            }
+            break;
+        }
+
+        let class = CharClass::of(c);
+        if run.is_some_and(|run| run != class) {
+            break;
+        }
+        if class != CharClass::Space {
+            run = Some(class);
+        }
+        boundary = offset;
+    }
+
+    boundary
+}
+
+/// Where Ctrl+Backspace stops deleting, before the caret at `position`
+pub fn previous_word_boundary(text: &str, position: usize) -> usize {
+    word_boundary(position, text[..position].char_indices().rev())
+}
+
+/// Where Ctrl+Delete stops deleting, after the caret at `position`
+pub fn next_word_boundary(text: &str, position: usize) -> usize {
+    word_boundary(
+        position,
+        text[position..]
+            .char_indices()
+            .map(|(offset, c)| (position + offset + c.len_utf8(), c)),
+    )
+}
+
+/// A single line text field with a caret and an (optional) selection
+pub struct TextInput {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+
+    text: String,
+    /// Byte offset of the caret in `text`
+    cursor: usize,
+    /// Where the selection started, it spans from here to `cursor`
+    selection_anchor: Option<usize>,
+
+    font_size: RasterHeight,
+    font_weight: FontWeight,
+
+    // Shape indices, only valid while the input is on a surface
+    selection_idx: Option<usize>,
+    text_idx: Option<usize>,
+    caret_idx: Option<usize>,
+
+    /// Only the focused input shows a caret
+    focused: bool,
+    /// Tick of the last edit or caret movement, the blink starts over from here
+    last_activity: u64,
+    /// What the caret shape currently shows, as (visible, dimmed)
+    caret_state: (bool, bool),
+}
+
+impl TextInput {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            text: String::new(),
+            cursor: 0,
+            selection_anchor: None,
+            font_size: RasterHeight::Size16,
+            font_weight: FontWeight::Regular,
+            selection_idx: None,
+            text_idx: None,
+            caret_idx: None,
+            focused: false,
+            last_activity: 0,
+            caret_state: (true, false),
+        }
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Focus or unfocus the input, the caret shape follows on the next [`TextInput::blink`]
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.last_activity = ticks();
+    }
+
+    /// Whether the caret still changes without input, so [`TextInput::blink`] must keep being called
+    pub fn is_blinking(&self) -> bool {
+        self.focused && ticks().saturating_sub(self.last_activity) < ms_to_ticks(CARET_IDLE_MS)
+    }
+
+    /// Caret (visible, dimmed) at the given tick
+    fn caret_state_at(&self, now: u64) -> (bool, bool) {
+        if !self.focused {
+            return (false, false);
+        }
+
+        let idle = now.saturating_sub(self.last_activity);
+        if idle >= ms_to_ticks(CARET_IDLE_MS) {
+            return (true, true);
+        }
+
+        ((idle / ms_to_ticks(CARET_BLINK_MS)) % 2 == 0, false)
+    }
+
+    /// Show, hide or dim the caret for the current tick.
+    /// Only the caret shape is touched, and only when its state changes.
+    pub fn blink(&mut self, surface: &mut Surface) {
+        let Some(idx) = self.caret_idx else {
+            return;
+        };
+
+        let state = self.caret_state_at(ticks());
+        if state == self.caret_state {
+            return;
+        }
+
+        let (visible, dimmed) = state;
+        if visible {
+            surface.show_shape(idx);
+        } else {
+            surface.hide_shape(idx);
+        }
+        if dimmed != self.caret_state.1 {
+            let color = if dimmed {
+                CARET_IDLE_COLOR
+            } else {
+                Color::BLACK
+            };
+            surface.update_rectangle_color(idx, color);
+        }
+
+        self.caret_state = state;
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.selection_anchor = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.set_text("");
+    }
+
+    /// Byte offset of the caret
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The selected byte range, if anything is selected
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.selection_anchor?;
+        if anchor == self.cursor {
+            return None;
+        }
+
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    pub fn selected_text(&self) -> Option<&str> {
+        self.selection().map(|range| &self.text[range])
+    }
+
+    /// Insert a character at the caret, replacing the selection if there is one
+    pub fn insert_char(&mut self, c: char) {
+        self.delete_selection();
+
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Remove the selection or the character before the caret
+    pub fn backspace(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.text.remove(self.cursor);
+        }
+    }
+
+    /// Remove the selection or the character after the caret
+    pub fn delete(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    /// Remove the selection or the word before the caret
+    pub fn delete_word_back(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        let start = previous_word_boundary(&self.text, self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Remove the selection or the word after the caret
+    pub fn delete_word_forward(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        let end = next_word_boundary(&self.text, self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let Some(range) = self.selection() else {
+            self.selection_anchor = None;
+            return false;
+        };
+
+        self.text.replace_range(range.clone(), "");
+        self.cursor = range.start;
+        self.selection_anchor = None;
+        true
+    }
+
+    /// Handles caret movement keys, Backspace and Delete. Holding shift extends the
+    /// selection, Ctrl deletes whole words. Returns true if the key was used by the input.
+    pub fn handle_key(&mut self, key: KeyCode, modifiers: &Modifiers) -> bool {
+        let new_cursor = match key {
+            KeyCode::Backspace if modifiers.is_ctrl() => {
+                self.delete_word_back();
+                return true;
+            }
+            KeyCode::Backspace => {
+                self.backspace();
+                return true;
+            }
+            KeyCode::Delete if modifiers.is_ctrl() => {
+                self.delete_word_forward();
+                return true;
+            }
+            KeyCode::Delete => {
+                self.delete();
+                return true;
+            }
+            KeyCode::ArrowLeft => match self.selection() {
+                Some(range) if !modifiers.is_shifted() => range.start,
+                _ => self.text[..self.cursor]
+                    .chars()
+                    .next_back()
+                    .map_or(self.cursor, |c| self.cursor - c.len_utf8()),
+            },
+            KeyCode::ArrowRight => match self.selection() {
+                Some(range) if !modifiers.is_shifted() => range.end,
+                _ => self.text[self.cursor..]
+                    .chars()
+                    .next()
+                    .map_or(self.cursor, |c| self.cursor + c.len_utf8()),
+            },
+            KeyCode::Home => 0,
+            KeyCode::End => self.text.len(),
+            _ => return false,
+        };
+
+        if modifiers.is_shifted() {
+            if self.selection_anchor.is_none() {
+                self.selection_anchor = Some(self.cursor);
+            }
+        } else {
+            self.selection_anchor = None;
+        }
+
+        self.cursor = new_cursor;
+        true
+    }
+
+    /// Pixel offset of the given byte offset from the start of the text
+    fn offset_of(&self, byte_offset: usize) -> usize {
+        measure_text(&self.text[..byte_offset], self.font_weight, self.font_size).0
+    }
+
+    /// Add the input to a surface. The shapes are kept up to date by [`TextInput::update_surface`].
+    pub fn add_to_surface(&mut self, surface: &mut Surface) {
+        surface.add_shape(Shape::Rectangle {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            color: Color::WHITE,
+            filled: true,
+            hide: false,
+        });
+
+        surface.add_shape(Shape::Rectangle {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            color: Color::BLACK,
+            filled: false,
+            hide: false,
+        });
+
+        self.selection_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: self.x + PADDING,
+            y: self.y + 2,
+            width: 1,
+            height: self.height - 4,
+            color: SELECTION_COLOR,
+            filled: true,
+            hide: true,
+        }));
+
+        self.text_idx = Some(surface.add_shape(Shape::Text {
+            x: self.x + PADDING,
+            y: self.y + PADDING,
+            content: self.text.clone(),
+            color: Color::BLACK,
+            background_color: Color::WHITE,
+            font_size: self.font_size,
+            font_weight: self.font_weight,
+            hide: false,
+        }));
+
+        self.caret_idx = Some(surface.add_shape(Shape::Rectangle {
+            x: self.x + PADDING + self.offset_of(self.cursor),
+            y: self.y + 3,
+            width: 1,
+            height: self.height - 6,
+            color: Color::BLACK,
+            filled: true,
+            hide: false,
+        }));
+        self.caret_state = (true, false);
+
+        self.update_surface(surface);
+    }
+
+    /// Forget the shapes, for example after the surface was cleared
+    pub fn detach(&mut self) {
+        self.selection_idx = None;
+        self.text_idx = None;
+        self.caret_idx = None;
+    }
+
+    /// Sync the shapes after an edit, this also restarts the caret blink
+    pub fn update_surface(&mut self, surface: &mut Surface) {
+        self.last_activity = ticks();
+
+        if let Some(idx) = self.text_idx {
+            surface.update_text_content(idx, self.text.clone(), None);
+        }
+
+        if let Some(idx) = self.caret_idx {
+            surface.move_shape(
+                idx,
+                self.x + PADDING + self.offset_of(self.cursor),
+                self.y + 3,
+            );
+        }
+
+        if let Some(idx) = self.selection_idx {
+            match self.selection() {
+                Some(range) => {
+                    let start = self.offset_of(range.start);
+                    let end = self.offset_of(range.end);
+
+                    surface.move_shape(idx, self.x + PADDING + start, self.y + 2);
+                    surface.update_rectangle_size(idx, end - start, self.height - 4);
+                    surface.show_shape(idx);
+                }
+                None => {
+                    surface.hide_shape(idx);
+                }
+            }
+        }
+
+        self.blink(surface);
+    }
+}
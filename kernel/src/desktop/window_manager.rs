@@ -1,31 +1,134 @@
-use alloc::{
-    string::{String, ToString},
-    vec::Vec,
-};
-use pc_keyboard::KeyCode;
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use pc_keyboard::{DecodedKey, KeyCode, Modifiers};
+use x86_64::instructions::interrupts::without_interrupts;
 
 use crate::{
     desktop::{
-        calculator::Calculator, filemanager::FileManager, notepad::Notepad, sysinfo::SysInfo,
+        animation::Animation,
+        application::{Action, Application},
+        associations::Associations,
+        clipboard, clipboard_history,
+        input::{self, DOUBLE_CLICK_TICKS, MouseSpeed},
+        osd::{self, Osd},
+        recent,
+        repaint_debug::{self, InspectedWindow},
+        taskbar,
+        toast::Toasts,
+        window_positions,
+        workspaces::{self, Workspaces},
     },
-    framebuffer::{Color, FrameBufferWriter},
-    fs::fat32::FileEntry,
+    framebuffer::{Color, FRAMEBUFFER, FrameBufferWriter, SCREEN_SIZE},
+    fs::bench::{self, BenchConfig},
+    interrupts, log,
+    serial::{self, LogLevel},
+    serial_println,
     surface::{Rect, Surface},
 };
 
+/// Distance from a screen edge, in pixels, at which a dragged window snaps to it
+pub const SNAP_ZONE_THICKNESS: usize = 8;
+const SNAP_PREVIEW_COLOR: Color = Color::new(80, 140, 255);
+const SNAP_PREVIEW_ALPHA: u8 = 80;
+/// Frames each measurement of the compositing benchmark draws
+const COMPOSITE_BENCH_FRAMES: u64 = 20;
+
+/// Screen areas a window can be snapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapZone {
+    Left,
+    Right,
+    Maximized,
+}
+
+impl SnapZone {
+    /// The zone a window dragged with the cursor at (x, y) snaps to
+    fn at(x: usize, y: usize) -> Option<Self> {
+        let area = taskbar::layout().desktop_area();
+
+        if x < area.x + SNAP_ZONE_THICKNESS {
+            Some(SnapZone::Left)
+        } else if x + SNAP_ZONE_THICKNESS >= area.x + area.width {
+            Some(SnapZone::Right)
+        } else if y < area.y + SNAP_ZONE_THICKNESS {
+            Some(SnapZone::Maximized)
+        } else {
+            None
+        }
+    }
+
+    /// Full window bounds, with title bar and border, of a window snapped here
+    fn bounds(self) -> Rect {
+        let area = taskbar::layout().desktop_area();
+        let half = area.width / 2;
+
+        match self {
+            SnapZone::Left => Rect::new(area.x, area.y, half, area.height),
+            SnapZone::Right => Rect::new(area.x + half, area.y, area.width - half, area.height),
+            SnapZone::Maximized => area,
+        }
+    }
+}
+
+/// Tinted area showing where the dragged window will snap, with the pixels it covers
+struct SnapPreview {
+    zone: SnapZone,
+    bounds: Rect,
+    background: Vec<u8>,
+}
+
+impl SnapPreview {
+    fn show(framebuffer: &mut FrameBufferWriter, zone: SnapZone) -> Self {
+        let bounds = zone.bounds();
+
+        let mut background = Vec::new();
+        for y in bounds.y..bounds.y + bounds.height {
+            background.extend_from_slice(framebuffer.read_raw_pixel_row(bounds.x, y, bounds.width));
+        }
+
+        framebuffer.blend_rect(
+            (bounds.x, bounds.y),
+            (bounds.x + bounds.width - 1, bounds.y + bounds.height - 1),
+            SNAP_PREVIEW_COLOR,
+            SNAP_PREVIEW_ALPHA,
+        );
+
+        Self {
+            zone,
+            bounds,
+            background,
+        }
+    }
+
+    fn hide(self, framebuffer: &mut FrameBufferWriter) {
+        let row_size = self.background.len() / self.bounds.height.max(1);
+        if row_size == 0 {
+            return;
+        }
+
+        for (row, pixels) in self.background.chunks_exact(row_size).enumerate() {
+            framebuffer.write_raw_pixel_row(self.bounds.x, self.bounds.y + row, pixels);
+        }
+    }
+}
+
 pub struct DragCache {
     background_buffer: Vec<u8>,
     cached_bounds: Rect,
     is_valid: bool,
 }
 
-pub enum Application {
-    Calculator(Calculator),
-    FileManager(FileManager),
-    Notepad(Notepad),
-    SysInfo(SysInfo),
+/// A document of a tabbed window that isn't shown, see [`Window::add_tab`]
+struct Tab {
+    title: String,
+    application: Box<dyn Application>,
+    surface: Surface,
 }
 
+/// Tabs in the title bar are at most this wide, they share the space when there are more
+const MAX_TAB_WIDTH: usize = 140;
+/// Width of a character in the title bar font
+const TITLE_CHAR_WIDTH: usize = 8;
+
 pub struct Window {
     pub x: usize,
     pub y: usize,
@@ -39,7 +142,24 @@ pub struct Window {
     pub drag_preview_x: usize,
     pub drag_preview_y: usize,
     drag_cache: Option<DragCache>,
-    pub application: Option<Application>,
+    /// Set when the application state changed and it has to re-render into its surface
+    pub content_dirty: bool,
+    /// Content bounds from before the window was snapped
+    restore_bounds: Option<Rect>,
+    snap_zone: Option<SnapZone>,
+    /// The app of the shown tab, it gets the input and draws into `surface`
+    pub application: Box<dyn Application>,
+    /// The other documents, in tab order without the shown one
+    tabs: Vec<Tab>,
+    /// Position of the shown document among all tabs
+    active_tab: usize,
+    /// Plays instead of drawing the window until it's done
+    opening: Option<Animation>,
+    /// Content area from before the window went fullscreen, set while it is
+    fullscreen_bounds: Option<Rect>,
+    /// What changed in the surface last frame, in screen coordinates, only kept for the
+    /// window inspector
+    changed_regions: Vec<Rect>,
 }
 
 impl Window {
@@ -49,16 +169,10 @@ impl Window {
         width: usize,
         height: usize,
         id: usize,
-        title: String,
-        application: Option<Application>,
+        application: Box<dyn Application>,
     ) -> Self {
-        let background_color = application.as_ref().map_or(Color::BLACK, |app| match app {
-            Application::Calculator(_) => Color::GRAY,
-            Application::FileManager(_) => Color::new(240, 240, 240),
-            Application::Notepad(_) => Color::WHITE,
-            Application::SysInfo(_) => Color::DARKGRAY,
-        });
-        let surface = Surface::new(width, height, background_color);
+        let title = application.title();
+        let surface = Surface::new(width, height, application.background_color());
 
         Self {
             x,
@@ -74,6 +188,135 @@ impl Window {
             drag_preview_x: x,
             drag_preview_y: y,
             drag_cache: None,
+            content_dirty: true,
+            restore_bounds: None,
+            snap_zone: None,
+            tabs: Vec::new(),
+            active_tab: 0,
+            opening: None,
+            fullscreen_bounds: None,
+            changed_regions: Vec::new(),
+        }
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen_bounds.is_some()
+    }
+
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len() + 1
+    }
+
+    /// Open another document in this window and show it
+    pub fn add_tab(&mut self, mut application: Box<dyn Application>) {
+        let mut surface = Surface::new(self.width, self.height, application.background_color());
+        application.init(&mut surface);
+
+        let tab = Tab {
+            title: core::mem::replace(&mut self.title, application.title()),
+            application: core::mem::replace(&mut self.application, application),
+            surface: core::mem::replace(&mut self.surface, surface),
+        };
+        self.tabs.insert(self.active_tab, tab);
+        self.active_tab = self.tabs.len();
+        self.content_dirty = true;
+    }
+
+    /// Show another tab. The full bounds have to be redrawn afterwards.
+    pub fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tab_count() {
+            return;
+        }
+
+        // The background list has no slot for the shown tab, indices after it shift down
+        let slot = if index < self.active_tab {
+            index
+        } else {
+            index - 1
+        };
+        let mut tab = self.tabs.remove(slot);
+        core::mem::swap(&mut tab.title, &mut self.title);
+        core::mem::swap(&mut tab.application, &mut self.application);
+        core::mem::swap(&mut tab.surface, &mut self.surface);
+
+        let old_slot = if self.active_tab < index {
+            self.active_tab
+        } else {
+            self.active_tab - 1
+        };
+        self.tabs.insert(old_slot, tab);
+        self.active_tab = index;
+
+        // The window may have been resized while the tab was hidden
+        self.surface.resize(self.width, self.height);
+        self.content_dirty = true;
+    }
+
+    /// Close the shown document and show the tab next to it. Returns false if it was the
+    /// only one, then the whole window has to be closed.
+    pub fn close_tab(&mut self) -> bool {
+        if self.tabs.is_empty() {
+            return false;
+        }
+
+        let slot = self.active_tab.min(self.tabs.len() - 1);
+        let tab = self.tabs.remove(slot);
+        self.title = tab.title;
+        self.application = tab.application;
+        self.surface = tab.surface;
+        self.active_tab = slot;
+
+        self.surface.resize(self.width, self.height);
+        self.content_dirty = true;
+        true
+    }
+
+    /// The apps of the tabs that aren't shown, with their surfaces
+    fn background_tabs(
+        &mut self,
+    ) -> impl Iterator<Item = (&mut Box<dyn Application>, &mut Surface)> {
+        self.tabs
+            .iter_mut()
+            .map(|tab| (&mut tab.application, &mut tab.surface))
+    }
+
+    /// Let the apps free what they can load again and drop the pixels of what isn't shown,
+    /// the whole window's if `shown` is false. Returns the bytes of pixels and of the apps.
+    fn trim_memory(&mut self, shown: bool) -> (usize, usize) {
+        let mut pixels = 0;
+        let mut apps = self.application.trim_memory(&mut self.surface);
+        if shown {
+            self.content_dirty = true;
+        } else {
+            pixels += self.surface.drop_pixels();
+        }
+
+        for (application, surface) in self.background_tabs() {
+            apps += application.trim_memory(surface);
+            pixels += surface.drop_pixels();
+        }
+        (pixels, apps)
+    }
+
+    fn tab_width(&self) -> usize {
+        (self.width.saturating_sub(40) / self.tab_count()).min(MAX_TAB_WIDTH)
+    }
+
+    /// The tab under a point of the title bar, only when there is more than one
+    fn tab_at(&self, x: usize, y: usize) -> Option<usize> {
+        if self.tabs.is_empty() || !self.title_bar_contains(x, y) {
+            return None;
+        }
+
+        let index = (x - self.x) / self.tab_width().max(1);
+        (index < self.tab_count()).then_some(index)
+    }
+
+    fn tab_title(&self, index: usize) -> &str {
+        match index.cmp(&self.active_tab) {
+            core::cmp::Ordering::Less => &self.tabs[index].title,
+            core::cmp::Ordering::Equal => &self.title,
+            core::cmp::Ordering::Greater => &self.tabs[index - 1].title,
         }
     }
 
@@ -92,32 +335,138 @@ impl Window {
         Rect::new(self.x, self.y, self.width, self.height)
     }
 
-    /// Check if this window intersects with the given dirty regions
-    pub fn intersects_dirty_regions(&self, dirty_regions: &[Rect]) -> bool {
-        let window_bounds = self.get_full_bounds();
-        dirty_regions
-            .iter()
-            .any(|rect| rect.intersects(&window_bounds))
+    /// Move and resize the content area, the application keeps its layout
+    fn set_content_bounds(&mut self, bounds: Rect) {
+        self.x = bounds.x;
+        self.y = bounds.y;
+        self.width = bounds.width;
+        self.height = bounds.height;
+        self.drag_preview_x = bounds.x;
+        self.drag_preview_y = bounds.y;
+
+        self.surface.resize(bounds.width, bounds.height);
+        self.content_dirty = true;
     }
 
-    pub fn render(&mut self, framebuffer: &mut FrameBufferWriter, force: bool) -> bool {
-        match &mut self.application {
-            Some(Application::Calculator(calculator)) => {
-                calculator.render(&mut self.surface);
-            }
-            Some(Application::FileManager(filemanager)) => {
-                filemanager.render(&mut self.surface);
-            }
-            Some(Application::Notepad(notepad)) => {
-                notepad.render(&mut self.surface);
-            }
-            Some(Application::SysInfo(sysinfo)) => {
-                sysinfo.render(&mut self.surface);
+    /// Fill a snap zone, the size from before is remembered for [`Window::unsnap`]
+    pub fn snap(&mut self, zone: SnapZone) {
+        if self.restore_bounds.is_none() {
+            self.restore_bounds = Some(self.get_content_bounds());
+        }
+
+        self.snap_zone = Some(zone);
+
+        // The content sits inside the border and under the title bar
+        let bounds = zone.bounds();
+        self.set_content_bounds(Rect::new(
+            bounds.x + 1,
+            bounds.y + 20,
+            bounds.width.saturating_sub(2),
+            bounds.height.saturating_sub(21),
+        ));
+    }
+
+    /// Go back to the place and size from before snapping
+    pub fn unsnap(&mut self) -> bool {
+        let Some(bounds) = self.restore_bounds.take() else {
+            return false;
+        };
+
+        self.snap_zone = None;
+        self.set_content_bounds(bounds);
+        true
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        self.snap_zone == Some(SnapZone::Maximized)
+    }
+
+    /// Maximize the window, or put it back if it already is.
+    /// Returns the screen area to redraw.
+    pub fn toggle_maximize(&mut self) -> Rect {
+        let old_bounds = self.get_full_bounds();
+
+        if self.is_maximized() {
+            self.unsnap();
+        } else {
+            self.snap(SnapZone::Maximized);
+        }
+
+        old_bounds.union(&self.get_full_bounds())
+    }
+
+    /// Whether a point is on the title bar, but not on one of its buttons
+    fn title_bar_contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width.saturating_sub(40) && y >= self.y - 20 && y < self.y
+    }
+
+    fn maximize_button_contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x + self.width - 40
+            && x < self.x + self.width - 20
+            && y >= self.y - 20
+            && y < self.y
+    }
+
+    /// Dragging a snapped window away gives it its old size back at the new place
+    fn restore_size(&mut self) {
+        if let Some(bounds) = self.restore_bounds.take() {
+            self.snap_zone = None;
+            self.set_content_bounds(Rect::new(self.x, self.y, bounds.width, bounds.height));
+        }
+    }
+
+    /// Change the title, only the title bar is added to `damage` to be redrawn
+    pub fn set_title(&mut self, title: String, damage: &mut Vec<Rect>) {
+        self.title = title;
+        damage.push(self.decoration_bounds()[0]);
+    }
+
+    /// The title bar and the outline around the content
+    fn decoration_bounds(&self) -> [Rect; 4] {
+        let left = self.x.saturating_sub(1);
+        [
+            Rect::new(left, self.y.saturating_sub(20), self.width + 2, 21),
+            Rect::new(left, self.y, 1, self.height + 1),
+            Rect::new(self.x + self.width, self.y, 1, self.height + 1),
+            Rect::new(left, self.y + self.height, self.width + 2, 1),
+        ]
+    }
+
+    /// Redraw the parts of the window under `damage`, adding the ones that changed
+    pub fn render(&mut self, framebuffer: &mut FrameBufferWriter, damage: &mut Vec<Rect>) {
+        if self.content_dirty {
+            self.application.render(&mut self.surface);
+
+            // Apps that are waiting for something check again next frame
+            self.content_dirty = self.application.needs_redraw();
+
+            let title = self.application.title();
+            if title != self.title {
+                self.set_title(title, damage);
             }
-            None => {}
         }
 
-        return self.surface.render(framebuffer, self.x, self.y, force);
+        // The surface keeps its pixels, so a window that was drawn over is only copied back
+        let damaged = damage.len();
+        self.surface.render(framebuffer, self.x, self.y, damage);
+        if repaint_debug::inspect_windows() {
+            self.changed_regions.clear();
+            self.changed_regions.extend_from_slice(&damage[damaged..]);
+        }
+
+        // No title bar or border, they would be off screen
+        if self.is_fullscreen() {
+            return;
+        }
+
+        let decorations = self.decoration_bounds();
+        let decorations_damaged = decorations
+            .iter()
+            .any(|bounds| damage.iter().any(|region| region.intersects(bounds)));
+        if decorations_damaged {
+            self.render_decorations(framebuffer);
+            damage.extend_from_slice(&decorations);
+        }
     }
 
     pub fn render_decorations(&self, framebuffer: &mut FrameBufferWriter) {
@@ -134,15 +483,50 @@ impl Window {
             (self.x + self.width, self.y),
             Color::BLACK,
         );
-        framebuffer.draw_raw_text(
-            &self.title,
-            self.x + 5,
-            self.y - 15,
-            Color::WHITE,
-            Color::BLACK,
-            noto_sans_mono_bitmap::FontWeight::Regular,
-            noto_sans_mono_bitmap::RasterHeight::Size16,
+        if self.tabs.is_empty() {
+            framebuffer.draw_raw_text(
+                &self.title,
+                self.x + 5,
+                self.y - 15,
+                Color::WHITE,
+                Color::BLACK,
+                noto_sans_mono_bitmap::FontWeight::Regular,
+                noto_sans_mono_bitmap::RasterHeight::Size16,
+            );
+        } else {
+            self.render_tabs(framebuffer);
+        }
+
+        // Maximize button, two stacked frames when it restores
+        let button_x = self.x + self.width - 40;
+        framebuffer.draw_rect(
+            (button_x, self.y - 20),
+            (button_x + 19, self.y),
+            Color::DARKGRAY,
         );
+        if self.is_maximized() {
+            framebuffer.draw_rect_outline(
+                (button_x + 7, self.y - 16),
+                (button_x + 15, self.y - 8),
+                Color::WHITE,
+            );
+            framebuffer.draw_rect(
+                (button_x + 4, self.y - 13),
+                (button_x + 12, self.y - 5),
+                Color::DARKGRAY,
+            );
+            framebuffer.draw_rect_outline(
+                (button_x + 4, self.y - 13),
+                (button_x + 12, self.y - 5),
+                Color::WHITE,
+            );
+        } else {
+            framebuffer.draw_rect_outline(
+                (button_x + 5, self.y - 15),
+                (button_x + 15, self.y - 5),
+                Color::WHITE,
+            );
+        }
 
         // Close button
         framebuffer.draw_rect(
@@ -162,6 +546,33 @@ impl Window {
         );
     }
 
+    /// The tab strip in the title bar, the shown tab is lighter
+    fn render_tabs(&self, framebuffer: &mut FrameBufferWriter) {
+        let tab_width = self.tab_width();
+        let max_chars = tab_width.saturating_sub(10) / TITLE_CHAR_WIDTH;
+
+        for index in 0..self.tab_count() {
+            let x = self.x + index * tab_width;
+            let background = if index == self.active_tab {
+                Color::DARKGRAY
+            } else {
+                Color::BLACK
+            };
+            framebuffer.draw_rect((x, self.y - 20), (x + tab_width - 2, self.y), background);
+
+            let title: String = self.tab_title(index).chars().take(max_chars).collect();
+            framebuffer.draw_raw_text(
+                &title,
+                x + 5,
+                self.y - 15,
+                Color::WHITE,
+                background,
+                noto_sans_mono_bitmap::FontWeight::Regular,
+                noto_sans_mono_bitmap::RasterHeight::Size16,
+            );
+        }
+    }
+
     /// Get the drag preview outline bounds
     fn get_drag_preview_bounds(&self) -> Rect {
         Rect::new(
@@ -260,6 +671,12 @@ impl Window {
         );
     }
 
+    /// Remove the drag outline while a snap preview is shown instead
+    fn hide_drag_outline(&mut self, framebuffer: &mut FrameBufferWriter) {
+        self.restore_cached_background(framebuffer);
+        self.drag_cache = None;
+    }
+
     /// Start dragging - enter drag mode
     pub fn start_drag(&mut self, framebuffer: &FrameBufferWriter) {
         self.is_dragging = true;
@@ -316,45 +733,233 @@ impl Window {
 
         (old_bounds, new_bounds)
     }
+
+    /// Leave drag mode where the window was, the caller repaints over the outline
+    fn cancel_drag(&mut self) {
+        self.dragging_offset = None;
+        self.is_dragging = false;
+        self.drag_cache = None;
+        self.drag_preview_x = self.x;
+        self.drag_preview_y = self.y;
+    }
 }
 
 pub struct WindowManager {
     pub windows: Vec<Window>,
+    /// Id of the window that gets the mouse moves while the button is held down
+    mouse_capture: Option<usize>,
+    /// Fading outlines of closed windows
+    closing: Vec<Animation>,
+    /// Messages drawn over all windows
+    toasts: Toasts,
+    /// The level a hotkey changed, over the windows too
+    osd: Osd,
+    /// Id of the window that covers the screen and gets all the input
+    fullscreen: Option<usize>,
+    snap_preview: Option<SnapPreview>,
+    /// Screen areas to redraw after windows changed outside of a mouse drag
+    dirty_regions: Vec<Rect>,
+    /// Window id and tick of the last click on a title bar, to detect double clicks
+    last_title_click: Option<(usize, u64)>,
+    /// The windows of the other workspaces
+    workspaces: Workspaces<Window>,
 }
 
 impl WindowManager {
     pub fn new() -> Self {
         Self {
             windows: Vec::new(),
+            mouse_capture: None,
+            closing: Vec::new(),
+            toasts: Toasts::new(),
+            osd: Osd::new(),
+            fullscreen: None,
+            snap_preview: None,
+            dirty_regions: Vec::new(),
+            last_title_click: None,
+            workspaces: Workspaces::new(),
         }
     }
 
+    pub fn take_dirty_regions(&mut self) -> Vec<Rect> {
+        core::mem::take(&mut self.dirty_regions)
+    }
+
     pub fn add_window(&mut self, mut window: Window) {
-        match &mut window.application {
-            Some(Application::Calculator(calculator)) => {
-                calculator.init(&mut window.surface);
-            }
-            Some(Application::FileManager(filemanager)) => {
-                filemanager.setup_ui(&mut window.surface);
-            }
-            Some(Application::Notepad(notepad)) => {
-                notepad.init(&mut window.surface);
+        let mut bounds = window.get_content_bounds();
+        if let Some(saved) = window_positions::saved(window.id) {
+            bounds.x = saved.x;
+            bounds.y = saved.y;
+            if !window.application.content_sized() {
+                bounds.width = saved.width;
+                bounds.height = saved.height;
             }
-            Some(Application::SysInfo(sysinfo)) => {
-                sysinfo.init(&mut window.surface);
-            }
-            None => {}
         }
 
+        let open: Vec<Rect> = self
+            .windows
+            .iter()
+            .map(Window::get_content_bounds)
+            .collect();
+        let bounds = window_positions::clamp_to_desktop(window_positions::cascade(bounds, &open));
+        if bounds != window.get_content_bounds() {
+            window.set_content_bounds(bounds);
+        }
+
+        window.application.init(&mut window.surface);
+        window.opening = Animation::open(
+            window.get_full_bounds(),
+            window.application.background_color(),
+        );
         self.windows.push(window);
     }
 
-    pub fn render(
+    /// Open an app as a new tab of the topmost window with `id`, which is brought to the
+    /// front to show it. The app is given back if there is no such window.
+    pub fn add_tab(
         &mut self,
-        framebuffer: &mut FrameBufferWriter,
-        desktop_dirty_regions: &[Rect],
-    ) -> bool {
-        let mut did_render = false;
+        id: usize,
+        application: Box<dyn Application>,
+    ) -> Result<(), Box<dyn Application>> {
+        let Some(index) = self.windows.iter().rposition(|w| w.id == id) else {
+            return Err(application);
+        };
+
+        let mut window = self.windows.remove(index);
+        window.add_tab(application);
+        self.dirty_regions.push(window.get_full_bounds());
+        self.windows.push(window);
+        Ok(())
+    }
+
+    /// Remove a window and remember its place, the caller redraws what it covered
+    fn close_window(&mut self, id: usize) {
+        if self.fullscreen == Some(id) {
+            self.fullscreen = None;
+            self.dirty_regions.push(screen_bounds());
+        }
+
+        if let Some(window) = self.windows.iter().find(|w| w.id == id) {
+            // A snapped or fullscreen window reopens at its size from before
+            let place = window
+                .fullscreen_bounds
+                .or(window.restore_bounds)
+                .unwrap_or_else(|| window.get_content_bounds());
+            window_positions::remember(id, place);
+
+            let bounds = window.get_full_bounds();
+            self.closing.extend(Animation::close(
+                bounds,
+                window.application.background_color(),
+            ));
+        }
+        self.windows.retain(|w| w.id != id);
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen.is_some()
+    }
+
+    /// Let the topmost window cover the screen, the other windows and the taskbar are hidden
+    /// and all input goes to it until [`WindowManager::exit_fullscreen`]
+    pub fn enter_fullscreen(&mut self) {
+        if self.fullscreen.is_some() {
+            return;
+        }
+        let Some(window) = self.windows.last_mut() else {
+            return;
+        };
+
+        window.fullscreen_bounds = Some(window.get_content_bounds());
+        window.set_content_bounds(screen_bounds());
+        self.fullscreen = Some(window.id);
+        self.mouse_capture = None;
+    }
+
+    /// Put the fullscreen window back and repaint the desktop around it
+    pub fn exit_fullscreen(&mut self) {
+        let Some(id) = self.fullscreen.take() else {
+            return;
+        };
+
+        if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+            if let Some(bounds) = window.fullscreen_bounds.take() {
+                window.set_content_bounds(bounds);
+            }
+        }
+        self.mouse_capture = None;
+        self.dirty_regions.push(screen_bounds());
+    }
+
+    /// Index of the shown workspace, from 0
+    pub fn workspace(&self) -> usize {
+        self.workspaces.active()
+    }
+
+    /// Show the windows of another workspace, the ones shown now wait until theirs is shown
+    /// again
+    pub fn switch_workspace(&mut self, index: usize) {
+        self.exit_fullscreen();
+        if !self.workspaces.switch(&mut self.windows, index) {
+            return;
+        }
+
+        self.mouse_capture = None;
+        self.last_title_click = None;
+        self.dirty_regions.push(screen_bounds());
+    }
+
+    /// Move the topmost window to another workspace and show that one
+    pub fn move_to_workspace(&mut self, index: usize) {
+        if index == self.workspaces.active() {
+            return;
+        }
+        let Some(window) = self.windows.pop() else {
+            return;
+        };
+
+        self.workspaces.send(window, index);
+        self.switch_workspace(index);
+    }
+
+    /// The windows that get input, only the fullscreen one while there is one. It borrows all
+    /// of self, loops that call other methods filter `windows` themselves.
+    fn input_windows(&mut self) -> impl Iterator<Item = &mut Window> {
+        let fullscreen = self.fullscreen;
+        self.windows
+            .iter_mut()
+            .filter(move |window| fullscreen.is_none_or(|id| window.id == id))
+    }
+
+    /// The shown windows from the bottom up, for the window inspector
+    pub fn inspect(&self) -> Vec<InspectedWindow> {
+        self.windows
+            .iter()
+            .filter(|window| self.fullscreen.is_none_or(|id| window.id == id))
+            .map(|window| InspectedWindow {
+                id: window.id,
+                title: window.title.clone(),
+                full_bounds: window.get_full_bounds(),
+                content_bounds: window.get_content_bounds(),
+                dirty_regions: window.changed_regions.clone(),
+            })
+            .collect()
+    }
+
+    /// Draw the windows from the bottom up. Each one redraws its part of `damage` and adds
+    /// what changed in it, so the windows above cover that again.
+    pub fn render(&mut self, framebuffer: &mut FrameBufferWriter, damage: &mut Vec<Rect>) {
+        if let Some(id) = self.fullscreen {
+            // It covers everything else
+            if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+                window.render(framebuffer, damage);
+            }
+            self.toasts
+                .render(framebuffer, damage, &mut self.dirty_regions);
+            self.osd
+                .render(framebuffer, damage, &mut self.dirty_regions);
+            return;
+        }
 
         for window in &mut self.windows {
             // Skip rendering if window is being dragged (only show drag preview)
@@ -362,21 +967,42 @@ impl WindowManager {
                 continue;
             }
 
-            // Only render window if it intersects with dirty regions or window itself is dirty
-            let intersects_dirty = window.intersects_dirty_regions(desktop_dirty_regions);
-            let should_render = window.surface.is_dirty || intersects_dirty;
-
-            if window.render(framebuffer, should_render) {
-                did_render = true;
+            if let Some(animation) = &window.opening {
+                if !animation.is_done() {
+                    continue;
+                }
+                // Drawn next frame, with all of it exposed
+                window.opening = None;
+                self.dirty_regions.push(window.get_full_bounds());
+                continue;
             }
 
-            if did_render {
-                // Always render decorations when we render the window
-                window.render_decorations(framebuffer);
-            }
+            window.render(framebuffer, damage);
         }
 
-        did_render
+        self.render_animations(framebuffer, damage);
+        self.toasts
+            .render(framebuffer, damage, &mut self.dirty_regions);
+        self.osd
+            .render(framebuffer, damage, &mut self.dirty_regions);
+    }
+
+    /// Draw the animations over all the windows. The areas they cover are exposed next frame,
+    /// so each frame starts from what is under them.
+    fn render_animations(&mut self, framebuffer: &mut FrameBufferWriter, damage: &mut Vec<Rect>) {
+        self.closing.retain(|animation| !animation.is_done());
+
+        let animations = self
+            .windows
+            .iter()
+            .filter_map(|window| window.opening.as_ref())
+            .chain(&self.closing);
+        for animation in animations {
+            if let Some(rect) = animation.render(framebuffer) {
+                damage.push(rect);
+                self.dirty_regions.push(rect);
+            }
+        }
     }
 
     /// Handles mouse click events on windows.
@@ -385,84 +1011,201 @@ impl WindowManager {
         &mut self,
         x: i16,
         y: i16,
-    ) -> (bool, Option<(usize, usize, usize, usize)>) {
-        for window in &mut self.windows {
-            if x as usize >= window.x
-                && x as usize <= window.x + window.width
-                && y as usize >= window.y
-                && y as usize <= window.y + window.height
+        modifiers: &Modifiers,
+    ) -> (bool, Option<Rect>) {
+        // Only the windows field stays borrowed, the actions below need the rest of self
+        let fullscreen = self.fullscreen;
+        let windows = self
+            .windows
+            .iter_mut()
+            .filter(|window| fullscreen.is_none_or(|id| window.id == id));
+        let mut clicked = None;
+        for window in windows {
+            if window
+                .get_content_bounds()
+                .contains_point(x as usize, y as usize)
             {
-                if let Some(Application::Calculator(calculator)) = &mut window.application {
-                    let x = (x as usize).saturating_sub(window.x);
-                    let y = (y as usize).saturating_sub(window.y);
-
-                    calculator.handle_mouse_click(x, y);
-                    return (true, None);
+                let x = (x as usize).saturating_sub(window.x);
+                let y = (y as usize).saturating_sub(window.y);
+
+                if !window
+                    .application
+                    .handle_click(x, y, modifiers, &mut window.surface)
+                {
+                    continue;
                 }
-                if let Some(Application::FileManager(filemanager)) = &mut window.application {
-                    let x = (x as usize).saturating_sub(window.x);
-                    let y = (y as usize).saturating_sub(window.y);
+                window.content_dirty = true;
 
-                    let (_, open_app) = filemanager.handle_click(x, y, &mut window.surface);
-                    if let Some((entry, app)) = open_app {
-                        self.open_app_handler(entry, app);
-                    }
-
-                    return (true, None);
+                let action = window.application.take_action();
+                // Closing one of several tabs keeps the window
+                if matches!(action, Some(Action::Close)) && window.close_tab() {
+                    return (true, Some(window.get_full_bounds()));
                 }
-                if let Some(Application::SysInfo(sysinfo)) = &mut window.application {
-                    let x = (x as usize).saturating_sub(window.x);
-                    let y = (y as usize).saturating_sub(window.y);
+                clicked = Some((window.id, window.get_full_bounds(), action));
+                break;
+            }
+        }
 
-                    sysinfo.handle_mouse_click(x, y);
-                    return (true, None);
+        if let Some((window_id, bounds, action)) = clicked {
+            return match action {
+                Some(Action::OpenFile(entry, directory, app)) => {
+                    app.open(self, entry, directory);
+                    (true, None)
                 }
-            }
+                Some(Action::Close) => {
+                    self.close_window(window_id);
+                    (true, Some(bounds))
+                }
+                Some(Action::Paste) => {
+                    self.close_window(window_id);
+                    self.paste();
+                    (true, Some(bounds))
+                }
+                None => (true, None),
+            };
+        }
+
+        // Nothing else is shown
+        if self.fullscreen.is_some() {
+            return (true, None);
         }
 
         // Check if the click was on the close button
-        for window in &self.windows {
+        for window in &mut self.windows {
+            // The title bar ends above the first row of the content
             if x as usize >= window.x + window.width - 20
-                && x as usize <= window.x + window.width
+                && (x as usize) < window.x + window.width
                 && y as usize >= window.y - 20
-                && y as usize <= window.y
+                && (y as usize) < window.y
             {
+                if !window.application.can_close() {
+                    return (true, None);
+                }
+
                 let window_id = window.id; // Rust borrowing checker goes brrr
-                let bounds = (
-                    window.x - 1,
-                    window.y - 20,
-                    window.width + 2,
-                    window.height + 21,
-                ); // Don't forget the outline and title bar :)
-
-                self.windows.retain(|w| w.id != window_id);
+                let bounds = window.get_full_bounds(); // Don't forget the outline and title bar :)
+
+                // A tabbed window only closes the shown document
+                if !window.close_tab() {
+                    self.close_window(window_id);
+                }
                 return (true, Some(bounds));
             }
         }
 
+        // Maximize button, or a double click anywhere else on the title bar
+        let (x, y) = (x as usize, y as usize);
+        for window in &mut self.windows {
+            let active_tab = window.active_tab;
+            if let Some(index) = window.tab_at(x, y).filter(|&index| index != active_tab) {
+                window.switch_tab(index);
+                return (true, Some(window.get_full_bounds()));
+            }
+
+            let double_click = if window.title_bar_contains(x, y) {
+                let now = interrupts::ticks();
+                let double_click = matches!(
+                    self.last_title_click,
+                    Some((id, tick)) if id == window.id && now - tick <= DOUBLE_CLICK_TICKS
+                );
+                self.last_title_click = (!double_click).then_some((window.id, now));
+
+                if !double_click {
+                    return (true, None);
+                }
+                true
+            } else {
+                false
+            };
+
+            if double_click || window.maximize_button_contains(x, y) {
+                return (true, Some(window.toggle_maximize()));
+            }
+        }
+
         (false, None)
     }
 
-    fn open_app_handler(&mut self, file: FileEntry, app: String) {
-        match app.as_str() {
-            "notepad" => launch_notepad_with_file(self, file),
-            "calculator" => launch_calculator(self), // Who tf opens his files in calculator?!
-            _ => {}
+    /// Handles right click events on window contents.
+    /// Returns true if a window was under the cursor.
+    pub fn handle_mouse_right_click(&mut self, x: i16, y: i16) -> bool {
+        for window in self.input_windows() {
+            if !window
+                .get_content_bounds()
+                .contains_point(x as usize, y as usize)
+            {
+                continue;
+            }
+
+            let x = x as usize - window.x;
+            let y = y as usize - window.y;
+
+            window
+                .application
+                .handle_right_click(x, y, &mut window.surface);
+
+            return true;
+        }
+
+        false
+    }
+
+    /// Open a file from the recent list with its default app
+    pub fn open_recent(&mut self, path: &str) {
+        let (file, directory) = match recent::find(path) {
+            Ok(found) => found,
+            Err(e) => {
+                log!(LogLevel::Warn, "Can't open recent file {}: {}", path, e);
+                return;
+            }
+        };
+
+        match Associations::load().default_app(&file.name) {
+            Some(app) => {
+                recent::record(path);
+                app.open(self, file, directory);
+            }
+            None => log!(LogLevel::Warn, "No app is set to open {}", file.name),
         }
     }
 
     pub fn handle_mouse_down(&mut self, x: i16, y: i16, framebuffer: &FrameBufferWriter) {
-        for window in &mut self.windows {
-            if x as usize >= window.x
-                && x as usize <= window.x + window.width - 20
-                && y as usize >= window.y - 20
-                && y as usize <= window.y
+        // A fullscreen window has no title bar to drag
+        if self.fullscreen.is_none() {
+            for window in &mut self.windows {
+                if window.title_bar_contains(x as usize, y as usize) {
+                    window.dragging_offset = Some((x, y));
+                    window.start_drag(framebuffer);
+
+                    return;
+                }
+            }
+        }
+
+        let fullscreen = self.fullscreen;
+        let windows = self
+            .windows
+            .iter_mut()
+            .filter(|window| fullscreen.is_none_or(|id| window.id == id));
+        for window in windows {
+            if !window
+                .get_content_bounds()
+                .contains_point(x as usize, y as usize)
             {
-                window.dragging_offset = Some((x, y));
-                window.start_drag(framebuffer);
+                continue;
+            }
 
-                return;
+            let local_x = x as usize - window.x;
+            let local_y = y as usize - window.y;
+
+            if window
+                .application
+                .handle_mouse_down(local_x, local_y, &mut window.surface)
+            {
+                self.mouse_capture = Some(window.id);
             }
+
+            return;
         }
     }
 
@@ -474,49 +1217,89 @@ impl WindowManager {
 
                 window.dragging_offset = Some((x, y));
 
-                // Calculate new position
+                // Calculate new position, the title bar can't go above the desktop area
+                let top = taskbar::layout().desktop_area().y as i16 + 20;
                 let new_x = (window.drag_preview_x as i16)
                     .saturating_add(delta_x)
                     .max(1) as usize;
                 let new_y = (window.drag_preview_y as i16)
                     .saturating_add(delta_y)
-                    .max(20) as usize;
+                    .max(top) as usize;
 
-                // Update drag preview
-                window.update_drag_preview(framebuffer, new_x, new_y);
+                let zone = SnapZone::at(x.max(0) as usize, y.max(0) as usize);
+                let current_zone = self.snap_preview.as_ref().map(|preview| preview.zone);
+                if zone != current_zone {
+                    if let Some(preview) = self.snap_preview.take() {
+                        preview.hide(framebuffer);
+                    }
+                }
+
+                match zone {
+                    Some(zone) => {
+                        // Only the snap preview is shown, the outline comes back outside the zone
+                        window.hide_drag_outline(framebuffer);
+                        window.drag_preview_x = new_x;
+                        window.drag_preview_y = new_y;
+
+                        if current_zone != Some(zone) {
+                            self.snap_preview = Some(SnapPreview::show(framebuffer, zone));
+                        }
+                    }
+                    None => window.update_drag_preview(framebuffer, new_x, new_y),
+                }
 
                 return;
             }
         }
+
+        if let Some(id) = self.mouse_capture {
+            let Some(window) = self.windows.iter_mut().find(|w| w.id == id) else {
+                self.mouse_capture = None;
+                return;
+            };
+
+            // Coordinates outside of the window are clamped by the application
+            let local_x = (x.max(0) as usize).saturating_sub(window.x);
+            let local_y = (y.max(0) as usize).saturating_sub(window.y);
+
+            window
+                .application
+                .handle_mouse_drag(local_x, local_y, &mut window.surface);
+        }
     }
 
-    pub fn handle_mouse_release(
-        &mut self,
-        framebuffer: &mut FrameBufferWriter,
-    ) -> Vec<(usize, usize, usize, usize)> {
+    pub fn handle_mouse_release(&mut self, framebuffer: &mut FrameBufferWriter) -> Vec<Rect> {
         let mut dirty_regions = Vec::new();
 
+        if let Some(id) = self.mouse_capture.take() {
+            if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+                window.application.handle_mouse_up(&mut window.surface);
+                window.content_dirty = true;
+            }
+        }
+
         for window in &mut self.windows {
             if window.dragging_offset.is_some() {
                 window.dragging_offset = None;
 
+                let snap_zone = self.snap_preview.take().map(|preview| {
+                    let zone = preview.zone;
+                    preview.hide(framebuffer);
+                    zone
+                });
+
                 // End drag and get dirty regions
-                let (old_bounds, new_bounds) = window.end_drag(framebuffer);
+                let (old_bounds, _) = window.end_drag(framebuffer);
+                match snap_zone {
+                    Some(zone) => window.snap(zone),
+                    None => window.restore_size(),
+                }
+                let new_bounds = window.get_full_bounds();
 
                 // Add both old and new positions as dirty regions
-                dirty_regions.push((
-                    old_bounds.x,
-                    old_bounds.y,
-                    old_bounds.width,
-                    old_bounds.height,
-                ));
+                dirty_regions.push(old_bounds);
                 if old_bounds != new_bounds {
-                    dirty_regions.push((
-                        new_bounds.x,
-                        new_bounds.y,
-                        new_bounds.width,
-                        new_bounds.height,
-                    ));
+                    dirty_regions.push(new_bounds);
                 }
             }
         }
@@ -524,97 +1307,295 @@ impl WindowManager {
         dirty_regions
     }
 
-    pub fn handle_char_input(&mut self, ch: char) {
-        // Send character input to the focused window (for now, just the first notepad or filemanager window)
+    /// Drop a drag or mouse capture without a release, for when something covers the whole
+    /// screen in the middle of it. The dragged window stays where it was.
+    pub fn cancel_drag(&mut self) {
+        if let Some(id) = self.mouse_capture.take() {
+            if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+                window.application.handle_mouse_up(&mut window.surface);
+                window.content_dirty = true;
+            }
+        }
+
+        // Whatever covers the screen paints over the preview, there is nothing to restore
+        self.snap_preview = None;
         for window in &mut self.windows {
-            match &mut window.application {
-                Some(Application::Notepad(notepad)) => {
-                    notepad.handle_char_input(ch);
-                    // break; // Only send to first notepad for now
+            if window.dragging_offset.is_some() || window.is_dragging {
+                window.cancel_drag();
+                self.dirty_regions.push(window.get_full_bounds());
+            }
+        }
+    }
+
+    /// Route a decoded key, from the PS/2 keyboard or a synthetic source, to the windows
+    pub fn handle_decoded_key(&mut self, key: DecodedKey, modifiers: &Modifiers) {
+        // Ctrl+Alt+Escape leaves fullscreen, every other key goes to the fullscreen app
+        if self.fullscreen.is_some() {
+            if modifiers.is_ctrl()
+                && modifiers.is_alt()
+                && matches!(key, DecodedKey::Unicode('\u{1b}'))
+            {
+                self.exit_fullscreen();
+            } else {
+                self.route_key(key, modifiers);
+            }
+            return;
+        }
+
+        // F12 is global, it makes the serial log more verbose and wraps around to quiet
+        if matches!(key, DecodedKey::RawKey(KeyCode::F12)) {
+            let level = serial::cycle_log_level();
+            serial_println!("Log level set to {}", level.name());
+            return;
+        }
+
+        // F11 is global too, it switches to the next mouse speed
+        if matches!(key, DecodedKey::RawKey(KeyCode::F11)) {
+            let speed = input::cycle_mouse_speed();
+            log!(LogLevel::Info, "Mouse speed set to {}", speed.name());
+            let level = (speed as usize + 1) * 100 / MouseSpeed::ALL.len();
+            osd::show(&format!("Mouse speed: {}", speed.name()), level);
+
+            // The system information window shows the speed
+            for window in &mut self.windows {
+                window.content_dirty = true;
+            }
+            return;
+        }
+
+        // F8 times the disk in debug builds, the numbers go to the log
+        if cfg!(debug_assertions) && matches!(key, DecodedKey::RawKey(KeyCode::F8)) {
+            bench::log_benchmark(&BenchConfig::default());
+            return;
+        }
+
+        // F6 times compositing the open windows in debug builds
+        if cfg!(debug_assertions) && matches!(key, DecodedKey::RawKey(KeyCode::F6)) {
+            self.log_composite_benchmark();
+            return;
+        }
+
+        // F9 and F10 toggle the repaint overlays in debug builds
+        if repaint_debug::handle_key(key) {
+            return;
+        }
+
+        // Alt+Enter makes the topmost window fullscreen
+        if modifiers.is_alt() && matches!(key, DecodedKey::Unicode('\n')) {
+            self.enter_fullscreen();
+            return;
+        }
+
+        // Ctrl+Alt+Left and Right switch workspaces, with Shift the topmost window goes along
+        if modifiers.is_ctrl() && modifiers.is_alt() {
+            let step = match key {
+                DecodedKey::RawKey(KeyCode::ArrowLeft) => Some(-1),
+                DecodedKey::RawKey(KeyCode::ArrowRight) => Some(1),
+                _ => None,
+            };
+            if let Some(step) = step {
+                let index = self.workspaces.neighbour(step, workspaces::count());
+                if modifiers.is_shifted() {
+                    self.move_to_workspace(index);
+                } else {
+                    self.switch_workspace(index);
                 }
-                Some(Application::FileManager(filemanager)) => {
-                    filemanager.handle_char_input(ch, &mut window.surface);
-                    // break; // Only send to first filemanager for now
+                return;
+            }
+        }
+
+        // Alt+arrows snap the topmost window, Alt+Down puts it back
+        if modifiers.is_alt() {
+            let zone = match key {
+                DecodedKey::RawKey(KeyCode::ArrowLeft) => Some(SnapZone::Left),
+                DecodedKey::RawKey(KeyCode::ArrowRight) => Some(SnapZone::Right),
+                DecodedKey::RawKey(KeyCode::ArrowUp) => Some(SnapZone::Maximized),
+                DecodedKey::RawKey(KeyCode::ArrowDown) => None,
+                _ => return self.route_key(key, modifiers),
+            };
+
+            if let Some(window) = self.windows.last_mut() {
+                let old_bounds = window.get_full_bounds();
+                let changed = match zone {
+                    Some(zone) => {
+                        window.snap(zone);
+                        true
+                    }
+                    None => window.unsnap(),
+                };
+
+                if changed {
+                    self.dirty_regions.push(old_bounds);
+                    self.dirty_regions.push(window.get_full_bounds());
                 }
-                _ => {}
             }
+            return;
         }
-    }
 
-    pub fn handle_key_input(&mut self, key: KeyCode) {
-        // Handle key input for focused window (for now, just the first notepad or filemanager window)
-        for window in &mut self.windows {
-            match &mut window.application {
-                Some(Application::Notepad(notepad)) => {
-                    notepad.handle_key_input(key);
-                    // break; // Only send to first notepad for now
+        // Ctrl+S saves the open documents, Ctrl is ignored by the keyboard so the key arrives
+        // as 's'
+        if modifiers.is_ctrl() && matches!(key, DecodedKey::Unicode('s' | 'S')) {
+            for window in &mut self.windows {
+                window.application.handle_save();
+                for (application, _) in window.background_tabs() {
+                    application.handle_save();
                 }
-                Some(Application::FileManager(filemanager)) => {
-                    filemanager.handle_key_input(key, &mut window.surface);
-                    // break; // Only send to first filemanager for now
+                window.content_dirty = true;
+            }
+            return;
+        }
+
+        // Ctrl+C and Ctrl+V only go to the topmost window, Ctrl+Shift+V lists older items
+        if modifiers.is_ctrl() {
+            match key {
+                DecodedKey::Unicode('c' | 'C') => return self.copy(),
+                DecodedKey::Unicode('v' | 'V') if modifiers.is_shifted() => {
+                    return clipboard_history::launch(self);
                 }
+                DecodedKey::Unicode('v' | 'V') => return self.paste(),
                 _ => {}
             }
         }
+
+        self.route_key(key, modifiers);
     }
-}
 
-pub fn launch_calculator(window_manager: &mut WindowManager) {
-    window_manager.add_window(Window::new(
-        100,
-        100,
-        205,
-        315,
-        1,
-        "Calculator".to_string(),
-        Some(Application::Calculator(Calculator::new())),
-    ));
-}
+    fn copy(&mut self) {
+        if let Some(clip) = self
+            .input_windows()
+            .last()
+            .and_then(|window| window.application.copy())
+        {
+            clipboard::copy(clip);
+        }
+    }
 
-pub fn launch_filemanager(window_manager: &mut WindowManager) {
-    window_manager.add_window(Window::new(
-        120,
-        80,
-        500,
-        400,
-        4,
-        "File Manager".to_string(),
-        Some(Application::FileManager(FileManager::new())),
-    ));
-}
+    fn paste(&mut self) {
+        let Some(clip) = clipboard::latest() else {
+            return;
+        };
+        if let Some(window) = self.input_windows().last() {
+            window.application.paste(&clip, &mut window.surface);
+            window.content_dirty = true;
+        }
+    }
 
-pub fn launch_notepad(window_manager: &mut WindowManager) {
-    window_manager.add_window(Window::new(
-        150,
-        150,
-        600,
-        400,
-        2,
-        "Notepad".to_string(),
-        Some(Application::Notepad(Notepad::new(None))),
-    ));
-}
+    fn route_key(&mut self, key: DecodedKey, modifiers: &Modifiers) {
+        match key {
+            // Tab moves the focus, so it goes with the modifiers to see Shift
+            DecodedKey::Unicode('\t') => self.handle_key_input(KeyCode::Tab, modifiers),
+            // Ctrl deletes whole words, the characters can't carry it
+            DecodedKey::Unicode('\u{8}') if modifiers.is_ctrl() => {
+                self.handle_key_input(KeyCode::Backspace, modifiers)
+            }
+            DecodedKey::Unicode('\u{7f}') if modifiers.is_ctrl() => {
+                self.handle_key_input(KeyCode::Delete, modifiers)
+            }
+            DecodedKey::Unicode(character) => self.handle_char_input(character),
+            DecodedKey::RawKey(key) => self.handle_key_input(key, modifiers),
+        }
+    }
+
+    /// Free what the windows can make again while memory is low, the windows of other
+    /// workspaces give up their pixels. Returns the bytes of pixels and of the apps.
+    pub fn trim_memory(&mut self) -> (usize, usize) {
+        let shown = self
+            .windows
+            .iter_mut()
+            .map(|window| window.trim_memory(true));
+        let hidden = self
+            .workspaces
+            .hidden_mut()
+            .map(|window| window.trim_memory(false));
+
+        shown
+            .chain(hidden)
+            .fold((0, 0), |(pixels, apps), (more_pixels, more_apps)| {
+                (pixels + more_pixels, apps + more_apps)
+            })
+    }
+
+    /// Time full-screen frames of the open windows, once copying the pixels their surfaces
+    /// keep and once with every app rendering again first, and log both
+    fn log_composite_benchmark(&mut self) {
+        let Some(tsc_per_ms) = bench::tsc_per_ms() else {
+            log!(LogLevel::Warn, "Benchmark needs the timer interrupt");
+            return;
+        };
+        let Some(framebuffer) = FRAMEBUFFER.get() else {
+            return;
+        };
+
+        let (kept, rendered) = without_interrupts(|| {
+            let mut framebuffer = framebuffer.lock();
+            let kept = self.time_frames(&mut framebuffer, false);
+            let rendered = self.time_frames(&mut framebuffer, true);
+            (kept, rendered)
+        });
+        // The cursor and whatever else was drawn over the windows comes back next frame
+        self.dirty_regions.push(screen_bounds());
+
+        let micros = |cycles: u64| cycles * 1000 / tsc_per_ms.max(1) / COMPOSITE_BENCH_FRAMES;
+        log!(
+            LogLevel::Info,
+            "Compositing {} windows: {} us per frame from kept pixels, {} us re-rendering the apps",
+            self.windows.len(),
+            micros(kept),
+            micros(rendered)
+        );
+    }
+
+    /// TSC cycles of [`COMPOSITE_BENCH_FRAMES`] frames with the whole screen damaged
+    fn time_frames(&mut self, framebuffer: &mut FrameBufferWriter, rerender: bool) -> u64 {
+        let begin = bench::cycles();
+        for _ in 0..COMPOSITE_BENCH_FRAMES {
+            let mut damage = vec![screen_bounds()];
+            for window in &mut self.windows {
+                window.content_dirty |= rerender;
+                window.render(framebuffer, &mut damage);
+            }
+        }
+        bench::cycles() - begin
+    }
+
+    /// Save the editors whose auto-save interval ran out, called every frame. The windows of
+    /// the other workspaces are saved too.
+    pub fn run_autosave(&mut self) {
+        for window in self.windows.iter_mut().chain(self.workspaces.hidden_mut()) {
+            if window.application.is_autosave_due() {
+                window.application.autosave(&mut window.surface);
+                window.content_dirty = true;
+            }
+            for (application, surface) in window.background_tabs() {
+                if application.is_autosave_due() {
+                    application.autosave(surface);
+                }
+            }
+        }
+    }
 
-pub fn launch_notepad_with_file(window_manager: &mut WindowManager, file: FileEntry) {
-    window_manager.add_window(Window::new(
-        150,
-        150,
-        600,
-        400,
-        2,
-        "Notepad".to_string(),
-        Some(Application::Notepad(Notepad::new(Some(file)))),
-    ));
+    pub fn handle_char_input(&mut self, ch: char) {
+        // Every window gets the key, apps without text input ignore it
+        for window in self.input_windows() {
+            window.application.handle_char(ch, &mut window.surface);
+            window.content_dirty = true;
+        }
+    }
+
+    pub fn handle_key_input(&mut self, key: KeyCode, modifiers: &Modifiers) {
+        for window in self.input_windows() {
+            window
+                .application
+                .handle_key(key, modifiers, &mut window.surface);
+            window.content_dirty = true;
+        }
+    }
 }
 
-pub fn launch_sysinfo(window_manager: &mut WindowManager) {
-    window_manager.add_window(Window::new(
-        200,
-        100,
-        400,
-        350,
-        3,
-        "System Information".to_string(),
-        Some(Application::SysInfo(SysInfo::new())),
-    ));
+/// The whole screen, for fullscreen windows
+fn screen_bounds() -> Rect {
+    let (width, height) = SCREEN_SIZE
+        .get()
+        .map_or((0, 0), |&(w, h)| (w as usize, h as usize));
+    Rect::new(0, 0, width, height)
 }
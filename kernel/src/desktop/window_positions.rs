@@ -0,0 +1,151 @@
+//! Where each application's window was when it was last closed, so it opens there again.
+//!
+//! The places are kept in `WINPOS.CFG` in the root directory. Every line is a window id and
+//! the content area, as x, y, width and height:
+//!
+//! ```text
+//! 1 = 340, 220, 205, 315
+//! ```
+//!
+//! Lines that can't be read are skipped, windows without a line open at their defaults.
+
+use alloc::{collections::BTreeMap, format, string::String};
+use spin::Mutex;
+
+use crate::{
+    desktop::{
+        config::{entries, read_root_config},
+        taskbar,
+    },
+    fs::{
+        fat32::open_flags,
+        manager::{is_mounted, root_directory_cluster, write_file_with_flags},
+    },
+    log,
+    serial::LogLevel,
+    surface::Rect,
+};
+
+pub const CONFIG_FILENAME: &str = "WINPOS.CFG";
+
+/// Height of the title bar above the content
+const TITLE_BAR_HEIGHT: usize = 20;
+/// How far a window is moved when it would open exactly on top of another one
+const CASCADE_OFFSET: usize = 20;
+
+static POSITIONS: Mutex<BTreeMap<usize, Rect>> = Mutex::new(BTreeMap::new());
+
+/// Read the places of a config file, invalid lines are skipped
+fn parse(text: &str) -> BTreeMap<usize, Rect> {
+    let mut positions = BTreeMap::new();
+
+    for (id, bounds) in entries(text) {
+        let Ok(id) = id.parse::<usize>() else {
+            continue;
+        };
+
+        let mut values = bounds.split(',').map(|value| value.trim().parse::<usize>());
+        if let (Some(Ok(x)), Some(Ok(y)), Some(Ok(width)), Some(Ok(height)), None) = (
+            values.next(),
+            values.next(),
+            values.next(),
+            values.next(),
+            values.next(),
+        ) {
+            positions.insert(id, Rect::new(x, y, width, height));
+        }
+    }
+
+    positions
+}
+
+/// Read the places from disk, none are known if there is no file or no filesystem
+pub fn load() {
+    *POSITIONS.lock() = read_root_config(CONFIG_FILENAME)
+        .map(|text| parse(&text))
+        .unwrap_or_default();
+}
+
+/// The content area a window was closed with, if it was closed before
+pub fn saved(id: usize) -> Option<Rect> {
+    POSITIONS.lock().get(&id).copied()
+}
+
+/// Remember where a window was closed and write the places to disk
+pub fn remember(id: usize, bounds: Rect) {
+    // Only kept until the next boot without a disk
+    if !is_mounted() {
+        POSITIONS.lock().insert(id, bounds);
+        return;
+    }
+
+    let text = {
+        let mut positions = POSITIONS.lock();
+        if positions.insert(id, bounds) == Some(bounds) {
+            return;
+        }
+
+        let mut text = String::from("# window id = x, y, width, height\n");
+        for (id, bounds) in positions.iter() {
+            text.push_str(&format!(
+                "{} = {}, {}, {}, {}\n",
+                id, bounds.x, bounds.y, bounds.width, bounds.height
+            ));
+        }
+        text
+    };
+
+    let result = root_directory_cluster().and_then(|root| {
+        write_file_with_flags(
+            root,
+            CONFIG_FILENAME,
+            text.as_bytes(),
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )
+    });
+    if let Err(e) = result {
+        log!(
+            LogLevel::Warn,
+            "Window positions: Error writing config: {}",
+            e
+        );
+    }
+}
+
+/// Move a content area onto the desktop, for places saved at another resolution. The title
+/// bar and the border stay visible, the bottom right may stick out if it doesn't fit.
+pub fn clamp_to_desktop(bounds: Rect) -> Rect {
+    let area = taskbar::layout().desktop_area();
+
+    let min_x = area.x + 1;
+    let min_y = area.y + TITLE_BAR_HEIGHT;
+    let max_x = (area.x + area.width)
+        .saturating_sub(bounds.width + 1)
+        .max(min_x);
+    let max_y = (area.y + area.height)
+        .saturating_sub(bounds.height + 1)
+        .max(min_y);
+
+    Rect::new(
+        bounds.x.clamp(min_x, max_x),
+        bounds.y.clamp(min_y, max_y),
+        bounds.width,
+        bounds.height,
+    )
+}
+
+/// Shift a content area down and right until no open window starts at the same spot
+pub fn cascade(mut bounds: Rect, open: &[Rect]) -> Rect {
+    // Bounded, a full diagonal of windows can't be avoided
+    for _ in 0..open.len() {
+        if !open
+            .iter()
+            .any(|other| other.x == bounds.x && other.y == bounds.y)
+        {
+            break;
+        }
+        bounds.x += CASCADE_OFFSET;
+        bounds.y += CASCADE_OFFSET;
+    }
+    bounds
+}
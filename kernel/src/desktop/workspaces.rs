@@ -0,0 +1,143 @@
+//! Virtual desktops, each with its own windows. Ctrl+Alt+Left and Right switch between them,
+//! with Shift the topmost window goes along.
+//!
+//! How many there are is loaded from `WORKSPC.CFG` in the root directory:
+//!
+//! ```text
+//! # Between 1 and 9
+//! count = 4
+//! ```
+//!
+//! A missing file or an invalid value keeps the default of 4.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::desktop::config::{entries, read_root_config};
+
+pub const CONFIG_FILENAME: &str = "WORKSPC.CFG";
+
+const DEFAULT_COUNT: usize = 4;
+pub const MAX_COUNT: usize = 9;
+
+static COUNT: AtomicUsize = AtomicUsize::new(DEFAULT_COUNT);
+
+/// Load the number of workspaces from the config file
+pub fn init() {
+    if let Some(count) = read_root_config(CONFIG_FILENAME).and_then(|text| parse(&text)) {
+        COUNT.store(count, Ordering::Relaxed);
+    }
+}
+
+/// The count of a config file, if it has one in range
+fn parse(text: &str) -> Option<usize> {
+    entries(text)
+        .filter(|&(key, _)| key == "count")
+        .filter_map(|(_, value)| value.parse::<usize>().ok())
+        .filter(|count| (1..=MAX_COUNT).contains(count))
+        .last()
+}
+
+pub fn count() -> usize {
+    COUNT.load(Ordering::Relaxed)
+}
+
+/// The items of the workspaces that aren't shown. The shown ones stay with the caller, so
+/// nothing else has to know about workspaces.
+pub struct Workspaces<T> {
+    active: usize,
+    /// One list per workspace, the active one's is empty
+    stored: Vec<Vec<T>>,
+}
+
+impl<T> Default for Workspaces<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Workspaces<T> {
+    pub const fn new() -> Self {
+        Self {
+            active: 0,
+            stored: Vec::new(),
+        }
+    }
+
+    /// Index of the shown workspace, from 0
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// The workspace `step` away from the shown one, wrapping around after `count`
+    pub fn neighbour(&self, step: isize, count: usize) -> usize {
+        (self.active as isize + step).rem_euclid(count.max(1) as isize) as usize
+    }
+
+    fn list(&mut self, index: usize) -> &mut Vec<T> {
+        if self.stored.len() <= index {
+            self.stored.resize_with(index + 1, Vec::new);
+        }
+        &mut self.stored[index]
+    }
+
+    /// Put the `shown` items away and take out the ones of workspace `index`. Returns false
+    /// if it's already shown.
+    pub fn switch(&mut self, shown: &mut Vec<T>, index: usize) -> bool {
+        if index == self.active {
+            return false;
+        }
+
+        let active = self.active;
+        core::mem::swap(shown, self.list(active));
+        core::mem::swap(shown, self.list(index));
+        self.active = index;
+        true
+    }
+
+    /// Move an item to a workspace that isn't shown, it's on top there
+    pub fn send(&mut self, item: T, index: usize) {
+        self.list(index).push(item);
+    }
+
+    /// The items of the workspaces that aren't shown
+    pub fn hidden_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.stored.iter_mut().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test_case]
+    fn test_switch_restores_items() {
+        let mut workspaces = Workspaces::new();
+        let mut shown = vec![1, 2];
+
+        assert!(workspaces.switch(&mut shown, 1));
+        assert!(shown.is_empty());
+        shown.push(3);
+
+        assert!(workspaces.switch(&mut shown, 0));
+        assert_eq!(shown, [1, 2]);
+        assert!(!workspaces.switch(&mut shown, 0));
+
+        let top = shown.pop().unwrap();
+        workspaces.send(top, 1);
+        assert_eq!(workspaces.hidden_mut().count(), 2);
+        assert!(workspaces.switch(&mut shown, 1));
+        assert_eq!(shown, [3, 2]);
+        assert_eq!(workspaces.active(), 1);
+        assert_eq!(workspaces.neighbour(1, 2), 0);
+        assert_eq!(workspaces.neighbour(-2, 4), 3);
+    }
+
+    #[test_case]
+    fn test_parse_config() {
+        assert_eq!(parse("# workspaces\ncount = 6\n"), Some(6));
+        assert_eq!(parse("count = 0\ncount = 12\n"), None);
+        assert_eq!(parse("count = 2\ncount = lots\n"), Some(2));
+    }
+}
@@ -1,16 +1,23 @@
-use alloc::vec::Vec;
+use crate::{
+    lock_order::{LockLevel, OrderedMutex},
+    log,
+    serial::LogLevel,
+    surface::Rect,
+};
+use alloc::{vec, vec::Vec};
 use bootloader_api::info::{FrameBuffer, FrameBufferInfo, PixelFormat};
 use conquer_once::spin::OnceCell;
-use core::{fmt, ptr};
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr,
+};
 use font_constants::BACKUP_CHAR;
 use noto_sans_mono_bitmap::{
     FontWeight, RasterHeight, RasterizedChar, get_raster, get_raster_width,
 };
-use spinning_top::Spinlock;
-
-use crate::serial_println;
 
-pub static FRAMEBUFFER: OnceCell<Spinlock<FrameBufferWriter>> = OnceCell::uninit();
+pub static FRAMEBUFFER: OnceCell<OrderedMutex<FrameBufferWriter>> = OnceCell::uninit();
 pub static SCREEN_SIZE: OnceCell<(u16, u16)> = OnceCell::uninit();
 
 /// Prints to framebuffer
@@ -34,12 +41,23 @@ macro_rules! println {
 pub fn _print(args: fmt::Arguments) {
     use fmt::Write;
     use x86_64::instructions::interrupts;
+
+    // Keep the boot splash clean, the serial log still gets everything
+    if crate::splash::is_active() {
+        crate::serial::_print(args);
+        return;
+    }
+
     interrupts::without_interrupts(|| {
         if let Some(fb) = FRAMEBUFFER.get() {
             if let Some(mut guard) = fb.try_lock() {
                 guard.write_fmt(args).unwrap();
             } else {
-                serial_println!("[FB DEADLOCK] - {}", args.as_str().unwrap_or("<unknown>"));
+                log!(
+                    LogLevel::Warn,
+                    "[FB DEADLOCK] - {}",
+                    args.as_str().unwrap_or("<unknown>")
+                );
             }
         }
     });
@@ -53,6 +71,19 @@ const LETTER_SPACING: usize = 0;
 /// Padding from the border. Prevent that font is too close to border.
 const BORDER_PADDING: usize = 1;
 
+/// Columns between tab stops, unless changed with [`FrameBufferWriter::set_tab_width`]
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Where a tab at `x_pos` moves the text to, in pixels. Columns are counted from the border
+/// padding, a tab always moves at least one column.
+fn next_tab_stop(x_pos: usize, tab_width: usize) -> usize {
+    let char_width = font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING;
+    let stop_width = tab_width.max(1) * char_width;
+    let column = x_pos.saturating_sub(BORDER_PADDING) / stop_width + 1;
+
+    BORDER_PADDING + column * stop_width
+}
+
 const fn calculate_cursor_bg_data_size(rows: &[(isize, isize)]) -> usize {
     let mut sum = 0;
     let mut i = 0;
@@ -90,6 +121,16 @@ const CURSOR_BG_DATA_SIZE: usize = calculate_cursor_bg_data_size(&CURSOR_ROWS);
 const CURSOR_ROW_OFFSET: usize = 0; // Offset for the cursor rows from the top of the cursor
 const CURSOR_COLOR: Color = Color::BLUE;
 
+/// A color as the bytes of one pixel, only the first `bytes_per_pixel` are used
+fn pixel_bytes(format: PixelFormat, color: Color) -> [u8; 4] {
+    match format {
+        PixelFormat::Rgb => [color.r, color.g, color.b, 0],
+        PixelFormat::Bgr => [color.b, color.g, color.r, 0],
+        PixelFormat::U8 => [if color.to_u8() > 200 { 0xf } else { 0 }, 0, 0, 0],
+        other => panic!("pixel format {:?} not supported for writing", other),
+    }
+}
+
 const TEXT_COLOR: Color = Color::new(255, 255, 150);
 
 /// Constants for the usage of the [`noto_sans_mono_bitmap`] crate.
@@ -110,6 +151,82 @@ mod font_constants {
     pub const FONT_WEIGHT: FontWeight = FontWeight::Regular;
 }
 
+/// Control sequence understood by the text writer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EscapeCommand {
+    /// `ESC[2J`
+    ClearScreen,
+    /// `ESC[H` or `ESC[row;colH`, both counted from 1
+    MoveCursor { row: usize, col: usize },
+    /// `ESC[K`
+    EraseLine,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EscapeAction {
+    Print(char),
+    /// In the middle of a sequence, or one that isn't supported
+    Skip,
+    Run(EscapeCommand),
+}
+
+/// Collects `ESC[` sequences one char at a time, so they can be split across writes
+#[derive(Default)]
+struct EscapeParser {
+    /// `None` outside a sequence, the number of `;` so far inside one
+    param: Option<usize>,
+    /// Seen `ESC` but not `[` yet
+    escaped: bool,
+    params: [Option<usize>; 2],
+}
+
+impl EscapeParser {
+    fn feed(&mut self, c: char) -> EscapeAction {
+        if self.escaped {
+            self.escaped = false;
+            if c == '[' {
+                self.param = Some(0);
+                self.params = [None; 2];
+            }
+            return EscapeAction::Skip;
+        }
+
+        let Some(param) = self.param else {
+            if c == '\x1b' {
+                self.escaped = true;
+                return EscapeAction::Skip;
+            }
+            return EscapeAction::Print(c);
+        };
+
+        match c {
+            '0'..='9' => {
+                if let Some(value) = self.params.get_mut(param) {
+                    let digit = c as usize - '0' as usize;
+                    *value = Some(value.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                }
+                EscapeAction::Skip
+            }
+            ';' => {
+                self.param = Some(param + 1);
+                EscapeAction::Skip
+            }
+            _ => {
+                self.param = None;
+                match (c, self.params) {
+                    ('J', [Some(2), _]) => EscapeAction::Run(EscapeCommand::ClearScreen),
+                    ('H' | 'f', [row, col]) => EscapeAction::Run(EscapeCommand::MoveCursor {
+                        row: row.unwrap_or(1).max(1),
+                        col: col.unwrap_or(1).max(1),
+                    }),
+                    ('K', [None | Some(0), _]) => EscapeAction::Run(EscapeCommand::EraseLine),
+                    _ => EscapeAction::Skip,
+                }
+            }
+        }
+    }
+}
+
 /// Returns the raster of the given char or the raster of [`font_constants::BACKUP_CHAR`].
 fn get_char_raster(c: char, font_weight: FontWeight, font_size: RasterHeight) -> RasterizedChar {
     fn get(c: char, font_weight: FontWeight, font_size: RasterHeight) -> Option<RasterizedChar> {
@@ -120,7 +237,88 @@ fn get_char_raster(c: char, font_weight: FontWeight, font_size: RasterHeight) ->
     })
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Returns the size in pixels that `text` takes up when drawn with [`FrameBufferWriter::draw_raw_text`].
+pub fn measure_text(
+    text: &str,
+    font_weight: FontWeight,
+    font_size: RasterHeight,
+) -> (usize, usize) {
+    let char_width = get_raster_width(font_weight, font_size) + LETTER_SPACING;
+
+    let mut lines = 1;
+    let mut line_width = 0;
+    let mut max_width = 0;
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                lines += 1;
+                max_width = max_width.max(line_width);
+                line_width = 0;
+            }
+            '\r' => {
+                max_width = max_width.max(line_width);
+                line_width = 0;
+            }
+            _ => line_width += char_width,
+        }
+    }
+
+    (
+        max_width.max(line_width),
+        lines * font_size.val() + (lines - 1) * LINE_SPACING,
+    )
+}
+
+/// Distance between the tops of two lines of text
+pub const fn line_height(font_size: RasterHeight) -> usize {
+    font_size.val() + LINE_SPACING
+}
+
+/// Split `text` into lines that are at most `max_width` pixels wide.
+/// Lines break at `\n` and, if possible, at the last space that fits, long words are cut.
+pub fn wrap_text(
+    text: &str,
+    max_width: usize,
+    font_weight: FontWeight,
+    font_size: RasterHeight,
+) -> Vec<&str> {
+    let char_width = get_raster_width(font_weight, font_size) + LETTER_SPACING;
+    let max_chars = (max_width / char_width).max(1);
+
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut rest = paragraph.trim_end_matches('\r');
+
+        loop {
+            // Byte offset of the first character that doesn't fit
+            let Some((limit, _)) = rest.char_indices().nth(max_chars) else {
+                lines.push(rest);
+                break;
+            };
+
+            let space = if rest[limit..].starts_with(' ') {
+                Some(limit)
+            } else {
+                rest[..limit].rfind(' ').filter(|&i| i > 0)
+            };
+
+            match space {
+                Some(i) => {
+                    lines.push(&rest[..i]);
+                    rest = &rest[i + 1..];
+                }
+                None => {
+                    lines.push(&rest[..limit]);
+                    rest = &rest[limit..];
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -153,7 +351,7 @@ impl Color {
     pub const BLUE: Color = Color { r: 0, g: 0, b: 255 };
 
     pub fn to_u8(&self) -> u8 {
-        (self.r + self.g + self.b) / 3
+        ((self.r as u16 + self.g as u16 + self.b as u16) / 3) as u8
     }
 
     pub fn to_rgb(&self) -> [u8; 3] {
@@ -183,24 +381,81 @@ impl CursorBackground {
     }
 }
 
+/// Memory a [`FrameBufferWriter`] draws into
+enum PixelBuffer {
+    /// The framebuffer from the bootloader, what is on the screen
+    Screen(&'static mut [u8]),
+    /// Pixels of an off-screen writer, see [`FrameBufferWriter::offscreen`]
+    Owned(Vec<u8>),
+}
+
+impl Deref for PixelBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Screen(buffer) => buffer,
+            Self::Owned(buffer) => buffer,
+        }
+    }
+}
+
+impl DerefMut for PixelBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Self::Screen(buffer) => buffer,
+            Self::Owned(buffer) => buffer,
+        }
+    }
+}
+
 /// Allows logging text to a pixel-based framebuffer.
 pub struct FrameBufferWriter {
-    framebuffer: &'static mut [u8],
+    framebuffer: PixelBuffer,
     info: FrameBufferInfo,
     x_pos: usize,
     y_pos: usize,
     cursor_background: CursorBackground,
+    escape: EscapeParser,
+    tab_width: usize,
 }
 
 impl FrameBufferWriter {
     /// Creates a new logger that uses the given framebuffer.
     pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+        Self::with_buffer(PixelBuffer::Screen(framebuffer), info)
+    }
+
+    /// A writer that draws into its own memory instead of the screen. The pixels can be
+    /// copied elsewhere with [`Self::read_raw_pixel_row`].
+    pub fn offscreen(
+        width: usize,
+        height: usize,
+        pixel_format: PixelFormat,
+        bytes_per_pixel: usize,
+    ) -> Self {
+        let byte_len = width * height * bytes_per_pixel;
+        let info = FrameBufferInfo {
+            byte_len,
+            width,
+            height,
+            pixel_format,
+            bytes_per_pixel,
+            stride: width,
+        };
+
+        Self::with_buffer(PixelBuffer::Owned(vec![0; byte_len]), info)
+    }
+
+    fn with_buffer(framebuffer: PixelBuffer, info: FrameBufferInfo) -> Self {
         let mut logger = Self {
             framebuffer,
             info,
             x_pos: 0,
             y_pos: 0,
             cursor_background: CursorBackground::new(info.bytes_per_pixel),
+            escape: EscapeParser::default(),
+            tab_width: DEFAULT_TAB_WIDTH,
         };
         logger.clear();
         logger
@@ -215,8 +470,10 @@ impl FrameBufferWriter {
         self.x_pos = BORDER_PADDING;
     }
 
-    pub fn fill(&mut self, brightness: u8) {
-        self.framebuffer.fill(brightness);
+    /// Fill the whole screen with one color
+    pub fn fill(&mut self, color: Color) {
+        let (width, height) = self.size();
+        self.fill_rect((0, 0), (width - 1, height - 1), color);
     }
 
     /// Erases all text on the screen. Resets `self.x_pos` and `self.y_pos`.
@@ -238,12 +495,71 @@ impl FrameBufferWriter {
         (self.info.width, self.info.height)
     }
 
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.info.bytes_per_pixel
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.info.pixel_format
+    }
+
+    /// Columns between tab stops in printed text
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    fn run_escape(&mut self, command: EscapeCommand) {
+        let line_height = font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
+        let char_width = font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING;
+
+        match command {
+            EscapeCommand::ClearScreen => self.framebuffer.fill(0),
+            EscapeCommand::MoveCursor { row, col } => {
+                // Kept on screen, the next char wraps or clears like after any other text
+                self.x_pos = (col - 1)
+                    .saturating_mul(char_width)
+                    .saturating_add(BORDER_PADDING)
+                    .min(self.width().saturating_sub(char_width));
+                self.y_pos = (row - 1)
+                    .saturating_mul(line_height)
+                    .saturating_add(BORDER_PADDING)
+                    .min(self.height().saturating_sub(line_height));
+            }
+            EscapeCommand::EraseLine => {
+                let bottom = self.y_pos + line_height - 1;
+                self.fill_rect((self.x_pos, self.y_pos), (usize::MAX, bottom), Color::BLACK);
+            }
+        }
+    }
+
     /// Writes a single char to the framebuffer. Takes care of special control characters, such as
-    /// newlines and carriage returns.
+    /// newlines and carriage returns, and of the escape sequences in [`EscapeCommand`].
     fn write_char(&mut self, c: char) {
+        let c = match self.escape.feed(c) {
+            EscapeAction::Print(c) => c,
+            EscapeAction::Skip => return,
+            EscapeAction::Run(command) => {
+                self.run_escape(command);
+                return;
+            }
+        };
+
         match c {
             '\n' => self.newline(),
             '\r' => self.carriage_return(),
+            // Backspace only moves back, `"\u{08} \u{08}"` erases the character
+            '\u{08}' => {
+                self.x_pos = self
+                    .x_pos
+                    .saturating_sub(font_constants::CHAR_RASTER_WIDTH)
+                    .max(BORDER_PADDING);
+            }
+            '\t' => {
+                self.x_pos = next_tab_stop(self.x_pos, self.tab_width);
+                if self.x_pos + font_constants::CHAR_RASTER_WIDTH >= self.width() {
+                    self.newline();
+                }
+            }
             c => {
                 let new_xpos = self.x_pos + font_constants::CHAR_RASTER_WIDTH;
                 if new_xpos >= self.width() {
@@ -304,6 +620,42 @@ impl FrameBufferWriter {
         }
     }
 
+    /// Like [`Self::write_rendered_char_at_pos`], but only pixels inside `clip` are written.
+    /// The y position may be above the clip rectangle.
+    fn write_rendered_char_clipped(
+        &mut self,
+        (pos_x, pos_y): (usize, isize),
+        rendered_char: &RasterizedChar,
+        color: Color,
+        bg_color: Color,
+        clip: &Rect,
+    ) {
+        for (y, row) in rendered_char.raster().iter().enumerate() {
+            let pixel_y = pos_y + y as isize;
+            if pixel_y < 0 || !(clip.y..clip.y + clip.height).contains(&(pixel_y as usize)) {
+                continue;
+            }
+
+            for (x, byte) in row.iter().enumerate() {
+                let pixel_x = pos_x + x;
+                if *byte == 0 || !(clip.x..clip.x + clip.width).contains(&pixel_x) {
+                    continue;
+                }
+
+                let byte = *byte as f32 / 255.0;
+                let byte_reverse = 1.0 - byte;
+
+                let color = Color::new(
+                    ((color.r as f32 * byte) + (bg_color.r as f32 * byte_reverse)) as u8,
+                    ((color.g as f32 * byte) + (bg_color.g as f32 * byte_reverse)) as u8,
+                    ((color.b as f32 * byte) + (bg_color.b as f32 * byte_reverse)) as u8,
+                );
+
+                self.write_pixel(pixel_x, pixel_y as usize, color);
+            }
+        }
+    }
+
     pub fn write_pixel(&mut self, x: usize, y: usize, color: Color) {
         if x >= self.width() || y >= self.height() {
             return; // Out of bounds
@@ -396,7 +748,7 @@ impl FrameBufferWriter {
     }
 
     /// Get the bounds of the mouse cursor at the given position
-    pub fn get_cursor_bounds(x: usize, y: usize) -> (usize, usize, usize, usize) {
+    pub fn get_cursor_bounds(x: usize, y: usize) -> Rect {
         let start_y = y.saturating_sub(CURSOR_ROW_OFFSET);
         let mut min_x = x;
         let mut max_x = x;
@@ -412,7 +764,12 @@ impl FrameBufferWriter {
             max_y = max_y.max(cursor_y);
         }
 
-        (min_x, start_y, max_x - min_x + 1, max_y - start_y + 1)
+        Rect::new(min_x, start_y, max_x - min_x + 1, max_y - start_y + 1)
+    }
+
+    /// Leave the cursor where it is, the next one is drawn without removing it
+    pub fn forget_cursor_background(&mut self) {
+        self.cursor_background.previous_pos = None;
     }
 
     /// Get the previous cursor position
@@ -520,38 +877,37 @@ impl FrameBufferWriter {
 
     /// Writes a horizontal line of pixels at once.
     fn write_pixel_row(&mut self, x1: usize, x2: usize, y: usize, data: &Color) {
-        let x2 = x2.min(self.width() - 1); // Ensure x2 is within bounds
-        if y >= self.height() || x1 >= self.width() {
+        self.fill_rect((x1, y), (x2, y), *data);
+    }
+
+    /// Fill a rectangle, the pixel is converted once and copied row by row
+    pub fn fill_rect(
+        &mut self,
+        top_left: (usize, usize),
+        bottom_right: (usize, usize),
+        color: Color,
+    ) {
+        let (width, height) = self.size();
+        if top_left.0 >= width || top_left.1 >= height || bottom_right.0 < top_left.0 {
             return;
         }
 
-        let max_width = (x2 - x1 + 1).min(self.width() - x1);
+        let x2 = bottom_right.0.min(width - 1);
+        let y2 = bottom_right.1.min(height - 1);
         let bytes_per_pixel = self.info.bytes_per_pixel;
-        let start_offset = (y * self.info.stride + x1) * bytes_per_pixel;
+        let pixel = pixel_bytes(self.info.pixel_format, color);
 
-        // Convert the color to the appropriate pixel format
-        let color: [u8; 3] = match self.info.pixel_format {
-            PixelFormat::Rgb => [data.r, data.g, data.b],
-            PixelFormat::Bgr => [data.b, data.g, data.r],
-            PixelFormat::U8 => [if data.to_u8() > 200 { 0xf } else { 0 }, 0, 0],
-            _ => {
-                panic!(
-                    "pixel format {:?} not supported for writing",
-                    self.info.pixel_format
-                );
-            }
-        };
-
-        // Convert the color slice to the correct length
-        let data: Vec<u8> = color
+        let row: Vec<u8> = pixel[..bytes_per_pixel]
             .iter()
-            .cloned()
+            .copied()
             .cycle()
-            .take(max_width * bytes_per_pixel)
+            .take((x2 - top_left.0 + 1) * bytes_per_pixel)
             .collect();
 
-        self.framebuffer[start_offset..start_offset + max_width * bytes_per_pixel]
-            .copy_from_slice(&data);
+        for y in top_left.1..=y2 {
+            let start_offset = (y * self.info.stride + top_left.0) * bytes_per_pixel;
+            self.framebuffer[start_offset..start_offset + row.len()].copy_from_slice(&row);
+        }
     }
 
     pub fn draw_rect(
@@ -560,8 +916,34 @@ impl FrameBufferWriter {
         bottom_right: (usize, usize),
         color: Color,
     ) {
-        for y in top_left.1..=bottom_right.1 {
-            self.write_pixel_row(top_left.0, bottom_right.0, y, &color);
+        self.fill_rect(top_left, bottom_right, color);
+    }
+
+    /// Mix a color over a rectangle, `alpha` 255 covers it completely
+    pub fn blend_rect(
+        &mut self,
+        top_left: (usize, usize),
+        bottom_right: (usize, usize),
+        color: Color,
+        alpha: u8,
+    ) {
+        let mix = |under: u8, over: u8| {
+            ((under as u16 * (255 - alpha as u16) + over as u16 * alpha as u16) / 255) as u8
+        };
+
+        for y in top_left.1..=bottom_right.1.min(self.height().saturating_sub(1)) {
+            for x in top_left.0..=bottom_right.0.min(self.width().saturating_sub(1)) {
+                let under = self.read_pixel(x, y);
+                self.write_pixel(
+                    x,
+                    y,
+                    Color::new(
+                        mix(under.r, color.r),
+                        mix(under.g, color.g),
+                        mix(under.b, color.b),
+                    ),
+                );
+            }
         }
     }
 
@@ -614,6 +996,30 @@ impl FrameBufferWriter {
             x_offset += LETTER_SPACING + rendered_char.width(); // Move to the next character position
         }
     }
+
+    /// Draw a single line of text, only the part inside `clip`.
+    /// The y position may be above the clip rectangle, for a line that is scrolled partly out of view.
+    pub fn draw_text_line_clipped(
+        &mut self,
+        text: &str,
+        (x, y): (usize, isize),
+        color: Color,
+        bg_color: Color,
+        font_weight: FontWeight,
+        font_size: RasterHeight,
+        clip: &Rect,
+    ) {
+        let mut x_offset = x;
+        for c in text.chars() {
+            if x_offset >= clip.x + clip.width {
+                break;
+            }
+
+            let rendered_char = get_char_raster(c, font_weight, font_size);
+            self.write_rendered_char_clipped((x_offset, y), &rendered_char, color, bg_color, clip);
+            x_offset += LETTER_SPACING + rendered_char.width();
+        }
+    }
 }
 
 unsafe impl Send for FrameBufferWriter {}
@@ -634,10 +1040,23 @@ pub fn init(frame: &'static mut FrameBuffer) {
         (info.width as u16, info.height as u16)
     });
 
+    let info = frame.info();
+    let (min_width, min_height) = crate::MIN_SCREEN_SIZE;
+    if (info.width as u64) < min_width || (info.height as u64) < min_height {
+        log!(
+            LogLevel::Warn,
+            "No {}x{} screen mode, running at {}x{}",
+            min_width,
+            min_height,
+            info.width,
+            info.height
+        );
+    }
+
     FRAMEBUFFER.init_once(|| {
         let info = frame.info();
         let buffer = frame.buffer_mut();
-        spinning_top::Spinlock::new(FrameBufferWriter::new(buffer, info))
+        OrderedMutex::new(LockLevel::Framebuffer, FrameBufferWriter::new(buffer, info))
     });
 }
 
@@ -648,3 +1067,80 @@ pub fn set_buffer(buffer: &[u8]) {
         panic!("FrameBuffer not initialized");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn fill_pixel_keeps_every_channel() {
+        let color = Color::new(50, 111, 168);
+
+        assert_eq!(pixel_bytes(PixelFormat::Rgb, color)[..3], [50, 111, 168]);
+        assert_eq!(pixel_bytes(PixelFormat::Bgr, color)[..3], [168, 111, 50]);
+    }
+
+    #[test_case]
+    fn wrap_text_breaks_at_spaces_and_long_words() {
+        let width = 10 * get_raster_width(FontWeight::Regular, RasterHeight::Size16);
+        let wrap = |text| wrap_text(text, width, FontWeight::Regular, RasterHeight::Size16);
+
+        assert_eq!(wrap("hello world again"), ["hello", "world", "again"]);
+        assert_eq!(wrap("abcdefghijklmn"), ["abcdefghij", "klmn"]);
+        assert_eq!(wrap("one\r\n\ntwo"), ["one", "", "two"]);
+    }
+
+    #[test_case]
+    fn tabs_move_to_the_next_stop() {
+        let char_width = font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING;
+        let column = |x: usize| (x - BORDER_PADDING) / char_width;
+
+        // Writing "ab\tc\tdefghijkl\tm" puts c at column 8, d at 16 and m at 32
+        let ab = BORDER_PADDING + 2 * char_width;
+        assert_eq!(column(next_tab_stop(ab, 8)), 8);
+        let c = next_tab_stop(ab, 8) + char_width;
+        assert_eq!(column(next_tab_stop(c, 8)), 16);
+        let defghijkl = next_tab_stop(c, 8) + 9 * char_width;
+        assert_eq!(column(next_tab_stop(defghijkl, 8)), 32);
+
+        // A tab right on a stop still moves a whole stop
+        assert_eq!(column(next_tab_stop(BORDER_PADDING, 4)), 4);
+    }
+
+    #[test_case]
+    fn escape_sequences_survive_split_writes() {
+        let mut parser = EscapeParser::default();
+        let mut feed = |text: &str| {
+            text.chars()
+                .map(|c| parser.feed(c))
+                .filter(|action| *action != EscapeAction::Skip)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            feed("\x1b[2J\x1b[H"),
+            [
+                EscapeAction::Run(EscapeCommand::ClearScreen),
+                EscapeAction::Run(EscapeCommand::MoveCursor { row: 1, col: 1 }),
+            ]
+        );
+        assert_eq!(feed("a\x1b[1"), [EscapeAction::Print('a')]);
+        assert_eq!(
+            feed("2;5Hb"),
+            [
+                EscapeAction::Run(EscapeCommand::MoveCursor { row: 12, col: 5 }),
+                EscapeAction::Print('b'),
+            ]
+        );
+        assert_eq!(
+            feed("\x1b[K\x1b[1m"),
+            [EscapeAction::Run(EscapeCommand::EraseLine)]
+        );
+    }
+
+    #[test_case]
+    fn grayscale_of_bright_color() {
+        assert_eq!(Color::WHITE.to_u8(), 255);
+        assert_eq!(Color::GRAY.to_u8(), Color::GRAY.r);
+    }
+}
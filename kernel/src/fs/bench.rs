@@ -0,0 +1,219 @@
+//! Filesystem throughput, to compare disk and FAT changes with reproducible numbers.
+//!
+//! The benchmark writes a scratch file in the root directory, reads it back whole, then reads
+//! and overwrites its sectors in random order, and deletes it again. Time is counted in TSC
+//! cycles, which keep running while the filesystem lock has interrupts disabled.
+
+use alloc::{vec, vec::Vec};
+use core::{arch::x86_64::_rdtsc, hint::spin_loop};
+
+use crate::{
+    fs::fat32::{DiskOperations, Fat32FileSystem, cluster_values, open_flags},
+    fs::manager,
+    interrupts::{PIT_FREQUENCY_HZ, ticks},
+    log, random,
+    serial::LogLevel,
+};
+
+const BENCH_FILENAME: &str = "BENCH.TMP";
+/// Timer ticks the TSC is counted over to find its rate
+const CALIBRATION_TICKS: u64 = 10;
+
+pub struct BenchConfig {
+    /// Size of the scratch file
+    pub file_size: usize,
+    /// How often each phase runs
+    pub iterations: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            file_size: 256 * 1024,
+            iterations: 4,
+        }
+    }
+}
+
+/// One phase of the benchmark
+pub struct BenchResult {
+    pub name: &'static str,
+    pub bytes: u64,
+    pub cycles: u64,
+}
+
+impl BenchResult {
+    /// Throughput for a TSC running at `tsc_per_ms` cycles per millisecond
+    pub fn bytes_per_sec(&self, tsc_per_ms: u64) -> u64 {
+        let cycles = self.cycles.max(1) as u128;
+        (self.bytes as u128 * tsc_per_ms as u128 * 1000 / cycles) as u64
+    }
+}
+
+/// The time stamp counter, in CPU cycles
+pub fn cycles() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// TSC cycles per millisecond, counted against the timer interrupt. None while it can't
+/// fire.
+pub fn tsc_per_ms() -> Option<u64> {
+    if !x86_64::instructions::interrupts::are_enabled() {
+        return None;
+    }
+
+    // Start on a tick boundary
+    let start = ticks();
+    while ticks() == start {
+        spin_loop();
+    }
+
+    let first_tick = ticks();
+    let begin = cycles();
+    while ticks() < first_tick + CALIBRATION_TICKS {
+        spin_loop();
+    }
+    let elapsed_ms = CALIBRATION_TICKS * 1000 / PIT_FREQUENCY_HZ;
+    Some((cycles() - begin) / elapsed_ms)
+}
+
+/// Count the cycles `f` takes, `iterations` times, for `bytes` each time
+fn measure(
+    name: &'static str,
+    bytes: usize,
+    iterations: usize,
+    mut f: impl FnMut() -> Result<(), &'static str>,
+) -> Result<BenchResult, &'static str> {
+    let begin = cycles();
+    for _ in 0..iterations {
+        f()?;
+    }
+
+    Ok(BenchResult {
+        name,
+        bytes: (bytes * iterations) as u64,
+        cycles: cycles() - begin,
+    })
+}
+
+/// Run every phase on `fs`. The scratch file is removed again, also when a phase fails.
+pub fn run<D: DiskOperations>(
+    fs: &mut Fat32FileSystem<D>,
+    config: &BenchConfig,
+) -> Result<Vec<BenchResult>, &'static str> {
+    let root = fs.root_cluster();
+    let result = run_phases(fs, config);
+    let _ = fs.delete_file(root, BENCH_FILENAME);
+    result
+}
+
+fn run_phases<D: DiskOperations>(
+    fs: &mut Fat32FileSystem<D>,
+    config: &BenchConfig,
+) -> Result<Vec<BenchResult>, &'static str> {
+    let root = fs.root_cluster();
+    let data: Vec<u8> = (0..config.file_size).map(|i| i as u8).collect();
+    let mut results = Vec::new();
+
+    results.push(measure(
+        "Sequential write",
+        data.len(),
+        config.iterations,
+        || {
+            fs.write_file_with_flags(
+                root,
+                BENCH_FILENAME,
+                &data,
+                open_flags::CREATE | open_flags::TRUNCATE,
+            )
+            .map(|_| ())
+        },
+    )?);
+
+    let file = fs
+        .find_file_in_root(BENCH_FILENAME)?
+        .ok_or("Benchmark file not found")?;
+
+    results.push(measure(
+        "Sequential read",
+        data.len(),
+        config.iterations,
+        || {
+            if fs.read_file(file.first_cluster, file.size)? != data {
+                return Err("Benchmark file changed");
+            }
+            Ok(())
+        },
+    )?);
+
+    // Sectors of the file, the random phases go straight to the disk
+    let mut sectors = Vec::new();
+    let mut cluster = file.first_cluster;
+    while cluster >= 2 && cluster < cluster_values::END_OF_CHAIN {
+        sectors.extend(fs.cluster_sectors(cluster));
+        cluster = fs.get_next_cluster(cluster)?;
+    }
+    if sectors.is_empty() {
+        return Ok(results);
+    }
+
+    let sector_size = fs.cluster_size() as usize / fs.cluster_sectors(file.first_cluster).count();
+    let mut buffer = vec![0u8; sector_size];
+    let random_bytes = sectors.len() * sector_size;
+
+    results.push(measure(
+        "Random read",
+        random_bytes,
+        config.iterations,
+        || {
+            for _ in 0..sectors.len() {
+                let sector = sectors[random::range(sectors.len())];
+                fs.disk_mut().read_sector(sector, &mut buffer)?;
+            }
+            Ok(())
+        },
+    )?);
+
+    results.push(measure(
+        "Random write",
+        random_bytes,
+        config.iterations,
+        || {
+            for _ in 0..sectors.len() {
+                let sector = sectors[random::range(sectors.len())];
+                fs.disk_mut().write_sector(sector, &buffer)?;
+            }
+            Ok(())
+        },
+    )?);
+
+    Ok(results)
+}
+
+/// Benchmark the mounted disk and log the throughput of each phase
+pub fn log_benchmark(config: &BenchConfig) {
+    let Some(tsc_per_ms) = tsc_per_ms() else {
+        log!(LogLevel::Warn, "Benchmark needs the timer interrupt");
+        return;
+    };
+
+    log!(
+        LogLevel::Info,
+        "Benchmarking {} bytes, {} iterations",
+        config.file_size,
+        config.iterations
+    );
+    match manager::run_benchmark(config) {
+        Ok(results) => {
+            for result in results {
+                log!(
+                    LogLevel::Info,
+                    "{}: {} KiB/s",
+                    result.name,
+                    result.bytes_per_sec(tsc_per_ms) / 1024
+                );
+            }
+        }
+        Err(e) => log!(LogLevel::Error, "Benchmark failed: {}", e),
+    }
+}
@@ -0,0 +1,54 @@
+//! Code page 437, the OEM code page of the original IBM PC.
+//!
+//! 8.3 names are stored in the OEM code page of the system that wrote them, there is no
+//! way to tell which one it was. CP437 is the usual default, bytes below 0x80 are ASCII.
+
+/// Characters of the bytes 0x80 to 0xFF
+const HIGH_HALF: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+pub fn decode(byte: u8) -> char {
+    if byte < 0x80 {
+        byte as char
+    } else {
+        HIGH_HALF[(byte - 0x80) as usize]
+    }
+}
+
+/// The byte of a character, `None` if the code page doesn't have it
+pub fn encode(c: char) -> Option<u8> {
+    if c.is_ascii() {
+        return Some(c as u8);
+    }
+
+    HIGH_HALF
+        .iter()
+        .position(|&high| high == c)
+        .map(|index| 0x80 + index as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_round_trip() {
+        assert_eq!(decode(b'A'), 'A');
+        assert_eq!(decode(0x82), 'é');
+        assert_eq!(decode(0xC9), '╔');
+        assert_eq!(encode('é'), Some(0x82));
+        assert_eq!(encode('€'), None);
+
+        for byte in 0..=u8::MAX {
+            assert_eq!(encode(decode(byte)), Some(byte));
+        }
+    }
+}
@@ -1,22 +1,67 @@
 use crate::fs::fat32::DiskOperations;
+use crate::interrupts::{ms_to_ticks, ticks};
+use crate::serial::LogLevel;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU16, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use x86_64::instructions::interrupts;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
-/// Primary ATA controller ports
-const PRIMARY_ATA_DATA: u16 = 0x1F0;
-// const PRIMARY_ATA_ERROR: u16 = 0x1F1;
-// const PRIMARY_ATA_FEATURES: u16 = 0x1F1;
-const PRIMARY_ATA_SECTOR_COUNT: u16 = 0x1F2;
-const PRIMARY_ATA_LBA_LOW: u16 = 0x1F3;
-const PRIMARY_ATA_LBA_MID: u16 = 0x1F4;
-const PRIMARY_ATA_LBA_HIGH: u16 = 0x1F5;
-const PRIMARY_ATA_DRIVE: u16 = 0x1F6;
-const PRIMARY_ATA_STATUS: u16 = 0x1F7;
-const PRIMARY_ATA_COMMAND: u16 = 0x1F7;
-const PRIMARY_ATA_CONTROL: u16 = 0x3F6;
+/// ATA register offsets from the I/O base of a channel
+const ATA_REG_DATA: u16 = 0;
+// const ATA_REG_ERROR: u16 = 1;
+// const ATA_REG_FEATURES: u16 = 1;
+const ATA_REG_SECTOR_COUNT: u16 = 2;
+const ATA_REG_LBA_LOW: u16 = 3;
+const ATA_REG_LBA_MID: u16 = 4;
+const ATA_REG_LBA_HIGH: u16 = 5;
+const ATA_REG_DRIVE: u16 = 6;
+const ATA_REG_STATUS: u16 = 7;
+const ATA_REG_COMMAND: u16 = 7;
+
+/// An ATA controller channel, each has its own ports and IRQ line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaChannel {
+    /// Ports 0x1F0-0x1F7 and 0x3F6, IRQ14
+    Primary = 0,
+    /// Ports 0x170-0x177 and 0x376, IRQ15
+    Secondary = 1,
+}
+
+impl AtaChannel {
+    fn io_base(self) -> u16 {
+        match self {
+            AtaChannel::Primary => 0x1F0,
+            AtaChannel::Secondary => 0x170,
+        }
+    }
+
+    fn control_port(self) -> u16 {
+        match self {
+            AtaChannel::Primary => 0x3F6,
+            AtaChannel::Secondary => 0x376,
+        }
+    }
+
+    fn read_status(self) -> u8 {
+        unsafe { PortReadOnly::<u8>::new(self.io_base() + ATA_REG_STATUS).read() }
+    }
+}
+
+/// Set by the IRQ handler of each channel, cleared before every command
+static CHANNEL_INTERRUPT: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+/// Longest wait for the interrupt of a command, one second of timer ticks
+const INTERRUPT_TIMEOUT_TICKS: u64 = ms_to_ticks(1000);
 
 /// ATA commands
 const ATA_CMD_READ_SECTORS: u8 = 0x20;
 const ATA_CMD_WRITE_SECTORS: u8 = 0x30;
+const ATA_CMD_FLUSH_CACHE: u8 = 0xE7;
 const ATA_CMD_IDENTIFY: u8 = 0xEC;
 
 /// ATA status bits
@@ -26,6 +71,112 @@ const ATA_STATUS_DRDY: u8 = 0x40;
 const ATA_STATUS_DRQ: u8 = 0x08;
 const ATA_STATUS_ERR: u8 = 0x01;
 
+/// Control register values, nIEN masks the drive's interrupt
+const ATA_CONTROL_INTERRUPTS: u8 = 0x00;
+const ATA_CONTROL_NO_INTERRUPTS: u8 = 0x02;
+
+/// Error of [`AtaDisk::start_async_read`] while another async read is in flight
+pub const DISK_BUSY: &str = "Disk is busy";
+
+/// Progress of the read started by [`AtaDisk::start_async_read`]
+mod async_read {
+    pub const IDLE: u8 = 0;
+    pub const WAITING: u8 = 1;
+    /// Claimed by the IRQ handler or the synchronous path, which reads the data port
+    pub const READING: u8 = 2;
+    pub const DONE: u8 = 3;
+    pub const FAILED: u8 = 4;
+}
+
+static ASYNC_READ_STATE: AtomicU8 = AtomicU8::new(async_read::IDLE);
+/// The sector of a finished async read, filled by whoever completes it
+static ASYNC_READ_DATA: [AtomicU16; 256] = [const { AtomicU16::new(0) }; 256];
+static ASYNC_READ_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Fetch the sector of a pending async read if the status says the drive has it. Both IRQ14
+/// and the synchronous path call this, the one that claims the read gets the whole sector.
+fn complete_async_read(status: u8) {
+    if (status & ATA_STATUS_BSY) != 0 {
+        return;
+    }
+
+    let claim = |next| {
+        ASYNC_READ_STATE
+            .compare_exchange(
+                async_read::WAITING,
+                next,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+    };
+
+    let state = if (status & ATA_STATUS_ERR) != 0 {
+        if !claim(async_read::FAILED) {
+            return;
+        }
+        async_read::FAILED
+    } else if (status & ATA_STATUS_DRQ) != 0 {
+        if !claim(async_read::READING) {
+            return;
+        }
+        let mut data_port = Port::<u16>::new(AtaChannel::Primary.io_base() + ATA_REG_DATA);
+        for word in &ASYNC_READ_DATA {
+            word.store(unsafe { data_port.read() }, Ordering::Relaxed);
+        }
+        async_read::DONE
+    } else {
+        return;
+    };
+
+    ASYNC_READ_STATE.store(state, Ordering::SeqCst);
+    ASYNC_READ_WAKER.wake();
+}
+
+/// Called by the IRQ14 and IRQ15 handlers. Reading the status register acknowledges the
+/// interrupt, otherwise the drive keeps its line raised.
+pub fn handle_interrupt(channel: AtaChannel) {
+    let status = channel.read_status();
+
+    if channel == AtaChannel::Primary {
+        complete_async_read(status);
+    }
+
+    CHANNEL_INTERRUPT[channel as usize].store(true, Ordering::SeqCst);
+}
+
+/// Wait for the sector of the read started with [`AtaDisk::start_async_read`]
+pub fn wait_async_read() -> AsyncRead {
+    AsyncRead
+}
+
+pub struct AsyncRead;
+
+impl Future for AsyncRead {
+    type Output = Result<[u8; 512], &'static str>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        ASYNC_READ_WAKER.register(context.waker());
+
+        match ASYNC_READ_STATE.load(Ordering::SeqCst) {
+            async_read::DONE => {
+                let mut buffer = [0u8; 512];
+                for (bytes, word) in buffer.chunks_exact_mut(2).zip(&ASYNC_READ_DATA) {
+                    bytes.copy_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+                }
+
+                ASYNC_READ_STATE.store(async_read::IDLE, Ordering::SeqCst);
+                Poll::Ready(Ok(buffer))
+            }
+            async_read::FAILED => {
+                ASYNC_READ_STATE.store(async_read::IDLE, Ordering::SeqCst);
+                Poll::Ready(Err("ATA drive error"))
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
 /// Simple ATA disk driver
 pub struct AtaDisk {
     data_port: Port<u16>,
@@ -40,24 +191,90 @@ pub struct AtaDisk {
     command_port: PortWriteOnly<u8>,
     control_port: PortWriteOnly<u8>,
     drive_number: u8,
+    channel: AtaChannel,
+    /// Wait for the channel's IRQ instead of polling, turned off if it never arrives
+    use_interrupts: bool,
+    /// Sectors written since the last cache flush
+    unflushed_sectors: u32,
 }
 
 impl AtaDisk {
     /// Create a new ATA disk driver for the primary controller
     pub fn new_primary(drive_number: u8) -> Self {
+        Self::new(AtaChannel::Primary, drive_number)
+    }
+
+    /// Create a new ATA disk driver for the secondary controller
+    pub fn new_secondary(drive_number: u8) -> Self {
+        Self::new(AtaChannel::Secondary, drive_number)
+    }
+
+    fn new(channel: AtaChannel, drive_number: u8) -> Self {
+        let base = channel.io_base();
+
         AtaDisk {
-            data_port: Port::new(PRIMARY_ATA_DATA),
-            // error_port: PortReadOnly::new(PRIMARY_ATA_ERROR),
-            // features_port: PortWriteOnly::new(PRIMARY_ATA_FEATURES),
-            sector_count_port: Port::new(PRIMARY_ATA_SECTOR_COUNT),
-            lba_low_port: Port::new(PRIMARY_ATA_LBA_LOW),
-            lba_mid_port: Port::new(PRIMARY_ATA_LBA_MID),
-            lba_high_port: Port::new(PRIMARY_ATA_LBA_HIGH),
-            drive_port: Port::new(PRIMARY_ATA_DRIVE),
-            status_port: PortReadOnly::new(PRIMARY_ATA_STATUS),
-            command_port: PortWriteOnly::new(PRIMARY_ATA_COMMAND),
-            control_port: PortWriteOnly::new(PRIMARY_ATA_CONTROL),
+            data_port: Port::new(base + ATA_REG_DATA),
+            // error_port: PortReadOnly::new(base + ATA_REG_ERROR),
+            // features_port: PortWriteOnly::new(base + ATA_REG_FEATURES),
+            sector_count_port: Port::new(base + ATA_REG_SECTOR_COUNT),
+            lba_low_port: Port::new(base + ATA_REG_LBA_LOW),
+            lba_mid_port: Port::new(base + ATA_REG_LBA_MID),
+            lba_high_port: Port::new(base + ATA_REG_LBA_HIGH),
+            drive_port: Port::new(base + ATA_REG_DRIVE),
+            status_port: PortReadOnly::new(base + ATA_REG_STATUS),
+            command_port: PortWriteOnly::new(base + ATA_REG_COMMAND),
+            control_port: PortWriteOnly::new(channel.control_port()),
             drive_number: drive_number & 1, // Ensure it's 0 or 1
+            channel,
+            use_interrupts: false,
+            unflushed_sectors: 0,
+        }
+    }
+
+    /// Poll the status from now on instead of halting for the channel's interrupt. For the
+    /// crash path, which runs with interrupts off and may not get an IRQ from a broken kernel.
+    pub fn use_polling(&mut self) {
+        self.use_interrupts = false;
+    }
+
+    /// Halt until the channel raises its interrupt instead of spinning on the status.
+    /// Interrupts are let through while halted even if the caller disabled them, which is
+    /// fine since interrupt handlers never block on the filesystem lock.
+    fn wait_interrupt(&mut self) {
+        if !self.use_interrupts {
+            return;
+        }
+
+        let flag = &CHANNEL_INTERRUPT[self.channel as usize];
+        let were_enabled = interrupts::are_enabled();
+        let deadline = ticks() + INTERRUPT_TIMEOUT_TICKS;
+
+        // The interrupt must not slip in between checking the flag and halting
+        interrupts::disable();
+        let arrived = loop {
+            if flag.swap(false, Ordering::SeqCst) {
+                break true;
+            }
+            if ticks() > deadline {
+                break false;
+            }
+
+            interrupts::enable_and_hlt();
+            interrupts::disable();
+        };
+
+        if were_enabled {
+            interrupts::enable();
+        }
+
+        if !arrived {
+            // The caller still polls the status, so the command itself isn't lost
+            crate::log!(
+                LogLevel::Warn,
+                "ATA {:?} channel never raised its interrupt, falling back to polling",
+                self.channel
+            );
+            self.use_interrupts = false;
         }
     }
 
@@ -114,18 +331,88 @@ impl AtaDisk {
         Ok(())
     }
 
+    /// Send a one sector command for an LBA
+    fn send_command(&mut self, sector: u64, command: u8) -> Result<(), &'static str> {
+        self.wait_ready()?;
+        self.select_drive(sector)?;
+
+        // Forget the interrupt of the previous command
+        CHANNEL_INTERRUPT[self.channel as usize].store(false, Ordering::SeqCst);
+
+        unsafe {
+            self.sector_count_port.write(1);
+            self.lba_low_port.write((sector & 0xFF) as u8);
+            self.lba_mid_port.write(((sector >> 8) & 0xFF) as u8);
+            self.lba_high_port.write(((sector >> 16) & 0xFF) as u8);
+            self.command_port.write(command);
+        }
+
+        Ok(())
+    }
+
+    /// Start reading a sector without waiting for it, the drive raises IRQ14 once the data
+    /// is there and [`wait_async_read`] resolves. Only one async read can be in flight,
+    /// the others get [`DISK_BUSY`]. Only the primary channel supports this.
+    pub fn start_async_read(&mut self, sector: u64) -> Result<(), &'static str> {
+        if self.channel != AtaChannel::Primary {
+            return Err("Async reads need the primary ATA channel");
+        }
+
+        if ASYNC_READ_STATE
+            .compare_exchange(
+                async_read::IDLE,
+                async_read::WAITING,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_err()
+        {
+            return Err(DISK_BUSY);
+        }
+
+        if let Err(e) = self.send_command(sector, ATA_CMD_READ_SECTORS) {
+            ASYNC_READ_STATE.store(async_read::IDLE, Ordering::SeqCst);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Let an async read in flight finish before the drive gets another command
+    fn finish_async_read(&mut self) {
+        if ASYNC_READ_STATE.load(Ordering::SeqCst) != async_read::WAITING {
+            return;
+        }
+
+        let _ = self.wait_data();
+        complete_async_read(self.channel.read_status());
+
+        // The drive never answered, give up on the read
+        if ASYNC_READ_STATE
+            .compare_exchange(
+                async_read::WAITING,
+                async_read::FAILED,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+        {
+            ASYNC_READ_WAKER.wake();
+        }
+    }
+
     /// Initialize the disk
     pub fn init(&mut self) -> Result<(), &'static str> {
         // Disable interrupts on the ATA controller to avoid conflicts
         unsafe {
-            self.control_port.write(0x02); // Set nIEN bit to disable interrupts
+            self.control_port.write(ATA_CONTROL_NO_INTERRUPTS);
         }
 
         // Reset the controller
         unsafe {
             self.control_port.write(0x06); // Set reset bit + nIEN
             for _ in 0..1000 {} // Small delay
-            self.control_port.write(0x02); // Clear reset bit but keep nIEN
+            self.control_port.write(ATA_CONTROL_NO_INTERRUPTS); // Clear reset bit but keep nIEN
         }
 
         // Wait for drive to be ready
@@ -156,14 +443,18 @@ impl AtaDisk {
                         self.data_port.read();
                     }
                 }
-                Ok(())
             }
             Err(_) => {
                 // Drive exists but may not support IDENTIFY (some virtual drives)
                 // This is okay, we can still try to use it
-                Ok(())
             }
         }
+
+        // From now on the drive raises its IRQ once a command is done
+        unsafe { self.control_port.write(ATA_CONTROL_INTERRUPTS) };
+        self.use_interrupts = true;
+
+        Ok(())
     }
 }
 
@@ -173,19 +464,11 @@ impl DiskOperations for AtaDisk {
             return Err("Buffer must be exactly 512 bytes");
         }
 
-        self.wait_ready()?;
-        self.select_drive(sector)?;
-
-        // Set up the read operation
-        unsafe {
-            self.sector_count_port.write(1); // Read 1 sector
-            self.lba_low_port.write((sector & 0xFF) as u8);
-            self.lba_mid_port.write(((sector >> 8) & 0xFF) as u8);
-            self.lba_high_port.write(((sector >> 16) & 0xFF) as u8);
-            self.command_port.write(ATA_CMD_READ_SECTORS);
-        }
+        self.finish_async_read();
+        self.send_command(sector, ATA_CMD_READ_SECTORS)?;
 
-        // Wait for data to be ready
+        // Wait for data to be ready, the status check passes at once after the IRQ
+        self.wait_interrupt();
         self.wait_data()?;
 
         // Read the data
@@ -204,17 +487,8 @@ impl DiskOperations for AtaDisk {
             return Err("Buffer must be exactly 512 bytes");
         }
 
-        self.wait_ready()?;
-        self.select_drive(sector)?;
-
-        // Set up the write operation
-        unsafe {
-            self.sector_count_port.write(1); // Write 1 sector
-            self.lba_low_port.write((sector & 0xFF) as u8);
-            self.lba_mid_port.write(((sector >> 8) & 0xFF) as u8);
-            self.lba_high_port.write(((sector >> 16) & 0xFF) as u8);
-            self.command_port.write(ATA_CMD_WRITE_SECTORS);
-        }
+        self.finish_async_read();
+        self.send_command(sector, ATA_CMD_WRITE_SECTORS)?;
 
         // Wait for drive to be ready for data
         self.wait_data()?;
@@ -229,8 +503,41 @@ impl DiskOperations for AtaDisk {
         }
 
         // Wait for write to complete
+        self.wait_interrupt();
         self.wait_ready()?;
 
+        self.unflushed_sectors += 1;
         Ok(())
     }
+
+    fn flush(&mut self) -> Result<u32, &'static str> {
+        if self.unflushed_sectors == 0 {
+            return Ok(0);
+        }
+
+        self.finish_async_read();
+        self.wait_ready()?;
+        self.select_drive(0)?;
+        CHANNEL_INTERRUPT[self.channel as usize].store(false, Ordering::SeqCst);
+        unsafe { self.command_port.write(ATA_CMD_FLUSH_CACHE) };
+
+        // The drive is busy until everything is on the media, which can take a while
+        self.wait_interrupt();
+        let mut timeout = 10_000_000;
+        loop {
+            let status = unsafe { self.status_port.read() };
+            if status & ATA_STATUS_BSY == 0 {
+                if status & ATA_STATUS_ERR != 0 {
+                    return Err("ATA cache flush failed");
+                }
+                break;
+            }
+            timeout -= 1;
+            if timeout == 0 {
+                return Err("ATA drive timeout flushing the cache");
+            }
+        }
+
+        Ok(core::mem::take(&mut self.unflushed_sectors))
+    }
 }
@@ -1,10 +1,12 @@
-use alloc::string::String;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::mem;
 
-/// Boot sector of a FAT32 filesystem
-#[repr(packed)]
+use crate::fs::cp437;
+
+/// Boot sector of a FAT32 filesystem, see [`Fat32BootSector::parse`]
 #[derive(Debug, Clone, Copy)]
 pub struct Fat32BootSector {
     pub jump_instruction: [u8; 3],
@@ -40,8 +42,7 @@ pub struct Fat32BootSector {
     pub bootable_partition_signature: u16,
 }
 
-/// Directory entry structure for FAT32
-#[repr(packed)]
+/// Directory entry structure for FAT32, see [`DirectoryEntry::parse`]
 #[derive(Debug, Clone, Copy)]
 pub struct DirectoryEntry {
     pub name: [u8; 11],
@@ -58,6 +59,182 @@ pub struct DirectoryEntry {
     pub file_size: u32,
 }
 
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn read_array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(&bytes[offset..offset + N]);
+    array
+}
+
+impl Fat32BootSector {
+    pub const SIZE: usize = 512;
+
+    /// Read the little-endian fields of a boot sector
+    pub fn parse(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() < Self::SIZE {
+            return Err("Boot sector too short");
+        }
+
+        Ok(Self {
+            jump_instruction: read_array(bytes, 0),
+            oem_name: read_array(bytes, 3),
+            bytes_per_sector: read_u16(bytes, 11),
+            sectors_per_cluster: bytes[13],
+            reserved_sectors: read_u16(bytes, 14),
+            fat_count: bytes[16],
+            root_dir_entries: read_u16(bytes, 17),
+            total_sectors_16: read_u16(bytes, 19),
+            media_descriptor: bytes[21],
+            sectors_per_fat_16: read_u16(bytes, 22),
+            sectors_per_track: read_u16(bytes, 24),
+            head_count: read_u16(bytes, 26),
+            hidden_sectors: read_u32(bytes, 28),
+            total_sectors_32: read_u32(bytes, 32),
+            sectors_per_fat_32: read_u32(bytes, 36),
+            ext_flags: read_u16(bytes, 40),
+            filesystem_version: read_u16(bytes, 42),
+            root_cluster: read_u32(bytes, 44),
+            filesystem_info: read_u16(bytes, 48),
+            backup_boot_sector: read_u16(bytes, 50),
+            reserved: read_array(bytes, 52),
+            drive_number: bytes[64],
+            reserved1: bytes[65],
+            boot_signature: bytes[66],
+            volume_id: read_u32(bytes, 67),
+            volume_label: read_array(bytes, 71),
+            filesystem_type: read_array(bytes, 82),
+            boot_code: read_array(bytes, 90),
+            bootable_partition_signature: read_u16(bytes, 510),
+        })
+    }
+}
+
+impl DirectoryEntry {
+    pub const SIZE: usize = 32;
+
+    /// Read the little-endian fields of the entry at the start of `bytes`, which holds at
+    /// least [`DirectoryEntry::SIZE`] bytes
+    pub fn parse(bytes: &[u8]) -> Self {
+        Self {
+            name: read_array(bytes, 0),
+            attributes: bytes[11],
+            reserved: bytes[12],
+            creation_time_tenths: bytes[13],
+            creation_time: read_u16(bytes, 14),
+            creation_date: read_u16(bytes, 16),
+            last_access_date: read_u16(bytes, 18),
+            first_cluster_high: read_u16(bytes, 20),
+            last_write_time: read_u16(bytes, 22),
+            last_write_date: read_u16(bytes, 24),
+            first_cluster_low: read_u16(bytes, 26),
+            file_size: read_u32(bytes, 28),
+        }
+    }
+
+    /// The entry as it's stored on disk
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[..11].copy_from_slice(&self.name);
+        bytes[11] = self.attributes;
+        bytes[12] = self.reserved;
+        bytes[13] = self.creation_time_tenths;
+        bytes[14..16].copy_from_slice(&self.creation_time.to_le_bytes());
+        bytes[16..18].copy_from_slice(&self.creation_date.to_le_bytes());
+        bytes[18..20].copy_from_slice(&self.last_access_date.to_le_bytes());
+        bytes[20..22].copy_from_slice(&self.first_cluster_high.to_le_bytes());
+        bytes[22..24].copy_from_slice(&self.last_write_time.to_le_bytes());
+        bytes[24..26].copy_from_slice(&self.last_write_date.to_le_bytes());
+        bytes[26..28].copy_from_slice(&self.first_cluster_low.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.file_size.to_le_bytes());
+        bytes
+    }
+}
+
+/// First name byte of a slot that was never used, no entries follow it
+const END_OF_DIRECTORY: u8 = 0x00;
+/// First name byte of a deleted entry
+const DELETED_ENTRY: u8 = 0xE5;
+/// Stored as the first name byte of a name starting with 0xE5, which would mark it deleted
+const ESCAPED_DELETED_BYTE: u8 = 0x05;
+
+/// The name of an 8.3 directory entry, like `README.TXT`. The bytes are read as CP437.
+pub fn short_name(name: &[u8; 11]) -> String {
+    let decode = |(i, &byte): (usize, &u8)| match byte {
+        ESCAPED_DELETED_BYTE if i == 0 => cp437::decode(DELETED_ENTRY),
+        byte => cp437::decode(byte),
+    };
+
+    let mut short_name: String = name[..8].iter().enumerate().map(decode).collect();
+    short_name.truncate(short_name.trim_end_matches(' ').len());
+
+    let extension: String = name[8..].iter().map(|&byte| cp437::decode(byte)).collect();
+    let extension = extension.trim_end_matches(' ');
+    if !extension.is_empty() {
+        short_name.push('.');
+        short_name.push_str(extension);
+    }
+    short_name
+}
+
+/// What a 32-byte slot of a directory holds
+#[derive(Debug, Clone, Copy)]
+pub enum DirectorySlot {
+    /// A file, directory or volume label
+    Entry(DirectoryEntry),
+    /// Part of a long file name, those aren't read yet
+    LongName,
+    /// Free to reuse
+    Deleted,
+    /// Free, and the end of the directory
+    End,
+}
+
+/// The slots of a directory cluster with their offsets in it. Stops after
+/// [`DirectorySlot::End`], a directory continues in the next cluster when there's none.
+pub struct DirectorySlots<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DirectorySlots<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer, offset: 0 }
+    }
+}
+
+impl Iterator for DirectorySlots<'_> {
+    type Item = (usize, DirectorySlot);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let bytes = self.buffer.get(offset..offset + DirectoryEntry::SIZE)?;
+
+        let slot = match bytes[0] {
+            END_OF_DIRECTORY => {
+                self.offset = self.buffer.len();
+                return Some((offset, DirectorySlot::End));
+            }
+            DELETED_ENTRY => DirectorySlot::Deleted,
+            _ if bytes[11] == attributes::LONG_NAME => DirectorySlot::LongName,
+            _ => DirectorySlot::Entry(DirectoryEntry::parse(bytes)),
+        };
+        self.offset += DirectoryEntry::SIZE;
+        Some((offset, slot))
+    }
+}
+
 /// File attributes
 pub mod attributes {
     pub const READ_ONLY: u8 = 0x01;
@@ -77,6 +254,19 @@ pub mod cluster_values {
     pub const MASK: u32 = 0x0FFFFFFF;
 }
 
+/// Flags for [`Fat32FileSystem::open_file`] and [`Fat32FileSystem::write_file_with_flags`],
+/// combined with `|`
+pub mod open_flags {
+    /// Create the file when it doesn't exist, without it a missing file is an error
+    pub const CREATE: u32 = 1 << 0;
+    /// Drop the old content of an existing file
+    pub const TRUNCATE: u32 = 1 << 1;
+    /// Write after the old content instead of over it from the start
+    pub const APPEND: u32 = 1 << 2;
+    /// Only with `CREATE`, fail when the file already exists
+    pub const EXCLUSIVE: u32 = 1 << 3;
+}
+
 /// Represents a file or directory in the FAT32 filesystem
 #[derive(Debug, Clone)]
 pub struct FileEntry {
@@ -84,6 +274,8 @@ pub struct FileEntry {
     pub is_directory: bool,
     pub size: u32,
     pub first_cluster: u32,
+    /// See [`attributes`]
+    pub attributes: u8,
 
     pub creation_date: u16,
     pub creation_time: u16,
@@ -92,10 +284,186 @@ pub struct FileEntry {
     pub last_write_time: u16,
 }
 
+/// Where the next [`Fat32FileSystem::read_file_chunk`] continues in a file
+#[derive(Debug, Clone, Copy)]
+pub struct FileCursor {
+    cluster: u32,
+    position: u32,
+    size: u32,
+}
+
+impl FileCursor {
+    pub fn new(first_cluster: u32, size: u32) -> Self {
+        Self {
+            cluster: first_cluster,
+            position: 0,
+            size,
+        }
+    }
+
+    /// Bytes read so far
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        // Empty files have no clusters at all
+        self.position >= self.size || self.cluster < 2
+    }
+}
+
+/// Totals for a directory tree, see [`Fat32FileSystem::directory_size`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirectorySize {
+    pub bytes: u64,
+    pub files: u32,
+    pub directories: u32,
+    /// Set when a loop or a too deep tree was found, the totals only cover what was walked
+    pub incomplete: bool,
+}
+
+/// Layout of a file on disk, see [`Fat32FileSystem::cluster_chain`]
+#[derive(Debug, Clone, Default)]
+pub struct ClusterChain {
+    /// Clusters in the chain
+    pub length: u32,
+    /// Runs of contiguous clusters, 1 for an unfragmented file
+    pub fragments: u32,
+    /// First cluster and length of the leading runs, up to the requested number
+    pub runs: Vec<(u32, u32)>,
+    /// Set when the chain loops, the totals only cover the clusters before that
+    pub incomplete: bool,
+}
+
+/// Format a FAT date as YYYY-MM-DD
+pub fn format_date(date: u16) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        1980 + (date >> 9),
+        (date >> 5) & 0x0F,
+        date & 0x1F
+    )
+}
+
+/// Format a FAT time as HH:MM:SS
+pub fn format_time(time: u16) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        time >> 11,
+        (time >> 5) & 0x3F,
+        (time & 0x1F) * 2
+    )
+}
+
+/// Problems found by [`Fat32FileSystem::check_consistency`]
+#[derive(Debug, Clone, Default)]
+pub struct ConsistencyReport {
+    pub files_checked: u32,
+    pub directories_checked: u32,
+    /// Clusters marked as used in the FAT that no file or directory references
+    pub lost_clusters: Vec<u32>,
+    /// Clusters that are part of more than one chain
+    pub cross_linked_clusters: Vec<u32>,
+    /// Paths whose chain points outside the disk or into a free cluster
+    pub broken_chains: Vec<String>,
+    /// Paths with fewer clusters than their size needs
+    pub size_mismatches: Vec<String>,
+    /// Set when a directory loop or a too deep tree stopped the walk
+    pub incomplete: bool,
+    /// Lost clusters that were freed, only in repair mode
+    pub freed_clusters: u32,
+    /// Set when repair mode freed nothing because the walk missed part of the disk
+    pub repair_skipped: bool,
+}
+
+impl ConsistencyReport {
+    pub fn is_clean(&self) -> bool {
+        self.lost_clusters.is_empty()
+            && self.cross_linked_clusters.is_empty()
+            && self.broken_chains.is_empty()
+            && self.size_mismatches.is_empty()
+            && !self.incomplete
+    }
+}
+
+/// Hidden directory in the root that deleted files are moved to
+pub const TRASH_DIRECTORY: &str = ".TRASH";
+/// File in the trash directory that records where each file in it came from, one
+/// `<name in the trash>=<directory cluster>,<original name>` line per file
+const TRASH_INDEX: &str = "ORIGINS";
+/// Files with the same name the trash holds at once, they are numbered `~1` to `~999`
+const MAX_TRASH_DUPLICATES: usize = 999;
+
+/// Where a file in the trash was moved from
+struct TrashOrigin {
+    name: String,
+    directory: u32,
+    original_name: String,
+}
+
+impl TrashOrigin {
+    fn parse(line: &str) -> Option<Self> {
+        let (name, origin) = line.split_once('=')?;
+        let (directory, original_name) = origin.split_once(',')?;
+        Some(Self {
+            name: name.to_string(),
+            directory: directory.parse().ok()?,
+            original_name: original_name.to_string(),
+        })
+    }
+
+    fn line(&self) -> String {
+        format!("{}={},{}\n", self.name, self.directory, self.original_name)
+    }
+}
+
+/// Deeper trees are assumed to be a corrupt filesystem
+const MAX_DIRECTORY_DEPTH: usize = 32;
+
+/// Punctuation allowed in 8.3 names besides letters and digits
+const NAME_SPECIAL_CHARS: &str = "!#$%&'()-@^_`{}~";
+
+/// Check that a name fits an 8.3 directory entry as is, without being cut short
+pub fn validate_filename(name: &str) -> Result<(), &'static str> {
+    let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+
+    if base.is_empty() {
+        return Err("Name is empty");
+    }
+    if base.len() > 8 {
+        return Err("Name is longer than 8 characters");
+    }
+    if extension.contains('.') {
+        return Err("Name has more than one dot");
+    }
+    if extension.len() > 3 {
+        return Err("Extension is longer than 3 characters");
+    }
+    if !base
+        .chars()
+        .chain(extension.chars())
+        .all(|c| c.is_ascii_alphanumeric() || NAME_SPECIAL_CHARS.contains(c))
+    {
+        return Err("Name has invalid characters");
+    }
+
+    Ok(())
+}
+
 /// Trait for disk operations
 pub trait DiskOperations {
     fn read_sector(&mut self, sector: u64, buffer: &mut [u8]) -> Result<(), &'static str>;
     fn write_sector(&mut self, sector: u64, buffer: &[u8]) -> Result<(), &'static str>;
+
+    /// Put everything written so far on the media. Returns the sectors written since the
+    /// last flush.
+    fn flush(&mut self) -> Result<u32, &'static str> {
+        Ok(0)
+    }
 }
 
 /// FAT32 filesystem implementation
@@ -106,15 +474,17 @@ pub struct Fat32FileSystem<D: DiskOperations> {
     data_start_sector: u64,
     sectors_per_cluster: u64,
     bytes_per_sector: u64,
+    /// Where the search for free clusters starts, just behind the last allocation
+    next_free_hint: u32,
 }
 
 impl<D: DiskOperations> Fat32FileSystem<D> {
     /// Create a new FAT32 filesystem instance
     pub fn new(mut disk: D) -> Result<Self, &'static str> {
-        let mut boot_sector_data = [0u8; 512];
+        let mut boot_sector_data = [0u8; Fat32BootSector::SIZE];
         disk.read_sector(0, &mut boot_sector_data)?;
 
-        let boot_sector = unsafe { *(boot_sector_data.as_ptr() as *const Fat32BootSector) };
+        let boot_sector = Fat32BootSector::parse(&boot_sector_data)?;
 
         // Verify this is a FAT32 filesystem
         if boot_sector.bootable_partition_signature != 0xAA55 {
@@ -136,6 +506,7 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
             data_start_sector,
             sectors_per_cluster: boot_sector.sectors_per_cluster as u64,
             bytes_per_sector: boot_sector.bytes_per_sector as u64,
+            next_free_hint: 2,
         })
     }
 
@@ -147,6 +518,17 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         self.data_start_sector + (cluster as u64 - 2) * self.sectors_per_cluster
     }
 
+    /// Sectors that hold a cluster
+    pub fn cluster_sectors(&self, cluster: u32) -> core::ops::Range<u64> {
+        let first_sector = self.cluster_to_sector(cluster);
+        first_sector..first_sector + self.sectors_per_cluster
+    }
+
+    /// The disk under the filesystem, for transfers that bypass it
+    pub fn disk_mut(&mut self) -> &mut D {
+        &mut self.disk
+    }
+
     /// Read a cluster from the disk
     fn read_cluster(&mut self, cluster: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
         let sector = self.cluster_to_sector(cluster);
@@ -169,7 +551,7 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
     }
 
     /// Read the next cluster from the FAT
-    fn get_next_cluster(&mut self, cluster: u32) -> Result<u32, &'static str> {
+    pub fn get_next_cluster(&mut self, cluster: u32) -> Result<u32, &'static str> {
         let fat_offset = cluster * 4; // 4 bytes per FAT32 entry
         let fat_sector = self.fat_start_sector + (fat_offset as u64 / self.bytes_per_sector);
         let sector_offset = (fat_offset as u64 % self.bytes_per_sector) as usize;
@@ -200,25 +582,13 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         loop {
             self.read_cluster(current_cluster, &mut cluster_buffer)?;
 
-            let entries_per_cluster = cluster_size / mem::size_of::<DirectoryEntry>();
-
-            for i in 0..entries_per_cluster {
-                let entry_offset = i * mem::size_of::<DirectoryEntry>();
-                let entry = unsafe {
-                    *(cluster_buffer.as_ptr().add(entry_offset) as *const DirectoryEntry)
-                };
-
-                // Check if this is the end of directory entries
-                if entry.name[0] == 0x00 {
-                    return Ok(entries);
-                }
-
-                // Skip deleted entries and long filename entries
-                if entry.name[0] == 0xE5 || entry.attributes == attributes::LONG_NAME {
-                    continue;
+            // Deleted entries and long filename entries are skipped
+            for (_, slot) in DirectorySlots::new(&cluster_buffer) {
+                match slot {
+                    DirectorySlot::Entry(entry) => entries.push(entry),
+                    DirectorySlot::End => return Ok(entries),
+                    DirectorySlot::LongName | DirectorySlot::Deleted => {}
                 }
-
-                entries.push(entry);
             }
 
             // Get the next cluster in the chain
@@ -234,25 +604,7 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
 
     /// Convert a directory entry to a FileEntry
     fn entry_to_file_entry(&self, entry: &DirectoryEntry) -> FileEntry {
-        let mut name = String::new();
-
-        // Parse the 8.3 filename format
-        let mut i = 0;
-        while i < 8 && entry.name[i] != 0x20 {
-            name.push(entry.name[i] as char);
-            i += 1;
-        }
-
-        // Add extension if present
-        if entry.name[8] != 0x20 {
-            name.push('.');
-            let mut i = 8;
-            while i < 11 && entry.name[i] != 0x20 {
-                name.push(entry.name[i] as char);
-                i += 1;
-            }
-        }
-
+        let name = short_name(&entry.name);
         let first_cluster =
             ((entry.first_cluster_high as u32) << 16) | (entry.first_cluster_low as u32);
 
@@ -261,6 +613,7 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
             is_directory: (entry.attributes & attributes::DIRECTORY) != 0,
             size: entry.file_size,
             first_cluster,
+            attributes: entry.attributes,
             creation_date: entry.creation_date,
             creation_time: entry.creation_time,
             last_access_date: entry.last_access_date,
@@ -286,6 +639,36 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         Ok(files)
     }
 
+    /// Get the volume label, or an empty string if the volume has none.
+    /// The label entry in the root directory is preferred, the boot sector copy is often stale.
+    pub fn volume_label(&mut self) -> String {
+        let root_label = self
+            .read_directory_entries(self.boot_sector.root_cluster)
+            .ok()
+            .and_then(|entries| {
+                entries.into_iter().find(|entry| {
+                    entry.attributes & attributes::VOLUME_ID != 0
+                        && entry.attributes & attributes::DIRECTORY == 0
+                })
+            })
+            .map(|entry| entry.name);
+
+        let label = root_label.unwrap_or(self.boot_sector.volume_label);
+        let label = label
+            .iter()
+            .map(|&byte| cp437::decode(byte))
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        // "NO NAME" is what formatting tools write when no label was given
+        if label == "NO NAME" {
+            String::new()
+        } else {
+            label
+        }
+    }
+
     /// List files in a specific directory
     pub fn list_directory(&mut self, dir_cluster: u32) -> Result<Vec<FileEntry>, &'static str> {
         let entries = self.read_directory_entries(dir_cluster)?;
@@ -303,6 +686,267 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         Ok(files)
     }
 
+    /// Size of a cluster in bytes
+    pub fn cluster_size(&self) -> u32 {
+        (self.sectors_per_cluster * self.bytes_per_sector) as u32
+    }
+
+    /// Count the clusters in a chain. Stops at the number of clusters on the disk,
+    /// so a looping FAT can't hang us.
+    pub fn cluster_count(&mut self, first_cluster: u32) -> Result<u32, &'static str> {
+        if first_cluster < 2 {
+            return Ok(0);
+        }
+
+        let max_clusters = (self.boot_sector.total_sectors_32 - self.data_start_sector as u32)
+            / self.boot_sector.sectors_per_cluster as u32;
+
+        let mut count = 0;
+        let mut current_cluster = first_cluster;
+
+        while count < max_clusters {
+            count += 1;
+
+            let next_cluster = self.get_next_cluster(current_cluster)?;
+            if next_cluster >= cluster_values::END_OF_CHAIN || next_cluster < 2 {
+                break;
+            }
+            current_cluster = next_cluster;
+        }
+
+        Ok(count)
+    }
+
+    /// Recursively sum the sizes of all files below a directory
+    pub fn directory_size(&mut self, dir_cluster: u32) -> Result<DirectorySize, &'static str> {
+        let mut size = DirectorySize::default();
+        let mut visited = BTreeSet::new();
+
+        self.add_directory_size(dir_cluster, 0, &mut visited, &mut size)?;
+
+        Ok(size)
+    }
+
+    fn add_directory_size(
+        &mut self,
+        dir_cluster: u32,
+        depth: usize,
+        visited: &mut BTreeSet<u32>,
+        size: &mut DirectorySize,
+    ) -> Result<(), &'static str> {
+        // A directory we've already seen means the filesystem has a loop
+        if depth > MAX_DIRECTORY_DEPTH || !visited.insert(dir_cluster) {
+            size.incomplete = true;
+            return Ok(());
+        }
+
+        for entry in self.list_directory(dir_cluster)? {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            if !entry.is_directory {
+                size.files += 1;
+                size.bytes += entry.size as u64;
+                continue;
+            }
+
+            size.directories += 1;
+
+            // Subdirectories always have a cluster, anything else points back to the root
+            if entry.first_cluster < 2 {
+                size.incomplete = true;
+                continue;
+            }
+
+            self.add_directory_size(entry.first_cluster, depth + 1, visited, size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Totals for the whole root directory tree
+    pub fn root_directory_size(&mut self) -> Result<DirectorySize, &'static str> {
+        self.directory_size(self.boot_sector.root_cluster)
+    }
+
+    /// One past the highest valid cluster number
+    fn cluster_limit(&self) -> u32 {
+        (self.boot_sector.total_sectors_32 - self.data_start_sector as u32)
+            / self.boot_sector.sectors_per_cluster as u32
+            + 2
+    }
+
+    /// Read the whole first FAT, one entry per cluster
+    fn read_fat(&mut self) -> Result<Vec<u32>, &'static str> {
+        let cluster_limit = self.cluster_limit() as usize;
+        let mut fat = Vec::with_capacity(cluster_limit);
+        let mut sector_buffer = [0u8; 512];
+        let mut sector = self.fat_start_sector;
+
+        while fat.len() < cluster_limit {
+            self.disk.read_sector(sector, &mut sector_buffer)?;
+            sector += 1;
+
+            for entry in sector_buffer.chunks_exact(4) {
+                fat.push(
+                    u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]])
+                        & cluster_values::MASK,
+                );
+            }
+        }
+
+        fat.truncate(cluster_limit);
+        Ok(fat)
+    }
+
+    /// Walk every directory and cluster chain and compare them with the FAT.
+    /// With `repair`, clusters that are used in the FAT but belong to nothing are freed.
+    pub fn check_consistency(&mut self, repair: bool) -> Result<ConsistencyReport, &'static str> {
+        let fat = self.read_fat()?;
+        let mut used = vec![false; fat.len()];
+        let mut report = ConsistencyReport::default();
+
+        let root = self.boot_sector.root_cluster;
+        self.check_chain(&fat, &mut used, root, None, "/", &mut report);
+        self.check_directory(&fat, &mut used, root, "", 0, &mut report)?;
+
+        for (cluster, &entry) in fat.iter().enumerate().skip(2) {
+            if entry != cluster_values::FREE && entry != cluster_values::BAD && !used[cluster] {
+                report.lost_clusters.push(cluster as u32);
+            }
+        }
+
+        // Clusters behind a broken chain or past where the walk stopped were never marked as
+        // used, they may belong to live files
+        if repair && (report.incomplete || !report.broken_chains.is_empty()) {
+            report.repair_skipped = true;
+        } else if repair {
+            for i in 0..report.lost_clusters.len() {
+                self.update_fat_entry(report.lost_clusters[i], cluster_values::FREE)?;
+                report.freed_clusters += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn check_directory(
+        &mut self,
+        fat: &[u32],
+        used: &mut [bool],
+        dir_cluster: u32,
+        path: &str,
+        depth: usize,
+        report: &mut ConsistencyReport,
+    ) -> Result<(), &'static str> {
+        report.directories_checked += 1;
+
+        if depth > MAX_DIRECTORY_DEPTH {
+            report.incomplete = true;
+            return Ok(());
+        }
+
+        for entry in self.list_directory(dir_cluster)? {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            let entry_path = format!("{}/{}", path, entry.name);
+
+            if entry.is_directory {
+                if entry.first_cluster < 2 {
+                    report.broken_chains.push(entry_path);
+                    continue;
+                }
+
+                // A directory chain we've already walked means there is a loop
+                if used
+                    .get(entry.first_cluster as usize)
+                    .copied()
+                    .unwrap_or(false)
+                {
+                    report.incomplete = true;
+                    report.cross_linked_clusters.push(entry.first_cluster);
+                    continue;
+                }
+
+                if self.check_chain(fat, used, entry.first_cluster, None, &entry_path, report) {
+                    self.check_directory(
+                        fat,
+                        used,
+                        entry.first_cluster,
+                        &entry_path,
+                        depth + 1,
+                        report,
+                    )?;
+                }
+            } else {
+                report.files_checked += 1;
+
+                if entry.first_cluster >= 2 {
+                    self.check_chain(
+                        fat,
+                        used,
+                        entry.first_cluster,
+                        Some(entry.size),
+                        &entry_path,
+                        report,
+                    );
+                } else if entry.size > 0 {
+                    report.size_mismatches.push(entry_path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mark the clusters of a chain as used. Returns false if the chain is broken.
+    fn check_chain(
+        &self,
+        fat: &[u32],
+        used: &mut [bool],
+        first_cluster: u32,
+        size: Option<u32>,
+        path: &str,
+        report: &mut ConsistencyReport,
+    ) -> bool {
+        let mut cluster = first_cluster;
+        let mut count = 0u64;
+
+        loop {
+            let index = cluster as usize;
+            if cluster < 2 || index >= fat.len() || fat[index] == cluster_values::FREE {
+                report.broken_chains.push(path.to_string());
+                return false;
+            }
+
+            // Following a cross-linked chain any further would only report it twice
+            if used[index] {
+                report.cross_linked_clusters.push(cluster);
+                return true;
+            }
+
+            used[index] = true;
+            count += 1;
+
+            let next = fat[index];
+            if next >= cluster_values::BAD {
+                break;
+            }
+            cluster = next;
+        }
+
+        if let Some(size) = size {
+            if count * (self.cluster_size() as u64) < size as u64 {
+                report.size_mismatches.push(path.to_string());
+            }
+        }
+
+        true
+    }
+
     /// Read a file's content
     pub fn read_file(
         &mut self,
@@ -310,33 +954,41 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         file_size: u32,
     ) -> Result<Vec<u8>, &'static str> {
         let mut file_data = Vec::new();
-        let mut current_cluster = first_cluster;
-        let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
-        let mut bytes_read = 0u32;
+        let mut cursor = FileCursor::new(first_cluster, file_size);
 
-        while bytes_read < file_size {
-            let mut cluster_buffer = vec![0u8; cluster_size];
-            self.read_cluster(current_cluster, &mut cluster_buffer)?;
+        while !cursor.is_at_end() {
+            file_data.extend_from_slice(&self.read_file_chunk(&mut cursor)?);
+        }
+
+        Ok(file_data)
+    }
 
-            let bytes_to_read =
-                core::cmp::min(cluster_size as u32, file_size - bytes_read) as usize;
+    /// Read the next cluster of a file and move the cursor past it.
+    /// Returns an empty buffer once the end of the file is reached.
+    pub fn read_file_chunk(&mut self, cursor: &mut FileCursor) -> Result<Vec<u8>, &'static str> {
+        if cursor.is_at_end() {
+            return Ok(Vec::new());
+        }
 
-            file_data.extend_from_slice(&cluster_buffer[..bytes_to_read]);
-            bytes_read += bytes_to_read as u32;
+        let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
+        let mut cluster_buffer = vec![0u8; cluster_size];
+        self.read_cluster(cursor.cluster, &mut cluster_buffer)?;
 
-            if bytes_read >= file_size {
-                break;
-            }
+        let bytes_to_read = core::cmp::min(cluster_size as u32, cursor.size - cursor.position);
+        cluster_buffer.truncate(bytes_to_read as usize);
+        cursor.position += bytes_to_read;
 
-            // Get the next cluster
-            let next_cluster = self.get_next_cluster(current_cluster)?;
+        if !cursor.is_at_end() {
+            let next_cluster = self.get_next_cluster(cursor.cluster)?;
             if next_cluster >= cluster_values::END_OF_CHAIN {
-                break;
+                // The chain is shorter than the directory entry claims, stop here
+                cursor.size = cursor.position;
+            } else {
+                cursor.cluster = next_cluster;
             }
-            current_cluster = next_cluster;
         }
 
-        Ok(file_data)
+        Ok(cluster_buffer)
     }
 
     /// Find a file in a directory by name
@@ -383,33 +1035,173 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
 
     /// Find a free cluster in the FAT
     fn find_free_cluster(&mut self) -> Result<u32, &'static str> {
-        // Start searching from cluster 2 (first data cluster)
-        let mut cluster = 2u32;
-        let max_clusters = (self.boot_sector.total_sectors_32 - self.data_start_sector as u32)
-            / self.boot_sector.sectors_per_cluster as u32;
+        Ok(self.find_free_clusters(1)?[0])
+    }
+
+    /// Find `count` free clusters, in one contiguous run if there is one. The search starts
+    /// behind the last allocation and wraps around, so new files go after the old ones
+    /// instead of into the small gaps deleted files left.
+    fn find_free_clusters(&mut self, count: u32) -> Result<Vec<u32>, &'static str> {
+        let limit = self.cluster_limit();
+        let total = limit - 2;
+        let start = if (2..limit).contains(&self.next_free_hint) {
+            self.next_free_hint
+        } else {
+            2
+        };
 
-        while cluster < max_clusters {
-            let fat_entry = self.get_next_cluster(cluster)?;
-            if fat_entry == cluster_values::FREE {
-                return Ok(cluster);
+        // The first free clusters in search order, used when no run is long enough
+        let mut scattered = Vec::new();
+        let mut run_start = 0;
+        let mut run_length = 0;
+
+        // A FAT sector holds the entries of 128 clusters, it is read once for all of them
+        let mut fat_buffer = [0u8; 512];
+        let mut loaded_sector = None;
+
+        for i in 0..total {
+            let cluster = 2 + (start - 2 + i) % total;
+            if cluster == 2 {
+                // Wrapped around, a run can't continue across the end
+                run_length = 0;
             }
-            cluster += 1;
-        }
 
-        Err("No free clusters available")
-    }
+            let fat_offset = cluster as u64 * 4;
+            let fat_sector = self.fat_start_sector + fat_offset / self.bytes_per_sector;
+            if loaded_sector != Some(fat_sector) {
+                self.disk.read_sector(fat_sector, &mut fat_buffer)?;
+                loaded_sector = Some(fat_sector);
+            }
 
-    /// Update a FAT entry
-    fn update_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), &'static str> {
-        let fat_offset = cluster * 4; // 4 bytes per FAT32 entry
-        let fat_sector = self.fat_start_sector + (fat_offset as u64 / self.bytes_per_sector);
-        let sector_offset = (fat_offset as u64 % self.bytes_per_sector) as usize;
+            let entry_offset = (fat_offset % self.bytes_per_sector) as usize;
+            let fat_entry = u32::from_le_bytes([
+                fat_buffer[entry_offset],
+                fat_buffer[entry_offset + 1],
+                fat_buffer[entry_offset + 2],
+                fat_buffer[entry_offset + 3],
+            ]) & cluster_values::MASK;
 
-        // Read the sector
-        let mut sector_buffer = [0u8; 512];
-        self.disk.read_sector(fat_sector, &mut sector_buffer)?;
+            if fat_entry != cluster_values::FREE {
+                run_length = 0;
+                continue;
+            }
 
-        // Update the FAT entry (preserve upper 4 bits)
+            if scattered.len() < count as usize {
+                scattered.push(cluster);
+            }
+            if run_length == 0 {
+                run_start = cluster;
+            }
+            run_length += 1;
+
+            if run_length == count {
+                self.next_free_hint = run_start + count;
+                return Ok((run_start..run_start + count).collect());
+            }
+        }
+
+        if scattered.len() < count as usize {
+            return Err("No free clusters available");
+        }
+        self.next_free_hint = scattered[scattered.len() - 1] + 1;
+        Ok(scattered)
+    }
+
+    /// Average distance from one cluster of a chain to the next, 1 when the chain is
+    /// contiguous and 0 for chains of a single cluster
+    pub fn average_jump_distance(&mut self, first_cluster: u32) -> Result<u32, &'static str> {
+        let limit = self.cluster_limit();
+        let mut cluster = first_cluster;
+        let mut jumps = 0u32;
+        let mut distance = 0u64;
+
+        while cluster >= 2 && cluster < limit {
+            let next = self.get_next_cluster(cluster)?;
+            if next < 2 || next >= limit {
+                break;
+            }
+
+            jumps += 1;
+            distance += next.abs_diff(cluster) as u64;
+            if jumps >= limit {
+                return Err("Cluster chain loops");
+            }
+            cluster = next;
+        }
+
+        Ok(distance.checked_div(jumps as u64).unwrap_or(0) as u32)
+    }
+
+    /// Walk a cluster chain and collect its runs of contiguous clusters. Only the first
+    /// `max_runs` runs are kept, the counts cover the whole chain.
+    pub fn cluster_chain(
+        &mut self,
+        first_cluster: u32,
+        max_runs: usize,
+    ) -> Result<ClusterChain, &'static str> {
+        let limit = self.cluster_limit();
+        let mut chain = ClusterChain::default();
+        let mut cluster = first_cluster;
+        let mut run = (first_cluster, 0);
+
+        while cluster >= 2 && cluster < limit {
+            // A chain can't be longer than the disk, anything more is a loop
+            if chain.length >= limit {
+                chain.incomplete = true;
+                break;
+            }
+            chain.length += 1;
+            run.1 += 1;
+
+            let next = self.get_next_cluster(cluster)?;
+            if next != cluster + 1 || next < 2 || next >= limit {
+                chain.fragments += 1;
+                if chain.runs.len() < max_runs {
+                    chain.runs.push(run);
+                }
+                run = (next, 0);
+            }
+            cluster = next;
+        }
+
+        Ok(chain)
+    }
+
+    /// The sectors that hold the first `size` bytes of a file, in order, for writes that
+    /// can't go through the filesystem
+    pub fn file_sectors(
+        &mut self,
+        first_cluster: u32,
+        size: u32,
+    ) -> Result<Vec<u64>, &'static str> {
+        let count = (size as u64).div_ceil(self.bytes_per_sector) as usize;
+        let limit = self.cluster_limit();
+        let mut sectors = Vec::with_capacity(count);
+        let mut cluster = first_cluster;
+
+        while sectors.len() < count {
+            if cluster < 2 || cluster >= limit {
+                return Err("Cluster chain is shorter than the file");
+            }
+            sectors.extend(self.cluster_sectors(cluster));
+            cluster = self.get_next_cluster(cluster)?;
+        }
+
+        sectors.truncate(count);
+        Ok(sectors)
+    }
+
+    /// Update a FAT entry
+    fn update_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), &'static str> {
+        let fat_offset = cluster * 4; // 4 bytes per FAT32 entry
+        let fat_sector = self.fat_start_sector + (fat_offset as u64 / self.bytes_per_sector);
+        let sector_offset = (fat_offset as u64 % self.bytes_per_sector) as usize;
+
+        // Read the sector
+        let mut sector_buffer = [0u8; 512];
+        self.disk.read_sector(fat_sector, &mut sector_buffer)?;
+
+        // Update the FAT entry (preserve upper 4 bits)
         let masked_value = value & cluster_values::MASK;
         let existing_upper = u32::from_le_bytes([
             sector_buffer[sector_offset],
@@ -443,23 +1235,16 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
             return Err("Cannot allocate zero clusters");
         }
 
-        let first_cluster = self.find_free_cluster()?;
-        let mut current_cluster = first_cluster;
-
-        // Allocate the requested number of clusters
-        for i in 0..num_clusters {
-            if i == num_clusters - 1 {
-                // Last cluster - mark as end of chain
-                self.update_fat_entry(current_cluster, cluster_values::END_OF_CHAIN)?;
-            } else {
-                // Find next free cluster
-                let next_cluster = self.find_free_cluster()?;
-                self.update_fat_entry(current_cluster, next_cluster)?;
-                current_cluster = next_cluster;
-            }
+        let clusters = self.find_free_clusters(num_clusters)?;
+        for (i, &cluster) in clusters.iter().enumerate() {
+            let next = clusters
+                .get(i + 1)
+                .copied()
+                .unwrap_or(cluster_values::END_OF_CHAIN);
+            self.update_fat_entry(cluster, next)?;
         }
 
-        Ok(first_cluster)
+        Ok(clusters[0])
     }
 
     /// Write file data to allocated clusters
@@ -540,47 +1325,322 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         Ok(())
     }
 
-    /// Convert filename to 8.3 format
+    /// Reject flag combinations that have no meaning
+    fn check_open_flags(flags: u32) -> Result<(), &'static str> {
+        if flags & open_flags::EXCLUSIVE != 0 && flags & open_flags::CREATE == 0 {
+            return Err("EXCLUSIVE needs CREATE");
+        }
+        if flags & open_flags::TRUNCATE != 0 && flags & open_flags::APPEND != 0 {
+            return Err("TRUNCATE and APPEND can't be combined");
+        }
+        Ok(())
+    }
+
+    /// Find a file for [`Self::open_file`], or `None` if it has to be created
+    fn find_file_to_open(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+        flags: u32,
+    ) -> Result<Option<FileEntry>, &'static str> {
+        Self::check_open_flags(flags)?;
+
+        match self.find_file_in_directory(dir_cluster, filename)? {
+            Some(file) if file.is_directory => Err("Is a directory"),
+            Some(_) if flags & open_flags::EXCLUSIVE != 0 => Err("File already exists"),
+            Some(file) => Ok(Some(file)),
+            None if flags & open_flags::CREATE != 0 => Ok(None),
+            None => Err("File not found"),
+        }
+    }
+
+    /// Open a file in one step, without a window where it is missing:
+    ///
+    /// - missing file: created empty with `CREATE`, an error otherwise
+    /// - existing file: an error with `EXCLUSIVE`, emptied with `TRUNCATE`, untouched otherwise
+    /// - directories can't be opened, `EXCLUSIVE` without `CREATE` and `TRUNCATE` with
+    ///   `APPEND` are rejected
+    ///
+    /// `APPEND` only matters for [`Self::write_file_with_flags`].
+    pub fn open_file(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+        flags: u32,
+    ) -> Result<FileEntry, &'static str> {
+        let Some(file) = self.find_file_to_open(dir_cluster, filename, flags)? else {
+            self.create_file(dir_cluster, filename, &[])?;
+            return self
+                .find_file_in_directory(dir_cluster, filename)?
+                .ok_or("File not found");
+        };
+
+        if flags & open_flags::TRUNCATE == 0 || file.size == 0 {
+            return Ok(file);
+        }
+
+        // The entry is emptied before the chain is freed, so it never points at free clusters
+        self.update_directory_entry(dir_cluster, filename, 0, 0)?;
+        if file.first_cluster >= 2 {
+            self.free_cluster_chain(file.first_cluster)?;
+        }
+
+        Ok(FileEntry {
+            first_cluster: 0,
+            size: 0,
+            ..file
+        })
+    }
+
+    /// Open a file like [`Self::open_file`] and write `data` to it. Without `TRUNCATE` or
+    /// `APPEND` the data overwrites the start of the file and the rest is kept.
+    ///
+    /// The new content is written to fresh clusters and the directory entry is switched
+    /// over at the end, so a failed write leaves the old file as it was. `APPEND` only writes
+    /// the new bytes, see [`Self::append_in_place`].
+    pub fn write_file_with_flags(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+        data: &[u8],
+        flags: u32,
+    ) -> Result<FileEntry, &'static str> {
+        let Some(file) = self.find_file_to_open(dir_cluster, filename, flags)? else {
+            self.create_file(dir_cluster, filename, data)?;
+            return self
+                .find_file_in_directory(dir_cluster, filename)?
+                .ok_or("File not found");
+        };
+
+        if flags & open_flags::APPEND != 0 && flags & open_flags::TRUNCATE == 0 && file.size > 0 {
+            return self.append_in_place(dir_cluster, filename, file, data);
+        }
+
+        let content = if flags & open_flags::TRUNCATE != 0 || file.size == 0 {
+            data.to_vec()
+        } else {
+            let mut content = self.read_file(file.first_cluster, file.size)?;
+            if flags & open_flags::APPEND != 0 {
+                content.extend_from_slice(data);
+            } else {
+                if content.len() < data.len() {
+                    content.resize(data.len(), 0);
+                }
+                content[..data.len()].copy_from_slice(data);
+            }
+            content
+        };
+
+        let first_cluster = if content.is_empty() {
+            0
+        } else {
+            let cluster_size = self.cluster_size() as usize;
+            let first_cluster =
+                self.allocate_cluster_chain(content.len().div_ceil(cluster_size) as u32)?;
+            if let Err(e) = self.write_file(first_cluster, &content) {
+                self.free_cluster_chain(first_cluster)?;
+                return Err(e);
+            }
+            first_cluster
+        };
+
+        if let Err(e) =
+            self.update_directory_entry(dir_cluster, filename, first_cluster, content.len() as u32)
+        {
+            // Nothing refers to the new chain yet, the old one is still the file
+            if first_cluster >= 2 {
+                self.free_cluster_chain(first_cluster)?;
+            }
+            return Err(e);
+        }
+        if file.first_cluster >= 2 {
+            self.free_cluster_chain(file.first_cluster)?;
+        }
+
+        Ok(FileEntry {
+            first_cluster,
+            size: content.len() as u32,
+            ..file
+        })
+    }
+
+    /// Add `data` to the end of a file without rewriting it. The free space of the last
+    /// cluster is filled first, new clusters are only linked on once they are written and the
+    /// size in the directory entry is updated last, so a failure leaves the old content.
+    fn append_in_place(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+        file: FileEntry,
+        data: &[u8],
+    ) -> Result<FileEntry, &'static str> {
+        if data.is_empty() {
+            return Ok(file);
+        }
+        let new_size =
+            u32::try_from(file.size as usize + data.len()).map_err(|_| "File too large")?;
+
+        let cluster_size = self.cluster_size() as usize;
+        let sector_size = self.bytes_per_sector as usize;
+        let limit = self.cluster_limit();
+
+        let mut last_cluster = file.first_cluster;
+        for _ in 1..(file.size as usize).div_ceil(cluster_size) {
+            last_cluster = self.get_next_cluster(last_cluster)?;
+            if last_cluster < 2 || last_cluster >= limit {
+                return Err("Cluster chain is shorter than the file");
+            }
+        }
+
+        // The rest of the last cluster, the bytes after the old end aren't part of the file yet
+        let mut written = 0;
+        let mut position = file.size as usize % cluster_size;
+        if position != 0 {
+            let first_sector = self.cluster_to_sector(last_cluster);
+            let mut sector_buffer = [0u8; 512];
+            while position < cluster_size && written < data.len() {
+                let sector = first_sector + (position / sector_size) as u64;
+                let start = position % sector_size;
+                if start == 0 {
+                    sector_buffer.fill(0);
+                } else {
+                    self.disk.read_sector(sector, &mut sector_buffer)?;
+                }
+
+                let count = (sector_size - start).min(data.len() - written);
+                sector_buffer[start..start + count]
+                    .copy_from_slice(&data[written..written + count]);
+                self.disk.write_sector(sector, &sector_buffer)?;
+
+                position += count;
+                written += count;
+            }
+        }
+
+        let rest = &data[written..];
+        let new_chain = if rest.is_empty() {
+            None
+        } else {
+            let first_cluster =
+                self.allocate_cluster_chain(rest.len().div_ceil(cluster_size) as u32)?;
+            if let Err(e) = self
+                .write_file(first_cluster, rest)
+                .and_then(|()| self.update_fat_entry(last_cluster, first_cluster))
+            {
+                self.free_cluster_chain(first_cluster)?;
+                return Err(e);
+            }
+            Some(first_cluster)
+        };
+
+        if let Err(e) =
+            self.update_directory_entry(dir_cluster, filename, file.first_cluster, new_size)
+        {
+            if let Some(first_cluster) = new_chain {
+                self.update_fat_entry(last_cluster, cluster_values::END_OF_CHAIN)?;
+                self.free_cluster_chain(first_cluster)?;
+            }
+            return Err(e);
+        }
+
+        Ok(FileEntry {
+            size: new_size,
+            ..file
+        })
+    }
+
+    /// Convert filename to 8.3 format, in CP437 like [`short_name`] reads it. Characters
+    /// CP437 doesn't have become '_'.
     fn format_filename_8_3(&self, filename: &str) -> [u8; 11] {
         let mut name_8_3 = [0x20u8; 11]; // Fill with spaces
 
-        let filename_upper = filename.to_uppercase();
-        let parts: Vec<&str> = filename_upper.split('.').collect();
+        // Other letters are kept, short names written elsewhere may have them in lowercase
+        let filename_upper = filename.to_ascii_uppercase();
+
+        // A leading dot belongs to the name, like for the hidden trash directory
+        let first = filename_upper.chars().next().map_or(0, char::len_utf8);
+        let (name_part, ext_part) = match filename_upper[first..].find('.') {
+            Some(dot) => (
+                &filename_upper[..first + dot],
+                filename_upper[first + dot + 1..].split('.').next(),
+            ),
+            None => (filename_upper.as_str(), None),
+        };
+
+        let encode = |c| cp437::encode(c).unwrap_or(b'_');
 
         // Handle name part (up to 8 characters)
-        let name_part = parts[0];
-        let name_len = core::cmp::min(name_part.len(), 8);
-        name_8_3[..name_len].copy_from_slice(&name_part.as_bytes()[..name_len]);
+        for (byte, c) in name_8_3[..8].iter_mut().zip(name_part.chars()) {
+            *byte = encode(c);
+        }
 
         // Handle extension part (up to 3 characters)
-        if parts.len() > 1 {
-            let ext_part = parts[1];
-            let ext_len = core::cmp::min(ext_part.len(), 3);
-            name_8_3[8..8 + ext_len].copy_from_slice(&ext_part.as_bytes()[..ext_len]);
+        if let Some(ext_part) = ext_part {
+            for (byte, c) in name_8_3[8..].iter_mut().zip(ext_part.chars()) {
+                *byte = encode(c);
+            }
         }
 
+        if name_8_3[0] == DELETED_ENTRY {
+            name_8_3[0] = ESCAPED_DELETED_BYTE;
+        }
         name_8_3
     }
 
-    /// Create a directory entry
-    fn create_directory_entry(
+    /// Create a new, empty directory and return its first cluster
+    pub fn create_directory(
         &mut self,
-        dir_cluster: u32,
-        filename: &str,
-        first_cluster: u32,
-        file_size: u32,
-        is_directory: bool,
-    ) -> Result<(), &'static str> {
-        let name_8_3 = self.format_filename_8_3(filename);
+        parent_cluster: u32,
+        name: &str,
+    ) -> Result<u32, &'static str> {
+        self.create_directory_with_attributes(parent_cluster, name, 0)
+    }
 
-        // Create the directory entry
-        let entry = DirectoryEntry {
-            name: name_8_3,
-            attributes: if is_directory {
-                attributes::DIRECTORY
-            } else {
-                attributes::ARCHIVE
-            },
+    fn create_directory_with_attributes(
+        &mut self,
+        parent_cluster: u32,
+        name: &str,
+        extra_attributes: u8,
+    ) -> Result<u32, &'static str> {
+        if self.find_file_in_directory(parent_cluster, name)?.is_some() {
+            return Err("File already exists");
+        }
+
+        let cluster = self.allocate_cluster_chain(1)?;
+
+        // Every directory starts with "." and "..", ".." is 0 when the parent is the root
+        let parent = if parent_cluster == self.boot_sector.root_cluster {
+            0
+        } else {
+            parent_cluster
+        };
+
+        let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
+        let mut cluster_buffer = vec![0u8; cluster_size];
+        let entry_size = DirectoryEntry::SIZE;
+
+        for (i, (dot_name, dot_cluster)) in [(*b".          ", cluster), (*b"..         ", parent)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut entry = Self::empty_directory_entry(dot_name, dot_cluster, 0);
+            entry.attributes = attributes::DIRECTORY;
+            cluster_buffer[i * entry_size..(i + 1) * entry_size].copy_from_slice(&entry.to_bytes());
+        }
+
+        self.write_cluster(cluster, &cluster_buffer)?;
+
+        let mut entry = Self::empty_directory_entry(self.format_filename_8_3(name), cluster, 0);
+        entry.attributes = attributes::DIRECTORY | extra_attributes;
+        self.add_directory_entry(parent_cluster, &entry)?;
+
+        Ok(cluster)
+    }
+
+    fn empty_directory_entry(name: [u8; 11], first_cluster: u32, file_size: u32) -> DirectoryEntry {
+        DirectoryEntry {
+            name,
+            attributes: attributes::ARCHIVE,
             reserved: 0,
             creation_time_tenths: 0,
             creation_time: 0,
@@ -591,22 +1651,344 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
             last_write_date: 0,
             first_cluster_low: (first_cluster & 0xFFFF) as u16,
             file_size,
+        }
+    }
+
+    /// Find the raw directory entry for a name
+    fn find_directory_entry(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+    ) -> Result<Option<DirectoryEntry>, &'static str> {
+        let entries = self.read_directory_entries(dir_cluster)?;
+
+        Ok(entries.into_iter().find(|entry| {
+            (entry.attributes & attributes::VOLUME_ID) == 0
+                && self.entry_to_file_entry(entry).name.to_uppercase() == filename.to_uppercase()
+        }))
+    }
+
+    /// Move or rename a file or directory. Only the directory entry is moved,
+    /// the data clusters stay where they are.
+    pub fn move_file(
+        &mut self,
+        src_dir_cluster: u32,
+        src_name: &str,
+        dst_dir_cluster: u32,
+        dst_name: &str,
+    ) -> Result<(), &'static str> {
+        if matches!(src_name, "." | "..") || matches!(dst_name, "." | "..") {
+            return Err("Cannot move . or ..");
+        }
+
+        let mut entry = self
+            .find_directory_entry(src_dir_cluster, src_name)?
+            .ok_or("File not found")?;
+
+        let new_name = self.format_filename_8_3(dst_name);
+        let same_directory = src_dir_cluster == dst_dir_cluster;
+
+        if same_directory && new_name == self.format_filename_8_3(src_name) {
+            return Ok(());
+        }
+
+        if self
+            .find_file_in_directory(dst_dir_cluster, dst_name)?
+            .is_some()
+        {
+            return Err("File already exists in destination");
+        }
+
+        let is_directory = (entry.attributes & attributes::DIRECTORY) != 0;
+        let first_cluster =
+            ((entry.first_cluster_high as u32) << 16) | (entry.first_cluster_low as u32);
+
+        if is_directory && !same_directory && self.is_in_subtree(dst_dir_cluster, first_cluster)? {
+            return Err("Cannot move a directory into itself");
+        }
+
+        entry.name = new_name;
+
+        // Add the new entry first, so a failure can't lose the file
+        self.add_directory_entry(dst_dir_cluster, &entry)?;
+        self.mark_directory_entry_deleted(src_dir_cluster, src_name)?;
+
+        if is_directory && !same_directory {
+            self.set_parent_directory(first_cluster, dst_dir_cluster)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parent of a directory, read from its ".." entry
+    fn parent_directory(&mut self, dir_cluster: u32) -> Result<u32, &'static str> {
+        let parent = self
+            .read_directory_entries(dir_cluster)?
+            .into_iter()
+            .find(|entry| entry.name == *b"..         ")
+            .map(|entry| {
+                ((entry.first_cluster_high as u32) << 16) | (entry.first_cluster_low as u32)
+            })
+            .ok_or("Directory has no parent entry")?;
+
+        // ".." is 0 when the parent is the root
+        Ok(if parent == 0 {
+            self.boot_sector.root_cluster
+        } else {
+            parent
+        })
+    }
+
+    /// Check if a directory is `ancestor` or somewhere below it
+    fn is_in_subtree(&mut self, dir_cluster: u32, ancestor: u32) -> Result<bool, &'static str> {
+        let root = self.boot_sector.root_cluster;
+        let mut current = dir_cluster;
+
+        for _ in 0..=MAX_DIRECTORY_DEPTH {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if current == root {
+                return Ok(false);
+            }
+
+            current = self.parent_directory(current)?;
+        }
+
+        Err("Directory tree is too deep")
+    }
+
+    /// Point the ".." entry of a directory at a new parent
+    fn set_parent_directory(&mut self, dir_cluster: u32, parent: u32) -> Result<(), &'static str> {
+        let parent = if parent == self.boot_sector.root_cluster {
+            0
+        } else {
+            parent
+        };
+
+        let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
+        let entry_size = DirectoryEntry::SIZE;
+        let mut cluster_buffer = vec![0u8; cluster_size];
+        self.read_cluster(dir_cluster, &mut cluster_buffer)?;
+
+        // ".." is always the second entry
+        if cluster_buffer[entry_size..entry_size + 11] != *b"..         " {
+            return Err("Directory has no parent entry");
+        }
+
+        let mut entry = DirectoryEntry::parse(&cluster_buffer[entry_size..]);
+        entry.first_cluster_high = (parent >> 16) as u16;
+        entry.first_cluster_low = (parent & 0xFFFF) as u16;
+        cluster_buffer[entry_size..2 * entry_size].copy_from_slice(&entry.to_bytes());
+
+        self.write_cluster(dir_cluster, &cluster_buffer)
+    }
+
+    /// Cluster of the root directory
+    pub fn root_cluster(&self) -> u32 {
+        self.boot_sector.root_cluster
+    }
+
+    /// Get the trash directory cluster, creating the directory if asked to
+    fn trash_cluster(&mut self, create: bool) -> Result<Option<u32>, &'static str> {
+        let root = self.boot_sector.root_cluster;
+
+        if let Some(trash) = self.find_file_in_directory(root, TRASH_DIRECTORY)? {
+            if !trash.is_directory || trash.first_cluster < 2 {
+                return Err("Trash is not a directory");
+            }
+            return Ok(Some(trash.first_cluster));
+        }
+
+        if !create {
+            return Ok(None);
+        }
+
+        self.create_directory_with_attributes(root, TRASH_DIRECTORY, attributes::HIDDEN)
+            .map(Some)
+    }
+
+    /// Move a file into the trash instead of freeing its clusters. If the trash already has
+    /// a file with that name, it gets a `~1`, `~2`... at the end of its base name. Folders
+    /// can't be trashed.
+    pub fn move_to_trash(&mut self, dir_cluster: u32, filename: &str) -> Result<(), &'static str> {
+        let file = self
+            .find_file_in_directory(dir_cluster, filename)?
+            .ok_or("File not found")?;
+        if file.is_directory {
+            return Err("Folders can't be moved to the trash");
+        }
+
+        let trash = self.trash_cluster(true)?.ok_or("Trash not found")?;
+        if dir_cluster == trash {
+            return Err("File is already in the trash");
+        }
+
+        let trash_name = self.free_trash_name(trash, &file.name)?;
+        self.move_file(dir_cluster, filename, trash, &trash_name)?;
+
+        let mut origins = self.read_trash_index(trash)?;
+        origins.push(TrashOrigin {
+            name: trash_name,
+            directory: dir_cluster,
+            original_name: file.name,
+        });
+        self.write_trash_index(trash, &origins)
+    }
+
+    /// `filename`, or the first free name with a number added to it in the trash
+    fn free_trash_name(&mut self, trash: u32, filename: &str) -> Result<String, &'static str> {
+        let (base, extension) = match filename.rsplit_once('.') {
+            Some((base, extension)) if !base.is_empty() => (base, Some(extension)),
+            _ => (filename, None),
+        };
+
+        let mut name = filename.to_string();
+        for number in 1..=MAX_TRASH_DUPLICATES {
+            let taken = name.eq_ignore_ascii_case(TRASH_INDEX)
+                || self.find_file_in_directory(trash, &name)?.is_some();
+            if !taken {
+                return Ok(name);
+            }
+
+            let suffix = format!("~{}", number);
+            let base: String = base.chars().take(8 - suffix.len()).collect();
+            name = match extension {
+                Some(extension) => format!("{}{}.{}", base, suffix, extension),
+                None => format!("{}{}", base, suffix),
+            };
+        }
+
+        Err("Too many files with that name in the trash")
+    }
+
+    /// Where the files in the trash came from, see [`TRASH_INDEX`]
+    fn read_trash_index(&mut self, trash: u32) -> Result<Vec<TrashOrigin>, &'static str> {
+        let Some(index) = self.find_file_in_directory(trash, TRASH_INDEX)? else {
+            return Ok(Vec::new());
         };
 
+        let data = self.read_file(index.first_cluster, index.size)?;
+        let text = String::from_utf8(data).map_err(|_| "Trash index is not valid UTF-8")?;
+        Ok(text.lines().filter_map(TrashOrigin::parse).collect())
+    }
+
+    fn write_trash_index(
+        &mut self,
+        trash: u32,
+        origins: &[TrashOrigin],
+    ) -> Result<(), &'static str> {
+        if origins.is_empty() {
+            if self.find_file_in_directory(trash, TRASH_INDEX)?.is_some() {
+                self.delete_file(trash, TRASH_INDEX)?;
+            }
+            return Ok(());
+        }
+
+        let text: String = origins.iter().map(TrashOrigin::line).collect();
+        self.write_file_with_flags(
+            trash,
+            TRASH_INDEX,
+            text.as_bytes(),
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )?;
+        Ok(())
+    }
+
+    /// List the files in the trash
+    pub fn list_trash(&mut self) -> Result<Vec<FileEntry>, &'static str> {
+        let Some(trash) = self.trash_cluster(false)? else {
+            return Ok(Vec::new());
+        };
+
+        Ok(self
+            .list_directory(trash)?
+            .into_iter()
+            .filter(|file| !file.is_directory && !file.name.eq_ignore_ascii_case(TRASH_INDEX))
+            .collect())
+    }
+
+    /// Move a file from the trash back to the folder it was trashed from, under its old
+    /// name. Files whose folder is gone go to the root directory.
+    pub fn restore_from_trash(&mut self, filename: &str) -> Result<(), &'static str> {
+        let trash = self.trash_cluster(false)?.ok_or("Trash is empty")?;
+        let root = self.boot_sector.root_cluster;
+
+        let mut origins = self.read_trash_index(trash)?;
+        let origin = origins
+            .iter()
+            .position(|origin| origin.name.eq_ignore_ascii_case(filename))
+            .map(|index| origins.remove(index));
+
+        let (directory, name) = match &origin {
+            Some(origin) if self.is_directory_cluster(origin.directory) => {
+                (origin.directory, origin.original_name.as_str())
+            }
+            Some(origin) => (root, origin.original_name.as_str()),
+            None => (root, filename),
+        };
+
+        self.move_file(trash, filename, directory, name)?;
+        self.write_trash_index(trash, &origins)
+    }
+
+    /// Whether a cluster still starts a directory, it has the ".." entry every directory
+    /// but the root has
+    fn is_directory_cluster(&mut self, cluster: u32) -> bool {
+        cluster == self.boot_sector.root_cluster
+            || (cluster >= 2
+                && cluster < cluster_values::BAD
+                && self.parent_directory(cluster).is_ok())
+    }
+
+    /// Permanently delete everything in the trash
+    pub fn empty_trash(&mut self) -> Result<(), &'static str> {
+        let Some(trash) = self.trash_cluster(false)? else {
+            return Ok(());
+        };
+
+        for file in self.list_directory(trash)? {
+            if !file.is_directory {
+                self.delete_file(trash, &file.name)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a directory entry
+    fn create_directory_entry(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+        first_cluster: u32,
+        file_size: u32,
+        is_directory: bool,
+    ) -> Result<(), &'static str> {
+        let name_8_3 = self.format_filename_8_3(filename);
+
+        // Create the directory entry
+        let mut entry = Self::empty_directory_entry(name_8_3, first_cluster, file_size);
+        if is_directory {
+            entry.attributes = attributes::DIRECTORY;
+        }
+
         // Find an empty slot in the directory
         self.add_directory_entry(dir_cluster, &entry)?;
 
         Ok(())
     }
 
-    /// Add a directory entry to a directory
+    /// Add a directory entry to a directory. Only the sector that holds the new entry is
+    /// written, and a new directory cluster is filled in before the FAT links it, so the
+    /// directory never points at a cluster of garbage.
     fn add_directory_entry(
         &mut self,
         dir_cluster: u32,
         entry: &DirectoryEntry,
     ) -> Result<(), &'static str> {
         let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
-        let entries_per_cluster = cluster_size / mem::size_of::<DirectoryEntry>();
+        let sector_size = self.bytes_per_sector as usize;
         let mut current_cluster = dir_cluster;
 
         loop {
@@ -614,30 +1996,23 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
             let mut cluster_buffer = vec![0u8; cluster_size];
             self.read_cluster(current_cluster, &mut cluster_buffer)?;
 
-            // Look for an empty slot
-            for i in 0..entries_per_cluster {
-                let entry_offset = i * mem::size_of::<DirectoryEntry>();
-                let existing_entry = unsafe {
-                    *(cluster_buffer.as_ptr().add(entry_offset) as *const DirectoryEntry)
-                };
-
-                // Check if this slot is empty (deleted or unused)
-                if existing_entry.name[0] == 0x00 || existing_entry.name[0] == 0xE5 {
-                    // Found an empty slot - write the new entry
-                    let entry_bytes = unsafe {
-                        core::slice::from_raw_parts(
-                            entry as *const DirectoryEntry as *const u8,
-                            mem::size_of::<DirectoryEntry>(),
-                        )
-                    };
-
-                    cluster_buffer[entry_offset..entry_offset + mem::size_of::<DirectoryEntry>()]
-                        .copy_from_slice(entry_bytes);
-
-                    // Write the cluster back
-                    self.write_cluster(current_cluster, &cluster_buffer)?;
-                    return Ok(());
-                }
+            // Look for an empty slot (deleted or unused)
+            let free_slot = DirectorySlots::new(&cluster_buffer)
+                .find(|(_, slot)| matches!(slot, DirectorySlot::Deleted | DirectorySlot::End))
+                .map(|(offset, _)| offset);
+            if let Some(entry_offset) = free_slot {
+                // Found an empty slot - write the new entry
+                cluster_buffer[entry_offset..entry_offset + DirectoryEntry::SIZE]
+                    .copy_from_slice(&entry.to_bytes());
+
+                // Write back the sector with the entry
+                let sector_index = entry_offset / sector_size;
+                let sector_start = sector_index * sector_size;
+                self.disk.write_sector(
+                    self.cluster_to_sector(current_cluster) + sector_index as u64,
+                    &cluster_buffer[sector_start..sector_start + sector_size],
+                )?;
+                return Ok(());
             }
 
             // No empty slot found, try next cluster
@@ -645,23 +2020,16 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
             if next_cluster >= cluster_values::END_OF_CHAIN {
                 // Need to allocate a new cluster for the directory
                 let new_cluster = self.find_free_cluster()?;
-                self.update_fat_entry(current_cluster, new_cluster)?;
-                self.update_fat_entry(new_cluster, cluster_values::END_OF_CHAIN)?;
 
                 // Initialize the new cluster with zeros
                 let mut new_cluster_buffer = vec![0u8; cluster_size];
 
                 // Add the entry to the beginning of the new cluster
-                let entry_bytes = unsafe {
-                    core::slice::from_raw_parts(
-                        entry as *const DirectoryEntry as *const u8,
-                        mem::size_of::<DirectoryEntry>(),
-                    )
-                };
-
-                new_cluster_buffer[..mem::size_of::<DirectoryEntry>()].copy_from_slice(entry_bytes);
+                new_cluster_buffer[..DirectoryEntry::SIZE].copy_from_slice(&entry.to_bytes());
 
                 self.write_cluster(new_cluster, &new_cluster_buffer)?;
+                self.update_fat_entry(new_cluster, cluster_values::END_OF_CHAIN)?;
+                self.update_fat_entry(current_cluster, new_cluster)?;
                 return Ok(());
             }
             current_cluster = next_cluster;
@@ -719,36 +2087,86 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         filename: &str,
     ) -> Result<(), &'static str> {
         let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
-        let entries_per_cluster = cluster_size / mem::size_of::<DirectoryEntry>();
         let mut current_cluster = dir_cluster;
 
         loop {
             let mut cluster_buffer = vec![0u8; cluster_size];
             self.read_cluster(current_cluster, &mut cluster_buffer)?;
 
-            for i in 0..entries_per_cluster {
-                let entry_offset = i * mem::size_of::<DirectoryEntry>();
-                let entry = unsafe {
-                    *(cluster_buffer.as_ptr().add(entry_offset) as *const DirectoryEntry)
+            let mut found = None;
+            for (entry_offset, slot) in DirectorySlots::new(&cluster_buffer) {
+                let entry = match slot {
+                    DirectorySlot::Entry(entry) => entry,
+                    DirectorySlot::End => return Err("File not found in directory"),
+                    DirectorySlot::LongName | DirectorySlot::Deleted => continue,
                 };
 
-                if entry.name[0] == 0x00 {
-                    return Err("File not found in directory");
+                let entry_file = self.entry_to_file_entry(&entry);
+                if entry_file.name.to_uppercase() == filename.to_uppercase() {
+                    found = Some(entry_offset);
+                    break;
                 }
+            }
 
-                if entry.name[0] == 0xE5 || entry.attributes == attributes::LONG_NAME {
-                    continue;
-                }
+            if let Some(entry_offset) = found {
+                // Mark as deleted
+                cluster_buffer[entry_offset] = DELETED_ENTRY;
+                return self.write_cluster(current_cluster, &cluster_buffer);
+            }
 
-                let entry_file = self.entry_to_file_entry(&entry);
-                if entry_file.name.to_uppercase() == filename.to_uppercase() {
-                    // Mark as deleted
-                    cluster_buffer[entry_offset] = 0xE5;
-                    self.write_cluster(current_cluster, &cluster_buffer)?;
-                    return Ok(());
+            let next_cluster = self.get_next_cluster(current_cluster)?;
+            if next_cluster >= cluster_values::END_OF_CHAIN {
+                break;
+            }
+            current_cluster = next_cluster;
+        }
+
+        Err("File not found in directory")
+    }
+
+    /// Point an existing directory entry at a new cluster chain and size
+    fn update_directory_entry(
+        &mut self,
+        dir_cluster: u32,
+        filename: &str,
+        first_cluster: u32,
+        file_size: u32,
+    ) -> Result<(), &'static str> {
+        let cluster_size = (self.sectors_per_cluster * self.bytes_per_sector) as usize;
+        let entry_size = DirectoryEntry::SIZE;
+        let mut current_cluster = dir_cluster;
+
+        loop {
+            let mut cluster_buffer = vec![0u8; cluster_size];
+            self.read_cluster(current_cluster, &mut cluster_buffer)?;
+
+            let mut found = None;
+            for (entry_offset, slot) in DirectorySlots::new(&cluster_buffer) {
+                let entry = match slot {
+                    DirectorySlot::Entry(entry) => entry,
+                    DirectorySlot::End => return Err("File not found in directory"),
+                    DirectorySlot::LongName | DirectorySlot::Deleted => continue,
+                };
+
+                if (entry.attributes & (attributes::VOLUME_ID | attributes::DIRECTORY)) == 0
+                    && self.entry_to_file_entry(&entry).name.to_uppercase()
+                        == filename.to_uppercase()
+                {
+                    found = Some((entry_offset, entry));
+                    break;
                 }
             }
 
+            if let Some((entry_offset, mut entry)) = found {
+                entry.first_cluster_high = (first_cluster >> 16) as u16;
+                entry.first_cluster_low = (first_cluster & 0xFFFF) as u16;
+                entry.file_size = file_size;
+                cluster_buffer[entry_offset..entry_offset + entry_size]
+                    .copy_from_slice(&entry.to_bytes());
+
+                return self.write_cluster(current_cluster, &cluster_buffer);
+            }
+
             let next_cluster = self.get_next_cluster(current_cluster)?;
             if next_cluster >= cluster_values::END_OF_CHAIN {
                 break;
@@ -769,3 +2187,513 @@ impl<D: DiskOperations> Fat32FileSystem<D> {
         self.delete_file(self.boot_sector.root_cluster, filename)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTOR_SIZE: usize = 512;
+    const RESERVED_SECTORS: u16 = 32;
+    const SECTORS_PER_FAT: u32 = 8;
+    const TOTAL_SECTORS: u32 = 1040;
+
+    struct RamDisk {
+        data: Vec<u8>,
+        /// Sectors written so far
+        writes: usize,
+    }
+
+    impl DiskOperations for RamDisk {
+        fn read_sector(&mut self, sector: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+            let start = sector as usize * SECTOR_SIZE;
+            let sector_data = self
+                .data
+                .get(start..start + SECTOR_SIZE)
+                .ok_or("Out of range")?;
+            buffer[..SECTOR_SIZE].copy_from_slice(sector_data);
+            Ok(())
+        }
+
+        fn write_sector(&mut self, sector: u64, buffer: &[u8]) -> Result<(), &'static str> {
+            let start = sector as usize * SECTOR_SIZE;
+            let sector_data = self
+                .data
+                .get_mut(start..start + SECTOR_SIZE)
+                .ok_or("Out of range")?;
+            sector_data.copy_from_slice(&buffer[..SECTOR_SIZE]);
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    /// An empty filesystem with one sector per cluster and a single FAT
+    fn empty_filesystem() -> Fat32FileSystem<RamDisk> {
+        filesystem_with_cluster_sectors(1)
+    }
+
+    fn filesystem_with_cluster_sectors(sectors_per_cluster: u8) -> Fat32FileSystem<RamDisk> {
+        let mut data = vec![0u8; TOTAL_SECTORS as usize * SECTOR_SIZE];
+
+        data[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        data[13] = sectors_per_cluster;
+        data[14..16].copy_from_slice(&RESERVED_SECTORS.to_le_bytes());
+        data[16] = 1; // FAT count
+        data[32..36].copy_from_slice(&TOTAL_SECTORS.to_le_bytes());
+        data[36..40].copy_from_slice(&SECTORS_PER_FAT.to_le_bytes());
+        data[44..48].copy_from_slice(&2u32.to_le_bytes()); // root cluster
+        data[510..512].copy_from_slice(&0xAA55u16.to_le_bytes());
+
+        // Media descriptor, reserved entry and the root directory chain
+        let fat = RESERVED_SECTORS as usize * SECTOR_SIZE;
+        data[fat..fat + 4].copy_from_slice(&0x0FFFFFF8u32.to_le_bytes());
+        data[fat + 4..fat + 8].copy_from_slice(&cluster_values::END_OF_CHAIN.to_le_bytes());
+        data[fat + 8..fat + 12].copy_from_slice(&cluster_values::END_OF_CHAIN.to_le_bytes());
+
+        Fat32FileSystem::new(RamDisk { data, writes: 0 }).expect("test image should be valid")
+    }
+
+    fn content(fs: &mut Fat32FileSystem<RamDisk>, file: &FileEntry) -> Vec<u8> {
+        fs.read_file(file.first_cluster, file.size).unwrap()
+    }
+
+    #[test_case]
+    fn test_parse_little_endian_fields() {
+        let fs = filesystem_with_cluster_sectors(4);
+        assert_eq!(fs.boot_sector.bytes_per_sector, SECTOR_SIZE as u16);
+        assert_eq!(fs.boot_sector.sectors_per_cluster, 4);
+        assert_eq!(fs.boot_sector.total_sectors_32, TOTAL_SECTORS);
+        assert_eq!(fs.root_cluster(), 2);
+        assert!(Fat32BootSector::parse(&[0u8; 100]).is_err());
+
+        let mut bytes = [0u8; DirectoryEntry::SIZE];
+        bytes[..11].copy_from_slice(b"NOTES   TXT");
+        bytes[11] = attributes::ARCHIVE;
+        bytes[20..22].copy_from_slice(&[0x01, 0x00]);
+        bytes[26..28].copy_from_slice(&[0x34, 0x12]);
+        bytes[28..32].copy_from_slice(&[0x00, 0x02, 0x00, 0x00]);
+
+        let entry = DirectoryEntry::parse(&bytes);
+        assert_eq!(entry.first_cluster_high, 1);
+        assert_eq!(entry.first_cluster_low, 0x1234);
+        assert_eq!(entry.file_size, 512);
+        assert_eq!(entry.to_bytes(), bytes);
+    }
+
+    #[test_case]
+    fn test_directory_slots() {
+        let mut buffer = [0u8; 6 * DirectoryEntry::SIZE];
+        let mut set_slot = |index: usize, name: &[u8; 11], attributes: u8| {
+            let offset = index * DirectoryEntry::SIZE;
+            buffer[offset..offset + 11].copy_from_slice(name);
+            buffer[offset + 11] = attributes;
+        };
+        set_slot(0, b"A       TXT", attributes::ARCHIVE);
+        set_slot(1, b"\xE5       TXT", attributes::ARCHIVE);
+        set_slot(2, b"Alon\0g\0na\0m", attributes::LONG_NAME);
+        set_slot(3, b"DIR        ", attributes::DIRECTORY);
+        // Slot 4 ends the directory, slot 5 is never looked at
+        set_slot(5, b"B       TXT", attributes::ARCHIVE);
+
+        let slots: Vec<(usize, DirectorySlot)> = DirectorySlots::new(&buffer).collect();
+        assert_eq!(slots.len(), 5);
+        assert!(matches!(slots[0].1, DirectorySlot::Entry(entry) if entry.name == *b"A       TXT"));
+        assert!(matches!(slots[1].1, DirectorySlot::Deleted));
+        assert!(matches!(slots[2].1, DirectorySlot::LongName));
+        assert!(
+            matches!(slots[3].1, DirectorySlot::Entry(entry) if entry.attributes == attributes::DIRECTORY)
+        );
+        assert!(matches!(slots[4], (128, DirectorySlot::End)));
+
+        // Without an end marker the slots run to the end of the buffer
+        assert_eq!(
+            DirectorySlots::new(&buffer[..4 * DirectoryEntry::SIZE]).count(),
+            4
+        );
+    }
+
+    #[test_case]
+    fn test_open_missing_file() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+
+        assert!(fs.open_file(root, "A.TXT", 0).is_err());
+        assert!(fs.open_file(root, "A.TXT", open_flags::TRUNCATE).is_err());
+
+        let file = fs.open_file(root, "A.TXT", open_flags::CREATE).unwrap();
+        assert_eq!(file.size, 0);
+
+        let flags = open_flags::CREATE | open_flags::EXCLUSIVE;
+        assert!(fs.open_file(root, "B.TXT", flags).is_ok());
+        assert!(fs.find_file_in_root("B.TXT").unwrap().is_some());
+    }
+
+    #[test_case]
+    fn test_open_existing_file() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+        fs.create_file(root, "A.TXT", b"hello").unwrap();
+
+        let flags = open_flags::CREATE | open_flags::EXCLUSIVE;
+        assert!(fs.open_file(root, "A.TXT", flags).is_err());
+
+        let file = fs.open_file(root, "A.TXT", open_flags::CREATE).unwrap();
+        assert_eq!(content(&mut fs, &file), b"hello");
+
+        let file = fs.open_file(root, "A.TXT", open_flags::TRUNCATE).unwrap();
+        assert_eq!(file.size, 0);
+        assert_eq!(fs.find_file_in_root("A.TXT").unwrap().unwrap().size, 0);
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_invalid_open_flags() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+        fs.create_file(root, "A.TXT", b"hello").unwrap();
+        fs.create_directory(root, "DIR").unwrap();
+
+        assert!(fs.open_file(root, "A.TXT", open_flags::EXCLUSIVE).is_err());
+        let flags = open_flags::TRUNCATE | open_flags::APPEND;
+        assert!(fs.open_file(root, "A.TXT", flags).is_err());
+        assert!(fs.open_file(root, "DIR", open_flags::CREATE).is_err());
+    }
+
+    #[test_case]
+    fn test_write_file_with_flags() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+        let long = [b'x'; 1500];
+
+        let file = fs
+            .write_file_with_flags(root, "A.TXT", &long, open_flags::CREATE)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), long);
+
+        let file = fs
+            .write_file_with_flags(root, "A.TXT", b"short", open_flags::TRUNCATE)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), b"short");
+
+        let file = fs
+            .write_file_with_flags(root, "A.TXT", b" text", open_flags::APPEND)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), b"short text");
+
+        let file = fs.write_file_with_flags(root, "A.TXT", b"SH", 0).unwrap();
+        assert_eq!(content(&mut fs, &file), b"SHort text");
+
+        let flags = open_flags::CREATE | open_flags::EXCLUSIVE;
+        assert!(
+            fs.write_file_with_flags(root, "A.TXT", b"new", flags)
+                .is_err()
+        );
+        assert!(fs.write_file_with_flags(root, "B.TXT", b"new", 0).is_err());
+
+        // The old chains were freed and nothing is left behind
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_combined_open_flags() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+        let create_truncate = open_flags::CREATE | open_flags::TRUNCATE;
+
+        // CREATE | TRUNCATE creates a missing file and empties an existing one
+        let file = fs.open_file(root, "A.TXT", create_truncate).unwrap();
+        assert_eq!(file.size, 0);
+        fs.write_file_with_flags(root, "A.TXT", b"hello", 0)
+            .unwrap();
+        let file = fs.open_file(root, "A.TXT", create_truncate).unwrap();
+        assert_eq!(file.size, 0);
+        assert_eq!(fs.find_file_in_root("A.TXT").unwrap().unwrap().size, 0);
+
+        let file = fs
+            .write_file_with_flags(root, "B.TXT", b"new", create_truncate)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), b"new");
+        let file = fs
+            .write_file_with_flags(root, "B.TXT", b"b", create_truncate)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), b"b");
+
+        // CREATE | APPEND on a missing file writes the data as the whole content
+        let flags = open_flags::CREATE | open_flags::APPEND;
+        let file = fs
+            .write_file_with_flags(root, "C.TXT", b"c", flags)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), b"c");
+        let file = fs
+            .write_file_with_flags(root, "C.TXT", b"c", flags)
+            .unwrap();
+        assert_eq!(content(&mut fs, &file), b"cc");
+
+        // open_file leaves the content alone with APPEND
+        let file = fs.open_file(root, "C.TXT", open_flags::APPEND).unwrap();
+        assert_eq!(content(&mut fs, &file), b"cc");
+
+        // EXCLUSIVE | TRUNCATE is rejected without CREATE, with it only a missing file opens
+        let flags = open_flags::EXCLUSIVE | open_flags::TRUNCATE;
+        assert!(fs.open_file(root, "D.TXT", flags).is_err());
+        assert!(fs.open_file(root, "C.TXT", flags).is_err());
+        let flags = flags | open_flags::CREATE;
+        assert!(fs.open_file(root, "C.TXT", flags).is_err());
+        assert_eq!(fs.open_file(root, "D.TXT", flags).unwrap().size, 0);
+        assert_eq!(fs.find_file_in_root("C.TXT").unwrap().unwrap().size, 2);
+
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_append_in_place() {
+        let mut fs = filesystem_with_cluster_sectors(2);
+        let root = fs.root_cluster();
+        let flags = open_flags::CREATE | open_flags::APPEND;
+
+        let mut expected = vec![b'a'; 700];
+        let first = fs
+            .write_file_with_flags(root, "LOG.TXT", &expected, flags)
+            .unwrap();
+
+        // Goes into the second sector, only that one and the directory cluster are written
+        let before = fs.disk_mut().writes;
+        let file = fs
+            .write_file_with_flags(root, "LOG.TXT", b"bb", flags)
+            .unwrap();
+        expected.extend_from_slice(b"bb");
+        assert_eq!(fs.disk_mut().writes - before, 1 + 2);
+        assert_eq!(file.first_cluster, first.first_cluster);
+        assert_eq!(content(&mut fs, &file), expected);
+
+        // Goes past the end of the cluster, a new one is linked on
+        let more = vec![b'c'; 1500];
+        let file = fs
+            .write_file_with_flags(root, "LOG.TXT", &more, flags)
+            .unwrap();
+        expected.extend_from_slice(&more);
+        assert_eq!(file.first_cluster, first.first_cluster);
+        assert_eq!(file.size as usize, expected.len());
+        assert_eq!(content(&mut fs, &file), expected);
+        assert_eq!(fs.cluster_count(file.first_cluster).unwrap(), 3);
+
+        // Ends exactly on a cluster boundary, the next append starts a fresh cluster
+        let fill = vec![b'd'; 3 * 1024 - expected.len()];
+        fs.write_file_with_flags(root, "LOG.TXT", &fill, flags)
+            .unwrap();
+        expected.extend_from_slice(&fill);
+        let file = fs
+            .write_file_with_flags(root, "LOG.TXT", b"e", flags)
+            .unwrap();
+        expected.push(b'e');
+        assert_eq!(content(&mut fs, &file), expected);
+        assert_eq!(fs.cluster_count(file.first_cluster).unwrap(), 4);
+
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_repair_frees_only_lost_clusters() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+        fs.create_file(root, "A.TXT", &[b'a'; 1500]).unwrap();
+        fs.create_file(root, "B.TXT", b"b").unwrap();
+        let a = fs.find_file_in_root("A.TXT").unwrap().unwrap();
+        let b = fs.find_file_in_root("B.TXT").unwrap().unwrap();
+        let a_second = fs.read_fat().unwrap()[a.first_cluster as usize];
+
+        // An orphaned cluster and B running on into the middle of A
+        let orphan = 100;
+        fs.update_fat_entry(orphan, cluster_values::END_OF_CHAIN)
+            .unwrap();
+        fs.update_fat_entry(b.first_cluster, a_second).unwrap();
+
+        let report = fs.check_consistency(true).unwrap();
+        assert_eq!(report.lost_clusters, [orphan]);
+        assert!(report.cross_linked_clusters.contains(&a_second));
+        assert_eq!(report.freed_clusters, 1);
+        assert!(!report.repair_skipped);
+
+        let fat = fs.read_fat().unwrap();
+        assert_eq!(fat[orphan as usize], cluster_values::FREE);
+        assert_eq!(fat[b.first_cluster as usize], a_second);
+        assert_eq!(content(&mut fs, &a), [b'a'; 1500]);
+
+        // With a broken chain, what it pointed to may be live and nothing is freed
+        fs.update_fat_entry(orphan, cluster_values::END_OF_CHAIN)
+            .unwrap();
+        fs.update_fat_entry(a.first_cluster, cluster_values::FREE)
+            .unwrap();
+        let report = fs.check_consistency(true).unwrap();
+        assert_eq!(report.broken_chains.len(), 1);
+        assert!(report.repair_skipped);
+        assert_eq!(report.freed_clusters, 0);
+        assert_ne!(
+            fs.read_fat().unwrap()[orphan as usize],
+            cluster_values::FREE
+        );
+    }
+
+    #[test_case]
+    fn test_trash_keeps_duplicates_and_origins() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+        let dir = fs.create_directory(root, "DIR").unwrap();
+        fs.create_file(root, "A.TXT", b"root").unwrap();
+        fs.create_file(dir, "A.TXT", b"dir").unwrap();
+
+        assert!(fs.move_to_trash(root, "DIR").is_err());
+        fs.move_to_trash(root, "A.TXT").unwrap();
+        fs.move_to_trash(dir, "A.TXT").unwrap();
+
+        let mut names: Vec<String> = fs
+            .list_trash()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["A.TXT", "A~1.TXT"]);
+
+        // Each goes back to the folder it came from, under its old name
+        fs.restore_from_trash("A~1.TXT").unwrap();
+        let file = fs.find_file_in_directory(dir, "A.TXT").unwrap().unwrap();
+        assert_eq!(content(&mut fs, &file), b"dir");
+        fs.restore_from_trash("A.TXT").unwrap();
+        let file = fs.find_file_in_root("A.TXT").unwrap().unwrap();
+        assert_eq!(content(&mut fs, &file), b"root");
+
+        assert!(fs.list_trash().unwrap().is_empty());
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_validate_filename() {
+        assert!(validate_filename("NOTES.TXT").is_ok());
+        assert!(validate_filename("readme").is_ok());
+        assert!(validate_filename("A_B-1.C").is_ok());
+
+        assert!(validate_filename("").is_err());
+        assert!(validate_filename(".TXT").is_err());
+        assert!(validate_filename("TOOLONGNAME.TXT").is_err());
+        assert!(validate_filename("A.TEXT").is_err());
+        assert!(validate_filename("A.B.C").is_err());
+        assert!(validate_filename("MY FILE.TXT").is_err());
+        assert!(validate_filename("A*.TXT").is_err());
+    }
+
+    #[test_case]
+    fn test_benchmark_on_ram_disk() {
+        let mut fs = empty_filesystem();
+        let config = crate::fs::bench::BenchConfig {
+            file_size: 8 * 1024,
+            iterations: 2,
+        };
+
+        let results = crate::fs::bench::run(&mut fs, &config).unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|result| result.bytes == 16 * 1024));
+
+        // The scratch file is gone again
+        assert!(fs.list_root_directory().unwrap().is_empty());
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_files_are_allocated_contiguously() {
+        let mut fs = empty_filesystem();
+        let root = fs.root_cluster();
+
+        for (name, size) in [("A.BIN", 1500), ("B.BIN", 3000), ("C.BIN", 600)] {
+            fs.create_file(root, name, &vec![1u8; size]).unwrap();
+        }
+        fs.delete_file(root, "B.BIN").unwrap();
+
+        // Doesn't fit the gap B left, so it goes behind C in one piece
+        fs.create_file(root, "D.BIN", &vec![2u8; 4000]).unwrap();
+        let c = fs.find_file_in_root("C.BIN").unwrap().unwrap();
+        let d = fs.find_file_in_root("D.BIN").unwrap().unwrap();
+        assert!(d.first_cluster > c.first_cluster);
+
+        for name in ["A.BIN", "C.BIN", "D.BIN"] {
+            let file = fs.find_file_in_root(name).unwrap().unwrap();
+            assert_eq!(fs.average_jump_distance(file.first_cluster).unwrap(), 1);
+        }
+        assert_eq!(content(&mut fs, &d), vec![2u8; 4000]);
+        assert!(fs.check_consistency(false).unwrap().is_clean());
+    }
+
+    #[test_case]
+    fn test_free_cluster_search_across_fat_sectors() {
+        let mut fs = empty_filesystem();
+        let limit = fs.cluster_limit();
+
+        // Clusters 0 to 127 are in the first FAT sector, 128 and up in the next ones
+        for cluster in 2..limit {
+            if ![126, 127, 128, 129, 200, 300].contains(&cluster) {
+                fs.update_fat_entry(cluster, cluster_values::END_OF_CHAIN)
+                    .unwrap();
+            }
+        }
+
+        fs.next_free_hint = 2;
+        assert_eq!(fs.find_free_clusters(4).unwrap(), [126, 127, 128, 129]);
+
+        // No run is long enough, the first free clusters in search order are used
+        fs.next_free_hint = 127;
+        assert_eq!(fs.find_free_clusters(5).unwrap(), [127, 128, 129, 200, 300]);
+        fs.next_free_hint = 200;
+        assert_eq!(fs.find_free_clusters(3).unwrap(), [200, 300, 126]);
+        assert!(fs.find_free_clusters(7).is_err());
+    }
+
+    #[test_case]
+    fn test_directory_entry_writes_one_sector() {
+        let mut fs = filesystem_with_cluster_sectors(4);
+        let root = fs.root_cluster();
+
+        let before = fs.disk_mut().writes;
+        fs.create_file(root, "A.TXT", b"").unwrap();
+        assert_eq!(fs.disk_mut().writes - before, 1);
+        assert!(fs.find_file_in_root("A.TXT").unwrap().is_some());
+    }
+
+    #[test_case]
+    fn test_short_names_are_cp437() {
+        assert_eq!(short_name(b"README  TXT"), "README.TXT");
+        assert_eq!(short_name(b"CAF\x82    TXT"), "CAF\u{e9}.TXT");
+        assert_eq!(
+            short_name(b"\x05\xC9\xCD\xBB       "),
+            "\u{3c3}\u{2554}\u{2550}\u{2557}"
+        );
+
+        let fs = empty_filesystem();
+        assert_eq!(fs.format_filename_8_3("caf\u{e9}.txt"), *b"CAF\x82    TXT");
+        assert_eq!(fs.format_filename_8_3("\u{c9}TUDE.TXT"), *b"\x90TUDE   TXT");
+        assert_eq!(fs.format_filename_8_3(".TRASH"), *b".TRASH     ");
+        assert_eq!(
+            fs.format_filename_8_3("\u{3c3}\u{20ac}"),
+            *b"\x05_         "
+        );
+    }
+
+    #[test_case]
+    fn test_cluster_chain_lists_fragments() {
+        let mut fs = empty_filesystem();
+
+        for (cluster, next) in [(10, 11), (11, 12), (12, 20), (20, 21)] {
+            fs.update_fat_entry(cluster, next).unwrap();
+        }
+        fs.update_fat_entry(21, cluster_values::END_OF_CHAIN)
+            .unwrap();
+
+        let chain = fs.cluster_chain(10, 8).unwrap();
+        assert_eq!(chain.length, 5);
+        assert_eq!(chain.fragments, 2);
+        assert_eq!(chain.runs, vec![(10, 3), (20, 2)]);
+        assert!(!chain.incomplete);
+        assert_eq!(fs.cluster_chain(10, 1).unwrap().runs, vec![(10, 3)]);
+
+        fs.update_fat_entry(21, 10).unwrap();
+        assert!(fs.cluster_chain(10, 8).unwrap().incomplete);
+    }
+}
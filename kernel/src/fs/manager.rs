@@ -1,55 +1,77 @@
-use crate::fs::disk::AtaDisk;
-use crate::fs::fat32::{Fat32FileSystem, FileEntry};
+use crate::fs::bench::{self, BenchConfig, BenchResult};
+use crate::fs::disk::{AtaDisk, DISK_BUSY, wait_async_read};
+use crate::fs::fat32::{
+    ClusterChain, ConsistencyReport, DirectorySize, Fat32FileSystem, FileCursor, FileEntry,
+};
+use crate::fs::fat32::{DiskOperations, cluster_values};
+use crate::lock_order::{LockLevel, OrderedMutex};
+use crate::serial::LogLevel;
+use crate::task::yield_now;
+use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
-use spin::Mutex;
 use x86_64::instructions::interrupts;
 
+/// Error of the `try_*` functions when another operation holds the filesystem
+pub const FILESYSTEM_BUSY: &str = "Filesystem is busy";
+
 lazy_static! {
-    pub static ref FILESYSTEM: Mutex<Option<Fat32FileSystem<AtaDisk>>> = Mutex::new(None);
+    pub static ref FILESYSTEM: OrderedMutex<Option<Fat32FileSystem<AtaDisk>>> =
+        OrderedMutex::new(LockLevel::Filesystem, None);
+}
+
+/// A FAT32 filesystem was found by [`init_filesystem`]
+static MOUNTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether there is a filesystem, the desktop works without one but can't open or save files
+pub fn is_mounted() -> bool {
+    MOUNTED.load(Ordering::Relaxed)
 }
 
-/// Initialize the filesystem
+/// Initialize the filesystem. It can be called again after it failed, to retry.
 pub fn init_filesystem() -> Result<(), &'static str> {
-    crate::serial_println!("Initializing filesystem...");
+    crate::log!(LogLevel::Info, "Initializing filesystem...");
 
     // Try primary master first (drive 0)
-    crate::serial_println!("Trying primary master drive (0)...");
+    crate::log!(LogLevel::Info, "Trying primary master drive (0)...");
     let mut disk = AtaDisk::new_primary(0);
     if let Ok(_) = disk.init() {
-        crate::serial_println!("Primary master initialized successfully");
+        crate::log!(LogLevel::Info, "Primary master initialized successfully");
         match Fat32FileSystem::new(disk) {
             Ok(filesystem) => {
-                crate::serial_println!("FAT32 filesystem found on primary master");
+                crate::log!(LogLevel::Info, "FAT32 filesystem found on primary master");
                 *FILESYSTEM.lock() = Some(filesystem);
+                MOUNTED.store(true, Ordering::Relaxed);
                 return Ok(());
             }
             Err(e) => {
-                crate::serial_println!("Primary master is not FAT32: {}", e);
+                crate::log!(LogLevel::Warn, "Primary master is not FAT32: {}", e);
             }
         }
     } else {
-        crate::serial_println!("Failed to initialize primary master");
+        crate::log!(LogLevel::Warn, "Failed to initialize primary master");
     }
 
     // Try primary slave (drive 1)
-    crate::serial_println!("Trying primary slave drive (1)...");
+    crate::log!(LogLevel::Info, "Trying primary slave drive (1)...");
     let mut disk = AtaDisk::new_primary(1);
     if let Ok(_) = disk.init() {
-        crate::serial_println!("Primary slave initialized successfully");
+        crate::log!(LogLevel::Info, "Primary slave initialized successfully");
         match Fat32FileSystem::new(disk) {
             Ok(filesystem) => {
-                crate::serial_println!("FAT32 filesystem found on primary slave");
+                crate::log!(LogLevel::Info, "FAT32 filesystem found on primary slave");
                 *FILESYSTEM.lock() = Some(filesystem);
+                MOUNTED.store(true, Ordering::Relaxed);
                 return Ok(());
             }
             Err(e) => {
-                crate::serial_println!("Primary slave is not FAT32: {}", e);
+                crate::log!(LogLevel::Warn, "Primary slave is not FAT32: {}", e);
             }
         }
     } else {
-        crate::serial_println!("Failed to initialize primary slave");
+        crate::log!(LogLevel::Warn, "Failed to initialize primary slave");
     }
 
     Err("No FAT32 filesystem found on any drive")
@@ -64,6 +86,105 @@ pub fn list_root_files() -> Result<Vec<FileEntry>, &'static str> {
     }
 }
 
+/// Run `f` on the filesystem, waiting for it if another operation holds it
+fn with_filesystem<T>(
+    f: impl FnOnce(&mut Fat32FileSystem<AtaDisk>) -> Result<T, &'static str>,
+) -> Result<T, &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => f(fs),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Run `f` on the filesystem if it's free right now, fails with [`FILESYSTEM_BUSY`] instead
+/// of waiting. For UI code that would rather try again next frame than stall.
+fn try_with_filesystem<T>(
+    f: impl FnOnce(&mut Fat32FileSystem<AtaDisk>) -> Result<T, &'static str>,
+) -> Result<T, &'static str> {
+    let mut fs_guard = FILESYSTEM.try_lock().ok_or(FILESYSTEM_BUSY)?;
+    match fs_guard.as_mut() {
+        Some(fs) => f(fs),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// [`list_root_files`] without waiting for the filesystem
+pub fn try_list_root_files() -> Result<Vec<FileEntry>, &'static str> {
+    try_with_filesystem(|fs| fs.list_root_directory())
+}
+
+/// [`list_directory_files`] without waiting for the filesystem
+pub fn try_list_directory_files(cluster: u32) -> Result<Vec<FileEntry>, &'static str> {
+    try_with_filesystem(|fs| fs.list_directory(cluster))
+}
+
+/// [`list_trash_files`] without waiting for the filesystem
+pub fn try_list_trash_files() -> Result<Vec<FileEntry>, &'static str> {
+    try_with_filesystem(|fs| fs.list_trash())
+}
+
+/// [`read_file_chunk`] without waiting for the filesystem, the cursor only moves on success
+pub fn try_read_file_chunk(cursor: &mut FileCursor) -> Result<Vec<u8>, &'static str> {
+    try_with_filesystem(|fs| fs.read_file_chunk(cursor))
+}
+
+/// [`write_file_with_flags`] in the root directory without waiting for the filesystem
+pub fn try_write_root_file(filename: &str, data: &[u8], flags: u32) -> Result<(), &'static str> {
+    try_with_filesystem(|fs| {
+        let root = fs.root_cluster();
+        fs.write_file_with_flags(root, filename, data, flags)
+            .map(|_| ())
+    })
+}
+
+/// Rename a root file to `old_name` without waiting for the filesystem, an earlier file
+/// with that name is deleted
+pub fn try_rotate_root_file(filename: &str, old_name: &str) -> Result<(), &'static str> {
+    try_with_filesystem(|fs| {
+        let root = fs.root_cluster();
+        if fs.find_file_in_directory(root, old_name)?.is_some() {
+            fs.delete_file(root, old_name)?;
+        }
+        fs.move_file(root, filename, root, old_name)
+    })
+}
+
+/// Run `f` on the disk under the filesystem if it's free right now, for writes that go
+/// around the filesystem. Doesn't allocate, the crash path uses it.
+pub fn try_with_disk<T>(
+    f: impl FnOnce(&mut AtaDisk) -> Result<T, &'static str>,
+) -> Result<T, &'static str> {
+    try_with_filesystem(|fs| f(fs.disk_mut()))
+}
+
+/// Time reads and writes on the mounted disk, see [`bench`]
+pub fn run_benchmark(config: &BenchConfig) -> Result<Vec<BenchResult>, &'static str> {
+    with_filesystem(|fs| bench::run(fs, config))
+}
+
+/// Make sure everything written so far survives a reset, by flushing the drive's write
+/// cache. The filesystem itself writes through. Returns the sectors flushed.
+pub fn sync() -> Result<u32, &'static str> {
+    with_filesystem(|fs| fs.disk_mut().flush())
+}
+
+/// [`sync`] without waiting for the filesystem
+pub fn try_sync() -> Result<u32, &'static str> {
+    try_with_filesystem(|fs| fs.disk_mut().flush())
+}
+
+/// Get the volume label, empty if the volume has none
+pub fn volume_label() -> Result<String, &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => Ok(fs.volume_label()),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
 /// List files in a directory by cluster
 pub fn list_directory_files(cluster: u32) -> Result<Vec<FileEntry>, &'static str> {
     let mut fs_guard = FILESYSTEM.lock();
@@ -94,8 +215,100 @@ pub fn find_file_in_directory(
     }
 }
 
+/// Look up a path like `/DOCS/NOTES.TXT` from the root directory. Gives the entry and the
+/// cluster of the directory it is in, `None` is the root directory itself.
+pub fn resolve_path(path: &str) -> Result<Option<(FileEntry, u32)>, &'static str> {
+    let mut directory = root_directory_cluster()?;
+    let mut found: Option<(FileEntry, u32)> = None;
+
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        if let Some((entry, _)) = &found {
+            if !entry.is_directory {
+                return Err("Not a directory");
+            }
+            directory = entry.first_cluster;
+        }
+        let entry = find_file_in_directory(directory, name)?.ok_or("File not found")?;
+        found = Some((entry, directory));
+    }
+
+    Ok(found)
+}
+
+/// List the directory at a path, see [`resolve_path`]
+pub fn list_path(path: &str) -> Result<Vec<FileEntry>, &'static str> {
+    match resolve_path(path)? {
+        None => list_root_files(),
+        Some((entry, _)) if entry.is_directory => list_directory_files(entry.first_cluster),
+        Some(_) => Err("Not a directory"),
+    }
+}
+
+/// Read the text file at a path, see [`resolve_path`]
+pub fn read_text_path(path: &str) -> Result<String, &'static str> {
+    match resolve_path(path)? {
+        Some((entry, _)) if !entry.is_directory => read_text_file(entry.first_cluster, entry.size),
+        _ => Err("Not a file"),
+    }
+}
+
+/// Recursively sum the sizes of the files below a directory
+pub fn directory_size(dir_cluster: u32) -> Result<DirectorySize, &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => fs.directory_size(dir_cluster),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// Recursively sum the sizes of all files on the volume
+pub fn root_directory_size() -> Result<DirectorySize, &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => fs.root_directory_size(),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// Get the number of clusters used by a file and the size of one cluster
+pub fn file_clusters(first_cluster: u32) -> Result<(u32, u32), &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => Ok((fs.cluster_count(first_cluster)?, fs.cluster_size())),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// Get the runs of contiguous clusters a file is stored in, see
+/// [`Fat32FileSystem::cluster_chain`]
+pub fn cluster_chain(first_cluster: u32, max_runs: usize) -> Result<ClusterChain, &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => fs.cluster_chain(first_cluster, max_runs),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// Get the sectors that hold the first `size` bytes of a file, see
+/// [`Fat32FileSystem::file_sectors`]
+pub fn file_sectors(first_cluster: u32, size: u32) -> Result<Vec<u64>, &'static str> {
+    with_filesystem(|fs| fs.file_sectors(first_cluster, size))
+}
+
+/// Largest file [`read_file`], [`read_text_file`] and [`read_file_async`] load at once,
+/// bigger files have to be read with [`read_file_chunk`]
+static MAX_WHOLE_FILE_SIZE: AtomicU32 = AtomicU32::new(4 * 1024 * 1024);
+
+pub fn set_max_whole_file_size(size: u32) {
+    MAX_WHOLE_FILE_SIZE.store(size, Ordering::Relaxed);
+}
+
 /// Read a file's content
 pub fn read_file(first_cluster: u32, file_size: u32) -> Result<Vec<u8>, &'static str> {
+    if file_size > MAX_WHOLE_FILE_SIZE.load(Ordering::Relaxed) {
+        return Err("File is too large to load at once");
+    }
+
     let mut fs_guard = FILESYSTEM.lock();
     match fs_guard.as_mut() {
         Some(fs) => fs.read_file(first_cluster, file_size),
@@ -103,6 +316,15 @@ pub fn read_file(first_cluster: u32, file_size: u32) -> Result<Vec<u8>, &'static
     }
 }
 
+/// Read the next piece (one cluster) of a file, see [`FileCursor`]
+pub fn read_file_chunk(cursor: &mut FileCursor) -> Result<Vec<u8>, &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => fs.read_file_chunk(cursor),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
 /// Read a text file and return it as a string
 pub fn read_text_file(first_cluster: u32, file_size: u32) -> Result<String, &'static str> {
     let data = read_file(first_cluster, file_size)?;
@@ -112,6 +334,56 @@ pub fn read_text_file(first_cluster: u32, file_size: u32) -> Result<String, &'st
     }
 }
 
+/// Read a whole file without keeping the caller busy while the drive works. Every sector
+/// waits for the disk interrupt, the filesystem is only locked between the transfers.
+/// The number of bytes read so far is kept in `progress`, for a progress bar.
+pub async fn read_file_async(
+    first_cluster: u32,
+    file_size: u32,
+    progress: &AtomicUsize,
+) -> Result<Vec<u8>, &'static str> {
+    if file_size > MAX_WHOLE_FILE_SIZE.load(Ordering::Relaxed) {
+        return Err("File is too large to load at once");
+    }
+
+    let file_size = file_size as usize;
+    let mut data = Vec::new();
+    data.try_reserve_exact(file_size)
+        .map_err(|_| "Not enough memory to load the file")?;
+    let mut cluster = first_cluster;
+
+    while data.len() < file_size {
+        if !(2..cluster_values::BAD).contains(&cluster) {
+            return Err("Unexpected end of cluster chain");
+        }
+
+        for sector in with_filesystem(|fs| Ok(fs.cluster_sectors(cluster)))? {
+            if data.len() >= file_size {
+                break;
+            }
+
+            // Someone else's async read has to be picked up first
+            loop {
+                match with_filesystem(|fs| fs.disk_mut().start_async_read(sector)) {
+                    Err(DISK_BUSY) => yield_now().await,
+                    result => break result?,
+                }
+            }
+
+            let buffer = wait_async_read().await?;
+            let length = (file_size - data.len()).min(buffer.len());
+            data.extend_from_slice(&buffer[..length]);
+            progress.store(data.len(), Ordering::Relaxed);
+        }
+
+        if data.len() < file_size {
+            cluster = with_filesystem(|fs| fs.get_next_cluster(cluster))?;
+        }
+    }
+
+    Ok(data)
+}
+
 /// Create a new file in the root directory
 pub fn create_file_in_root(filename: &str, data: &[u8]) -> Result<(), &'static str> {
     interrupts::without_interrupts(|| {
@@ -138,12 +410,106 @@ pub fn create_file_in_directory(
     })
 }
 
+/// Create an empty directory in a specific directory, returns its cluster
+pub fn create_directory_in_directory(dir_cluster: u32, name: &str) -> Result<u32, &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => fs.create_directory(dir_cluster, name),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Open or create a file according to [`open_flags`](crate::fs::fat32::open_flags)
+pub fn open_file(dir_cluster: u32, filename: &str, flags: u32) -> Result<FileEntry, &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => fs.open_file(dir_cluster, filename, flags),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Open a file like [`open_file`] and write to it, the old content stays intact if this fails
+pub fn write_file_with_flags(
+    dir_cluster: u32,
+    filename: &str,
+    data: &[u8],
+    flags: u32,
+) -> Result<FileEntry, &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => fs.write_file_with_flags(dir_cluster, filename, data, flags),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Error of deleting a file that an app has open, see [`OpenFileRef`]
+pub const FILE_IN_USE: &str = "File is in use, close it first";
+
+/// How many times each file is open, by first cluster. Deleting an open file would free
+/// the clusters its app still reads and saves to.
+static OPEN_FILES: spin::Mutex<BTreeMap<u32, usize>> = spin::Mutex::new(BTreeMap::new());
+
+/// Keeps a file from being deleted while an app has it open, until it is dropped. Files
+/// without clusters aren't tracked, there is nothing of them to free.
+pub struct OpenFileRef {
+    first_cluster: u32,
+}
+
+impl OpenFileRef {
+    pub fn new(first_cluster: u32) -> Self {
+        if first_cluster != 0 {
+            *OPEN_FILES.lock().entry(first_cluster).or_insert(0) += 1;
+        }
+        Self { first_cluster }
+    }
+}
+
+impl Drop for OpenFileRef {
+    fn drop(&mut self) {
+        let mut open_files = OPEN_FILES.lock();
+        if let Some(count) = open_files.get_mut(&self.first_cluster) {
+            *count -= 1;
+            if *count == 0 {
+                open_files.remove(&self.first_cluster);
+            }
+        }
+    }
+}
+
+pub fn is_file_open(first_cluster: u32) -> bool {
+    OPEN_FILES.lock().contains_key(&first_cluster)
+}
+
+/// Delete a file unless it is open
+fn delete_closed_file(
+    fs: &mut Fat32FileSystem<AtaDisk>,
+    dir_cluster: u32,
+    filename: &str,
+) -> Result<(), &'static str> {
+    let file = fs
+        .find_file_in_directory(dir_cluster, filename)?
+        .ok_or("File not found")?;
+    if is_file_open(file.first_cluster) {
+        return Err(FILE_IN_USE);
+    }
+    fs.delete_file(dir_cluster, filename)
+}
+
 /// Delete a file from the root directory
 pub fn delete_file_from_root(filename: &str) -> Result<(), &'static str> {
     interrupts::without_interrupts(|| {
         let mut fs_guard = FILESYSTEM.lock();
         match fs_guard.as_mut() {
-            Some(fs) => fs.delete_file_from_root(filename),
+            Some(fs) => {
+                let root = fs.root_cluster();
+                delete_closed_file(fs, root, filename)
+            }
             None => Err("Filesystem not initialized"),
         }
     })
@@ -154,7 +520,109 @@ pub fn delete_file_from_directory(dir_cluster: u32, filename: &str) -> Result<()
     interrupts::without_interrupts(|| {
         let mut fs_guard = FILESYSTEM.lock();
         match fs_guard.as_mut() {
-            Some(fs) => fs.delete_file(dir_cluster, filename),
+            Some(fs) => delete_closed_file(fs, dir_cluster, filename),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Look for lost, cross-linked and broken cluster chains. With `repair`, lost clusters
+/// are freed.
+pub fn check_filesystem(repair: bool) -> Result<ConsistencyReport, &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        let report = match fs_guard.as_mut() {
+            Some(fs) => fs.check_consistency(repair)?,
+            None => return Err("Filesystem not initialized"),
+        };
+        drop(fs_guard);
+
+        crate::log!(
+            LogLevel::Info,
+            "Filesystem check: {} files, {} folders, {} lost, {} cross-linked, {} broken, {} size mismatches",
+            report.files_checked,
+            report.directories_checked,
+            report.lost_clusters.len(),
+            report.cross_linked_clusters.len(),
+            report.broken_chains.len(),
+            report.size_mismatches.len()
+        );
+        for path in report.broken_chains.iter().chain(&report.size_mismatches) {
+            crate::log!(LogLevel::Warn, "Filesystem check: problem with {}", path);
+        }
+
+        Ok(report)
+    })
+}
+
+/// Cluster of the root directory
+pub fn root_directory_cluster() -> Result<u32, &'static str> {
+    let fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_ref() {
+        Some(fs) => Ok(fs.root_cluster()),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// Move or rename a file or directory without copying its data
+pub fn move_file(
+    src_dir_cluster: u32,
+    src_name: &str,
+    dst_dir_cluster: u32,
+    dst_name: &str,
+) -> Result<(), &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => fs.move_file(src_dir_cluster, src_name, dst_dir_cluster, dst_name),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Move a file from a directory into the trash
+pub fn move_to_trash(dir_cluster: u32, filename: &str) -> Result<(), &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => fs.move_to_trash(dir_cluster, filename),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// List the files in the trash
+pub fn list_trash_files() -> Result<Vec<FileEntry>, &'static str> {
+    let mut fs_guard = FILESYSTEM.lock();
+    match fs_guard.as_mut() {
+        Some(fs) => fs.list_trash(),
+        None => Err("Filesystem not initialized"),
+    }
+}
+
+/// Move a file from the trash back to the folder it came from, or the root directory
+pub fn restore_from_trash(filename: &str) -> Result<(), &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => fs.restore_from_trash(filename),
+            None => Err("Filesystem not initialized"),
+        }
+    })
+}
+
+/// Permanently delete everything in the trash, nothing is deleted if a file in it is open
+pub fn empty_trash() -> Result<(), &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut fs_guard = FILESYSTEM.lock();
+        match fs_guard.as_mut() {
+            Some(fs) => {
+                let trash = fs.list_trash()?;
+                if trash.iter().any(|file| is_file_open(file.first_cluster)) {
+                    return Err(FILE_IN_USE);
+                }
+                fs.empty_trash()
+            }
             None => Err("Filesystem not initialized"),
         }
     })
@@ -1,3 +1,5 @@
+pub mod bench;
+pub mod cp437;
 pub mod disk;
 pub mod fat32;
 pub mod manager;
@@ -1,34 +1,118 @@
 use lazy_static::lazy_static;
 use x86_64::VirtAddr;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::paging::{Mapper, Page, Size4KiB};
 use x86_64::structures::tss::TaskStateSegment;
 
-const STACK_SIZE: usize = 4096 * 5;
+use crate::{log, serial::LogLevel};
+
+const PAGE_SIZE: usize = 4096;
+/// Stack the CPU switches to when an interrupt arrives in ring 3
+const PRIVILEGE_STACK_SIZE: usize = PAGE_SIZE * 5;
+/// The double fault handler writes a crash report, give it the most room
+pub const DOUBLE_FAULT_STACK_SIZE: usize = PAGE_SIZE * 8;
+pub const FAULT_STACK_SIZE: usize = PAGE_SIZE * 5;
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 pub const PAGE_FAULT_IST_INDEX: u16 = 1;
 pub const GENERAL_PROTECTION_FAULT_IST_INDEX: u16 = 2;
+pub const STACK_SEGMENT_FAULT_IST_INDEX: u16 = 3;
+pub const INVALID_TSS_IST_INDEX: u16 = 4;
+
+/// An interrupt stack with an unmapped guard page below it, see [`protect_ist_stacks`]
+#[repr(C, align(4096))]
+struct IstStack<const SIZE: usize> {
+    guard: [u8; PAGE_SIZE],
+    stack: [u8; SIZE],
+}
+
+impl<const SIZE: usize> IstStack<SIZE> {
+    const fn new() -> Self {
+        Self {
+            guard: [0; PAGE_SIZE],
+            stack: [0; SIZE],
+        }
+    }
+}
+
+static mut DOUBLE_FAULT_STACK: IstStack<DOUBLE_FAULT_STACK_SIZE> = IstStack::new();
+static mut PAGE_FAULT_STACK: IstStack<FAULT_STACK_SIZE> = IstStack::new();
+static mut GENERAL_PROTECTION_FAULT_STACK: IstStack<FAULT_STACK_SIZE> = IstStack::new();
+static mut STACK_SEGMENT_FAULT_STACK: IstStack<FAULT_STACK_SIZE> = IstStack::new();
+static mut INVALID_TSS_STACK: IstStack<FAULT_STACK_SIZE> = IstStack::new();
+
+/// Name and guard page address of each IST stack
+fn ist_guard_pages() -> [(&'static str, VirtAddr); 5] {
+    [
+        (
+            "double fault",
+            VirtAddr::from_ptr(&raw const DOUBLE_FAULT_STACK),
+        ),
+        (
+            "page fault",
+            VirtAddr::from_ptr(&raw const PAGE_FAULT_STACK),
+        ),
+        (
+            "general protection fault",
+            VirtAddr::from_ptr(&raw const GENERAL_PROTECTION_FAULT_STACK),
+        ),
+        (
+            "stack segment fault",
+            VirtAddr::from_ptr(&raw const STACK_SEGMENT_FAULT_STACK),
+        ),
+        (
+            "invalid TSS",
+            VirtAddr::from_ptr(&raw const INVALID_TSS_STACK),
+        ),
+    ]
+}
+
+/// The IST stack whose guard page contains `address`, a fault there means the stack overflowed
+pub fn overflowed_ist_stack(address: VirtAddr) -> Option<&'static str> {
+    ist_guard_pages()
+        .into_iter()
+        .find(|&(_, guard)| (guard..guard + PAGE_SIZE as u64).contains(&address))
+        .map(|(name, _)| name)
+}
+
+/// Unmap the guard pages, so running off the end of an IST stack faults instead of
+/// silently overwriting whatever lies below it
+pub fn protect_ist_stacks(mapper: &mut impl Mapper<Size4KiB>) {
+    for (name, guard) in ist_guard_pages() {
+        let page = Page::<Size4KiB>::containing_address(guard);
+        match mapper.unmap(page) {
+            Ok((_, flush)) => flush.flush(),
+            Err(e) => log!(
+                LogLevel::Warn,
+                "No guard page for the {} stack: {:?}",
+                name,
+                e
+            ),
+        }
+    }
+}
+
+fn stack_top<const SIZE: usize>(stack: *const IstStack<SIZE>) -> VirtAddr {
+    VirtAddr::from_ptr(stack) + size_of::<IstStack<SIZE>>() as u64
+}
 
 lazy_static! {
     pub static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         tss.privilege_stack_table[0] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(&raw const STACK) + STACK_SIZE as u64
-        };
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(&raw const STACK);
-            stack_start + STACK_SIZE as u64
-        };
-        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(&raw const STACK) + STACK_SIZE as u64
-        };
-        tss.interrupt_stack_table[GENERAL_PROTECTION_FAULT_IST_INDEX as usize] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(&raw const STACK) + STACK_SIZE as u64
+            static mut STACK: [u8; PRIVILEGE_STACK_SIZE] = [0; PRIVILEGE_STACK_SIZE];
+            VirtAddr::from_ptr(&raw const STACK) + PRIVILEGE_STACK_SIZE as u64
         };
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            stack_top(&raw const DOUBLE_FAULT_STACK);
+        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] =
+            stack_top(&raw const PAGE_FAULT_STACK);
+        tss.interrupt_stack_table[GENERAL_PROTECTION_FAULT_IST_INDEX as usize] =
+            stack_top(&raw const GENERAL_PROTECTION_FAULT_STACK);
+        tss.interrupt_stack_table[STACK_SEGMENT_FAULT_IST_INDEX as usize] =
+            stack_top(&raw const STACK_SEGMENT_FAULT_STACK);
+        tss.interrupt_stack_table[INVALID_TSS_IST_INDEX as usize] =
+            stack_top(&raw const INVALID_TSS_STACK);
         tss
     };
     pub static ref GDT: (GlobalDescriptorTable, Selectors) = {
@@ -0,0 +1,145 @@
+//! Small icons, stored in a run-length encoded format and decoded on demand.
+//!
+//! The format (all values are single bytes):
+//!
+//! ```text
+//! magic         "RLEI"
+//! width         1..=255
+//! height        1..=255
+//! palette_len   1..=255
+//! palette       palette_len * [r, g, b]
+//! runs          [count, palette index] pairs, count >= 1, left to right and top to bottom
+//! ```
+//!
+//! Palette index 0 is transparent and gets replaced by the background color passed to the
+//! decoder. Decoding stops after `width * height` pixels, so the work is bounded by the header.
+//! Truncated data is padded with the background color and runs that overflow the image are cut.
+
+use alloc::{vec, vec::Vec};
+
+use crate::{bmp::Bitmap, framebuffer::Color, surface::Shape};
+
+const MAGIC: &[u8; 4] = b"RLEI";
+const HEADER_SIZE: usize = MAGIC.len() + 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Icon {
+    File,
+    TextFile,
+    ImageFile,
+    Folder,
+    Calculator,
+    Keyboard,
+    Paint,
+    Minesweeper,
+    SysInfo,
+    Log,
+}
+
+impl Icon {
+    fn data(self) -> &'static [u8] {
+        match self {
+            Icon::File => include_bytes!("../assets/icons/file.rle"),
+            Icon::TextFile => include_bytes!("../assets/icons/text.rle"),
+            Icon::ImageFile => include_bytes!("../assets/icons/image.rle"),
+            Icon::Folder => include_bytes!("../assets/icons/folder.rle"),
+            Icon::Calculator => include_bytes!("../assets/icons/calculator.rle"),
+            Icon::Keyboard => include_bytes!("../assets/icons/keyboard.rle"),
+            Icon::Paint => include_bytes!("../assets/icons/paint.rle"),
+            Icon::Minesweeper => include_bytes!("../assets/icons/minesweeper.rle"),
+            Icon::SysInfo => include_bytes!("../assets/icons/sysinfo.rle"),
+            Icon::Log => include_bytes!("../assets/icons/log.rle"),
+        }
+    }
+
+    /// The icon that best fits a file name
+    pub fn for_file(name: &str, is_directory: bool) -> Self {
+        if is_directory {
+            return Icon::Folder;
+        }
+
+        let name = name.to_ascii_lowercase();
+        if name.ends_with(".txt") {
+            Icon::TextFile
+        } else if name.ends_with(".bmp") {
+            Icon::ImageFile
+        } else {
+            Icon::File
+        }
+    }
+
+    /// Decode the icon, a broken icon is drawn as an empty square
+    pub fn bitmap(self, background: Color) -> Bitmap {
+        decode(self.data(), background).unwrap_or_else(|_| Bitmap {
+            width: 16,
+            height: 16,
+            pixels: vec![background; 16 * 16],
+        })
+    }
+
+    /// An image shape showing the icon at the given position
+    pub fn shape(self, x: usize, y: usize, background: Color, hide: bool) -> Shape {
+        let bitmap = self.bitmap(background);
+
+        Shape::Image {
+            x,
+            y,
+            width: bitmap.width,
+            height: bitmap.height,
+            pixels: bitmap.pixels,
+            hide,
+        }
+    }
+}
+
+/// Decode a run-length encoded icon, see the module documentation for the format
+pub fn decode(data: &[u8], background: Color) -> Result<Bitmap, &'static str> {
+    if data.len() < HEADER_SIZE || !data.starts_with(MAGIC) {
+        return Err("Not an RLE icon");
+    }
+
+    let width = data[4] as usize;
+    let height = data[5] as usize;
+    let palette_len = data[6] as usize;
+    if width == 0 || height == 0 || palette_len == 0 {
+        return Err("Invalid RLE icon header");
+    }
+
+    let palette_end = HEADER_SIZE + palette_len * 3;
+    let palette: Vec<Color> = data
+        .get(HEADER_SIZE..palette_end)
+        .ok_or("Truncated RLE icon palette")?
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(i, rgb)| {
+            if i == 0 {
+                background
+            } else {
+                Color::new(rgb[0], rgb[1], rgb[2])
+            }
+        })
+        .collect();
+
+    let pixel_count = width * height;
+    let mut pixels = Vec::with_capacity(pixel_count);
+
+    for run in data[palette_end..].chunks_exact(2) {
+        let count = run[0] as usize;
+        let color = palette.get(run[1] as usize).copied().unwrap_or(background);
+
+        let count = count.min(pixel_count - pixels.len());
+        pixels.extend(core::iter::repeat_n(color, count));
+
+        if pixels.len() == pixel_count {
+            break;
+        }
+    }
+
+    pixels.resize(pixel_count, background);
+
+    Ok(Bitmap {
+        width,
+        height,
+        pixels,
+    })
+}
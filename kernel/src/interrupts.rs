@@ -1,11 +1,16 @@
-use crate::{hlt_loop, println, serial_println};
+use crate::{
+    desktop::input::MouseEvent, fs::disk::AtaChannel, hlt_loop, log, println, serial::LogLevel,
+    serial_println, vmmouse,
+};
+use core::sync::atomic::{AtomicU16, AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use ps2_mouse::{Mouse, MouseState};
 use spin::{self, lazy::Lazy};
 use spinning_top::Spinlock;
 use x86_64::{
-    instructions::port::PortReadOnly,
+    PrivilegeLevel,
+    instructions::port::{Port, PortReadOnly},
     structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
 };
 
@@ -15,39 +20,86 @@ pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub const KEYBOARD_INTERRUPT: u8 = PIC_1_OFFSET + 1;
 pub const MOUSE_INTERRUPT: u8 = PIC_1_OFFSET + 12;
+pub const PRIMARY_ATA_INTERRUPT: u8 = PIC_1_OFFSET + 14;
+pub const SECONDARY_ATA_INTERRUPT: u8 = PIC_1_OFFSET + 15;
+
+/// Timer interrupts since boot, at [`PIT_FREQUENCY_HZ`] once [`init_pit`] ran
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Input clock of the PIT, in Hz
+const PIT_BASE_FREQUENCY: u64 = 1_193_182;
+/// Rate the timer interrupt is programmed to
+pub const PIT_FREQUENCY_HZ: u64 = 100;
+/// Reload value for channel 0, rounded to the closest rate the PIT can do
+const PIT_DIVISOR: u64 = (PIT_BASE_FREQUENCY + PIT_FREQUENCY_HZ / 2) / PIT_FREQUENCY_HZ;
+
+const PIC_1_COMMAND: u16 = 0x20;
+const PIC_1_DATA: u16 = 0x21;
+const PIC_2_COMMAND: u16 = 0xA0;
+const PIC_2_DATA: u16 = 0xA1;
+/// Select the register the next read of a command port returns
+const READ_IRR: u8 = 0x0A;
+const READ_ISR: u8 = 0x0B;
+/// The secondary PIC is wired to this line of the primary one
+const CASCADE_LINE: u8 = 2;
 
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+/// One bit per IRQ line whose end of interrupt is still owed to the PICs
+static PENDING_EOI: AtomicU16 = AtomicU16::new(0);
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard = KEYBOARD_INTERRUPT,
     Mouse = MOUSE_INTERRUPT,
+    PrimaryAta = PRIMARY_ATA_INTERRUPT,
+    SecondaryAta = SECONDARY_ATA_INTERRUPT,
+    /// The lowest priority line of the primary PIC, where its spurious interrupts arrive. The
+    /// secondary PIC's arrive on the secondary ATA line.
+    PrimarySpurious = PIC_1_OFFSET + 7,
 }
 
 impl InterruptIndex {
     fn as_u8(self) -> u8 {
         self as u8
     }
+
+    /// The IRQ line, 0 to 15
+    fn line(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
+    }
 }
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
 
         unsafe {
+            // On its own stack, so an overflow of another IST stack can be reported
+            idt.page_fault
+                .set_handler_fn(page_fault_handler)
+                .set_stack_index(crate::gdt::PAGE_FAULT_IST_INDEX);
             idt.double_fault
                 .set_handler_fn(double_fault_handler)
                 .set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.stack_segment_fault
+                .set_handler_fn(stack_segment_fault_handler)
+                .set_stack_index(crate::gdt::STACK_SEGMENT_FAULT_IST_INDEX);
+            idt.invalid_tss
+                .set_handler_fn(invalid_tss_handler)
+                .set_stack_index(crate::gdt::INVALID_TSS_IST_INDEX);
         }
 
         idt[InterruptIndex::Timer.as_u8()].set_handler_fn(timer_handler);
         idt[InterruptIndex::Keyboard.as_u8()].set_handler_fn(keyboard_interrupt_handler);
         idt[InterruptIndex::Mouse.as_u8()].set_handler_fn(mouse_interrupt_handler);
+        idt[InterruptIndex::PrimaryAta.as_u8()].set_handler_fn(primary_ata_interrupt_handler);
+        idt[InterruptIndex::SecondaryAta.as_u8()].set_handler_fn(secondary_ata_interrupt_handler);
+        idt[InterruptIndex::PrimarySpurious.as_u8()].set_handler_fn(primary_spurious_handler);
 
         idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
         // idt.security_exception
@@ -64,11 +116,12 @@ pub fn init_idt() {
 pub fn init_mouse() {
     MOUSE.lock().init().unwrap();
     MOUSE.lock().set_on_complete(on_complete);
+    vmmouse::init();
 }
 
 // This will be fired when a packet is finished being processed.
 fn on_complete(mouse_state: MouseState) {
-    crate::desktop::input::add_mouse_state(mouse_state);
+    crate::desktop::input::add_mouse_state(MouseEvent::Relative(mouse_state));
 }
 
 // An example interrupt based on https://os.phil-opp.com/hardware-interrupts/. The ps2 mouse is configured to fire
@@ -77,13 +130,122 @@ extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFr
     let mut port = PortReadOnly::new(0x60);
     let packet = unsafe { port.read() };
 
-    // I know this is a bad practice but we are sort of forced to do this here
-    // I spent 3h trying to do it otherwise but none of the solutions worked.
-    MOUSE.lock().process_packet(packet);
+    if vmmouse::is_active() {
+        // The PS/2 byte only signals that the position changed
+        while let Some(event) = vmmouse::poll() {
+            crate::desktop::input::add_mouse_state(event);
+        }
+    } else if let Some(mut mouse) = MOUSE.try_lock() {
+        // I know this is a bad practice but we are sort of forced to do this here
+        // I spent 3h trying to do it otherwise but none of the solutions worked.
+        // The mouse is only locked elsewhere during init, if it's busy the byte is lost.
+        mouse.process_packet(packet);
+    }
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Mouse.as_u8());
+    end_of_interrupt(InterruptIndex::Mouse);
+}
+
+fn set_masked(line: u8, masked: bool) {
+    let (port, bit) = if line < 8 {
+        (PIC_1_DATA, line)
+    } else {
+        (PIC_2_DATA, line - 8)
+    };
+
+    let mut port = Port::<u8>::new(port);
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        let mask = port.read();
+        if masked {
+            port.write(mask | (1 << bit));
+        } else {
+            port.write(mask & !(1 << bit));
+        }
+    });
+}
+
+/// Let an IRQ line through the PICs, the lines of the secondary PIC also need the cascade
+pub fn unmask_irq(index: InterruptIndex) {
+    if index.line() >= 8 {
+        set_masked(CASCADE_LINE, false);
+    }
+    set_masked(index.line(), false);
+}
+
+/// Stop an IRQ line at the PICs. The cascade stays open for the other secondary lines.
+pub fn mask_irq(index: InterruptIndex) {
+    set_masked(index.line(), true);
+}
+
+/// Read the IRR or ISR of both PICs, one bit per IRQ line
+fn read_pic_register(select: u8) -> u16 {
+    let mut primary = Port::<u8>::new(PIC_1_COMMAND);
+    let mut secondary = Port::<u8>::new(PIC_2_COMMAND);
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        primary.write(select);
+        secondary.write(select);
+        ((secondary.read() as u16) << 8) | primary.read() as u16
+    })
+}
+
+/// IRQ lines raised but not yet passed to the CPU
+pub fn requested_irqs() -> u16 {
+    read_pic_register(READ_IRR)
+}
+
+/// IRQ lines being handled, their EOI wasn't sent yet
+pub fn in_service_irqs() -> u16 {
+    read_pic_register(READ_ISR)
+}
+
+/// IRQ 7 also fires when a line drops before the CPU took it. Only a real one is in
+/// service and gets an EOI.
+extern "x86-interrupt" fn primary_spurious_handler(_stack_frame: InterruptStackFrame) {
+    let index = InterruptIndex::PrimarySpurious;
+    if in_service_irqs() & (1 << index.line()) != 0 {
+        end_of_interrupt(index);
+    }
+}
+
+/// A drive on the primary channel finished a command
+extern "x86-interrupt" fn primary_ata_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::fs::disk::handle_interrupt(AtaChannel::Primary);
+
+    end_of_interrupt(InterruptIndex::PrimaryAta);
+}
+
+/// A drive on the secondary channel finished a command, the EOI goes to both PICs. IRQ 15
+/// is also the secondary PIC's spurious line, like [`primary_spurious_handler`] only a real
+/// one is handled, but the primary PIC did raise the cascade line and still needs its EOI.
+extern "x86-interrupt" fn secondary_ata_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let index = InterruptIndex::SecondaryAta;
+    if in_service_irqs() & (1 << index.line()) == 0 {
+        end_of_interrupt_line(CASCADE_LINE);
+        return;
+    }
+
+    crate::fs::disk::handle_interrupt(AtaChannel::Secondary);
+
+    end_of_interrupt(index);
+}
+
+/// Tell the PICs an interrupt was handled. Handlers must not spin on the lock, so when
+/// it's busy the EOI is kept and sent by the next handler that gets it, at the latest
+/// the timer, which still fires while lower priority lines wait for their EOI.
+fn end_of_interrupt(index: InterruptIndex) {
+    end_of_interrupt_line(index.line());
+}
+
+/// An EOI for a line of the primary PIC only goes to the primary PIC
+fn end_of_interrupt_line(line: u8) {
+    PENDING_EOI.fetch_or(1 << line, Ordering::SeqCst);
+
+    if let Some(mut pics) = PICS.try_lock() {
+        let pending = PENDING_EOI.swap(0, Ordering::SeqCst);
+        for line in 0..16 {
+            if pending & (1 << line) != 0 {
+                unsafe { pics.notify_end_of_interrupt(PIC_1_OFFSET + line) };
+            }
+        }
     }
 }
 
@@ -111,6 +273,14 @@ extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
+    if let Some(stack) = Cr2::read().ok().and_then(crate::gdt::overflowed_ist_stack) {
+        serial_println!(
+            "IST stack overflow: the {} stack ran into its guard page",
+            stack
+        );
+        println!("IST stack overflow: {} stack", stack);
+    }
+
     println!("EXCEPTION: PAGE FAULT",);
     println!("Accessed Address: {:?}", Cr2::read());
     println!("Error Code: {:?}", error_code);
@@ -123,40 +293,122 @@ extern "x86-interrupt" fn page_fault_handler(
     hlt_loop();
 }
 
+extern "x86-interrupt" fn stack_segment_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    report_segment_fault("STACK SEGMENT FAULT", &stack_frame, error_code);
+}
+
+extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    report_segment_fault("INVALID TSS", &stack_frame, error_code);
+}
+
+/// Logs a segment-related fault (#SS / #TS) and halts.
+///
+/// The error code is a segment selector index (or 0 if the fault wasn't caused by a selector).
+fn report_segment_fault(name: &str, stack_frame: &InterruptStackFrame, error_code: u64) -> ! {
+    let user_mode = stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3;
+
+    serial_println!(
+        "EXCEPTION: {} ({} mode). Error code: {:#x}",
+        name,
+        if user_mode { "user" } else { "kernel" },
+        error_code
+    );
+    serial_println!("{:#?}", stack_frame);
+
+    if user_mode {
+        // There is no process manager on this branch that could kill the offending
+        // process, so the best we can do is stop here with the dump above.
+        serial_println!("Fault originated in ring 3, halting.");
+    } else {
+        println!(
+            "EXCEPTION: {}\nError code: {:#x}\n{:#?}",
+            name, error_code, stack_frame
+        );
+    }
+
+    hlt_loop();
+}
+
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
-    _error_code: u64,
+    error_code: u64,
 ) -> ! {
     serial_println!("Double fault occurred, halting the system.");
-    serial_println!("Stack frame: {:#?}", stack_frame);
+    let overflowed = x86_64::registers::control::Cr2::read()
+        .ok()
+        .and_then(crate::gdt::overflowed_ist_stack)
+        .or_else(|| crate::gdt::overflowed_ist_stack(stack_frame.stack_pointer));
+    if let Some(stack) = overflowed {
+        serial_println!(
+            "IST stack overflow: the {} stack ran into its guard page",
+            stack
+        );
+    }
+    crate::crash::report_double_fault(&stack_frame, error_code);
 
     println!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 
     hlt_loop();
 }
 
-extern "x86-interrupt" fn timer_handler(_stack_frame: InterruptStackFrame) {
-    // print!(".");
+/// Program PIT channel 0 to fire the timer interrupt at [`PIT_FREQUENCY_HZ`]
+pub fn init_pit() {
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel_0 = Port::<u8>::new(0x40);
 
-    // Notify the Programmable Interrupt Controller (PIC) that the interrupt has been handled
     unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+        // Channel 0, low byte then high byte, mode 3 (square wave), binary
+        command.write(0x36);
+        channel_0.write(PIT_DIVISOR as u8);
+        channel_0.write((PIT_DIVISOR >> 8) as u8);
     }
+
+    let centihertz = PIT_BASE_FREQUENCY * 100 / PIT_DIVISOR;
+    log!(
+        LogLevel::Info,
+        "PIT divisor {}, timer at {}.{:02} Hz",
+        PIT_DIVISOR,
+        centihertz / 100,
+        centihertz % 100
+    );
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use x86_64::instructions::port::Port;
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Timer ticks in a duration, rounded up so short waits still last at least one tick
+pub const fn ms_to_ticks(ms: u64) -> u64 {
+    (ms * PIT_BASE_FREQUENCY).div_ceil(PIT_DIVISOR * 1000)
+}
+
+/// Milliseconds since the timer was started, with the resolution of one tick (10 ms)
+pub fn uptime_ms() -> u64 {
+    ticks() * PIT_DIVISOR * 1000 / PIT_BASE_FREQUENCY
+}
+
+extern "x86-interrupt" fn timer_handler(_stack_frame: InterruptStackFrame) {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::task::timer::wake_due(now);
+    crate::splash::tick(now);
+
+    // Notify the Programmable Interrupt Controller (PIC) that the interrupt has been handled
+    end_of_interrupt(InterruptIndex::Timer);
+}
 
+extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
     // crate::task::keyboard::add_scancode(scancode);
-    crate::desktop::input::add_scancode(scancode);
-
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+    if !crate::keyboard_leds::handle_byte(scancode) {
+        crate::safe_mode::note_scancode(scancode);
+        crate::desktop::input::add_scancode(scancode);
     }
+
+    end_of_interrupt(InterruptIndex::Keyboard);
 }
 
 #[cfg(test)]
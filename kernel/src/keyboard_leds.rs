@@ -0,0 +1,114 @@
+//! Caps, Num and Scroll Lock LEDs of the PS/2 keyboard.
+//!
+//! Setting the LEDs takes two bytes, the `0xED` command and then the LED mask, and the
+//! keyboard acknowledges each one with `0xFA`. The acknowledgements arrive through the
+//! keyboard interrupt like scancodes, so the handler passes them to [`handle_byte`], which
+//! sends the mask once the command was accepted.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+use x86_64::instructions::{interrupts, port::Port};
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+/// Set while the controller hasn't taken the last byte yet
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// Give up on a byte after this many status reads
+const WRITE_ATTEMPTS: usize = 10_000;
+
+const COMMAND_SET_LEDS: u8 = 0xED;
+const ACK: u8 = 0xFA;
+const RESEND: u8 = 0xFE;
+
+const LED_SCROLL: u8 = 1 << 0;
+const LED_NUM: u8 = 1 << 1;
+const LED_CAPS: u8 = 1 << 2;
+
+// Where the exchange with the keyboard is
+const IDLE: u8 = 0;
+const COMMAND_SENT: u8 = 1;
+const MASK_SENT: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(IDLE);
+/// The mask the LEDs should show
+static WANTED: AtomicU8 = AtomicU8::new(0);
+/// The mask the keyboard was last sent
+static SENT: AtomicU8 = AtomicU8::new(0);
+
+/// Which lock keys are on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockKeys {
+    pub caps: bool,
+    pub num: bool,
+    pub scroll: bool,
+}
+
+impl LockKeys {
+    fn led_mask(self) -> u8 {
+        let mut mask = 0;
+        if self.scroll {
+            mask |= LED_SCROLL;
+        }
+        if self.num {
+            mask |= LED_NUM;
+        }
+        if self.caps {
+            mask |= LED_CAPS;
+        }
+        mask
+    }
+}
+
+/// Write a byte to the keyboard once the controller can take it
+fn write_byte(byte: u8) {
+    let mut status = Port::<u8>::new(STATUS_PORT);
+    for _ in 0..WRITE_ATTEMPTS {
+        if unsafe { status.read() } & STATUS_INPUT_FULL == 0 {
+            unsafe { Port::<u8>::new(DATA_PORT).write(byte) };
+            return;
+        }
+    }
+}
+
+/// Show the lock keys on the LEDs. If an update is still in flight the new state is sent
+/// when it's done.
+pub fn update(locks: LockKeys) {
+    WANTED.store(locks.led_mask(), Ordering::Relaxed);
+
+    // Interrupts are off so the acknowledgement can't come before the state is set
+    interrupts::without_interrupts(|| {
+        if STATE.load(Ordering::Relaxed) == IDLE {
+            STATE.store(COMMAND_SENT, Ordering::Relaxed);
+            write_byte(COMMAND_SET_LEDS);
+        }
+    });
+}
+
+/// Handle a byte from the keyboard interrupt. Returns true if it was an answer to an LED
+/// update, then it isn't a scancode.
+pub fn handle_byte(byte: u8) -> bool {
+    let state = STATE.load(Ordering::Relaxed);
+    if state == IDLE || !matches!(byte, ACK | RESEND) {
+        return false;
+    }
+
+    match (state, byte) {
+        (COMMAND_SENT, ACK) => {
+            let mask = WANTED.load(Ordering::Relaxed);
+            SENT.store(mask, Ordering::Relaxed);
+            STATE.store(MASK_SENT, Ordering::Relaxed);
+            write_byte(mask);
+        }
+        (COMMAND_SENT, _) => write_byte(COMMAND_SET_LEDS),
+        (_, ACK) => {
+            // The LEDs changed again while this update was going on
+            if SENT.load(Ordering::Relaxed) != WANTED.load(Ordering::Relaxed) {
+                STATE.store(COMMAND_SENT, Ordering::Relaxed);
+                write_byte(COMMAND_SET_LEDS);
+            } else {
+                STATE.store(IDLE, Ordering::Relaxed);
+            }
+        }
+        (_, _) => write_byte(SENT.load(Ordering::Relaxed)),
+    }
+    true
+}
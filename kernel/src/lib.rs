@@ -16,22 +16,38 @@ use exit::{QemuExitCode, exit_qemu};
 extern crate alloc;
 
 pub mod allocator;
+pub mod bmp;
+pub mod crash;
 pub mod desktop;
 pub mod exit;
 pub mod framebuffer;
 pub mod fs;
 pub mod gdt;
+pub mod icons;
 pub mod interrupts;
+pub mod keyboard_leds;
+pub mod lock_order;
+pub mod log_buffer;
+pub mod log_sink;
 pub mod memory;
+pub mod random;
+pub mod safe_mode;
+pub mod screen_mode;
 pub mod serial;
+pub mod serial_console;
+pub mod splash;
 pub mod surface;
 pub mod sysinfo;
+pub mod task;
 pub mod time;
+pub mod vmmouse;
 
 use bootloader_api::config::{BootloaderConfig, Mapping};
 
 use crate::interrupts::init_mouse;
 
+pub use screen_mode::MIN_SCREEN_SIZE;
+
 pub static BOOTLOADER_CONFIG: BootloaderConfig = {
     let mut config = BootloaderConfig::new_default();
     config.mappings.physical_memory = Some(Mapping::Dynamic);
@@ -41,6 +57,8 @@ pub static BOOTLOADER_CONFIG: BootloaderConfig = {
 pub static PHYSICAL_MEMORY_OFFSET: OnceCell<x86_64::VirtAddr> = OnceCell::uninit();
 
 pub fn init(physical_memory_offset: x86_64::VirtAddr) {
+    use interrupts::InterruptIndex;
+
     // Initialize the physical memory offset
     PHYSICAL_MEMORY_OFFSET.init_once(|| physical_memory_offset);
 
@@ -49,6 +67,16 @@ pub fn init(physical_memory_offset: x86_64::VirtAddr) {
     init_mouse();
 
     unsafe { interrupts::PICS.lock().initialize() };
+    interrupts::init_pit();
+    for index in [
+        InterruptIndex::Timer,
+        InterruptIndex::Keyboard,
+        InterruptIndex::Mouse,
+        InterruptIndex::PrimaryAta,
+        InterruptIndex::SecondaryAta,
+    ] {
+        interrupts::unmask_irq(index);
+    }
 
     // Disable interrupts to prevent switching to processes before they are initialized
     x86_64::instructions::interrupts::disable();
@@ -98,9 +126,16 @@ entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
 fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
     use x86_64::VirtAddr;
 
-    init(VirtAddr::new(
-        boot_info.physical_memory_offset.into_option().unwrap(),
-    ));
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    init(phys_mem_offset);
+
+    // The filesystem tests need the heap
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    gdt::protect_ist_stacks(&mut mapper);
+    let mut frame_allocator =
+        unsafe { memory::BootInfoFrameAllocator::init(&boot_info.memory_regions) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
     test_main();
     hlt_loop();
 }
@@ -0,0 +1,130 @@
+//! A global order for the kernel's big locks, so they can't deadlock each other.
+//!
+//! Locks are taken from the outermost level inwards:
+//!
+//! 1. [`LockLevel::Framebuffer`], the desktop holds it while routing mouse events to apps
+//! 2. [`LockLevel::Filesystem`]
+//! 3. [`LockLevel::LogSinks`], anything may log while holding a lock
+//! 4. [`LockLevel::LogBuffer`], [`LockLevel::Serial`] and [`LockLevel::LogFile`], the sinks
+//!
+//! Debug builds panic when a lock is taken while one on the same or an inner level is held.
+//! `try_lock` can't deadlock so it isn't checked, but the lock counts as held once it succeeds.
+//! Interrupt handlers must never block on a lock, they only use `try_lock`.
+
+use core::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use spin::{Mutex, MutexGuard};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LockLevel {
+    Framebuffer = 0,
+    Filesystem,
+    LogSinks,
+    LogBuffer,
+    Serial,
+    LogFile,
+}
+
+impl LockLevel {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// One bit per level that is currently held. There is a single CPU, so one set is enough.
+static HELD: AtomicU32 = AtomicU32::new(0);
+
+fn check_order(level: LockLevel) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    // Bits of this level and every inner one
+    let inner = HELD.load(Ordering::Relaxed) & !(level.bit() - 1);
+    if inner != 0 {
+        // Forget the held locks so the panic handler can still print
+        HELD.store(0, Ordering::Relaxed);
+        panic!(
+            "Lock order violation: taking {:?} while holding level {}",
+            level,
+            inner.trailing_zeros()
+        );
+    }
+}
+
+fn mark_held(level: LockLevel) {
+    if cfg!(debug_assertions) {
+        HELD.fetch_or(level.bit(), Ordering::Relaxed);
+    }
+}
+
+fn mark_released(level: LockLevel) {
+    if cfg!(debug_assertions) {
+        HELD.fetch_and(!level.bit(), Ordering::Relaxed);
+    }
+}
+
+/// A spin mutex with a place in the lock order
+pub struct OrderedMutex<T> {
+    level: LockLevel,
+    inner: Mutex<T>,
+}
+
+impl<T> OrderedMutex<T> {
+    pub const fn new(level: LockLevel, value: T) -> Self {
+        Self {
+            level,
+            inner: Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> OrderedMutexGuard<'_, T> {
+        check_order(self.level);
+        let guard = self.inner.lock();
+        mark_held(self.level);
+
+        OrderedMutexGuard {
+            level: self.level,
+            guard,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<OrderedMutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        mark_held(self.level);
+
+        Some(OrderedMutexGuard {
+            level: self.level,
+            guard,
+        })
+    }
+}
+
+pub struct OrderedMutexGuard<'a, T> {
+    level: LockLevel,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> Deref for OrderedMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for OrderedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for OrderedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        mark_released(self.level);
+    }
+}
@@ -0,0 +1,123 @@
+//! In-memory copy of the kernel log, so messages can be read without a serial cable.
+//!
+//! Lines are stored back to back in a fixed byte ring. Nothing is allocated while
+//! writing, and interrupts are disabled around the lock, so logging from an interrupt
+//! handler is fine. When the ring is full the oldest lines are dropped.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Write};
+use x86_64::instructions::interrupts;
+
+use crate::lock_order::{LockLevel, OrderedMutex};
+
+pub const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+struct LogBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    start: usize,
+    len: usize,
+}
+
+impl LogBuffer {
+    const fn new() -> Self {
+        Self {
+            data: [0; LOG_BUFFER_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        if self.len == LOG_BUFFER_SIZE {
+            self.drop_oldest_line();
+        }
+
+        self.data[(self.start + self.len) % LOG_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+
+    /// Drop bytes up to and including the first newline
+    fn drop_oldest_line(&mut self) {
+        while self.len > 0 {
+            let byte = self.data[self.start];
+            self.start = (self.start + 1) % LOG_BUFFER_SIZE;
+            self.len -= 1;
+
+            if byte == b'\n' {
+                break;
+            }
+        }
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|i| self.data[(self.start + i) % LOG_BUFFER_SIZE])
+    }
+}
+
+impl Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.push_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+static LOG_BUFFER: OrderedMutex<LogBuffer> =
+    OrderedMutex::new(LockLevel::LogBuffer, LogBuffer::new());
+
+/// Append formatted text to the log buffer. The lock is only busy if an exception
+/// interrupted a write, then the text is dropped instead of waiting forever.
+pub fn write(args: fmt::Arguments) {
+    interrupts::without_interrupts(|| {
+        if let Some(mut buffer) = LOG_BUFFER.try_lock() {
+            let _ = buffer.write_fmt(args);
+        }
+    });
+}
+
+/// A copy of the lines currently in the buffer, oldest first
+pub fn lines() -> Vec<String> {
+    let bytes: Vec<u8> = interrupts::without_interrupts(|| LOG_BUFFER.lock().bytes().collect());
+
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+/// Write the last `count` lines to `out` without allocating, for crash reports. Returns
+/// false if the buffer is locked, an exception may have interrupted a write.
+pub fn try_write_tail(count: usize, out: &mut impl Write) -> bool {
+    let Some(buffer) = LOG_BUFFER.try_lock() else {
+        return false;
+    };
+    let byte_at = |i: usize| buffer.data[(buffer.start + i) % LOG_BUFFER_SIZE];
+
+    // Walk back over `count` newlines, the one ending the last line doesn't count
+    let mut start = buffer.len;
+    let mut newlines = 0;
+    while start > 0 {
+        if byte_at(start - 1) == b'\n' && start != buffer.len {
+            newlines += 1;
+            if newlines == count {
+                break;
+            }
+        }
+        start -= 1;
+    }
+
+    for i in start..buffer.len {
+        let _ = out.write_char(byte_at(i) as char);
+    }
+    true
+}
+
+/// Forget all buffered lines
+pub fn clear() {
+    interrupts::without_interrupts(|| {
+        let mut buffer = LOG_BUFFER.lock();
+        buffer.start = 0;
+        buffer.len = 0;
+    });
+}
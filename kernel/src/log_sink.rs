@@ -0,0 +1,272 @@
+//! Where `log!` lines go. Every line is handed to each active sink, which picks the levels
+//! it wants:
+//!
+//! - [`SERIAL_SINK`], the serial port, at the level set with [`set_log_level`]
+//! - [`BUFFER_SINK`], the [log buffer](crate::log_buffer) that dmesg shows
+//! - [`FILE_SINK`], `SYSTEM.LOG` in the root directory, once [`enable_file_log`] ran. It
+//!   moves to `SYSTEM.OLD` when it reaches [`MAX_LOG_FILE_SIZE`].
+//!
+//! Sinks are written with interrupts disabled and must never block or allocate, a sink
+//! that is busy drops the line instead.
+//!
+//! [`set_log_level`]: crate::serial::set_log_level
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+use x86_64::instructions::interrupts;
+
+use crate::{
+    fs::{
+        fat32::open_flags,
+        manager::{try_rotate_root_file, try_write_root_file},
+    },
+    interrupts::{ms_to_ticks, ticks},
+    lock_order::{LockLevel, OrderedMutex},
+    log, log_buffer,
+    serial::{self, LogLevel},
+};
+
+pub const LOG_FILENAME: &str = "SYSTEM.LOG";
+/// The previous log, once the current one got too big
+pub const OLD_LOG_FILENAME: &str = "SYSTEM.OLD";
+/// The log file moves to [`OLD_LOG_FILENAME`] before it gets bigger than this
+pub const MAX_LOG_FILE_SIZE: usize = 256 * 1024;
+
+/// Messages at least this important are kept in the buffer and the file even when the
+/// serial level is quieter
+const KEEP_LEVEL: LogLevel = LogLevel::Info;
+const MAX_SINKS: usize = 4;
+/// Lines waiting for [`flush_file_log`], more are dropped until the next flush
+const FILE_PENDING_SIZE: usize = 4096;
+/// Time between writes to the log file, unless the pending lines fill half the buffer
+const FILE_FLUSH_INTERVAL_TICKS: u64 = ms_to_ticks(1000);
+
+pub trait LogSink: Sync {
+    /// Whether lines of this level are wanted, asked before a line is formatted
+    fn accepts(&self, level: LogLevel) -> bool;
+    /// Write one line, it already ends with a newline
+    fn write(&self, line: fmt::Arguments);
+}
+
+pub struct SerialSink;
+
+impl LogSink for SerialSink {
+    fn accepts(&self, level: LogLevel) -> bool {
+        level <= serial::log_level()
+    }
+
+    fn write(&self, line: fmt::Arguments) {
+        serial::_print(line);
+    }
+}
+
+pub struct BufferSink;
+
+impl LogSink for BufferSink {
+    fn accepts(&self, level: LogLevel) -> bool {
+        level <= serial::log_level() || level <= KEEP_LEVEL
+    }
+
+    fn write(&self, line: fmt::Arguments) {
+        log_buffer::write(line);
+    }
+}
+
+/// Lines not written to the file yet
+struct PendingLines {
+    data: [u8; FILE_PENDING_SIZE],
+    len: usize,
+    /// Lines were dropped because the buffer was full
+    overflowed: bool,
+}
+
+impl Write for PendingLines {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(FILE_PENDING_SIZE - self.len);
+        self.data[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        self.overflowed |= count < s.len();
+        Ok(())
+    }
+}
+
+/// Collects lines in memory, the main loop writes them out with [`flush_file_log`]
+pub struct FileSink {
+    pending: OrderedMutex<PendingLines>,
+    /// Set while flushing, the filesystem's own log lines would keep the file busy forever
+    flushing: AtomicBool,
+    /// The first flush of a boot starts the file over
+    truncate: AtomicBool,
+    /// Bytes written to the file this boot
+    file_size: AtomicUsize,
+    /// Tick before which [`flush_file_log`] leaves the lines pending
+    next_flush: AtomicU64,
+}
+
+impl LogSink for FileSink {
+    fn accepts(&self, level: LogLevel) -> bool {
+        level <= serial::log_level() || level <= KEEP_LEVEL
+    }
+
+    fn write(&self, line: fmt::Arguments) {
+        if self.flushing.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(mut pending) = self.pending.try_lock() {
+            let _ = pending.write_fmt(line);
+        }
+    }
+}
+
+pub static SERIAL_SINK: SerialSink = SerialSink;
+pub static BUFFER_SINK: BufferSink = BufferSink;
+pub static FILE_SINK: FileSink = FileSink {
+    pending: OrderedMutex::new(
+        LockLevel::LogFile,
+        PendingLines {
+            data: [0; FILE_PENDING_SIZE],
+            len: 0,
+            overflowed: false,
+        },
+    ),
+    flushing: AtomicBool::new(false),
+    truncate: AtomicBool::new(true),
+    file_size: AtomicUsize::new(0),
+    next_flush: AtomicU64::new(0),
+};
+
+static SINKS: OrderedMutex<[Option<&'static dyn LogSink>; MAX_SINKS]> = OrderedMutex::new(
+    LockLevel::LogSinks,
+    [Some(&SERIAL_SINK), Some(&BUFFER_SINK), None, None],
+);
+
+fn is_same(a: &dyn LogSink, b: &dyn LogSink) -> bool {
+    core::ptr::addr_eq(a, b)
+}
+
+/// Start sending lines to a sink, adding one that is already active does nothing
+pub fn add(sink: &'static dyn LogSink) -> Result<(), &'static str> {
+    interrupts::without_interrupts(|| {
+        let mut sinks = SINKS.lock();
+        if sinks.iter().flatten().any(|active| is_same(*active, sink)) {
+            return Ok(());
+        }
+
+        let slot = sinks
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or("Too many log sinks")?;
+        *slot = Some(sink);
+        Ok(())
+    })
+}
+
+pub fn remove(sink: &'static dyn LogSink) {
+    interrupts::without_interrupts(|| {
+        for slot in SINKS.lock().iter_mut() {
+            if slot.is_some_and(|active| is_same(active, sink)) {
+                *slot = None;
+            }
+        }
+    });
+}
+
+/// Whether any sink wants lines of this level. False while a sink is writing, so a sink
+/// that logs itself can't recurse.
+pub fn accepts(level: LogLevel) -> bool {
+    interrupts::without_interrupts(|| {
+        SINKS
+            .try_lock()
+            .is_some_and(|sinks| sinks.iter().flatten().any(|sink| sink.accepts(level)))
+    })
+}
+
+/// Hand a line to every sink that wants its level
+pub fn write(level: LogLevel, line: fmt::Arguments) {
+    interrupts::without_interrupts(|| {
+        let Some(sinks) = SINKS.try_lock() else {
+            return;
+        };
+        for sink in sinks.iter().flatten() {
+            if sink.accepts(level) {
+                sink.write(line);
+            }
+        }
+    });
+}
+
+/// Also log to `SYSTEM.LOG`, once the filesystem is mounted
+pub fn enable_file_log() {
+    if let Err(e) = add(&FILE_SINK) {
+        log!(LogLevel::Warn, "Can't log to {}: {}", LOG_FILENAME, e);
+    }
+}
+
+/// Append the collected lines to the log file, at most once per flush interval unless the
+/// pending buffer is filling up. Lines stay pending while the filesystem is busy, so call
+/// this regularly.
+pub fn flush_file_log() {
+    let (bytes, copied): (Vec<u8>, usize) = interrupts::without_interrupts(|| {
+        let pending = FILE_SINK.pending.lock();
+        if pending.len < FILE_PENDING_SIZE / 2
+            && ticks() < FILE_SINK.next_flush.load(Ordering::Relaxed)
+        {
+            return (Vec::new(), 0);
+        }
+        let mut bytes = pending.data[..pending.len].to_vec();
+        if pending.overflowed {
+            bytes.extend_from_slice(b"(log lines dropped)\n");
+        }
+        (bytes, pending.len)
+    });
+    if bytes.is_empty() {
+        return;
+    }
+
+    FILE_SINK.flushing.store(true, Ordering::Relaxed);
+    let result = write_pending(&bytes);
+    FILE_SINK.flushing.store(false, Ordering::Relaxed);
+    if result.is_err() {
+        return;
+    }
+
+    FILE_SINK
+        .next_flush
+        .store(ticks() + FILE_FLUSH_INTERVAL_TICKS, Ordering::Relaxed);
+    interrupts::without_interrupts(|| {
+        // Lines added by interrupts since the copy move to the front
+        let mut pending = FILE_SINK.pending.lock();
+        let len = pending.len;
+        pending.data.copy_within(copied..len, 0);
+        pending.len -= copied;
+        pending.overflowed = false;
+    });
+}
+
+/// Add the lines to the log file, or start it over when it would get too big
+fn write_pending(bytes: &[u8]) -> Result<(), &'static str> {
+    let file_size = FILE_SINK.file_size.load(Ordering::Relaxed);
+    if !FILE_SINK.truncate.load(Ordering::Relaxed) && file_size + bytes.len() > MAX_LOG_FILE_SIZE {
+        try_rotate_root_file(LOG_FILENAME, OLD_LOG_FILENAME)?;
+        FILE_SINK.truncate.store(true, Ordering::Relaxed);
+    }
+
+    if FILE_SINK.truncate.load(Ordering::Relaxed) {
+        try_write_root_file(
+            LOG_FILENAME,
+            bytes,
+            open_flags::CREATE | open_flags::TRUNCATE,
+        )?;
+        FILE_SINK.truncate.store(false, Ordering::Relaxed);
+        FILE_SINK.file_size.store(bytes.len(), Ordering::Relaxed);
+    } else {
+        try_write_root_file(LOG_FILENAME, bytes, open_flags::CREATE | open_flags::APPEND)?;
+        FILE_SINK
+            .file_size
+            .fetch_add(bytes.len(), Ordering::Relaxed);
+    }
+    Ok(())
+}
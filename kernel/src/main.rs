@@ -9,39 +9,41 @@ use core::panic::PanicInfo;
 extern crate alloc;
 
 use bootloader_api::{BootInfo, entry_point};
-use kernel::sysinfo::{STACK_BASE, get_stack_pointer};
-use kernel::{desktop::main::run_desktop, memory::BootInfoFrameAllocator, println, serial_println};
+use kernel::sysinfo::{STACK_BASE, get_stack_pointer, record_memory_map};
+use kernel::{
+    desktop::main::run_desktop, log, memory::BootInfoFrameAllocator, println, safe_mode,
+    serial::LogLevel, splash,
+};
 
-use bootloader_api::config::{BootloaderConfig, Mapping};
-use kernel::{allocator, memory};
+use kernel::{BOOTLOADER_CONFIG, allocator, gdt, memory};
 use x86_64::VirtAddr;
 use x86_64::instructions::interrupts;
 
-pub static BOOTLOADER_CONFIG: BootloaderConfig = {
-    let mut config = BootloaderConfig::new_default();
-    config.mappings.physical_memory = Some(Mapping::Dynamic);
-    config
-};
-
 entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
 
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     unsafe { STACK_BASE = get_stack_pointer() as usize };
 
-    serial_println!("Booting goofy OS...");
+    log!(LogLevel::Info, "Booting goofy OS...");
 
     let frame = boot_info.framebuffer.as_mut().unwrap();
     kernel::framebuffer::init(frame);
+    splash::show();
 
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
 
     // Initialize the OS
     kernel::init(phys_mem_offset);
+    splash::advance("Interrupts");
 
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    gdt::protect_ist_stacks(&mut mapper);
     let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_regions) };
+    splash::advance("Memory");
 
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    record_memory_map(&boot_info.memory_regions);
+    splash::advance("Heap");
 
     // Some tests for the heap allocator
     let heap_value = alloc::boxed::Box::new(41);
@@ -59,27 +61,40 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
 
     match kernel::fs::manager::init_filesystem() {
         Ok(_) => {
-            serial_println!("Filesystem initialized successfully!");
+            log!(LogLevel::Info, "Filesystem initialized successfully!");
             println!("Filesystem ready!");
+            kernel::crash::check_previous_crash();
+            kernel::log_sink::enable_file_log();
         }
         Err(e) => {
-            serial_println!("Failed to initialize filesystem: {}", e);
+            log!(LogLevel::Error, "Failed to initialize filesystem: {}", e);
             println!("Filesystem initialization failed: {}", e);
         }
     }
 
+    splash::advance("Filesystem");
+
     #[cfg(test)]
     test_main();
 
+    // Holding Shift skips the desktop, in case it is what's broken
+    let safe_mode = safe_mode::requested();
+    splash::finish();
+    if safe_mode {
+        safe_mode::run();
+    }
     run_desktop();
 }
 
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // The message has to reach the screen even if the splash is still up
+    splash::finish();
     println!("Panic occurred: {}", info);
-    serial_println!("[failed]\n");
-    serial_println!("Error: {}\n", info);
+    kernel::serial_println!("[failed]\n");
+    kernel::serial_println!("Error: {}\n", info);
+    kernel::crash::report_panic(info);
     kernel::hlt_loop();
 }
 
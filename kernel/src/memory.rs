@@ -9,7 +9,7 @@ use x86_64::{
 // use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
 use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
 
-use crate::serial_println;
+use crate::{log, serial::LogLevel};
 
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
 pub struct BootInfoFrameAllocator {
@@ -88,10 +88,15 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
         let frame = self.usable_frames().nth(self.next);
         if frame.is_none() {
-            serial_println!("Frame allocation failed at index {}", self.next);
+            log!(
+                LogLevel::Error,
+                "Frame allocation failed at index {}",
+                self.next
+            );
             // Count total available frames for debugging
             let total_frames = self.usable_frames().count();
-            serial_println!(
+            log!(
+                LogLevel::Error,
                 "Total usable frames: {}, requested index: {}",
                 total_frames,
                 self.next
@@ -185,7 +190,8 @@ impl ProcessAddressSpace {
     }
 
     pub fn cleanup(&mut self) {
-        serial_println!(
+        log!(
+            LogLevel::Debug,
             "Cleaning up address space for page table frame: {:?}",
             self.page_table_frame.start_address()
         );
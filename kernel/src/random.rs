@@ -0,0 +1,33 @@
+use core::arch::x86_64::_rdtsc;
+
+use spin::Mutex;
+
+/// Xorshift64 state, lazily seeded from the timestamp counter
+static STATE: Mutex<u64> = Mutex::new(0);
+
+/// A pseudo random number, good enough for games but not for anything secret
+pub fn next_u64() -> u64 {
+    let mut state = STATE.lock();
+
+    if *state == 0 {
+        // Xorshift gets stuck on zero, so make sure the seed never is
+        *state = unsafe { _rdtsc() } | 1;
+    }
+
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    *state = x;
+
+    x
+}
+
+/// A pseudo random number in `0..max`
+pub fn range(max: usize) -> usize {
+    if max == 0 {
+        return 0;
+    }
+
+    (next_u64() % max as u64) as usize
+}
@@ -0,0 +1,187 @@
+//! Text-only recovery console, for when the desktop or a driver is broken.
+//!
+//! Holding Shift while the kernel boots starts it instead of the desktop. Only the keyboard
+//! and the framebuffer text console are used, so no window or mouse code runs:
+//!
+//! ```text
+//! safe> ls /DOCS
+//!   NOTES/
+//!   TODO.TXT     1.20 KB
+//! ```
+
+use alloc::string::String;
+use core::sync::atomic::{AtomicBool, Ordering};
+use pc_keyboard::{DecodedKey, HandleControl, Keyboard, ScancodeSet1, layouts};
+use x86_64::instructions::{hlt, port::Port};
+
+use crate::{
+    desktop::input::{SCANCODE_QUEUE, init_queues},
+    fs::manager::{check_filesystem, list_path, read_text_path},
+    hlt_loop,
+    interrupts::{ms_to_ticks, ticks},
+    log, log_buffer, print, println,
+    serial::LogLevel,
+    sysinfo::format_memory_size,
+};
+
+const LEFT_SHIFT: u8 = 0x2A;
+const RIGHT_SHIFT: u8 = 0x36;
+const LEFT_SHIFT_RELEASED: u8 = LEFT_SHIFT | 0x80;
+const RIGHT_SHIFT_RELEASED: u8 = RIGHT_SHIFT | 0x80;
+
+/// A Shift pressed before the keyboard interrupt was on is seen when the key repeats
+const HOLD_GRACE_MS: u64 = 300;
+/// Lines `log` prints, more would scroll off the screen
+const LOG_LINES: usize = 30;
+const PROMPT: &str = "safe> ";
+
+static SHIFT_HELD: AtomicBool = AtomicBool::new(false);
+
+/// Track Shift from the keyboard interrupt, before anything else reads the scancodes
+pub fn note_scancode(scancode: u8) {
+    match scancode {
+        LEFT_SHIFT | RIGHT_SHIFT => SHIFT_HELD.store(true, Ordering::Relaxed),
+        LEFT_SHIFT_RELEASED | RIGHT_SHIFT_RELEASED => SHIFT_HELD.store(false, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+/// Whether Shift is held, needs interrupts to be enabled
+pub fn requested() -> bool {
+    let until = ticks() + ms_to_ticks(HOLD_GRACE_MS);
+    while ticks() < until && !SHIFT_HELD.load(Ordering::Relaxed) {
+        hlt();
+    }
+
+    SHIFT_HELD.load(Ordering::Relaxed)
+}
+
+/// Read and run commands until the machine is reset
+pub fn run() -> ! {
+    log!(LogLevel::Info, "Starting in safe mode");
+    init_queues();
+
+    print!("\x1b[2J");
+    println!("goofy-os safe mode, type help for the commands");
+    print!("{}", PROMPT);
+
+    let mut keyboard = Keyboard::new(ScancodeSet1::new(), layouts::Azerty, HandleControl::Ignore);
+    let mut line = String::new();
+
+    loop {
+        let Some(scancode) = SCANCODE_QUEUE.get().and_then(|queue| queue.pop()) else {
+            hlt();
+            continue;
+        };
+        let Ok(Some(event)) = keyboard.add_byte(scancode) else {
+            continue;
+        };
+        let Some(DecodedKey::Unicode(c)) = keyboard.process_keyevent(event) else {
+            continue;
+        };
+
+        match c {
+            '\n' => {
+                print!("\n");
+                run_command(&line);
+                line.clear();
+                print!("{}", PROMPT);
+            }
+            '\u{08}' => {
+                if line.pop().is_some() {
+                    print!("\u{08} \u{08}");
+                }
+            }
+            c if c.is_control() => {}
+            c => {
+                line.push(c);
+                print!("{}", c);
+            }
+        }
+    }
+}
+
+fn run_command(line: &str) {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return;
+    };
+    let path = words.next().unwrap_or("/");
+
+    let result = match command {
+        "help" => {
+            println!("  log          show the last kernel log lines");
+            println!("  ls [PATH]    list a directory");
+            println!("  cat PATH     print a text file");
+            println!("  fsck         check the filesystem without repairing it");
+            println!("  reboot       restart the machine");
+            Ok(())
+        }
+        "log" => {
+            let lines = log_buffer::lines();
+            for line in &lines[lines.len().saturating_sub(LOG_LINES)..] {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        "ls" => list(path),
+        "cat" => show_file(path),
+        "fsck" => check(),
+        "reboot" => reboot(),
+        _ => Err("Unknown command, type help for the commands"),
+    };
+
+    if let Err(e) = result {
+        println!("Error: {}", e);
+    }
+}
+
+fn list(path: &str) -> Result<(), &'static str> {
+    for file in list_path(path)? {
+        if file.is_directory {
+            println!("  {}/", file.name);
+        } else {
+            println!(
+                "  {:<12} {}",
+                file.name,
+                format_memory_size(file.size as usize)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn show_file(path: &str) -> Result<(), &'static str> {
+    println!("{}", read_text_path(path)?);
+    Ok(())
+}
+
+fn check() -> Result<(), &'static str> {
+    let report = check_filesystem(false)?;
+
+    println!(
+        "  {} files and {} directories checked",
+        report.files_checked, report.directories_checked
+    );
+    println!(
+        "  {} lost clusters, {} cross-linked clusters",
+        report.lost_clusters.len(),
+        report.cross_linked_clusters.len()
+    );
+    println!(
+        "  {} broken chains, {} size mismatches",
+        report.broken_chains.len(),
+        report.size_mismatches.len()
+    );
+    if report.incomplete {
+        println!("  The check stopped early, the directory tree is too deep or loops");
+    }
+    Ok(())
+}
+
+/// Pulse the reset line through the keyboard controller
+fn reboot() -> ! {
+    log!(LogLevel::Info, "Safe mode: rebooting");
+    unsafe { Port::<u8>::new(0x64).write(0xFE) };
+    hlt_loop();
+}
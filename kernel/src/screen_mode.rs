@@ -0,0 +1,6 @@
+//! Shared with the top-level `build.rs`, which includes this file to put the size in the
+//! boot config. It can't use anything from the kernel.
+
+/// Smallest screen the bootloader should pick. If the hardware has no such mode it keeps
+/// its default one, everything on screen is sized from the framebuffer info at runtime.
+pub const MIN_SCREEN_SIZE: (u64, u64) = (1024, 768);
@@ -1,12 +1,17 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
 use lazy_static::lazy_static;
-use spin::Mutex;
 use uart_16550::SerialPort;
 
+use crate::lock_order::{LockLevel, OrderedMutex};
+
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
+    pub static ref SERIAL1: OrderedMutex<SerialPort> = {
         let mut serial_port = unsafe { SerialPort::new(0x3F8) };
         serial_port.init();
-        Mutex::new(serial_port)
+        OrderedMutex::new(LockLevel::Serial, serial_port)
     };
 }
 
@@ -15,12 +20,13 @@ pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
     use x86_64::instructions::interrupts;
 
-    // Disable interrupts to prevent deadlocks or data corruption
+    // Disable interrupts to prevent deadlocks or data corruption. With interrupts off the
+    // port can only be busy if an exception hit in the middle of a print, waiting for it
+    // would hang forever, so the message is dropped instead.
     interrupts::without_interrupts(|| {
-        SERIAL1
-            .lock()
-            .write_fmt(args)
-            .expect("Printing to serial failed");
+        if let Some(mut serial) = SERIAL1.try_lock() {
+            serial.write_fmt(args).expect("Printing to serial failed");
+        }
     });
 }
 
@@ -40,3 +46,108 @@ macro_rules! serial_println {
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
         concat!($fmt, "\n"), $($arg)*))
 }
+
+/// How important a log message is, lower is more important
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Error = 0,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        }
+    }
+}
+
+/// Messages less important than this are dropped, quiet by default
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Warn as u8);
+
+pub fn log_level() -> LogLevel {
+    LogLevel::ALL[LOG_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Switch to the next more verbose level, wrapping around to the quietest one
+pub fn cycle_log_level() -> LogLevel {
+    let next = LogLevel::ALL[(log_level() as usize + 1) % LogLevel::ALL.len()];
+    set_log_level(next);
+    next
+}
+
+pub fn log_enabled(level: LogLevel) -> bool {
+    crate::log_sink::accepts(level)
+}
+
+/// Prefix log lines with the uptime, like `[12.345]`
+static LOG_TIMESTAMPS: AtomicBool = AtomicBool::new(true);
+
+pub fn set_log_timestamps(enabled: bool) {
+    LOG_TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+
+/// The part of a log line before the message
+struct LogPrefix {
+    level: LogLevel,
+    uptime_ms: Option<u64>,
+}
+
+impl fmt::Display for LogPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(uptime) = self.uptime_ms {
+            write!(f, "[{:>5}.{:03}] ", uptime / 1000, uptime % 1000)?;
+        }
+        write!(f, "[{}] ", self.level.name())
+    }
+}
+
+#[doc(hidden)]
+pub fn _log(level: LogLevel, args: fmt::Arguments) {
+    let prefix = LogPrefix {
+        level,
+        uptime_ms: LOG_TIMESTAMPS
+            .load(Ordering::Relaxed)
+            .then(crate::interrupts::uptime_ms),
+    };
+
+    // Formatted in the same write as the message, so lines from interrupts can't split it
+    crate::log_sink::write(level, format_args!("{}{}\n", prefix, args));
+}
+
+/// Logs to every [sink](crate::log_sink) that wants the level: the serial interface if the
+/// level is enabled, see [`set_log_level`], the [log buffer](crate::log_buffer) and the
+/// log file.
+///
+/// ```ignore
+/// log!(LogLevel::Info, "Found {} files", count);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {{
+        let level = $level;
+        if $crate::serial::log_enabled(level) {
+            $crate::serial::_log(level, format_args!($($arg)*));
+        }
+    }};
+}
@@ -0,0 +1,171 @@
+//! Commands from the host over COM1, so scripts can drive the running system without the
+//! GUI. Every command is one line, its output ends with a line that is either `OK` or
+//! `ERR <reason>`.
+//!
+//! - `ls [path]` lists a directory, one `<name>` or `<name>/` per line with the size
+//! - `cat <path>` prints a text file
+//! - `lockhash <password>` prints the `password` line of the lock screen config
+//! - `screenshot` sends the screen as a binary PPM, the size in its header tells how many
+//!   bytes follow before the `OK` line. Log lines don't go to the port meanwhile.
+//! - `quit` exits QEMU
+//!
+//! The port is polled once per timer tick, its receive interrupt isn't used.
+
+use alloc::{string::String, vec::Vec};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{
+    desktop::lock_screen,
+    exit::{QemuExitCode, exit_qemu},
+    framebuffer::{FRAMEBUFFER, FrameBufferWriter, SCREEN_SIZE},
+    fs::manager,
+    lock_order::OrderedMutex,
+    log,
+    log_sink::{self, SERIAL_SINK},
+    serial::{LogLevel, SERIAL1},
+    serial_println,
+    task::timer::Delay,
+};
+
+/// Longer lines are dropped, nothing the commands take is this long
+const MAX_LINE_LENGTH: usize = 256;
+
+/// Bytes of a screenshot row sent per lock of the port, so other prints aren't held up for a
+/// whole row
+const SCREENSHOT_CHUNK: usize = 64;
+
+/// Read commands from the serial port forever, spawn it on the desktop executor
+pub async fn run() {
+    let mut line = String::new();
+    let mut overflowed = false;
+
+    loop {
+        for byte in receive() {
+            match byte {
+                b'\r' | b'\n' => {
+                    if overflowed {
+                        serial_println!("ERR line too long");
+                    } else if !line.trim().is_empty() {
+                        execute(line.trim()).await;
+                    }
+                    line.clear();
+                    overflowed = false;
+                }
+                _ if line.len() >= MAX_LINE_LENGTH => overflowed = true,
+                byte => line.push(byte as char),
+            }
+        }
+
+        Delay::ticks(1).await;
+    }
+}
+
+/// The bytes waiting in the receive buffer
+fn receive() -> Vec<u8> {
+    without_interrupts(|| {
+        let mut bytes = Vec::new();
+        // Busy only while an exception prints, the bytes wait in the UART until next time
+        if let Some(mut serial) = SERIAL1.try_lock() {
+            while let Ok(byte) = serial.try_receive() {
+                bytes.push(byte);
+            }
+        }
+        bytes
+    })
+}
+
+async fn execute(line: &str) {
+    let (command, argument) = line
+        .split_once(' ')
+        .map_or((line, ""), |(command, argument)| (command, argument.trim()));
+
+    let result = match command {
+        "ls" => list(argument),
+        "cat" => print_file(argument),
+        "lockhash" if argument.is_empty() => Err("usage: lockhash <password>"),
+        "lockhash" => {
+            serial_println!("password = {:x}", lock_screen::hash_password(argument));
+            Ok(())
+        }
+        "quit" => {
+            serial_println!("OK");
+            exit_qemu(QemuExitCode::Success);
+            Ok(())
+        }
+        "run" => Err("there is no process loader"),
+        "screenshot" => screenshot().await,
+        _ => Err("unknown command"),
+    };
+
+    match result {
+        Ok(()) => serial_println!("OK"),
+        Err(e) => serial_println!("ERR {}", e),
+    }
+}
+
+fn list(path: &str) -> Result<(), &'static str> {
+    for entry in manager::list_path(path)? {
+        if entry.is_directory {
+            serial_println!("{}/", entry.name);
+        } else {
+            serial_println!("{} {}", entry.name, entry.size);
+        }
+    }
+    Ok(())
+}
+
+fn print_file(path: &str) -> Result<(), &'static str> {
+    if path.is_empty() {
+        return Err("usage: cat <path>");
+    }
+    for line in manager::read_text_path(path)?.lines() {
+        serial_println!("{}", line);
+    }
+    Ok(())
+}
+
+async fn screenshot() -> Result<(), &'static str> {
+    let (Some(framebuffer), Some(&(width, height))) = (FRAMEBUFFER.get(), SCREEN_SIZE.get()) else {
+        return Err("no framebuffer");
+    };
+
+    // A log line in the middle would break the image, the other sinks still get them
+    log_sink::remove(&SERIAL_SINK);
+    send_screen(framebuffer, width as usize, height as usize).await;
+    if let Err(e) = log_sink::add(&SERIAL_SINK) {
+        log!(
+            LogLevel::Error,
+            "Serial logging is off after a screenshot: {}",
+            e
+        );
+    }
+    Ok(())
+}
+
+/// Send the screen row by row, the framebuffer is only locked while a row is copied and the
+/// desktop gets a tick between rows
+async fn send_screen(framebuffer: &OrderedMutex<FrameBufferWriter>, width: usize, height: usize) {
+    serial_println!("P6\n{} {}\n255", width, height);
+    let mut row = Vec::with_capacity(width * 3);
+    for y in 0..height {
+        row.clear();
+        {
+            let framebuffer = framebuffer.lock();
+            for x in 0..width {
+                let color = framebuffer.read_pixel(x, y);
+                row.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+        }
+
+        // Interrupts stay on, a print from a handler meanwhile is dropped by its `try_lock`.
+        // Raw, `send` would turn some bytes into escape sequences.
+        for chunk in row.chunks(SCREENSHOT_CHUNK) {
+            let mut serial = SERIAL1.lock();
+            for &byte in chunk {
+                serial.send_raw(byte);
+            }
+        }
+
+        Delay::ticks(1).await;
+    }
+}
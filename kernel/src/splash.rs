@@ -0,0 +1,161 @@
+//! Graphical boot screen with the OS name, a progress bar and a spinner that the timer
+//! interrupt keeps turning while a stage takes long.
+//!
+//! While the splash is up, [`println!`](crate::println) goes to the serial port instead
+//! of the framebuffer, so the boot log stays complete without drawing over the splash.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
+
+use crate::{
+    desktop::widgets::spinner::{self, SPINNER_SIZE},
+    framebuffer::{Color, FRAMEBUFFER, measure_text},
+};
+
+/// Show the text boot log on the framebuffer instead of the splash
+pub const VERBOSE_BOOT: bool = false;
+
+/// Calls to [`advance`] before the bar is full
+const STAGES: usize = 4;
+
+const BACKGROUND: Color = Color::new(20, 30, 60);
+const TITLE: &str = "goofy-os";
+const BAR_WIDTH: usize = 300;
+const BAR_HEIGHT: usize = 12;
+/// Space between the title, the bar and the stage name
+const GAP: usize = 24;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static STAGES_DONE: AtomicUsize = AtomicUsize::new(0);
+/// Spinner frame on the screen, `usize::MAX` before the first one
+static SPINNER_FRAME: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Top left corner of the progress bar
+fn bar_position(width: usize, height: usize) -> (usize, usize) {
+    (width.saturating_sub(BAR_WIDTH) / 2, height / 2)
+}
+
+/// Top left corner of the spinner, centered under the stage name
+fn spinner_position(width: usize, height: usize) -> (usize, usize) {
+    let (_, bar_y) = bar_position(width, height);
+    (
+        width.saturating_sub(SPINNER_SIZE) / 2,
+        bar_y + BAR_HEIGHT + 2 * GAP + RasterHeight::Size16.val(),
+    )
+}
+
+/// Clear the screen and draw the title and an empty progress bar
+pub fn show() {
+    if VERBOSE_BOOT {
+        return;
+    }
+    let Some(fb) = FRAMEBUFFER.get() else {
+        return;
+    };
+
+    let mut fb = fb.lock();
+    let (width, height) = fb.size();
+    fb.draw_rect((0, 0), (width - 1, height - 1), BACKGROUND);
+
+    let (title_width, title_height) = measure_text(TITLE, FontWeight::Bold, RasterHeight::Size32);
+    let (bar_x, bar_y) = bar_position(width, height);
+    fb.draw_raw_text(
+        TITLE,
+        width.saturating_sub(title_width) / 2,
+        bar_y.saturating_sub(GAP + title_height),
+        Color::WHITE,
+        BACKGROUND,
+        FontWeight::Bold,
+        RasterHeight::Size32,
+    );
+    fb.draw_rect_outline(
+        (bar_x, bar_y),
+        (bar_x + BAR_WIDTH - 1, bar_y + BAR_HEIGHT - 1),
+        Color::WHITE,
+    );
+
+    STAGES_DONE.store(0, Ordering::Relaxed);
+    SPINNER_FRAME.store(usize::MAX, Ordering::Relaxed);
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Mark a boot stage as done and show its name under the bar
+pub fn advance(stage: &str) {
+    if !is_active() {
+        return;
+    }
+    let Some(fb) = FRAMEBUFFER.get() else {
+        return;
+    };
+
+    let done = (STAGES_DONE.fetch_add(1, Ordering::Relaxed) + 1).min(STAGES);
+
+    let mut fb = fb.lock();
+    let (width, height) = fb.size();
+    let (bar_x, bar_y) = bar_position(width, height);
+
+    // Inside the outline
+    let filled = (BAR_WIDTH - 4) * done / STAGES;
+    if filled > 0 {
+        fb.draw_rect(
+            (bar_x + 2, bar_y + 2),
+            (bar_x + 1 + filled, bar_y + BAR_HEIGHT - 3),
+            Color::WHITE,
+        );
+    }
+
+    // Clear the previous stage name, then center the new one
+    let label_y = bar_y + BAR_HEIGHT + GAP;
+    let (label_width, label_height) =
+        measure_text(stage, FontWeight::Regular, RasterHeight::Size16);
+    fb.draw_rect(
+        (0, label_y),
+        (width - 1, label_y + label_height),
+        BACKGROUND,
+    );
+    fb.draw_raw_text(
+        stage,
+        width.saturating_sub(label_width) / 2,
+        label_y,
+        Color::GRAY,
+        BACKGROUND,
+        FontWeight::Regular,
+        RasterHeight::Size16,
+    );
+}
+
+/// Turn the spinner if its frame changed, called by the timer interrupt. A frame is skipped
+/// while the boot code is drawing.
+pub fn tick(now: u64) {
+    if !is_active() {
+        return;
+    }
+    let frame = spinner::frame_at(now);
+    if SPINNER_FRAME.load(Ordering::Relaxed) == frame {
+        return;
+    }
+    let Some(mut fb) = FRAMEBUFFER.get().and_then(|fb| fb.try_lock()) else {
+        return;
+    };
+
+    let (width, height) = fb.size();
+    let (x, y) = spinner_position(width, height);
+    for (dot, rect) in spinner::dot_rects(x, y).iter().enumerate() {
+        fb.draw_rect(
+            (rect.x, rect.y),
+            (rect.x + rect.width - 1, rect.y + rect.height - 1),
+            spinner::dot_color(dot, frame, Color::WHITE, BACKGROUND),
+        );
+    }
+    SPINNER_FRAME.store(frame, Ordering::Relaxed);
+}
+
+/// Boot is done, the desktop draws over the splash from here
+pub fn finish() {
+    ACTIVE.store(false, Ordering::Relaxed);
+}
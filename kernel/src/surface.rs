@@ -1,9 +1,9 @@
 use alloc::{string::String, vec, vec::Vec};
 use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
 
-use crate::framebuffer::{Color, FrameBufferWriter};
+use crate::framebuffer::{Color, FrameBufferWriter, line_height, wrap_text};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
 pub struct Rect {
     pub x: usize,
     pub y: usize,
@@ -21,14 +21,31 @@ impl Rect {
         }
     }
 
+    /// No pixels in it, it's neither drawn nor overlaps anything
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Whether the rectangles share a pixel. The right and bottom edges are exclusive, so
+    /// rectangles that only touch don't overlap.
     pub fn intersects(&self, other: &Rect) -> bool {
-        self.x < other.x + other.width
+        !self.is_empty()
+            && !other.is_empty()
+            && self.x < other.x + other.width
             && self.x + self.width > other.x
             && self.y < other.y + other.height
             && self.y + self.height > other.y
     }
 
+    /// The smallest rectangle around both, an empty one doesn't add anything
     pub fn union(&self, other: &Rect) -> Rect {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+
         let x1 = self.x.min(other.x);
         let y1 = self.y.min(other.y);
         let x2 = (self.x + self.width).max(other.x + other.width);
@@ -42,9 +59,32 @@ impl Rect {
         }
     }
 
+    /// The overlapping part, None if the rectangles don't overlap
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        if !self.intersects(other) {
+            return None;
+        }
+
+        let x1 = self.x.max(other.x);
+        let y1 = self.y.max(other.y);
+        let x2 = (self.x + self.width).min(other.x + other.width);
+        let y2 = (self.y + self.height).min(other.y + other.height);
+
+        Some(Rect::new(x1, y1, x2 - x1, y2 - y1))
+    }
+
     pub fn contains_point(&self, x: usize, y: usize) -> bool {
         x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
     }
+
+    /// Whether every pixel of `other` is in this one, an empty rectangle is in any
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.is_empty()
+            || (other.x >= self.x
+                && other.y >= self.y
+                && other.x + other.width <= self.x + self.width
+                && other.y + other.height <= self.y + self.height)
+    }
 }
 
 pub enum Shape {
@@ -68,6 +108,34 @@ pub enum Shape {
         font_size: RasterHeight,
         font_weight: FontWeight,
 
+        hide: bool,
+    },
+    /// Text that wraps inside a fixed rectangle and can be scrolled.
+    /// Lines outside of the rectangle are clipped, the background fills the whole rectangle.
+    TextBlock {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        content: String,
+        color: Color,
+        background_color: Color,
+
+        font_size: RasterHeight,
+        font_weight: FontWeight,
+        /// Pixels scrolled down from the first line
+        scroll_y: usize,
+
+        hide: bool,
+    },
+    /// A block of pixels, stored row by row
+    Image {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: Vec<Color>,
+
         hide: bool,
     },
 }
@@ -111,11 +179,56 @@ impl Shape {
                     height,
                 }
             }
+            Shape::TextBlock {
+                x,
+                y,
+                width,
+                height,
+                ..
+            }
+            | Shape::Image {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => Rect {
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            },
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        match self {
+            Shape::Rectangle { hide, .. }
+            | Shape::Text { hide, .. }
+            | Shape::TextBlock { hide, .. }
+            | Shape::Image { hide, .. } => *hide,
+        }
+    }
+
+    /// Height of all wrapped lines of a text block, for sizing scrollbars
+    pub fn content_height(&self) -> Option<usize> {
+        if let Shape::TextBlock {
+            width,
+            content,
+            font_size,
+            font_weight,
+            ..
+        } = self
+        {
+            let lines = wrap_text(content, *width, *font_weight, *font_size).len();
+            Some(lines * line_height(*font_size))
+        } else {
+            None
         }
     }
 
     pub fn intersects_rect(&self, rect: &Rect) -> bool {
-        if let Shape::Rectangle { hide: true, .. } | Shape::Text { hide: true, .. } = self {
+        if self.is_hidden() {
             return false;
         }
 
@@ -142,6 +255,22 @@ impl Shape {
                 *shape_x = x;
                 *shape_y = y;
             }
+            Shape::TextBlock {
+                x: shape_x,
+                y: shape_y,
+                ..
+            } => {
+                *shape_x = x;
+                *shape_y = y;
+            }
+            Shape::Image {
+                x: shape_x,
+                y: shape_y,
+                ..
+            } => {
+                *shape_x = x;
+                *shape_y = y;
+            }
         }
 
         old_bounds.union(&self.get_bounds())
@@ -156,6 +285,12 @@ impl Shape {
             Shape::Text { hide, .. } => {
                 *hide = !visible;
             }
+            Shape::TextBlock { hide, .. } => {
+                *hide = !visible;
+            }
+            Shape::Image { hide, .. } => {
+                *hide = !visible;
+            }
         }
         bounds
     }
@@ -213,6 +348,55 @@ impl Shape {
                     *font_size,
                 );
             }
+            Shape::TextBlock {
+                x,
+                y,
+                width,
+                height,
+                content,
+                color,
+                background_color,
+                font_size,
+                font_weight,
+                scroll_y,
+                hide,
+            } => {
+                if *hide || *width == 0 || *height == 0 {
+                    return;
+                }
+
+                let clip = Rect::new(*x + offset_x, *y + offset_y, *width, *height);
+                framebuffer.draw_rect(
+                    (clip.x, clip.y),
+                    (clip.x + width - 1, clip.y + height - 1),
+                    *background_color,
+                );
+
+                let line_height = line_height(*font_size);
+                let first_line = scroll_y / line_height;
+                let visible_lines = height.div_ceil(line_height) + 1;
+
+                for (i, line) in wrap_text(content, *width, *font_weight, *font_size)
+                    .into_iter()
+                    .enumerate()
+                    .skip(first_line)
+                    .take(visible_lines)
+                {
+                    let line_y = (clip.y + i * line_height) as isize - *scroll_y as isize;
+                    framebuffer.draw_text_line_clipped(
+                        line,
+                        (clip.x, line_y),
+                        *color,
+                        *background_color,
+                        *font_weight,
+                        *font_size,
+                        &clip,
+                    );
+                }
+            }
+            Shape::Image { .. } => {
+                self.render_clipped(framebuffer, offset_x, offset_y, &self.get_bounds());
+            }
         }
     }
 
@@ -223,8 +407,35 @@ impl Shape {
         offset_y: usize,
         clip_rect: &Rect,
     ) {
-        let shape_bounds = self.get_bounds();
-        if !shape_bounds.intersects(clip_rect) {
+        let Some(visible) = self.get_bounds().intersection(clip_rect) else {
+            return;
+        };
+
+        if let Shape::Image {
+            x,
+            y,
+            width,
+            pixels,
+            hide,
+            ..
+        } = self
+        {
+            if *hide {
+                return;
+            }
+
+            // Images are big, so only draw the part that was asked for
+            for pixel_y in visible.y..visible.y + visible.height {
+                let row = (pixel_y - y) * width;
+                for pixel_x in visible.x..visible.x + visible.width {
+                    framebuffer.write_pixel(
+                        pixel_x + offset_x,
+                        pixel_y + offset_y,
+                        pixels[row + pixel_x - x],
+                    );
+                }
+            }
+
             return;
         }
 
@@ -237,10 +448,13 @@ pub struct Surface {
     pub width: usize,
     pub height: usize,
     pub background_color: Color,
-    pub just_fill_bg: bool,
     shapes: Vec<Shape>,
     pub is_dirty: bool,
     pub dirty_regions: Vec<Rect>,
+    /// The shapes drawn off-screen, made by [`Surface::rasterize`]
+    pixels: Option<FrameBufferWriter>,
+    /// Parts of the pixels that changed since they were last put on the screen
+    blit_regions: Vec<Rect>,
 }
 
 impl Surface {
@@ -249,14 +463,20 @@ impl Surface {
             width,
             height,
             background_color,
-            just_fill_bg: false,
             shapes: Vec::new(),
             is_dirty: true,
             dirty_regions: vec![Rect::new(0, 0, width, height)], // Initially everything is dirty
+            pixels: None,
+            blit_regions: Vec::new(),
         }
     }
 
     pub fn mark_region_dirty(&mut self, region: Rect) {
+        // Would still pull in the shapes at its corner
+        if region.is_empty() {
+            return;
+        }
+
         let expanded_region = self.expand_region_for_overlapping_shapes(region);
 
         // Merge overlapping dirty regions to avoid fragmentation
@@ -288,6 +508,11 @@ impl Surface {
 
             // Find all shapes that intersect with the current region
             for shape in &self.shapes {
+                // Images are rendered clipped, so they don't need to be redrawn as a whole
+                if let Shape::Image { .. } = shape {
+                    continue;
+                }
+
                 if shape.intersects_rect(&current_region) {
                     let shape_bounds = shape.get_bounds();
                     let new_region = current_region.union(&shape_bounds);
@@ -317,6 +542,20 @@ impl Surface {
         self.mark_region_dirty(Rect::new(x, y, width, height));
     }
 
+    /// Something else was drawn over this part of the screen, for example a window that moved
+    /// away. The pixels are still right, so the next render only copies them back.
+    pub fn mark_region_exposed(&mut self, region: Rect) {
+        self.blit_regions.push(region);
+        self.is_dirty = true;
+    }
+
+    /// Change the size, shapes keep their position and the whole surface is redrawn
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.force_full_redraw();
+    }
+
     pub fn force_full_redraw(&mut self) {
         self.dirty_regions.clear();
         self.dirty_regions
@@ -429,7 +668,7 @@ impl Surface {
             // Calculate old bounds using current position and old content length
             let old_bounds = shape.get_bounds();
 
-            if let Shape::Text { content, .. } = shape {
+            if let Shape::Text { content, .. } | Shape::TextBlock { content, .. } = shape {
                 *content = new_content;
             }
 
@@ -452,8 +691,44 @@ impl Surface {
         }
     }
 
+    /// Scroll a text block, clamped so the last line stays at the bottom
+    pub fn scroll_text_block(&mut self, shape_id: usize, new_scroll_y: usize) -> bool {
+        let Some(shape) = self.shapes.get_mut(shape_id) else {
+            return false;
+        };
+        let Some(content_height) = shape.content_height() else {
+            return false;
+        };
+
+        if let Shape::TextBlock {
+            height, scroll_y, ..
+        } = shape
+        {
+            let new_scroll_y = new_scroll_y.min(content_height.saturating_sub(*height));
+            if *scroll_y != new_scroll_y {
+                *scroll_y = new_scroll_y;
+                let bounds = shape.get_bounds();
+                self.mark_region_dirty(bounds);
+            }
+        }
+        true
+    }
+
+    /// Height of all lines of a text block and how far it is scrolled
+    pub fn text_block_metrics(&self, shape_id: usize) -> Option<(usize, usize)> {
+        let shape = self.shapes.get(shape_id)?;
+        let content_height = shape.content_height()?;
+
+        match shape {
+            Shape::TextBlock { scroll_y, .. } => Some((content_height, *scroll_y)),
+            _ => None,
+        }
+    }
+
     pub fn update_text_color(&mut self, shape_id: usize, new_color: Color) -> bool {
-        if let Some(Shape::Text { color, .. }) = self.shapes.get_mut(shape_id) {
+        if let Some(Shape::Text { color, .. } | Shape::TextBlock { color, .. }) =
+            self.shapes.get_mut(shape_id)
+        {
             *color = new_color;
             let bounds = self.shapes[shape_id].get_bounds();
             self.mark_region_dirty(bounds);
@@ -463,6 +738,43 @@ impl Surface {
         }
     }
 
+    pub fn update_text_background(&mut self, shape_id: usize, new_color: Color) -> bool {
+        if let Some(
+            Shape::Text {
+                background_color, ..
+            }
+            | Shape::TextBlock {
+                background_color, ..
+            },
+        ) = self.shapes.get_mut(shape_id)
+        {
+            *background_color = new_color;
+            let bounds = self.shapes[shape_id].get_bounds();
+            self.mark_region_dirty(bounds);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn image_pixels(&self, shape_id: usize) -> Option<&[Color]> {
+        if let Some(Shape::Image { pixels, .. }) = self.shapes.get(shape_id) {
+            Some(pixels)
+        } else {
+            None
+        }
+    }
+
+    /// Mutable access to the pixels of an image shape.
+    /// The caller is responsible for marking the changed region dirty.
+    pub fn image_pixels_mut(&mut self, shape_id: usize) -> Option<&mut [Color]> {
+        if let Some(Shape::Image { pixels, .. }) = self.shapes.get_mut(shape_id) {
+            Some(pixels)
+        } else {
+            None
+        }
+    }
+
     pub fn remove_shape(&mut self, shape_id: usize) -> bool {
         if shape_id < self.shapes.len() {
             let bounds = self.shapes[shape_id].get_bounds();
@@ -479,9 +791,7 @@ impl Surface {
     }
 
     pub fn is_shape_visible(&self, shape_id: usize) -> Option<bool> {
-        self.shapes.get(shape_id).map(|shape| match shape {
-            Shape::Rectangle { hide, .. } | Shape::Text { hide, .. } => !hide,
-        })
+        self.shapes.get(shape_id).map(|shape| !shape.is_hidden())
     }
 
     pub fn clear_all_shapes(&mut self) {
@@ -494,73 +804,180 @@ impl Surface {
     pub fn get_shapes_at_point(&self, x: usize, y: usize) -> Vec<usize> {
         let mut result = Vec::new();
         for (i, shape) in self.shapes.iter().enumerate() {
-            if shape.get_bounds().contains_point(x, y) {
-                if let Shape::Rectangle { hide: false, .. } | Shape::Text { hide: false, .. } =
-                    shape
-                {
-                    result.push(i);
-                }
+            if shape.get_bounds().contains_point(x, y) && !shape.is_hidden() {
+                result.push(i);
             }
         }
         result
     }
 
+    /// Put the surface on the screen with its top left corner at the offset. Dirty regions are
+    /// drawn into the surface's pixels first, the pixels that changed are added to `damage`, the
+    /// screen areas redrawn this frame. Then every part of the surface under `damage` is copied
+    /// to the screen, which also covers what surfaces below drew there.
     pub fn render(
         &mut self,
         framebuffer: &mut FrameBufferWriter,
         offset_x: usize,
         offset_y: usize,
-        force: bool,
-    ) -> bool {
-        if !self.is_dirty && !force {
-            return false;
+        damage: &mut Vec<Rect>,
+    ) {
+        self.rasterize(framebuffer);
+        self.is_dirty = false;
+
+        let Some(pixels) = &self.pixels else {
+            self.blit_regions.clear();
+            return;
+        };
+
+        let bounds = Rect::new(0, 0, self.width, self.height);
+        for region in self.blit_regions.drain(..) {
+            if let Some(region) = region.intersection(&bounds) {
+                damage.push(Rect::new(
+                    region.x + offset_x,
+                    region.y + offset_y,
+                    region.width,
+                    region.height,
+                ));
+            }
         }
 
-        if force {
-            self.dirty_regions.clear();
-            self.dirty_regions
-                .push(Rect::new(0, 0, self.width, self.height));
+        let screen_bounds = Rect::new(offset_x, offset_y, self.width, self.height);
+        for region in damage.iter() {
+            let Some(visible) = region.intersection(&screen_bounds) else {
+                continue;
+            };
+
+            for row in visible.y..visible.y + visible.height {
+                framebuffer.write_raw_pixel_row(
+                    visible.x,
+                    row,
+                    pixels.read_raw_pixel_row(visible.x - offset_x, row - offset_y, visible.width),
+                );
+            }
         }
+    }
 
-        // Render each dirty region
-        for region in &self.dirty_regions {
-            // Clear the dirty region with background
-            // Use just_fill_bg mode only if we're doing a full redraw OR if there are no shapes
-            if self.just_fill_bg && (force || self.shapes.is_empty()) {
-                // For just_fill_bg mode with full redraw or no shapes
-                for y in region.y..(region.y + region.height) {
-                    for x in region.x..(region.x + region.width) {
-                        if x < self.width && y < self.height {
-                            framebuffer.write_pixel(
-                                x + offset_x,
-                                y + offset_y,
-                                self.background_color,
-                            );
-                        }
-                    }
-                }
-            } else {
-                // Use rect-based clearing for dirty regions with shapes
-                framebuffer.draw_rect(
-                    (region.x + offset_x, region.y + offset_y),
-                    (
-                        region.x + region.width - 1 + offset_x,
-                        region.y + region.height - 1 + offset_y,
+    /// Put all of the surface on the screen mixed with what's there, `alpha` out of 255. Unlike
+    /// [`Surface::render`] the area has to be drawn again from below before every call.
+    pub fn render_blended(
+        &mut self,
+        framebuffer: &mut FrameBufferWriter,
+        offset_x: usize,
+        offset_y: usize,
+        alpha: u8,
+    ) {
+        self.rasterize(framebuffer);
+        self.is_dirty = false;
+        self.blit_regions.clear();
+
+        let Some(pixels) = &self.pixels else {
+            return;
+        };
+
+        let mix = |under: u8, over: u8| {
+            ((under as u16 * (255 - alpha as u16) + over as u16 * alpha as u16) / 255) as u8
+        };
+        let (screen_width, screen_height) = framebuffer.size();
+        for y in 0..self.height.min(screen_height.saturating_sub(offset_y)) {
+            for x in 0..self.width.min(screen_width.saturating_sub(offset_x)) {
+                let over = pixels.read_pixel(x, y);
+                let under = framebuffer.read_pixel(offset_x + x, offset_y + y);
+                framebuffer.write_pixel(
+                    offset_x + x,
+                    offset_y + y,
+                    Color::new(
+                        mix(under.r, over.r),
+                        mix(under.g, over.g),
+                        mix(under.b, over.b),
                     ),
-                    self.background_color,
                 );
             }
+        }
+    }
+
+    /// Draw the shapes in the dirty regions, which become regions to put on the screen
+    fn draw_dirty_regions(&mut self, target: &mut FrameBufferWriter) -> bool {
+        if self.dirty_regions.is_empty() {
+            return false;
+        }
+
+        for region in &self.dirty_regions {
+            if region.width == 0 || region.height == 0 {
+                continue;
+            }
+
+            // Clear the dirty region with background, the target clips it to the surface
+            target.fill_rect(
+                (region.x, region.y),
+                (region.x + region.width - 1, region.y + region.height - 1),
+                self.background_color,
+            );
 
             // Only render shapes that intersect with this dirty region
             for shape in &self.shapes {
                 if shape.intersects_rect(region) {
-                    shape.render_clipped(framebuffer, offset_x, offset_y, region);
+                    shape.render_clipped(target, 0, 0, region);
                 }
             }
         }
 
-        self.dirty_regions.clear();
-        self.is_dirty = false;
+        self.blit_regions.append(&mut self.dirty_regions);
+        true
+    }
+
+    /// Draw the dirty regions into the surface's own pixels, without putting them on the screen.
+    /// The pixels are made in the format of `screen` on first use or after a resize, then
+    /// everything is drawn. Returns false if nothing was dirty.
+    pub fn rasterize(&mut self, screen: &FrameBufferWriter) -> bool {
+        if self.width == 0 || self.height == 0 {
+            return false;
+        }
+
+        let mut pixels = match self.pixels.take() {
+            Some(pixels) if pixels.size() == (self.width, self.height) => pixels,
+            _ => {
+                self.force_full_redraw();
+                FrameBufferWriter::offscreen(
+                    self.width,
+                    self.height,
+                    screen.pixel_format(),
+                    screen.bytes_per_pixel(),
+                )
+            }
+        };
+
+        let drawn = self.draw_dirty_regions(&mut pixels);
+        self.pixels = Some(pixels);
+        drawn
+    }
+
+    /// Free the rasterized pixels, for a surface that isn't shown while memory is low. They are
+    /// made and drawn again on the next render. Returns the bytes freed.
+    pub fn drop_pixels(&mut self) -> usize {
+        let Some(pixels) = self.pixels.take() else {
+            return 0;
+        };
+
+        let (width, height) = pixels.size();
+        self.blit_regions.clear();
+        width * height * pixels.bytes_per_pixel()
+    }
+
+    /// Copy the rasterized pixels into the pixels of `dst`, with the top left corner at (x, y)
+    /// and clipped to `dst`, which puts them on the screen with its next render. The copy lasts
+    /// until that part of `dst` is rasterized again. Returns false if one of the surfaces wasn't
+    /// rasterized yet.
+    pub fn composite_onto(&self, dst: &mut Surface, x: usize, y: usize) -> bool {
+        let (Some(src), Some(dst_pixels)) = (&self.pixels, &mut dst.pixels) else {
+            return false;
+        };
+
+        for row in 0..self.height {
+            dst_pixels.write_raw_pixel_row(x, y + row, src.read_raw_pixel_row(0, row, self.width));
+        }
+
+        dst.mark_region_exposed(Rect::new(x, y, self.width, self.height));
         true
     }
 
@@ -585,3 +1002,121 @@ impl Surface {
         Rect::new(0, 0, self.width, self.height)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bootloader_api::info::PixelFormat;
+
+    #[test_case]
+    fn rect_intersection() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert_eq!(
+            a.intersection(&Rect::new(5, 8, 10, 10)),
+            Some(Rect::new(5, 8, 5, 2))
+        );
+        assert_eq!(a.intersection(&Rect::new(10, 0, 5, 5)), None);
+        assert!(a.contains_point(9, 9));
+        assert!(!a.contains_point(10, 9));
+    }
+
+    #[test_case]
+    fn rects_sharing_an_edge_dont_intersect() {
+        let a = Rect::new(10, 10, 10, 10);
+
+        // Right, left, below and above, then only touching at a corner
+        for b in [
+            Rect::new(20, 10, 5, 10),
+            Rect::new(5, 10, 5, 10),
+            Rect::new(10, 20, 10, 5),
+            Rect::new(10, 5, 10, 5),
+            Rect::new(20, 20, 5, 5),
+        ] {
+            assert!(!a.intersects(&b), "{:?} touches {:?}", a, b);
+            assert!(!b.intersects(&a));
+        }
+
+        // One pixel further in overlaps
+        assert_eq!(
+            a.intersection(&Rect::new(19, 19, 5, 5)),
+            Some(Rect::new(19, 19, 1, 1))
+        );
+    }
+
+    #[test_case]
+    fn rect_containment_and_partial_overlap() {
+        let a = Rect::new(0, 0, 10, 10);
+
+        assert!(a.contains(&a));
+        assert!(a.contains(&Rect::new(2, 2, 8, 8)));
+        assert!(!a.contains(&Rect::new(2, 2, 9, 8)));
+        assert_eq!(
+            a.intersection(&Rect::new(2, 2, 3, 3)),
+            Some(Rect::new(2, 2, 3, 3))
+        );
+
+        let crossing = Rect::new(4, 0, 2, 20);
+        assert!(a.intersects(&crossing));
+        assert!(!a.contains(&crossing));
+        assert_eq!(a.intersection(&crossing), Some(Rect::new(4, 0, 2, 10)));
+    }
+
+    #[test_case]
+    fn empty_rects_overlap_nothing() {
+        let a = Rect::new(0, 0, 10, 10);
+
+        for empty in [
+            Rect::new(5, 5, 0, 0),
+            Rect::new(5, 5, 0, 3),
+            Rect::new(5, 5, 3, 0),
+        ] {
+            assert!(empty.is_empty());
+            assert!(!a.intersects(&empty));
+            assert!(!empty.intersects(&a));
+            assert_eq!(a.intersection(&empty), None);
+            assert!(a.contains(&empty));
+            assert_eq!(a.union(&empty), a);
+            assert_eq!(empty.union(&a), a);
+        }
+
+        assert_eq!(
+            a.union(&Rect::new(10, 0, 5, 5)),
+            Rect::new(0, 0, 15, 10),
+            "adjacent rects union without a gap"
+        );
+    }
+
+    #[test_case]
+    fn composite_copies_rasterized_pixels() {
+        let screen = FrameBufferWriter::offscreen(1, 1, PixelFormat::Rgb, 3);
+
+        let mut window = Surface::new(4, 4, Color::WHITE);
+        window.add_shape(Shape::Rectangle {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+            color: Color::BLUE,
+            filled: true,
+            hide: false,
+        });
+        let mut desktop = Surface::new(8, 8, Color::BLACK);
+
+        assert!(!window.composite_onto(&mut desktop, 0, 0));
+        assert!(window.rasterize(&screen));
+        assert!(desktop.rasterize(&screen));
+        assert!(
+            !window.rasterize(&screen),
+            "nothing changed since the last time"
+        );
+
+        // Partly past the bottom right corner, the rest is clipped
+        assert!(window.composite_onto(&mut desktop, 5, 5));
+        let pixels = desktop.pixels.as_ref().unwrap();
+        let rgb = |x, y| pixels.read_pixel(x, y).to_rgb();
+        assert_eq!(rgb(4, 4), Color::BLACK.to_rgb());
+        assert_eq!(rgb(5, 5), Color::WHITE.to_rgb());
+        assert_eq!(rgb(6, 6), Color::BLUE.to_rgb());
+        assert_eq!(rgb(7, 7), Color::BLUE.to_rgb());
+    }
+}
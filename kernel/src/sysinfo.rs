@@ -4,10 +4,82 @@ use alloc::{
     vec::Vec,
 };
 
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+use conquer_once::spin::OnceCell;
+
 use crate::allocator::{ALLOCATOR, HEAP_SIZE, HEAP_START};
 
 pub static mut STACK_BASE: usize = 0;
 
+/// The bootloader's memory map, summarized once at boot since it never changes
+static MEMORY_MAP: OnceCell<MemoryMap> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Free for the kernel to use
+    Usable,
+    /// Holds the kernel, the bootloader or the boot info
+    Bootloader,
+    /// Firmware and device memory
+    Reserved,
+}
+
+impl MemoryKind {
+    fn of(kind: MemoryRegionKind) -> Self {
+        match kind {
+            MemoryRegionKind::Usable => MemoryKind::Usable,
+            MemoryRegionKind::Bootloader => MemoryKind::Bootloader,
+            _ => MemoryKind::Reserved,
+        }
+    }
+}
+
+/// A stretch of physical memory of one kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySpan {
+    pub kind: MemoryKind,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMap {
+    /// In address order, neighbouring regions of the same kind are merged
+    pub spans: Vec<MemorySpan>,
+    pub total_bytes: u64,
+    pub usable_bytes: u64,
+    pub largest_free_bytes: u64,
+}
+
+impl MemoryMap {
+    pub fn from_regions(regions: &[MemoryRegion]) -> Self {
+        let mut map = MemoryMap::default();
+
+        for region in regions {
+            let kind = MemoryKind::of(region.kind);
+            let bytes = region.end.saturating_sub(region.start);
+
+            map.total_bytes += bytes;
+            if kind == MemoryKind::Usable {
+                map.usable_bytes += bytes;
+                map.largest_free_bytes = map.largest_free_bytes.max(bytes);
+            }
+
+            match map.spans.last_mut() {
+                Some(last) if last.kind == kind => last.bytes += bytes,
+                _ => map.spans.push(MemorySpan { kind, bytes }),
+            }
+        }
+
+        map
+    }
+}
+
+/// Summarize the memory map for the system information panel, needs the heap
+pub fn record_memory_map(regions: &[MemoryRegion]) {
+    let map = MemoryMap::from_regions(regions);
+    MEMORY_MAP.init_once(|| map);
+}
+
 #[derive(Debug, Clone)]
 pub struct SystemInfo {
     pub os_name: String,
@@ -21,6 +93,7 @@ pub struct SystemInfo {
     pub heap_start: usize,
     pub heap_used: usize,
     pub stack_size: usize,
+    pub memory_map: MemoryMap,
     pub cpu_features: Vec<String>,
 }
 
@@ -43,6 +116,7 @@ impl SystemInfo {
             heap_used: heap_info.used_bytes,
 
             stack_size: 4096 * 5, // From gdt.rs STACK_SIZE
+            memory_map: MEMORY_MAP.get().cloned().unwrap_or_default(),
             cpu_features: cpu_info.features,
         }
     }
@@ -179,3 +253,32 @@ pub fn estimate_stack_usage() -> usize {
     // Clamp to reasonable values
     estimated_used.min(4096 * 5) // Max our known stack size
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn memory_map_merges_regions_of_one_kind() {
+        let region = |start, end, kind| MemoryRegion { start, end, kind };
+        let map = MemoryMap::from_regions(&[
+            region(0, 0x1000, MemoryRegionKind::Bootloader),
+            region(0x1000, 0x3000, MemoryRegionKind::Usable),
+            region(0x3000, 0x8000, MemoryRegionKind::Usable),
+            region(0x8000, 0x9000, MemoryRegionKind::UnknownBios(2)),
+            region(0x9000, 0xA000, MemoryRegionKind::Usable),
+        ]);
+
+        assert_eq!(map.total_bytes, 0xA000);
+        assert_eq!(map.usable_bytes, 0x8000);
+        assert_eq!(map.largest_free_bytes, 0x5000);
+        assert_eq!(map.spans.len(), 4);
+        assert_eq!(
+            map.spans[1],
+            MemorySpan {
+                kind: MemoryKind::Usable,
+                bytes: 0x7000
+            }
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use conquer_once::spin::OnceCell;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+
+use super::{Task, TaskId};
+use crate::{log, serial::LogLevel};
+
+/// Tasks that can wait to be polled, and to be picked up after being spawned
+const QUEUE_SIZE: usize = 100;
+
+/// Tasks spawned with [`spawn`], the executor takes them over on its next run
+static SPAWN_QUEUE: OnceCell<ArrayQueue<Task>> = OnceCell::uninit();
+
+/// Tasks that were spawned and haven't finished yet
+static TASK_COUNT: AtomicUsize = AtomicUsize::new(0);
+const DEFAULT_MAX_TASKS: usize = 32;
+/// Most tasks alive at once, see [`set_max_tasks`]
+static MAX_TASKS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_TASKS);
+
+/// Limit the tasks alive at once. It can't be more than the queue holds: a task is in its
+/// queue at most once, so then every wake up finds room.
+pub fn set_max_tasks(count: usize) {
+    MAX_TASKS.store(count.min(QUEUE_SIZE), Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// No [`Executor`] was created yet
+    NotRunning,
+    /// The limit of [`set_max_tasks`] is reached
+    TooManyTasks,
+    /// The queue of tasks waiting to start is full
+    QueueFull,
+    /// A task with the same id is already running
+    DuplicateId,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SpawnError::NotRunning => "Executor not running",
+            SpawnError::TooManyTasks => "Too many tasks",
+            SpawnError::QueueFull => "Too many tasks waiting to start",
+            SpawnError::DuplicateId => "Task id already in use",
+        })
+    }
+}
+
+/// Which run queue a task is woken into. High priority tasks are polled first, and again
+/// between the normal ones, so input doesn't wait behind background work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    High,
+    Normal,
+}
+
+/// Run a future on the executor of the desktop loop. The task keeps running when the
+/// returned handle is dropped.
+pub fn spawn<T: Send + 'static>(
+    future: impl Future<Output = T> + Send + 'static,
+) -> Result<JoinHandle<T>, SpawnError> {
+    spawn_with_priority(future, Priority::Normal)
+}
+
+pub fn spawn_with_priority<T: Send + 'static>(
+    future: impl Future<Output = T> + Send + 'static,
+    priority: Priority,
+) -> Result<JoinHandle<T>, SpawnError> {
+    let queue = SPAWN_QUEUE.get().ok_or(SpawnError::NotRunning)?;
+
+    let max_tasks = MAX_TASKS.load(Ordering::Relaxed);
+    TASK_COUNT
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+            (count < max_tasks).then_some(count + 1)
+        })
+        .map_err(|_| SpawnError::TooManyTasks)?;
+
+    let state = Arc::new(JoinState {
+        output: spin::Mutex::new(None),
+        finished: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+    });
+    let completion = state.clone();
+    let task = Task::new(
+        async move {
+            let output = future.await;
+            *completion.output.lock() = Some(output);
+            completion.finished.store(true, Ordering::Release);
+            completion.waker.wake();
+        },
+        priority,
+    );
+
+    let id = task.id;
+    queue.push(task).map_err(|_| {
+        TASK_COUNT.fetch_sub(1, Ordering::Relaxed);
+        SpawnError::QueueFull
+    })?;
+    Ok(JoinHandle { id, state })
+}
+
+struct JoinState<T> {
+    output: spin::Mutex<Option<T>>,
+    finished: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Waits for a spawned task and returns its output. The output can only be taken once,
+/// awaiting the handle again after that never finishes.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    state: Arc<JoinState<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    /// Take the output if the task finished, for code outside of a task like the apps
+    pub fn try_take(&self) -> Option<T> {
+        self.state.output.lock().take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        if let Some(output) = self.try_take() {
+            return Poll::Ready(output);
+        }
+
+        self.state.waker.register(context.waker());
+
+        // Finished before the waker was registered
+        match self.try_take() {
+            Some(output) => Poll::Ready(output),
+            None => Poll::Pending,
+        }
+    }
+}
+
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    high_priority_queue: Arc<ArrayQueue<TaskId>>,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+    waker_cache: BTreeMap<TaskId, Waker>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        let _ = SPAWN_QUEUE.try_init_once(|| ArrayQueue::new(QUEUE_SIZE));
+
+        Executor {
+            tasks: BTreeMap::new(),
+            high_priority_queue: Arc::new(ArrayQueue::new(QUEUE_SIZE)),
+            task_queue: Arc::new(ArrayQueue::new(QUEUE_SIZE)),
+            waker_cache: BTreeMap::new(),
+        }
+    }
+
+    fn queue(&self, priority: Priority) -> &Arc<ArrayQueue<TaskId>> {
+        match priority {
+            Priority::High => &self.high_priority_queue,
+            Priority::Normal => &self.task_queue,
+        }
+    }
+
+    fn add_task(&mut self, task: Task) -> Result<(), SpawnError> {
+        let task_id = task.id;
+        if self.tasks.contains_key(&task_id) {
+            return Err(SpawnError::DuplicateId);
+        }
+        self.queue(task.priority)
+            .push(task_id)
+            .map_err(|_| SpawnError::QueueFull)?;
+        task.queued.store(true, Ordering::SeqCst);
+        self.tasks.insert(task_id, task);
+        Ok(())
+    }
+
+    /// No task is waiting to be polled or started
+    pub fn is_idle(&self) -> bool {
+        self.high_priority_queue.is_empty()
+            && self.task_queue.is_empty()
+            && SPAWN_QUEUE.get().is_none_or(|queue| queue.is_empty())
+    }
+
+    /// Poll the tasks that were woken since the last run. Tasks woken during this run wait
+    /// for the next one, so a task that keeps yielding can't hold up the caller. The
+    /// exception are high priority tasks, they are also polled between the normal ones.
+    pub fn run_ready_tasks(&mut self) {
+        if let Some(spawn_queue) = SPAWN_QUEUE.get() {
+            while let Some(task) = spawn_queue.pop() {
+                // The task is dropped, its caller was already told it started
+                if let Err(e) = self.add_task(task) {
+                    log!(LogLevel::Error, "Dropping a spawned task: {}", e);
+                    TASK_COUNT.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        self.run_high_priority_tasks();
+        for _ in 0..self.task_queue.len() {
+            let Some(task_id) = self.task_queue.pop() else {
+                break;
+            };
+            self.poll_task(task_id);
+
+            // At most once per normal task, so a high priority task can't keep the run going
+            self.run_high_priority_tasks();
+        }
+    }
+
+    fn run_high_priority_tasks(&mut self) {
+        for _ in 0..self.high_priority_queue.len() {
+            let Some(task_id) = self.high_priority_queue.pop() else {
+                break;
+            };
+            self.poll_task(task_id);
+        }
+    }
+
+    fn poll_task(&mut self, task_id: TaskId) {
+        // A waker can outlive its task
+        let Some(task) = self.tasks.get_mut(&task_id) else {
+            return;
+        };
+        // Wakes from here on queue the task again
+        task.queued.store(false, Ordering::SeqCst);
+
+        let queue = match task.priority {
+            Priority::High => &self.high_priority_queue,
+            Priority::Normal => &self.task_queue,
+        };
+        let waker = self
+            .waker_cache
+            .entry(task_id)
+            .or_insert_with(|| TaskWaker::new(task_id, task.queued.clone(), queue.clone()));
+        let mut context = Context::from_waker(waker);
+
+        if let Poll::Ready(()) = task.poll(&mut context) {
+            self.tasks.remove(&task_id);
+            self.waker_cache.remove(&task_id);
+            TASK_COUNT.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct TaskWaker {
+    task_id: TaskId,
+    queued: Arc<AtomicBool>,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+}
+
+impl TaskWaker {
+    fn new(task_id: TaskId, queued: Arc<AtomicBool>, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            task_id,
+            queued,
+            task_queue,
+        }))
+    }
+
+    fn wake_task(&self) {
+        // Already waiting to be polled
+        if self.queued.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Wakers are called from interrupt handlers, so this must not block
+        if self.task_queue.push(self.task_id).is_err() {
+            self.queued.store(false, Ordering::SeqCst);
+            log!(LogLevel::Warn, "Task queue full, dropping a wake up");
+        }
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::yield_now;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn test_spawn_past_the_limit_fails() {
+        let mut executor = Executor::new();
+
+        set_max_tasks(1);
+        let first = spawn(async {});
+        let second = spawn(async {});
+        set_max_tasks(DEFAULT_MAX_TASKS);
+
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(SpawnError::TooManyTasks)));
+
+        // The finished task gives its slot back
+        executor.run_ready_tasks();
+        assert!(spawn(async {}).is_ok());
+        executor.run_ready_tasks();
+    }
+
+    #[test_case]
+    fn test_woken_task_is_queued_once() {
+        let mut executor = Executor::new();
+        let polls = Arc::new(AtomicUsize::new(0));
+        {
+            let polls = polls.clone();
+            spawn(core::future::poll_fn(move |context| {
+                if polls.fetch_add(1, Ordering::Relaxed) > 0 {
+                    return Poll::Ready(());
+                }
+
+                // More wake ups than the queue has room for
+                for _ in 0..2 * QUEUE_SIZE {
+                    context.waker().wake_by_ref();
+                }
+                Poll::Pending
+            }))
+            .unwrap();
+        }
+
+        executor.run_ready_tasks();
+        assert_eq!(executor.task_queue.len(), 1);
+
+        executor.run_ready_tasks();
+        assert_eq!(polls.load(Ordering::Relaxed), 2);
+        assert!(executor.is_idle());
+    }
+
+    #[test_case]
+    fn test_high_priority_task_runs_before_flood() {
+        let mut executor = Executor::new();
+        let order = Arc::new(spin::Mutex::new(Vec::new()));
+
+        for id in 0..20 {
+            let order = order.clone();
+            spawn(async move { order.lock().push(id) }).unwrap();
+        }
+        {
+            let order = order.clone();
+            spawn_with_priority(async move { order.lock().push(-1) }, Priority::High).unwrap();
+        }
+
+        executor.run_ready_tasks();
+
+        let order = order.lock();
+        assert_eq!(order.len(), 21);
+        assert_eq!(order[0], -1);
+    }
+
+    #[test_case]
+    fn test_task_awaits_spawned_child() {
+        let mut executor = Executor::new();
+        let log = Arc::new(spin::Mutex::new(Vec::new()));
+
+        let parent = {
+            let log = log.clone();
+            spawn(async move {
+                let child_log = log.clone();
+                let child = spawn(async move {
+                    yield_now().await;
+                    child_log.lock().push("child");
+                    42
+                })
+                .unwrap();
+
+                let output = child.await;
+                log.lock().push("parent");
+                output
+            })
+            .unwrap()
+        };
+
+        for _ in 0..10 {
+            executor.run_ready_tasks();
+        }
+
+        assert!(parent.is_finished());
+        assert_eq!(parent.try_take(), Some(42));
+        assert_eq!(*log.lock(), ["child", "parent"]);
+    }
+}
@@ -0,0 +1,73 @@
+//! Cooperative async tasks. The desktop loop runs them between frames, so long running
+//! work like disk reads can wait for interrupts without freezing the screen.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+pub mod executor;
+pub mod sync;
+pub mod timer;
+
+use executor::Priority;
+
+pub struct Task {
+    id: TaskId,
+    priority: Priority,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    /// Set while the id is in a run queue, shared with the waker so waking the task again
+    /// doesn't take another place in the queue
+    queued: Arc<AtomicBool>,
+}
+
+impl Task {
+    pub fn new(future: impl Future<Output = ()> + Send + 'static, priority: Priority) -> Task {
+        Task {
+            id: TaskId::new(),
+            priority,
+            future: Box::pin(future),
+            queued: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.future.as_mut().poll(context)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Give the other tasks a turn, the task continues on the next executor run
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,345 @@
+//! Locks for sharing state between async tasks. A task that has to wait is parked until it
+//! is woken, instead of spinning and holding up the executor.
+//!
+//! Interrupt handlers must keep using `spin::Mutex`, they can't wait for a task to give a
+//! lock back. [`Notify::notify`] is the exception, it never blocks.
+
+use alloc::collections::VecDeque;
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+use futures_util::task::AtomicWaker;
+
+/// A mutex whose [`Mutex::lock`] waits as a future. It isn't fair, a task that asks again
+/// right after unlocking can get the lock before a woken one.
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    /// Tasks waiting for the lock, one is woken per unlock
+    waiters: spin::Mutex<Waiters>,
+    data: UnsafeCell<T>,
+}
+
+/// One slot per waiting [`MutexLock`], it keeps the same slot when polled again and takes
+/// it out when it gets the lock or is dropped
+struct Waiters {
+    queue: VecDeque<(usize, Waker)>,
+    next_slot: usize,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            waiters: spin::Mutex::new(Waiters {
+                queue: VecDeque::new(),
+                next_slot: 0,
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Take the lock if it is free
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+            .then_some(MutexGuard { mutex: self })
+    }
+
+    /// Wait until the lock is free and take it
+    pub fn lock(&self) -> MutexLock<'_, T> {
+        MutexLock {
+            mutex: self,
+            slot: None,
+        }
+    }
+
+    fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+        self.wake_next();
+    }
+
+    fn wake_next(&self) {
+        let waiter = self.waiters.lock().queue.pop_front();
+        if let Some((_, waker)) = waiter {
+            waker.wake();
+        }
+    }
+
+    /// Queue a waker, or update the one in `slot` if it is still queued
+    fn park(&self, slot: Option<usize>, waker: &Waker) -> usize {
+        let mut waiters = self.waiters.lock();
+        if let Some(slot) = slot {
+            if let Some((_, queued)) = waiters.queue.iter_mut().find(|(id, _)| *id == slot) {
+                queued.clone_from(waker);
+                return slot;
+            }
+        }
+
+        let slot = waiters.next_slot;
+        waiters.next_slot = waiters.next_slot.wrapping_add(1);
+        waiters.queue.push_back((slot, waker.clone()));
+        slot
+    }
+
+    /// Take a slot out of the queue, false if an unlock already woke it
+    fn unpark(&self, slot: usize) -> bool {
+        let mut waiters = self.waiters.lock();
+        match waiters.queue.iter().position(|(id, _)| *id == slot) {
+            Some(index) => {
+                waiters.queue.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct MutexLock<'a, T> {
+    mutex: &'a Mutex<T>,
+    /// Our place in the queue once we had to wait
+    slot: Option<usize>,
+}
+
+impl<'a, T> MutexLock<'a, T> {
+    fn take(&mut self) -> Option<MutexGuard<'a, T>> {
+        let guard = self.mutex.try_lock()?;
+        if let Some(slot) = self.slot.take() {
+            self.mutex.unpark(slot);
+        }
+        Some(guard)
+    }
+}
+
+impl<'a, T> Future for MutexLock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        if let Some(guard) = self.take() {
+            return Poll::Ready(guard);
+        }
+
+        self.slot = Some(self.mutex.park(self.slot, context.waker()));
+
+        // Unlocked before the waker was queued, it would never be woken
+        match self.take() {
+            Some(guard) => Poll::Ready(guard),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for MutexLock<'_, T> {
+    fn drop(&mut self) {
+        // Woken by an unlock but never took the lock, the next waiter gets the wake up
+        if let Some(slot) = self.slot.take() {
+            if !self.mutex.unpark(slot) {
+                self.mutex.wake_next();
+            }
+        }
+    }
+}
+
+/// Unlocks the [`Mutex`] when dropped
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Wakes a task waiting for something to happen, like a condition variable for one waiter.
+/// A notification without a waiter is kept, so the next [`Notify::wait`] finishes at once
+/// and checking a condition before waiting can't miss it.
+pub struct Notify {
+    notified: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self {
+            notified: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Wake the waiting task, safe to call from interrupt handlers
+    pub fn notify(&self) {
+        self.notified.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    /// Wait for the next notification
+    pub fn wait(&self) -> Wait<'_> {
+        Wait { notify: self }
+    }
+}
+
+pub struct Wait<'a> {
+    notify: &'a Notify,
+}
+
+impl Future for Wait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.notify.notified.swap(false, Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        self.notify.waker.register(context.waker());
+
+        // Notified before the waker was registered
+        if self.notify.notified.swap(false, Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::{
+        executor::{self, Executor},
+        yield_now,
+    };
+    use alloc::{sync::Arc, vec::Vec};
+    use futures_util::task::noop_waker_ref;
+
+    /// Run the executor until no task is woken anymore
+    fn run_until_idle(executor: &mut Executor) {
+        for _ in 0..100 {
+            if executor.is_idle() {
+                return;
+            }
+            executor.run_ready_tasks();
+        }
+        panic!("tasks never went idle");
+    }
+
+    #[test_case]
+    fn test_contending_tasks_take_turns() {
+        let mut executor = Executor::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        for id in 0..2 {
+            let log = log.clone();
+            executor::spawn(async move {
+                for _ in 0..3 {
+                    let mut log = log.lock().await;
+                    log.push(id);
+                    // The other task runs now and has to wait for the lock
+                    yield_now().await;
+                    log.push(id);
+                }
+            })
+            .unwrap();
+        }
+
+        // A waiting task isn't polled again until it is woken, so a lost wake up would
+        // leave the log short
+        run_until_idle(&mut executor);
+
+        let log = log.try_lock().unwrap();
+        assert_eq!(log.len(), 12);
+        assert!(log.chunks(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test_case]
+    fn test_cancelled_waiter_passes_the_lock_on() {
+        let mut executor = Executor::new();
+        let mutex = Arc::new(Mutex::new(0));
+        let mut context = Context::from_waker(noop_waker_ref());
+
+        let spawn_contender = || {
+            let mutex = mutex.clone();
+            executor::spawn(async move {
+                *mutex.lock().await += 1;
+            })
+            .unwrap();
+        };
+
+        // Gives up while queued, polled twice it still has one slot
+        let guard = mutex.try_lock().unwrap();
+        let mut cancelled = mutex.lock();
+        assert!(Pin::new(&mut cancelled).poll(&mut context).is_pending());
+        assert!(Pin::new(&mut cancelled).poll(&mut context).is_pending());
+        drop(cancelled);
+
+        spawn_contender();
+        run_until_idle(&mut executor);
+        drop(guard);
+        run_until_idle(&mut executor);
+        assert_eq!(*mutex.try_lock().unwrap(), 1);
+
+        // Gives up after the unlock woke it, before it could take the lock
+        let guard = mutex.try_lock().unwrap();
+        let mut cancelled = mutex.lock();
+        assert!(Pin::new(&mut cancelled).poll(&mut context).is_pending());
+
+        spawn_contender();
+        run_until_idle(&mut executor);
+        drop(guard);
+        drop(cancelled);
+        run_until_idle(&mut executor);
+        assert_eq!(*mutex.try_lock().unwrap(), 2);
+    }
+
+    #[test_case]
+    fn test_notify_wakes_waiting_task() {
+        let mut executor = Executor::new();
+        let notify = Arc::new(Notify::new());
+        let done = Arc::new(AtomicBool::new(false));
+
+        {
+            let notify = notify.clone();
+            let done = done.clone();
+            executor::spawn(async move {
+                notify.wait().await;
+                done.store(true, Ordering::Relaxed);
+            })
+            .unwrap();
+        }
+
+        run_until_idle(&mut executor);
+        assert!(!done.load(Ordering::Relaxed));
+
+        notify.notify();
+        run_until_idle(&mut executor);
+        assert!(done.load(Ordering::Relaxed));
+    }
+}
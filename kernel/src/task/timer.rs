@@ -0,0 +1,147 @@
+//! Waiting for time to pass in a task. Each [`Delay`] registers its waker under the tick it
+//! is due at, and the timer interrupt wakes the due ones.
+//!
+//! The interrupt only calls `wake_by_ref`, a delay removes its own entry when it is polled
+//! or dropped. Dropping the last reference to a waker could free memory, and the interrupt
+//! may have stopped the allocator halfway.
+
+use alloc::collections::BTreeMap;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::interrupts::{ms_to_ticks, ticks};
+
+struct Timer {
+    waker: Waker,
+    /// Woken once, the interrupt skips it until the delay is polled again
+    woken: bool,
+}
+
+/// Registered delays by due tick, the second key tells delays due at the same tick apart.
+/// Only locked with interrupts off, so the interrupt never finds it locked on one CPU.
+static TIMERS: spin::Mutex<BTreeMap<(u64, u64), Timer>> = spin::Mutex::new(BTreeMap::new());
+/// Earliest tick a timer that wasn't woken yet is due at, so most ticks don't lock at all
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Wake the delays due at `now`, called by the timer interrupt
+pub(crate) fn wake_due(now: u64) {
+    if now < NEXT_DEADLINE.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(mut timers) = TIMERS.try_lock() else {
+        return;
+    };
+
+    for timer in timers.range_mut(..=(now, u64::MAX)).map(|(_, timer)| timer) {
+        if !timer.woken {
+            timer.woken = true;
+            timer.waker.wake_by_ref();
+        }
+    }
+
+    let next = timers.range((now + 1, 0)..).next();
+    NEXT_DEADLINE.store(
+        next.map_or(u64::MAX, |(&(tick, _), _)| tick),
+        Ordering::Relaxed,
+    );
+}
+
+/// Finishes once the timer reached a tick, see [`Delay::ticks`] and [`Delay::ms`]
+pub struct Delay {
+    deadline: u64,
+    /// Key in [`TIMERS`], set while registered
+    key: Option<(u64, u64)>,
+}
+
+impl Delay {
+    /// Wait for `count` timer ticks
+    pub fn ticks(count: u64) -> Self {
+        Self::until(ticks() + count)
+    }
+
+    /// Wait at least `ms` milliseconds, rounded up to whole ticks
+    pub fn ms(ms: u64) -> Self {
+        Self::ticks(ms_to_ticks(ms))
+    }
+
+    /// Wait until [`ticks`] reaches `deadline`
+    pub fn until(deadline: u64) -> Self {
+        Self {
+            deadline,
+            key: None,
+        }
+    }
+
+    fn unregister(&mut self) {
+        if let Some(key) = self.key.take() {
+            without_interrupts(|| TIMERS.lock().remove(&key));
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if ticks() >= self.deadline {
+            self.unregister();
+            return Poll::Ready(());
+        }
+
+        let deadline = self.deadline;
+        let key = *self
+            .key
+            .get_or_insert_with(|| (deadline, NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+        let timer = Timer {
+            waker: context.waker().clone(),
+            woken: false,
+        };
+        // A tick between the check above and here is fine, the next one finds the timer due
+        without_interrupts(|| {
+            TIMERS.lock().insert(key, timer);
+            NEXT_DEADLINE.fetch_min(key.0, Ordering::Relaxed);
+        });
+        Poll::Pending
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::executor::{self, Executor};
+
+    #[test_case]
+    fn test_delay_waits_for_ticks() {
+        let mut executor = Executor::new();
+        let start = ticks();
+
+        let handle = executor::spawn(async {
+            Delay::ticks(3).await;
+            ticks()
+        })
+        .unwrap();
+
+        // The tests run with interrupts off, the timer only ticks while halted
+        while !handle.is_finished() {
+            executor.run_ready_tasks();
+            x86_64::instructions::interrupts::enable_and_hlt();
+            x86_64::instructions::interrupts::disable();
+            assert!(ticks() < start + 100, "delay never finished");
+        }
+
+        assert!(handle.try_take().unwrap() >= start + 3);
+        assert!(TIMERS.lock().is_empty());
+    }
+}
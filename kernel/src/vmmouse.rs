@@ -0,0 +1,145 @@
+//! Absolute pointer of VMware and QEMU (`vmmouse`), talked to through the VMware backdoor port.
+//!
+//! Once absolute mode is on, the PS/2 mouse still raises IRQ 12 for every movement, but the
+//! bytes it sends are meaningless. The interrupt handler reads the position from here instead.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{desktop::input::MouseEvent, log, serial::LogLevel};
+
+/// How the cursor position is worked out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseMode {
+    /// Absolute if the VM has a vmmouse, relative PS/2 packets otherwise
+    Auto,
+    /// Always use the PS/2 deltas, even in a VM with an absolute pointer
+    Relative,
+    /// Use the vmmouse, falls back to relative with a warning if there is none
+    Absolute,
+}
+
+/// Change this to force one of the modes
+pub const MOUSE_MODE: MouseMode = MouseMode::Auto;
+
+const BACKDOOR_MAGIC: u32 = 0x564D_5868;
+const BACKDOOR_PORT: u16 = 0x5658;
+
+const CMD_GET_VERSION: u32 = 10;
+const CMD_ABSPOINTER_DATA: u32 = 39;
+const CMD_ABSPOINTER_STATUS: u32 = 40;
+const CMD_ABSPOINTER_COMMAND: u32 = 41;
+
+const ABSPOINTER_READ_ID: u32 = 0x4541_4552;
+const ABSPOINTER_DISABLE: u32 = 0x0000_00F5;
+const ABSPOINTER_REQUEST_ABSOLUTE: u32 = 0x5342_4152;
+const ABSPOINTER_VERSION_ID: u32 = 0x3442_554A;
+/// Status returned when the device has to be enabled again
+const ABSPOINTER_STATUS_ERROR: u32 = 0xFFFF_0000;
+
+const BUTTON_LEFT: u32 = 0x20;
+const BUTTON_RIGHT: u32 = 0x10;
+
+/// Words in one position packet: buttons, x, y and the wheel
+const PACKET_WORDS: u32 = 4;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the cursor position currently comes from the vmmouse
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Run a backdoor command, returns eax, ebx, ecx and edx afterwards.
+/// Without a VM the port reads as all ones, and ebx never holds the magic.
+fn backdoor(command: u32, argument: u32) -> [u32; 4] {
+    let (eax, ebx, ecx, edx): (u32, u64, u32, u32);
+
+    // rbx is reserved by LLVM, so the argument is swapped in and out of it
+    unsafe {
+        core::arch::asm!(
+            "xchg rbx, {argument}",
+            "in eax, dx",
+            "xchg rbx, {argument}",
+            argument = inout(reg) argument as u64 => ebx,
+            inout("eax") BACKDOOR_MAGIC => eax,
+            inout("ecx") command => ecx,
+            inout("edx") BACKDOOR_PORT as u32 => edx,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+
+    [eax, ebx as u32, ecx, edx]
+}
+
+fn is_present() -> bool {
+    backdoor(CMD_GET_VERSION, !BACKDOOR_MAGIC)[1] == BACKDOOR_MAGIC
+}
+
+/// Words waiting in the device queue, or `None` if it has to be enabled again
+fn queued_words() -> Option<u32> {
+    let status = backdoor(CMD_ABSPOINTER_STATUS, 0)[0];
+    (status != ABSPOINTER_STATUS_ERROR).then_some(status & 0xFFFF)
+}
+
+fn enable() -> bool {
+    backdoor(CMD_ABSPOINTER_COMMAND, ABSPOINTER_READ_ID);
+    if queued_words() != Some(1) {
+        return false;
+    }
+    if backdoor(CMD_ABSPOINTER_DATA, 1)[0] != ABSPOINTER_VERSION_ID {
+        return false;
+    }
+
+    backdoor(CMD_ABSPOINTER_COMMAND, ABSPOINTER_REQUEST_ABSOLUTE);
+    true
+}
+
+/// Switch to absolute mode if [`MOUSE_MODE`] allows it and the VM has a vmmouse
+pub fn init() {
+    if MOUSE_MODE == MouseMode::Relative {
+        log!(LogLevel::Info, "Mouse: relative mode forced");
+        return;
+    }
+
+    if is_present() && enable() {
+        ACTIVE.store(true, Ordering::Relaxed);
+        log!(LogLevel::Info, "Mouse: using the vmmouse absolute pointer");
+    } else if MOUSE_MODE == MouseMode::Absolute {
+        log!(
+            LogLevel::Warn,
+            "Mouse: absolute mode forced but there is no vmmouse, using relative mode"
+        );
+    } else {
+        log!(LogLevel::Info, "Mouse: no vmmouse, using relative mode");
+    }
+}
+
+/// Go back to relative PS/2 packets
+pub fn disable() {
+    if ACTIVE.swap(false, Ordering::Relaxed) {
+        backdoor(CMD_ABSPOINTER_COMMAND, ABSPOINTER_DISABLE);
+    }
+}
+
+/// Take the next position from the device queue, if a whole packet is there.
+/// Called from the mouse interrupt, so it must not block.
+pub fn poll() -> Option<MouseEvent> {
+    let Some(words) = queued_words() else {
+        // The device was reset, ask for absolute mode again
+        if !enable() {
+            ACTIVE.store(false, Ordering::Relaxed);
+        }
+        return None;
+    };
+    if words < PACKET_WORDS {
+        return None;
+    }
+
+    let [buttons, x, y, _wheel] = backdoor(CMD_ABSPOINTER_DATA, PACKET_WORDS);
+    Some(MouseEvent::Absolute {
+        x: x as u16,
+        y: y as u16,
+        left_button_down: buttons & BUTTON_LEFT != 0,
+        right_button_down: buttons & BUTTON_RIGHT != 0,
+    })
+}